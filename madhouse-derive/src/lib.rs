@@ -0,0 +1,327 @@
+//! `#[derive(Command)]` for [madhouse](https://docs.rs/madhouse).
+//!
+//! Use it through madhouse's `derive` feature, which re-exports the macro
+//! as `madhouse::Command` next to the trait it implements.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Expr, Fields, Ident, LitStr, Result, Type};
+
+/// Derives `madhouse::Command` from attributes.
+///
+/// On the struct:
+///
+/// - `#[command(state = S, context = C)]` (required): the state and test
+///   context types.
+/// - `#[apply(expr)]` (required): applies the command; `state` is the
+///   `&mut S`.
+/// - `#[check(expr)]`: whether the command can be applied; `state` is the
+///   `&S`. Defaults to `true`.
+/// - `#[label("...")]`: a format string for the label. Defaults to the
+///   struct name in screaming snake case followed by the field values, as
+///   in `INCREMENT(3)`.
+/// - `#[weight(expr)]`: how often random mode picks the command, as a
+///   `u32`; `ctx` is the `&C` (see `Command::weight`). Defaults to 1.
+///
+/// In `check`, `apply` and `label`, the fields are in scope by reference, as
+/// are `self` and `state`.
+///
+/// On fields, `#[strategy(expr)]` generates the field's values; `ctx` is
+/// the `Arc<C>`. Fields without one use `proptest::arbitrary::any`.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::{scenario, Command, State, TestContext};
+/// use std::sync::Arc;
+///
+/// #[derive(Debug, Default)]
+/// struct Counter { count: u64 }
+/// impl State for Counter {}
+///
+/// #[derive(Debug, Clone, Default)]
+/// struct Ctx {}
+/// impl TestContext for Ctx {}
+///
+/// #[derive(Command)]
+/// #[command(state = Counter, context = Ctx)]
+/// #[check(state.count < 100)]
+/// #[apply(state.count += amount)]
+/// #[label("INC({amount})")]
+/// #[weight(3)]
+/// struct Inc {
+///     #[strategy(1..=5u64)]
+///     amount: u64,
+/// }
+///
+/// #[derive(Command)]
+/// #[command(state = Counter, context = Ctx)]
+/// #[check(state.count > 0)]
+/// #[apply(state.count = 0)]
+/// struct Reset;
+///
+/// #[derive(Command)]
+/// #[command(state = Counter, context = Ctx)]
+/// #[apply(state.count += u64::from(*low) + u64::from(*high))]
+/// struct AddBoth {
+///     #[strategy(0..3u8)]
+///     low: u8,
+///     high: u8,
+/// }
+///
+/// assert_eq!(Inc { amount: 3 }.label(), "INC(3)");
+/// assert_eq!(<Inc as Command<Counter, Ctx>>::weight(&Ctx {}), 3);
+/// assert_eq!(Reset.label(), "RESET");
+/// assert_eq!(AddBoth { low: 1, high: 2 }.label(), "ADD_BOTH(1, 2)");
+///
+/// let ctx = Arc::new(Ctx::default());
+/// scenario![ctx, Inc, Reset, AddBoth];
+/// ```
+#[proc_macro_derive(Command, attributes(command, check, apply, label, strategy, weight))]
+pub fn derive_command(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// Attributes of the struct.
+struct Options {
+    state: Type,
+    context: Type,
+    check: Option<Expr>,
+    apply: Expr,
+    label: Option<LitStr>,
+    weight: Option<Expr>,
+}
+
+fn options(input: &DeriveInput) -> Result<Options> {
+    let (mut state, mut context, mut check, mut apply, mut label) = (None, None, None, None, None);
+    let mut weight = None;
+    for attr in &input.attrs {
+        let path = attr.path();
+        if path.is_ident("command") {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("state") {
+                    state = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("context") {
+                    context = Some(meta.value()?.parse()?);
+                } else {
+                    return Err(meta.error("expected `state` or `context`"));
+                }
+                Ok(())
+            })?;
+        } else if path.is_ident("check") {
+            check = Some(attr.parse_args()?);
+        } else if path.is_ident("apply") {
+            apply = Some(attr.parse_args()?);
+        } else if path.is_ident("label") {
+            label = Some(attr.parse_args()?);
+        } else if path.is_ident("weight") {
+            weight = Some(attr.parse_args()?);
+        }
+    }
+    let missing = |what: &str| Error::new_spanned(&input.ident, format!("missing {}", what));
+    Ok(Options {
+        state: state.ok_or_else(|| missing("`state` in #[command(state = ..., context = ...)]"))?,
+        context: context
+            .ok_or_else(|| missing("`context` in #[command(state = ..., context = ...)]"))?,
+        check,
+        apply: apply.ok_or_else(|| missing("#[apply(...)]"))?,
+        label,
+        weight,
+    })
+}
+
+/// A field and the strategy generating it.
+struct Field {
+    ident: Ident,
+    strategy: TokenStream2,
+}
+
+fn fields(input: &DeriveInput) -> Result<Option<Vec<Field>>> {
+    let Data::Struct(data) = &input.data else {
+        return Err(Error::new_spanned(
+            &input.ident,
+            "#[derive(Command)] only supports structs",
+        ));
+    };
+    let named = match &data.fields {
+        Fields::Named(named) => named,
+        Fields::Unit => return Ok(None),
+        Fields::Unnamed(_) => {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "#[derive(Command)] needs named fields (or none)",
+            ))
+        }
+    };
+    named
+        .named
+        .iter()
+        .map(|field| {
+            let ty = &field.ty;
+            let strategy = match field.attrs.iter().find(|a| a.path().is_ident("strategy")) {
+                Some(attr) => {
+                    let expr: Expr = attr.parse_args()?;
+                    quote!(#expr)
+                }
+                None => quote!(::madhouse::__private::proptest::arbitrary::any::<#ty>()),
+            };
+            Ok(Field {
+                ident: field.ident.clone().expect("named field"),
+                strategy,
+            })
+        })
+        .collect::<Result<Vec<_>>>()
+        .map(Some)
+}
+
+/// `IncrementBy` -> `INCREMENT_BY`.
+fn screaming_snake_case(name: &str) -> String {
+    let mut out = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            out.push('_');
+        }
+        out.extend(c.to_uppercase());
+    }
+    out
+}
+
+fn expand(input: &DeriveInput) -> Result<TokenStream2> {
+    let options = options(input)?;
+    let fields = fields(input)?;
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let Options {
+        state,
+        context,
+        check,
+        apply,
+        label,
+        weight,
+    } = &options;
+
+    let idents: Vec<&Ident> = fields.iter().flatten().map(|f| &f.ident).collect();
+    let bind = match &fields {
+        Some(_) => quote! {
+            #[allow(unused_variables)]
+            let Self { #(#idents,)* .. } = self;
+        },
+        None => quote!(),
+    };
+
+    let check = match check {
+        Some(check) => quote!(#check),
+        None => quote!(true),
+    };
+
+    let label = match label {
+        Some(format) => quote!(::std::format!(#format)),
+        None if idents.is_empty() => {
+            let label = screaming_snake_case(&name.to_string());
+            quote!(::std::string::String::from(#label))
+        }
+        None => {
+            let format = format!(
+                "{}({})",
+                screaming_snake_case(&name.to_string()),
+                vec!["{:?}"; idents.len()].join(", ")
+            );
+            quote!(::std::format!(#format, #(#idents),*))
+        }
+    };
+
+    let weight = weight.as_ref().map(|weight| {
+        quote! {
+            fn weight(ctx: &#context) -> u32 {
+                let _ = ctx;
+                #weight
+            }
+        }
+    });
+
+    let build = match &fields {
+        None => quote!(::madhouse::__private::proptest::strategy::Just(
+            ::madhouse::CommandWrapper::new(Self)
+        )),
+        Some(fields) if fields.is_empty() => {
+            quote!(::madhouse::__private::proptest::strategy::Just(
+                ::madhouse::CommandWrapper::new(Self {})
+            ))
+        }
+        Some(fields) => {
+            // Nested pairs, since proptest tuples stop at 12 elements.
+            let mut strategy = fields[0].strategy.clone();
+            let first = &fields[0].ident;
+            let mut pattern = quote!(#first);
+            for field in &fields[1..] {
+                let (next, ident) = (&field.strategy, &field.ident);
+                strategy = quote!((#strategy, #next));
+                pattern = quote!((#pattern, #ident));
+            }
+            quote! {
+                ::madhouse::__private::proptest::strategy::Strategy::prop_map(
+                    #strategy,
+                    |#pattern| ::madhouse::CommandWrapper::new(Self { #(#idents),* }),
+                )
+            }
+        }
+    };
+    Ok(quote! {
+        impl #impl_generics ::madhouse::Command<#state, #context> for #name #ty_generics #where_clause {
+            fn check(&self, state: &#state) -> bool {
+                #bind
+                #check
+            }
+
+            fn apply(&self, state: &mut #state) {
+                #bind
+                #apply;
+            }
+
+            fn label(&self) -> ::std::string::String {
+                #bind
+                #label
+            }
+
+            #weight
+
+            fn build(
+                ctx: ::std::sync::Arc<#context>,
+            ) -> impl ::madhouse::__private::proptest::strategy::Strategy<
+                Value = ::madhouse::CommandWrapper<#state, #context>,
+            > {
+                let _ = &ctx;
+                #build
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rejects_missing_attributes_and_tuple_structs() {
+        let input: DeriveInput = syn::parse_quote! {
+            #[command(state = Counter, context = Ctx)]
+            struct Inc { amount: u64 }
+        };
+        let err = expand(&input).unwrap_err();
+        assert_eq!(err.to_string(), "missing #[apply(...)]");
+
+        let input: DeriveInput = syn::parse_quote! {
+            #[command(state = Counter, context = Ctx)]
+            #[apply(state.count += 1)]
+            struct Inc(u64);
+        };
+        let err = expand(&input).unwrap_err();
+        assert!(err.to_string().contains("named fields"));
+
+        assert_eq!(screaming_snake_case("IncrementBy"), "INCREMENT_BY");
+    }
+}
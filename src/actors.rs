@@ -0,0 +1,396 @@
+//! Scenarios over several actors, each with its own state.
+//!
+//! A network of miners, or clients of one server, is naturally several
+//! copies of one state rather than a monolithic one. [`Actors`] holds `N`
+//! states, one per actor, and is itself a [`State`]. Commands written
+//! against one actor's state are addressed to a single actor with
+//! [`ToActor`], or to all of them with [`ToAll`]; their strategies pick
+//! the actor, so random mode interleaves the per-actor sequences.
+//!
+//! Addressed commands are labeled `LABEL@i` and broadcasts `LABEL@*`, and
+//! parse back if the inner command does.
+//!
+//! # Examples
+//!
+//! ```
+//! use madhouse::actors::{Actors, ToActor, ToAll};
+//! use madhouse::{scenario, Command, CommandWrapper, State, TestContext};
+//! use proptest::prelude::*;
+//! use std::sync::Arc;
+//!
+//! #[derive(Debug, Default)]
+//! struct Miner { height: u64 }
+//! impl State for Miner {}
+//!
+//! #[derive(Debug, Clone, Default)]
+//! struct Ctx {}
+//! impl TestContext for Ctx {}
+//!
+//! #[derive(Clone)]
+//! struct Mine;
+//! impl Command<Miner, Ctx> for Mine {
+//!     fn check(&self, _state: &Miner) -> bool { true }
+//!     fn apply(&self, state: &mut Miner) { state.height += 1; }
+//!     fn label(&self) -> String { "MINE".to_string() }
+//!     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Miner, Ctx>> {
+//!         Just(CommandWrapper::new(Mine))
+//!     }
+//! }
+//!
+//! #[derive(Clone)]
+//! struct Reorg;
+//! impl Command<Miner, Ctx> for Reorg {
+//!     fn check(&self, state: &Miner) -> bool { state.height > 0 }
+//!     fn apply(&self, state: &mut Miner) { state.height -= 1; }
+//!     fn label(&self) -> String { "REORG".to_string() }
+//!     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Miner, Ctx>> {
+//!         Just(CommandWrapper::new(Reorg))
+//!     }
+//! }
+//!
+//! // Three miners; each `Mine` goes to one of them, each `Reorg` to all.
+//! type MineOnOne = ToActor<Mine, 3>;
+//! type ReorgAll = ToAll<Reorg, 3>;
+//!
+//! let ctx = Arc::new(Ctx::default());
+//! scenario![
+//!     ctx,
+//!     MineOnOne,
+//!     ReorgAll,
+//!     (ToActor::<_, 3>::new(2, Mine))
+//! ];
+//!
+//! let mut miners = Actors::<Miner, 3>::default();
+//! CommandWrapper::new(ToActor::<_, 3>::new(1, Mine)).command.apply(&mut miners);
+//! assert_eq!(miners[1].height, 1);
+//! assert_eq!(Command::<Actors<Miner, 3>, Ctx>::label(&ToAll::<_, 3>::new(Reorg)), "REORG@*");
+//! ```
+
+use crate::gen::stable_hash;
+use crate::{Command, CommandError, CommandWrapper, Env, InvariantViolation, SkipReason};
+use crate::{State, TestContext};
+use proptest::prelude::Strategy;
+use std::ops::{Index, IndexMut};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The states of `N` actors.
+#[derive(Debug)]
+pub struct Actors<S, const N: usize> {
+    actors: [S; N],
+}
+
+impl<S, const N: usize> Actors<S, N> {
+    /// Creates the actors from their states.
+    pub fn new(actors: [S; N]) -> Self {
+        Self { actors }
+    }
+
+    /// Returns the states, by actor.
+    pub fn iter(&self) -> std::slice::Iter<'_, S> {
+        self.actors.iter()
+    }
+}
+
+impl<S: Default, const N: usize> Default for Actors<S, N> {
+    fn default() -> Self {
+        Self::new(std::array::from_fn(|_| S::default()))
+    }
+}
+
+impl<S, const N: usize> Index<usize> for Actors<S, N> {
+    type Output = S;
+
+    fn index(&self, actor: usize) -> &S {
+        &self.actors[actor]
+    }
+}
+
+impl<S, const N: usize> IndexMut<usize> for Actors<S, N> {
+    fn index_mut(&mut self, actor: usize) -> &mut S {
+        &mut self.actors[actor]
+    }
+}
+
+/// Holds if every actor's invariants hold.
+impl<S: State, const N: usize> State for Actors<S, N> {
+    fn invariants(&self) -> Result<(), InvariantViolation> {
+        for (actor, state) in self.actors.iter().enumerate() {
+            state.invariants().map_err(|violation| {
+                InvariantViolation::new(format!("actor {}: {}", actor, violation))
+            })?;
+        }
+        Ok(())
+    }
+
+    fn fingerprint(&self) -> u64 {
+        let fingerprints: Vec<u64> = self.actors.iter().map(State::fingerprint).collect();
+        stable_hash(&fingerprints)
+    }
+}
+
+/// Rebuilds the command a strategy of `Cmd` generated.
+fn unwrap<Cmd: Clone + 'static, S: State, C: TestContext>(command: CommandWrapper<S, C>) -> Cmd {
+    command
+        .downcast_ref::<Cmd>()
+        .cloned()
+        .expect("strategy of a command type generated another type")
+}
+
+/// A command applied to one actor's state.
+#[derive(Clone)]
+pub struct ToActor<Cmd, const N: usize> {
+    /// Index of the actor, below `N`.
+    pub actor: usize,
+    /// The command.
+    pub command: Cmd,
+}
+
+impl<Cmd, const N: usize> ToActor<Cmd, N> {
+    /// Addresses `command` to `actor`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `actor` is not below `N`.
+    pub fn new(actor: usize, command: Cmd) -> Self {
+        assert!(actor < N, "actor {} out of {}", actor, N);
+        Self { actor, command }
+    }
+}
+
+impl<S, C, Cmd, const N: usize> Command<Actors<S, N>, C> for ToActor<Cmd, N>
+where
+    S: State + 'static,
+    C: TestContext + 'static,
+    Cmd: Command<S, C> + Clone + 'static,
+{
+    fn check(&self, state: &Actors<S, N>) -> bool {
+        self.command.check(&state[self.actor])
+    }
+
+    fn check_detailed(&self, state: &Actors<S, N>) -> Result<(), SkipReason> {
+        self.command.check_detailed(&state[self.actor])
+    }
+
+    fn apply(&self, state: &mut Actors<S, N>) {
+        self.command.apply(&mut state[self.actor]);
+    }
+
+    fn apply_with(&self, state: &mut Actors<S, N>, env: &mut Env) {
+        self.command.apply_with(&mut state[self.actor], env);
+    }
+
+    fn try_apply(&self, state: &mut Actors<S, N>) -> Result<(), CommandError> {
+        self.command.try_apply(&mut state[self.actor])
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.command.timeout()
+    }
+
+    fn cleanup(&self, state: &mut Actors<S, N>) {
+        self.command.cleanup(&mut state[self.actor]);
+    }
+
+    fn label(&self) -> String {
+        format!("{}@{}", self.command.label(), self.actor)
+    }
+
+    fn group(&self) -> String {
+        self.command.group()
+    }
+
+    fn weight(ctx: &C) -> u32 {
+        Cmd::weight(ctx)
+    }
+
+    /// Picks the actor uniformly, shrinking toward the first one.
+    fn build(ctx: Arc<C>) -> impl Strategy<Value = CommandWrapper<Actors<S, N>, C>> {
+        (0..N, Cmd::build(ctx)).prop_map(|(actor, command)| {
+            CommandWrapper::new(Self::new(actor, unwrap::<Cmd, S, C>(command)))
+        })
+    }
+
+    fn parse(label: &str, ctx: Arc<C>) -> Option<CommandWrapper<Actors<S, N>, C>> {
+        let (inner, actor) = label.rsplit_once('@')?;
+        let actor = actor.parse().ok().filter(|&actor| actor < N)?;
+        let command = unwrap::<Cmd, S, C>(Cmd::parse(inner, ctx)?);
+        Some(CommandWrapper::new(Self::new(actor, command)))
+    }
+
+    /// Tries the first actor, then the command's own variants.
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        let first = (self.actor > 0).then(|| Self::new(0, self.command.clone()));
+        let actor = self.actor;
+        let variants = self
+            .command
+            .shrink()
+            .map(move |command| Self::new(actor, command));
+        Box::new(first.into_iter().chain(variants))
+    }
+}
+
+/// A command applied to every actor whose state it applies to.
+///
+/// It is skipped only if it applies to no actor, and fails at the first
+/// actor it fails on.
+#[derive(Clone)]
+pub struct ToAll<Cmd, const N: usize> {
+    /// The command.
+    pub command: Cmd,
+}
+
+impl<Cmd, const N: usize> ToAll<Cmd, N> {
+    /// Broadcasts `command` to every actor.
+    pub fn new(command: Cmd) -> Self {
+        Self { command }
+    }
+}
+
+impl<S, C, Cmd, const N: usize> Command<Actors<S, N>, C> for ToAll<Cmd, N>
+where
+    S: State + 'static,
+    C: TestContext + 'static,
+    Cmd: Command<S, C> + Clone + 'static,
+{
+    fn check(&self, state: &Actors<S, N>) -> bool {
+        state.iter().any(|actor| self.command.check(actor))
+    }
+
+    fn apply(&self, state: &mut Actors<S, N>) {
+        if let Err(err) = self.try_apply(state) {
+            panic!("{}: {}", Command::<Actors<S, N>, C>::label(self), err);
+        }
+    }
+
+    fn try_apply(&self, state: &mut Actors<S, N>) -> Result<(), CommandError> {
+        for (actor, state) in state.actors.iter_mut().enumerate() {
+            if self.command.check(state) {
+                self.command
+                    .try_apply(state)
+                    .map_err(|err| CommandError::new(format!("actor {}: {}", actor, err)))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.command.timeout()
+    }
+
+    fn cleanup(&self, state: &mut Actors<S, N>) {
+        for actor in state.actors.iter_mut() {
+            self.command.cleanup(actor);
+        }
+    }
+
+    fn label(&self) -> String {
+        format!("{}@*", self.command.label())
+    }
+
+    fn group(&self) -> String {
+        self.command.group()
+    }
+
+    fn weight(ctx: &C) -> u32 {
+        Cmd::weight(ctx)
+    }
+
+    fn build(ctx: Arc<C>) -> impl Strategy<Value = CommandWrapper<Actors<S, N>, C>> {
+        Cmd::build(ctx)
+            .prop_map(|command| CommandWrapper::new(Self::new(unwrap::<Cmd, S, C>(command))))
+    }
+
+    fn parse(label: &str, ctx: Arc<C>) -> Option<CommandWrapper<Actors<S, N>, C>> {
+        let inner = label.strip_suffix("@*")?;
+        let command = unwrap::<Cmd, S, C>(Cmd::parse(inner, ctx)?);
+        Some(CommandWrapper::new(Self::new(command)))
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        Box::new(self.command.shrink().map(Self::new))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execute_commands_silent;
+    use proptest::prelude::Just;
+
+    #[derive(Debug, Default)]
+    struct Inbox(Vec<u8>);
+
+    impl State for Inbox {
+        fn invariants(&self) -> Result<(), InvariantViolation> {
+            match self.0.len() {
+                0..=2 => Ok(()),
+                n => Err(InvariantViolation::new(format!("{} messages", n))),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Default)]
+    struct Ctx;
+
+    impl TestContext for Ctx {}
+
+    #[derive(Clone)]
+    struct Deliver(u8);
+
+    impl Command<Inbox, Ctx> for Deliver {
+        fn check(&self, state: &Inbox) -> bool {
+            !state.0.contains(&self.0)
+        }
+
+        fn apply(&self, state: &mut Inbox) {
+            state.0.push(self.0);
+        }
+
+        fn label(&self) -> String {
+            format!("DELIVER({})", self.0)
+        }
+
+        fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Inbox, Ctx>> {
+            Just(CommandWrapper::new(Deliver(1)))
+        }
+
+        fn parse(label: &str, _ctx: Arc<Ctx>) -> Option<CommandWrapper<Inbox, Ctx>> {
+            let n = label
+                .strip_prefix("DELIVER(")?
+                .strip_suffix(')')?
+                .parse()
+                .ok()?;
+            Some(CommandWrapper::new(Deliver(n)))
+        }
+    }
+
+    type Pair = Actors<Inbox, 2>;
+
+    #[test]
+    fn test_broadcasts_skip_actors_and_labels_round_trip() {
+        let commands = vec![
+            CommandWrapper::<Pair, Ctx>::new(ToActor::<_, 2>::new(1, Deliver(1))),
+            CommandWrapper::new(ToAll::<_, 2>::new(Deliver(1))),
+            CommandWrapper::new(ToAll::<_, 2>::new(Deliver(2))),
+            CommandWrapper::new(ToAll::<_, 2>::new(Deliver(3))),
+        ];
+        let mut pair = Pair::default();
+        let report = execute_commands_silent(&commands, &mut pair);
+        assert_eq!(pair[0].0, [1, 2, 3]);
+        assert_eq!(pair[1].0, [1, 2, 3]);
+        assert_eq!(
+            report.failure_message().unwrap(),
+            "invariant violated after command 4 (DELIVER(3)@*): actor 0: 3 messages"
+        );
+
+        let ctx = Arc::new(Ctx);
+        for label in ["DELIVER(4)@1", "DELIVER(4)@*"] {
+            let parsed = ToActor::<Deliver, 2>::parse(label, ctx.clone())
+                .or_else(|| ToAll::<Deliver, 2>::parse(label, ctx.clone()))
+                .unwrap();
+            assert_eq!(parsed.command.label(), label);
+        }
+        assert!(ToActor::<Deliver, 2>::parse("DELIVER(4)@2", ctx).is_none());
+    }
+}
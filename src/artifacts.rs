@@ -0,0 +1,73 @@
+//! Failure artifacts captured from the system under test.
+//!
+//! Enabled with the `artifacts` feature. Hooks registered with
+//! [`Scenario::on_failure`](crate::Scenario::on_failure) run right after a
+//! case first fails, while the SUT is still in the failing state, and save
+//! whatever helps diagnose it (a headless-browser screenshot of a dashboard,
+//! an HTTP snapshot of a status page, log tails) into a per-failure
+//! directory.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Default directory failure artifacts are written under.
+pub const DEFAULT_DIR: &str = "madhouse-artifacts";
+
+/// Where and why a failure's artifacts are captured.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::artifacts::FailureArtifacts;
+///
+/// fn snapshot_status_page(failure: &FailureArtifacts) -> std::io::Result<()> {
+///     // e.g. let body = http_get(&format!("{}/status", ctx.url))?;
+///     let body = format!("failed after {} commands", failure.labels.len());
+///     failure.write("status.html", body)?;
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct FailureArtifacts {
+    /// Directory for this failure, unique per failing trace.
+    pub dir: PathBuf,
+    /// Index of the failing case.
+    pub case: u32,
+    /// Failure message.
+    pub message: String,
+    /// Labels of the failing sequence, before shrinking.
+    pub labels: Vec<String>,
+}
+
+impl FailureArtifacts {
+    /// Writes an artifact into [`dir`](Self::dir), creating it if needed,
+    /// and returns its path.
+    pub fn write(&self, name: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> io::Result<PathBuf> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(name);
+        fs::write(&path, contents)?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_creates_directory() {
+        let dir = std::env::temp_dir().join(format!("madhouse-artifacts-{}", std::process::id()));
+        let failure = FailureArtifacts {
+            dir: dir.join("case"),
+            case: 0,
+            message: "boom".to_string(),
+            labels: vec!["A".to_string()],
+        };
+
+        let path = failure.write("status.txt", "down").unwrap();
+
+        assert_eq!(fs::read_to_string(path).unwrap(), "down");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
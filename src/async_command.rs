@@ -0,0 +1,353 @@
+//! Async commands for systems under test that expose async APIs.
+//!
+//! Enabled with the `async` feature. The executor is runtime-agnostic: it
+//! only awaits each command in turn, so it can be driven by tokio (e.g. from
+//! a `#[tokio::test]`) or any other executor. Timing covers the awaited
+//! `apply`, so durations reflect real async latency rather than the cost of
+//! a nested `block_on`.
+
+use crate::config::Format;
+use crate::{
+    check_invariants, CommandError, ExecutionReport, SkipReason, State, StepOutcome, StepReport,
+    TestContext,
+};
+use proptest::prelude::Strategy;
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Boxed future returned by [`ErasedAsyncCommand`].
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
+
+/// Async counterpart of [`Command`](crate::Command).
+///
+/// Preconditions stay synchronous since they only inspect the model state;
+/// `apply` is async so it can await the system under test.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::{AsyncCommand, AsyncCommandWrapper, State, TestContext};
+/// use proptest::prelude::*;
+/// use std::sync::Arc;
+///
+/// #[derive(Debug, Default)]
+/// struct Service { requests: u64 }
+/// impl State for Service {}
+///
+/// #[derive(Debug, Clone, Default)]
+/// struct Ctx {}
+/// impl TestContext for Ctx {}
+///
+/// struct Request;
+/// impl AsyncCommand<Service, Ctx> for Request {
+///     fn check(&self, _state: &Service) -> bool { true }
+///     async fn apply(&self, state: &mut Service) {
+///         // e.g. client.get(url).await
+///         state.requests += 1;
+///     }
+///     fn label(&self) -> String { "REQUEST".to_string() }
+///     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = AsyncCommandWrapper<Service, Ctx>> {
+///         Just(AsyncCommandWrapper::new(Request))
+///     }
+/// }
+/// ```
+pub trait AsyncCommand<S: State, C: TestContext> {
+    /// Checks if the command can be applied to the current state.
+    ///
+    /// # Arguments
+    /// * `state` - Current state to check against.
+    fn check(&self, state: &S) -> bool;
+
+    /// Applies the command to the state, modifying it.
+    ///
+    /// # Arguments
+    /// * `state` - State to modify.
+    fn apply(&self, state: &mut S) -> impl Future<Output = ()>;
+
+    /// Applies the command to the state, reporting failures as errors.
+    ///
+    /// The executor always calls this method; the default delegates to
+    /// `apply`.
+    ///
+    /// # Arguments
+    /// * `state` - State to modify.
+    fn try_apply(&self, state: &mut S) -> impl Future<Output = Result<(), CommandError>> {
+        async move {
+            self.apply(state).await;
+            Ok(())
+        }
+    }
+
+    /// Returns a human-readable label for the command.
+    fn label(&self) -> String;
+
+    /// Returns the group the command is reported under. The default is
+    /// the module path of the implementing type.
+    fn group(&self) -> String {
+        crate::report::module_group(std::any::type_name::<Self>()).to_string()
+    }
+
+    /// Builds a proptest strategy for generating instances of this command.
+    ///
+    /// # Arguments
+    /// * `ctx` - Test context used to parameterize command generation.
+    fn build(ctx: Arc<C>) -> impl Strategy<Value = AsyncCommandWrapper<S, C>>
+    where
+        Self: Sized;
+}
+
+/// Object-safe view of an [`AsyncCommand`].
+///
+/// Implemented for every `AsyncCommand`; there is no need to implement it
+/// by hand.
+pub trait ErasedAsyncCommand<S: State, C: TestContext> {
+    /// See [`AsyncCommand::check`].
+    fn check(&self, state: &S) -> bool;
+
+    /// See [`AsyncCommand::try_apply`].
+    fn try_apply<'a>(&'a self, state: &'a mut S) -> BoxFuture<'a, Result<(), CommandError>>;
+
+    /// See [`AsyncCommand::label`].
+    fn label(&self) -> String;
+
+    /// See [`AsyncCommand::group`].
+    fn group(&self) -> String;
+}
+
+impl<S: State, C: TestContext + 'static, T: AsyncCommand<S, C>> ErasedAsyncCommand<S, C> for T {
+    fn check(&self, state: &S) -> bool {
+        AsyncCommand::check(self, state)
+    }
+
+    fn try_apply<'a>(&'a self, state: &'a mut S) -> BoxFuture<'a, Result<(), CommandError>> {
+        Box::pin(AsyncCommand::try_apply(self, state))
+    }
+
+    fn label(&self) -> String {
+        AsyncCommand::label(self)
+    }
+
+    fn group(&self) -> String {
+        AsyncCommand::group(self)
+    }
+}
+
+/// Wrapper for async command trait objects.
+pub struct AsyncCommandWrapper<S: State, C: TestContext> {
+    /// The wrapped command trait object.
+    pub command: Arc<dyn ErasedAsyncCommand<S, C>>,
+}
+
+impl<S: State, C: TestContext + 'static> AsyncCommandWrapper<S, C> {
+    /// Creates a new wrapper for the given async command.
+    ///
+    /// # Arguments
+    ///
+    /// * `cmd` - The command to wrap.
+    pub fn new<Cmd: AsyncCommand<S, C> + 'static>(cmd: Cmd) -> Self {
+        Self {
+            command: Arc::new(cmd),
+        }
+    }
+}
+
+impl<S: State, C: TestContext> Clone for AsyncCommandWrapper<S, C> {
+    fn clone(&self) -> Self {
+        Self {
+            command: Arc::clone(&self.command),
+        }
+    }
+}
+
+impl<S: State, C: TestContext> Debug for AsyncCommandWrapper<S, C> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}", self.command.label())
+    }
+}
+
+/// Executes a sequence of async commands and reports what happened.
+///
+/// Behaves like [`execute_commands`](crate::execute_commands), awaiting each
+/// applied command before checking the next one, and stopping at the first
+/// [`State::invariants`](crate::State::invariants) violation.
+///
+/// # Examples
+///
+/// ```
+/// # use madhouse::{AsyncCommand, AsyncCommandWrapper, State, TestContext};
+/// # use proptest::prelude::*;
+/// # use std::sync::Arc;
+/// # #[derive(Debug, Default)]
+/// # struct Service { requests: u64 }
+/// # impl State for Service {}
+/// # #[derive(Debug, Clone, Default)]
+/// # struct Ctx {}
+/// # impl TestContext for Ctx {}
+/// # struct Request;
+/// # impl AsyncCommand<Service, Ctx> for Request {
+/// #     fn check(&self, _state: &Service) -> bool { true }
+/// #     async fn apply(&self, state: &mut Service) { state.requests += 1; }
+/// #     fn label(&self) -> String { "REQUEST".to_string() }
+/// #     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = AsyncCommandWrapper<Service, Ctx>> {
+/// #         Just(AsyncCommandWrapper::new(Request))
+/// #     }
+/// # }
+/// use madhouse::execute_commands_async;
+///
+/// #[tokio::main(flavor = "current_thread")]
+/// async fn main() {
+///     let mut state = Service::default();
+///     let commands = vec![AsyncCommandWrapper::new(Request), AsyncCommandWrapper::new(Request)];
+///
+///     let report = execute_commands_async(&commands, &mut state).await;
+///     assert_eq!(report.executed().count(), 2);
+///     assert_eq!(state.requests, 2);
+/// }
+/// ```
+pub async fn execute_commands_async<S: State, C: TestContext>(
+    commands: &[AsyncCommandWrapper<S, C>],
+    state: &mut S,
+) -> ExecutionReport {
+    let mut report = ExecutionReport {
+        steps: Vec::with_capacity(commands.len()),
+        ..Default::default()
+    };
+
+    for (index, cmd) in commands.iter().enumerate() {
+        let started = SystemTime::now();
+        let start = Instant::now();
+        let applies = cmd.command.check(state);
+        let check_duration = start.elapsed();
+        let (outcome, duration) = if applies {
+            let start = Instant::now();
+            let result = cmd.command.try_apply(state).await;
+            let duration = start.elapsed();
+            match result {
+                Ok(()) => (StepOutcome::Executed, duration),
+                Err(err) => (StepOutcome::Failed(err), duration),
+            }
+        } else {
+            (StepOutcome::Skipped(SkipReason::default()), Duration::ZERO)
+        };
+        let step = StepReport {
+            index,
+            label: cmd.command.label(),
+            group: cmd.command.group(),
+            outcome,
+            duration,
+            check_duration,
+            started,
+            finished: SystemTime::now(),
+        };
+        let broken = step
+            .was_applied()
+            .then(|| check_invariants(&step, state))
+            .flatten();
+        report.steps.push(step);
+        if broken.is_some() {
+            report.broken_invariant = broken;
+            break;
+        }
+    }
+
+    report.print(Format::from_env(), state);
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::Just;
+
+    #[derive(Debug, Default)]
+    struct Service {
+        log: Vec<String>,
+        online: bool,
+    }
+
+    impl State for Service {}
+
+    #[derive(Debug, Clone, Default)]
+    struct Ctx {}
+
+    impl TestContext for Ctx {}
+
+    struct Connect;
+
+    impl AsyncCommand<Service, Ctx> for Connect {
+        fn check(&self, state: &Service) -> bool {
+            !state.online
+        }
+
+        async fn apply(&self, state: &mut Service) {
+            tokio::task::yield_now().await;
+            state.online = true;
+            state.log.push("CONNECT".to_string());
+        }
+
+        fn label(&self) -> String {
+            "CONNECT".to_string()
+        }
+
+        fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = AsyncCommandWrapper<Service, Ctx>> {
+            Just(AsyncCommandWrapper::new(Connect))
+        }
+    }
+
+    struct Transmit;
+
+    impl AsyncCommand<Service, Ctx> for Transmit {
+        fn check(&self, _state: &Service) -> bool {
+            true
+        }
+
+        async fn apply(&self, _state: &mut Service) {}
+
+        async fn try_apply(&self, state: &mut Service) -> Result<(), CommandError> {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            if !state.online {
+                return Err(CommandError::new("not connected"));
+            }
+            state.log.push("SEND".to_string());
+            Ok(())
+        }
+
+        fn label(&self) -> String {
+            "SEND".to_string()
+        }
+
+        fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = AsyncCommandWrapper<Service, Ctx>> {
+            Just(AsyncCommandWrapper::new(Transmit))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_execute_commands_async() {
+        let commands = vec![
+            AsyncCommandWrapper::new(Transmit),
+            AsyncCommandWrapper::new(Connect),
+            AsyncCommandWrapper::new(Connect),
+            AsyncCommandWrapper::new(Transmit),
+        ];
+        let mut state = Service::default();
+
+        let report = execute_commands_async(&commands, &mut state).await;
+
+        assert_eq!(state.log, vec!["CONNECT", "SEND"]);
+        assert_eq!(
+            report.skipped().map(|s| s.index).collect::<Vec<_>>(),
+            vec![2]
+        );
+        let failures: Vec<_> = report
+            .failures()
+            .map(|(s, e)| (s.index, e.message()))
+            .collect();
+        assert_eq!(failures, vec![(0, "not connected")]);
+        // Durations measure the awaited work.
+        assert!(report.steps[3].duration >= Duration::from_millis(5));
+    }
+}
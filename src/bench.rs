@@ -0,0 +1,217 @@
+//! Benchmarks of command sequences, with the `bench` feature.
+//!
+//! The commands written for model-based tests drive the system under test,
+//! so they can benchmark it too. [`bench_sequence`] (or the
+//! [`bench_scenario!`](crate::bench_scenario) macro) runs a fixed sequence
+//! under [criterion](https://docs.rs/criterion), from a fresh state every
+//! iteration: one benchmark times the whole sequence end to end, and one
+//! per command reports the `apply()` duration madhouse measures for that
+//! step. Criterion is re-exported as [`criterion`], so its version always
+//! matches.
+//!
+//! # Examples
+//!
+//! In `benches/counter.rs`, with `harness = false` for that bench target:
+//!
+//! ```
+//! use madhouse::bench::criterion::Criterion;
+//! use madhouse::{bench_scenario, Command, CommandWrapper, State, TestContext};
+//! use proptest::prelude::*;
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//!
+//! #[derive(Debug, Default)]
+//! struct Counter { value: u64 }
+//! impl State for Counter {}
+//!
+//! #[derive(Debug, Clone, Default)]
+//! struct Ctx {}
+//! impl TestContext for Ctx {}
+//!
+//! struct Add(u64);
+//! impl Command<Counter, Ctx> for Add {
+//!     fn check(&self, _state: &Counter) -> bool { true }
+//!     fn apply(&self, state: &mut Counter) { state.value += self.0; }
+//!     fn label(&self) -> String { format!("ADD({})", self.0) }
+//!     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Counter, Ctx>> {
+//!         Just(CommandWrapper::new(Add(1)))
+//!     }
+//! }
+//!
+//! fn counter(c: &mut Criterion) {
+//!     // Benchmarks "counter/sequence", "counter/00 ADD(1)" and "counter/01 ADD(2)".
+//!     bench_scenario!(c, "counter", Counter::default(), Add(1), Add(2));
+//! }
+//!
+//! // In a bench target: `criterion_group!(benches, counter);` and
+//! // `criterion_main!(benches);`.
+//! let mut c = Criterion::default()
+//!     .sample_size(10)
+//!     .warm_up_time(Duration::from_millis(1))
+//!     .measurement_time(Duration::from_millis(10));
+//! counter(&mut c);
+//! ```
+
+use crate::{run_commands, CommandWrapper, State, TestContext};
+pub use criterion;
+use criterion::{BatchSize, Criterion};
+use std::time::Duration;
+
+/// Benchmarks `commands`, applied in order to the state `setup` returns,
+/// as the group `name` of `c`.
+///
+/// The `sequence` benchmark times the whole execution, invariant checks
+/// included; each `NN LABEL` benchmark, one per command, sums the
+/// `apply()` durations recorded for step `NN`, which are zero while the
+/// command is skipped.
+pub fn bench_sequence<S: State, C: TestContext>(
+    c: &mut Criterion,
+    name: &str,
+    setup: impl Fn() -> S,
+    commands: &[CommandWrapper<S, C>],
+) {
+    let mut group = c.benchmark_group(name);
+    group.bench_function("sequence", |b| {
+        b.iter_batched(
+            &setup,
+            |mut state| run_commands(commands, &mut state, &[], &mut ()),
+            BatchSize::SmallInput,
+        )
+    });
+    for (index, command) in commands.iter().enumerate() {
+        let id = format!("{:02} {}", index, command.command.label());
+        group.bench_function(id, |b| {
+            b.iter_custom(|iters| step_duration(commands, &setup, index, iters))
+        });
+    }
+    group.finish();
+}
+
+/// Runs `commands` `iters` times, returning the total `apply()` duration of
+/// step `index`.
+fn step_duration<S: State, C: TestContext>(
+    commands: &[CommandWrapper<S, C>],
+    setup: impl Fn() -> S,
+    index: usize,
+    iters: u64,
+) -> Duration {
+    (0..iters)
+        .map(|_| {
+            let report = run_commands(commands, &mut setup(), &[], &mut ());
+            report
+                .steps
+                .get(index)
+                .map(|step| step.duration)
+                .unwrap_or_default()
+        })
+        .sum()
+}
+
+/// Benchmarks a fixed sequence of commands with criterion (see
+/// [`bench_sequence`](crate::bench::bench_sequence)).
+///
+/// Takes the `Criterion` instance, the group name, an expression for the
+/// initial state (evaluated once per iteration), and the commands, which
+/// are wrapped in [`CommandWrapper`](crate::CommandWrapper)s.
+///
+/// # Examples
+///
+/// ```
+/// # use madhouse::bench::criterion::Criterion;
+/// # use madhouse::{bench_scenario, Command, CommandWrapper, State, TestContext};
+/// # use proptest::prelude::*;
+/// # use std::sync::Arc;
+/// # #[derive(Debug, Default)]
+/// # struct Counter { value: u64 }
+/// # impl State for Counter {}
+/// # #[derive(Debug, Clone, Default)]
+/// # struct Ctx {}
+/// # impl TestContext for Ctx {}
+/// # struct Add(u64);
+/// # impl Command<Counter, Ctx> for Add {
+/// #     fn check(&self, _state: &Counter) -> bool { true }
+/// #     fn apply(&self, state: &mut Counter) { state.value += self.0; }
+/// #     fn label(&self) -> String { format!("ADD({})", self.0) }
+/// #     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Counter, Ctx>> {
+/// #         Just(CommandWrapper::new(Add(1)))
+/// #     }
+/// # }
+/// fn counter(c: &mut Criterion) {
+///     bench_scenario!(c, "counter", Counter { value: 7 }, Add(1), Add(2));
+/// }
+/// ```
+#[macro_export]
+macro_rules! bench_scenario {
+    ($criterion:expr, $name:expr, $state:expr, $($command:expr),+ $(,)?) => {
+        $crate::bench::bench_sequence(
+            $criterion,
+            $name,
+            || $state,
+            &[$($crate::CommandWrapper::new($command)),+],
+        )
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Command;
+    use proptest::prelude::{Just, Strategy};
+    use std::sync::Arc;
+
+    #[derive(Debug, Default)]
+    struct Log {
+        lines: usize,
+    }
+
+    impl State for Log {}
+
+    #[derive(Debug, Clone, Default)]
+    struct Ctx {}
+
+    impl TestContext for Ctx {}
+
+    /// Writes a line after sleeping, up to `max` lines.
+    struct Write {
+        max: usize,
+        sleep: Duration,
+    }
+
+    impl Command<Log, Ctx> for Write {
+        fn check(&self, state: &Log) -> bool {
+            state.lines < self.max
+        }
+
+        fn apply(&self, state: &mut Log) {
+            std::thread::sleep(self.sleep);
+            state.lines += 1;
+        }
+
+        fn label(&self) -> String {
+            "WRITE".to_string()
+        }
+
+        fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Log, Ctx>> {
+            Just(CommandWrapper::new(Write {
+                max: 1,
+                sleep: Duration::ZERO,
+            }))
+        }
+    }
+
+    #[test]
+    fn test_each_step_is_timed_on_its_own() {
+        let write = |max, millis| {
+            CommandWrapper::new(Write {
+                max,
+                sleep: Duration::from_millis(millis),
+            })
+        };
+        let commands = [write(1, 5), write(1, 100), write(2, 0)];
+
+        assert!(step_duration(&commands, Log::default, 0, 2) >= Duration::from_millis(10));
+        // Skipped: nothing applied.
+        assert_eq!(step_duration(&commands, Log::default, 1, 2), Duration::ZERO);
+        assert_eq!(step_duration(&commands, Log::default, 3, 1), Duration::ZERO);
+    }
+}
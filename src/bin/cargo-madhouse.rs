@@ -0,0 +1,93 @@
+//! `cargo madhouse`: helpers for maintaining madhouse models.
+//!
+//! ```text
+//! cargo madhouse new-command <Name> --state <path> --context <path> [--dir <dir>]
+//! ```
+//!
+//! Writes `<dir>/<name>.rs` from a [`CommandSkeleton`] and declares it in
+//! `<dir>/mod.rs`, creating that file if needed. `<dir>` defaults to
+//! `tests/commands`.
+
+use madhouse::scaffold::{is_identifier, register, CommandSkeleton};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+const USAGE: &str =
+    "usage: cargo madhouse new-command <Name> --state <path> --context <path> [--dir <dir>]";
+
+fn main() -> ExitCode {
+    // Cargo passes the subcommand name as the first argument.
+    let args: Vec<String> = std::env::args()
+        .skip(1)
+        .skip_while(|arg| arg == "madhouse")
+        .collect();
+    match run(&args) {
+        Ok(message) => {
+            println!("{}", message);
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            eprintln!("error: {}\n{}", error, USAGE);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: &[String]) -> Result<String, String> {
+    let Some((subcommand, args)) = args.split_first() else {
+        return Err("missing subcommand".to_string());
+    };
+    if subcommand != "new-command" {
+        return Err(format!("unknown subcommand {:?}", subcommand));
+    }
+    let mut name = None;
+    let mut state = None;
+    let mut context = None;
+    let mut dir = PathBuf::from("tests/commands");
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .cloned()
+                .ok_or_else(|| format!("{} needs a value", arg))
+        };
+        match arg.as_str() {
+            "--state" => state = Some(value()?),
+            "--context" => context = Some(value()?),
+            "--dir" => dir = PathBuf::from(value()?),
+            flag if flag.starts_with("--") => return Err(format!("unknown option {}", flag)),
+            _ if name.is_none() => name = Some(arg.clone()),
+            _ => return Err(format!("unexpected argument {:?}", arg)),
+        }
+    }
+    let name = name.ok_or("missing command name")?;
+    let state = state.ok_or("missing --state")?;
+    let context = context.ok_or("missing --context")?;
+    if !is_identifier(&name) {
+        return Err(format!(
+            "command name must be a Rust identifier, got {:?}",
+            name
+        ));
+    }
+
+    let skeleton = CommandSkeleton::new(name, state, context);
+    let file = dir.join(format!("{}.rs", skeleton.module()));
+    if file.exists() {
+        return Err(format!("{} already exists", file.display()));
+    }
+    std::fs::create_dir_all(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    std::fs::write(&file, skeleton.to_string())
+        .map_err(|e| format!("{}: {}", file.display(), e))?;
+
+    let mod_file = dir.join("mod.rs");
+    let source = std::fs::read_to_string(&mod_file).unwrap_or_default();
+    if let Some(registered) = register(&source, &skeleton.module()) {
+        std::fs::write(&mod_file, registered)
+            .map_err(|e| format!("{}: {}", mod_file.display(), e))?;
+    }
+    Ok(format!(
+        "Created {} and registered it in {}",
+        file.display(),
+        mod_file.display()
+    ))
+}
@@ -0,0 +1,48 @@
+//! Cooperative cancellation of running scenarios.
+//!
+//! An application embedding madhouse (a TUI, a long-running service) may
+//! need to stop a campaign without killing its thread. A
+//! [`CancellationToken`] is a cheap, cloneable handle shared with the
+//! running [`Scenario`](crate::Scenario): once cancelled, the current case
+//! stops before its next command, runs its cleanup and teardown, and is
+//! reported as [`cancelled`](crate::ExecutionReport::cancelled); no further
+//! case is generated, and the run ends with its summaries instead of a
+//! failure.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Shared flag requesting a graceful stop.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::cancel::CancellationToken;
+///
+/// let token = CancellationToken::new();
+/// let handle = token.clone();
+/// std::thread::spawn(move || handle.cancel()).join().unwrap();
+/// assert!(token.is_cancelled());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    /// Creates a token that is not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requests a stop, from any thread.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns true once [`cancel`](Self::cancel) was called on this token
+    /// or a clone of it.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
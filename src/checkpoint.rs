@@ -0,0 +1,237 @@
+//! State snapshots taken before every applied command.
+//!
+//! For states that implement `Clone`, [`execute_commands_checkpointed`]
+//! clones the state before applying each command, so a failure can be
+//! inspected next to the state that led to it, and exploration can roll
+//! back to any step and try a different continuation from there.
+
+use crate::observer::Observer;
+#[cfg(feature = "json")]
+use crate::projection::Projections;
+use crate::{run_commands, CommandWrapper, ExecutionReport, State, TestContext};
+
+/// The state as it was right before a command was applied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checkpoint<S> {
+    /// Index of the command in the selected sequence.
+    pub index: usize,
+    /// Label of the command.
+    pub label: String,
+    /// The state before the command was applied.
+    pub state: S,
+}
+
+/// Observer cloning the state before every applied command.
+///
+/// Skipped commands leave the state unchanged, so they get no checkpoint.
+#[derive(Debug, Clone)]
+pub struct Checkpoints<S> {
+    checkpoints: Vec<Checkpoint<S>>,
+}
+
+impl<S> Default for Checkpoints<S> {
+    fn default() -> Self {
+        Self {
+            checkpoints: Vec::new(),
+        }
+    }
+}
+
+impl<S> Checkpoints<S> {
+    /// Returns the checkpoints, in execution order.
+    pub fn into_inner(self) -> Vec<Checkpoint<S>> {
+        self.checkpoints
+    }
+}
+
+impl<S: State + Clone, C: TestContext> Observer<S, C> for Checkpoints<S> {
+    fn on_before_apply(&mut self, index: usize, command: &CommandWrapper<S, C>, state: &S) {
+        self.checkpoints.push(Checkpoint {
+            index,
+            label: command.command.label(),
+            state: state.clone(),
+        });
+    }
+}
+
+/// Result of [`execute_commands_checkpointed`].
+#[derive(Debug, Clone)]
+pub struct Checkpointed<S> {
+    /// The execution report.
+    pub report: ExecutionReport,
+    /// The state before each applied command, in execution order.
+    pub checkpoints: Vec<Checkpoint<S>>,
+}
+
+impl<S: Clone> Checkpointed<S> {
+    /// Returns the state right before command `index` was applied, or
+    /// `None` if it was not applied.
+    pub fn before(&self, index: usize) -> Option<&S> {
+        self.checkpoints
+            .iter()
+            .find(|checkpoint| checkpoint.index == index)
+            .map(|checkpoint| &checkpoint.state)
+    }
+
+    /// Returns the checkpoint of the command that broke an invariant or,
+    /// failing that, of the first command that reported an error.
+    ///
+    /// For an invariant broken by a heartbeat, this is the state before the
+    /// command preceding the heartbeat.
+    pub fn before_failure(&self) -> Option<&Checkpoint<S>> {
+        let index = match &self.report.broken_invariant {
+            Some(broken) => broken.index,
+            None => self.report.failures().next()?.0.index,
+        };
+        self.checkpoints
+            .iter()
+            .find(|checkpoint| checkpoint.index == index)
+    }
+
+    /// Returns a copy of the state before command `index`, to continue
+    /// from there with other commands.
+    pub fn rollback(&self, index: usize) -> Option<S> {
+        self.before(index).cloned()
+    }
+
+    /// Evaluates `projections` on every checkpoint, returning each
+    /// command's index with the projected state before it.
+    #[cfg(feature = "json")]
+    pub fn project(&self, projections: &Projections<S>) -> Vec<(usize, serde_json::Value)> {
+        self.checkpoints
+            .iter()
+            .map(|checkpoint| (checkpoint.index, projections.evaluate(&checkpoint.state)))
+            .collect()
+    }
+}
+
+/// Executes a sequence of commands, cloning the state before each applied
+/// command.
+///
+/// Behaves like [`execute_commands_with`](crate::execute_commands_with)
+/// with a [`Checkpoints`] observer: nothing is printed, and the report comes
+/// back with the checkpoints. Cloning costs memory proportional to the
+/// number of applied commands, so this is meant for diagnostics and
+/// exploration rather than endurance runs.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::checkpoint::execute_commands_checkpointed;
+/// use madhouse::{Command, CommandError, CommandWrapper, State, TestContext};
+/// use proptest::prelude::*;
+/// use std::sync::Arc;
+///
+/// #[derive(Debug, Clone, Default, PartialEq)]
+/// struct Balance { amount: u64 }
+/// impl State for Balance {}
+///
+/// #[derive(Debug, Clone, Default)]
+/// struct Ctx {}
+/// impl TestContext for Ctx {}
+///
+/// struct Withdraw(u64);
+/// impl Command<Balance, Ctx> for Withdraw {
+///     fn check(&self, _state: &Balance) -> bool { true }
+///     fn apply(&self, _state: &mut Balance) {}
+///     fn try_apply(&self, state: &mut Balance) -> Result<(), CommandError> {
+///         state.amount = state.amount.checked_sub(self.0).ok_or("overdrawn")?;
+///         Ok(())
+///     }
+///     fn label(&self) -> String { format!("WITHDRAW({})", self.0) }
+///     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Balance, Ctx>> {
+///         Just(CommandWrapper::new(Withdraw(1)))
+///     }
+/// }
+///
+/// let commands = vec![
+///     CommandWrapper::new(Withdraw(3)),
+///     CommandWrapper::new(Withdraw(5)),
+/// ];
+/// let mut state = Balance { amount: 7 };
+/// let result = execute_commands_checkpointed(&commands, &mut state);
+///
+/// let failure = result.before_failure().unwrap();
+/// assert_eq!(failure.label, "WITHDRAW(5)");
+/// assert_eq!(failure.state, Balance { amount: 4 });
+/// assert_eq!(result.rollback(0), Some(Balance { amount: 7 }));
+/// ```
+pub fn execute_commands_checkpointed<S: State + Clone, C: TestContext>(
+    commands: &[CommandWrapper<S, C>],
+    state: &mut S,
+) -> Checkpointed<S> {
+    let mut checkpoints = Checkpoints::default();
+    let report = run_commands(commands, state, &[], &mut checkpoints);
+    Checkpointed {
+        report,
+        checkpoints: checkpoints.into_inner(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Command, InvariantViolation};
+    use proptest::prelude::{Just, Strategy};
+    use std::sync::Arc;
+
+    #[derive(Debug, Clone, Default, PartialEq)]
+    struct Level {
+        value: u64,
+    }
+
+    impl State for Level {
+        fn invariants(&self) -> Result<(), InvariantViolation> {
+            if self.value > 5 {
+                return Err("level above 5".into());
+            }
+            Ok(())
+        }
+    }
+
+    #[derive(Debug, Clone, Default)]
+    struct Ctx {}
+
+    impl TestContext for Ctx {}
+
+    struct Raise(u64);
+
+    impl Command<Level, Ctx> for Raise {
+        fn check(&self, _state: &Level) -> bool {
+            self.0 > 0
+        }
+
+        fn apply(&self, state: &mut Level) {
+            state.value += self.0;
+        }
+
+        fn label(&self) -> String {
+            format!("RAISE({})", self.0)
+        }
+
+        fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Level, Ctx>> {
+            Just(CommandWrapper::new(Raise(1)))
+        }
+    }
+
+    #[test]
+    fn test_checkpoints_skip_unapplied_commands_and_locate_broken_invariant() {
+        let commands = vec![
+            CommandWrapper::new(Raise(2)),
+            CommandWrapper::new(Raise(0)),
+            CommandWrapper::new(Raise(3)),
+            CommandWrapper::new(Raise(4)),
+        ];
+        let mut state = Level::default();
+
+        let result = execute_commands_checkpointed(&commands, &mut state);
+
+        let indices: Vec<usize> = result.checkpoints.iter().map(|c| c.index).collect();
+        assert_eq!(indices, vec![0, 2, 3]);
+        assert_eq!(result.before(1), None);
+        assert_eq!(result.before(2), Some(&Level { value: 2 }));
+        let failure = result.before_failure().unwrap();
+        assert_eq!((failure.index, failure.state.value), (3, 5));
+        assert_eq!(state.value, 9);
+    }
+}
@@ -0,0 +1,591 @@
+//! Replay and inspection of saved traces from the command line, with the
+//! `cli` feature.
+//!
+//! Debugging a serialized failure otherwise means writing throwaway test
+//! code. [`cli_main!`](crate::cli_main) generates the `main` of a binary,
+//! e.g. `src/bin/madhouse.rs`, that knows a model's commands:
+//!
+//! ```text
+//! madhouse replay <trace>               executes a trace and prints its report
+//! madhouse shrink <trace> [--out <file>] minimizes a failing trace
+//! madhouse stats <dir>                  summarizes the traces in a directory
+//! ```
+//!
+//! Traces ending in `.json` are arrays of labels, as serialized through a
+//! [`CommandRegistry`]; any other file holds one label per line, like
+//! regression files (see [`regression`](crate::regression)). Commands run
+//! from the state's `Default`.
+//!
+//! # Examples
+//!
+//! ```
+//! use madhouse::cli::Cli;
+//! use madhouse::registry::CommandRegistry;
+//! use madhouse::{Command, CommandWrapper, State, TestContext};
+//! use proptest::prelude::*;
+//! use std::sync::Arc;
+//!
+//! #[derive(Debug, Default)]
+//! struct Counter { value: u64 }
+//! impl State for Counter {}
+//!
+//! #[derive(Debug, Clone, Default)]
+//! struct Ctx {}
+//! impl TestContext for Ctx {}
+//!
+//! struct Reset;
+//! impl Command<Counter, Ctx> for Reset {
+//!     fn check(&self, _state: &Counter) -> bool { true }
+//!     fn apply(&self, state: &mut Counter) { state.value = 0; }
+//!     fn label(&self) -> String { "RESET".to_string() }
+//!     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Counter, Ctx>> {
+//!         Just(CommandWrapper::new(Reset))
+//!     }
+//! }
+//!
+//! # let dir = std::env::temp_dir().join(format!("madhouse-cli-doc-{}", std::process::id()));
+//! # std::fs::create_dir_all(&dir).unwrap();
+//! # std::fs::write(dir.join("reset.json"), r#"["RESET", "RESET"]"#).unwrap();
+//! let cli = Cli::new(CommandRegistry::new(Arc::new(Ctx::default())).fixed(CommandWrapper::new(Reset)));
+//! let stats = cli.run(&["stats".to_string(), dir.display().to_string()]).unwrap();
+//! assert!(stats.starts_with("1 trace(s), 2 command(s)"));
+//! # std::fs::remove_dir_all(&dir).unwrap();
+//! ```
+
+use crate::config::Format;
+use crate::panics;
+use crate::registry::CommandRegistry;
+use crate::regression;
+use crate::report::command_name;
+use crate::sequence::Sequence;
+use crate::shrink::Structural;
+use crate::{run_commands, CommandWrapper, ExecutionReport, State, TestContext};
+use proptest::strategy::{Just, Strategy, ValueTree};
+use proptest::test_runner::TestRunner;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+const USAGE: &str = "usage: madhouse replay <trace>\n       \
+                     madhouse shrink <trace> [--out <file>]\n       \
+                     madhouse stats <dir>";
+
+/// The `replay`, `shrink` and `stats` subcommands, for the commands of a
+/// registry.
+pub struct Cli<S: State, C: TestContext> {
+    registry: CommandRegistry<S, C>,
+}
+
+impl<S: State + Default + 'static, C: TestContext + 'static> Cli<S, C> {
+    /// Creates the command line of a model whose commands the registry
+    /// rebuilds.
+    pub fn new(registry: CommandRegistry<S, C>) -> Self {
+        Self { registry }
+    }
+
+    /// Runs the subcommand given as the process arguments, printing its
+    /// result, or the error and usage to stderr.
+    pub fn main(&self) -> ExitCode {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        match self.run(&args) {
+            Ok(message) => {
+                println!("{}", message);
+                ExitCode::SUCCESS
+            }
+            Err(error) => {
+                eprintln!("error: {}\n{}", error, USAGE);
+                ExitCode::FAILURE
+            }
+        }
+    }
+
+    /// Runs a subcommand and returns what it prints.
+    ///
+    /// # Errors
+    ///
+    /// Fails on unknown arguments, unreadable traces, labels no command
+    /// parses, a replayed trace that fails, or a shrunk trace that passes.
+    pub fn run(&self, args: &[String]) -> Result<String, String> {
+        let Some((subcommand, args)) = args.split_first() else {
+            return Err("missing subcommand".to_string());
+        };
+        let mut path = None;
+        let mut out = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--out" if subcommand == "shrink" => {
+                    out = Some(PathBuf::from(
+                        args.next()
+                            .ok_or_else(|| format!("{} needs a value", arg))?,
+                    ))
+                }
+                flag if flag.starts_with("--") => return Err(format!("unknown option {}", flag)),
+                _ if path.is_none() => path = Some(PathBuf::from(arg)),
+                _ => return Err(format!("unexpected argument {:?}", arg)),
+            }
+        }
+        let path = path.ok_or("missing path")?;
+        match subcommand.as_str() {
+            "replay" => self.replay(&path),
+            "shrink" => self.shrink(&path, out.as_deref()),
+            "stats" => self.stats(&path),
+            _ => Err(format!("unknown subcommand {:?}", subcommand)),
+        }
+    }
+
+    /// Reads a trace and rebuilds its commands.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file cannot be read or parsed, or if no registered
+    /// command parses one of its labels.
+    pub fn load(&self, path: &Path) -> Result<Vec<CommandWrapper<S, C>>, String> {
+        load_labels(path)?
+            .iter()
+            .map(|label| {
+                self.registry.parse(label).ok_or_else(|| {
+                    format!("{}: no command parses label `{}`", path.display(), label)
+                })
+            })
+            .collect()
+    }
+
+    /// Executes a trace, printing its report.
+    fn replay(&self, path: &Path) -> Result<String, String> {
+        let commands = self.load(path)?;
+        let mut state = S::default();
+        let report = panics::catch(|| run_commands(&commands, &mut state, &[], &mut ()))
+            .map_err(|panic| format!("{}: {}", path.display(), panic))?;
+        report.print(Format::Text, &state);
+        match report.failure_message() {
+            Some(message) => Err(format!("{}: {}", path.display(), message)),
+            None => Ok(format!("{}: passed", path.display())),
+        }
+    }
+
+    /// Minimizes a failing trace, printing it, or writing it to `out`.
+    fn shrink(&self, path: &Path, out: Option<&Path>) -> Result<String, String> {
+        let commands = self.load(path)?;
+        let original = commands.len();
+        let Some(failure) = self.failure(&commands) else {
+            return Err(format!("{}: the trace passes", path.display()));
+        };
+        let commands = self.minimize(commands, &failure.cause);
+        let message = self
+            .failure(&commands)
+            .map(|f| f.message)
+            .unwrap_or_default();
+        let labels: Vec<String> = commands.iter().map(|c| c.command.label()).collect();
+        let summary = format!(
+            "Shrunk {} from {} to {} command(s), failing with: {}",
+            path.display(),
+            original,
+            labels.len(),
+            message
+        );
+        match out {
+            Some(out) => {
+                save_labels(out, &labels)?;
+                Ok(format!("{}\nWrote {}", summary, out.display()))
+            }
+            None => Ok(format!("{}\n{}", summary, labels.join("\n"))),
+        }
+    }
+
+    /// Summarizes the traces in a directory: their lengths, how often each
+    /// command appears, and the labels no command parses.
+    fn stats(&self, dir: &Path) -> Result<String, String> {
+        let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+            .map_err(|e| format!("{}: {}", dir.display(), e))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file())
+            .collect();
+        paths.sort();
+
+        let mut lengths = Vec::new();
+        let mut counts = BTreeMap::<String, usize>::new();
+        let mut problems = Vec::new();
+        for path in &paths {
+            let labels = match load_labels(path) {
+                Ok(labels) => labels,
+                Err(error) => {
+                    problems.push(error);
+                    continue;
+                }
+            };
+            lengths.push(labels.len());
+            for label in &labels {
+                *counts.entry(command_name(label).to_string()).or_default() += 1;
+                if self.registry.parse(label).is_none() {
+                    problems.push(format!(
+                        "{}: no command parses label `{}`",
+                        path.display(),
+                        label
+                    ));
+                }
+            }
+        }
+
+        let total: usize = lengths.iter().sum();
+        let mut out = format!("{} trace(s), {} command(s)", lengths.len(), total);
+        if let (Some(min), Some(max)) = (lengths.iter().min(), lengths.iter().max()) {
+            out.push_str(&format!(
+                ", length {}..={} (mean {:.1})",
+                min,
+                max,
+                total as f64 / lengths.len() as f64
+            ));
+        }
+        let width = counts.keys().map(String::len).max().unwrap_or(0).max(7);
+        out.push_str(&format!("\n  {:<width$}  COUNT", "COMMAND", width = width));
+        for (name, count) in &counts {
+            out.push_str(&format!("\n  {:<width$}  {}", name, count, width = width));
+        }
+        if !problems.is_empty() {
+            out.push_str(&format!("\n{} problem(s):", problems.len()));
+            for problem in &problems {
+                out.push_str(&format!("\n  {}", problem));
+            }
+        }
+        Ok(out)
+    }
+
+    /// Describes how a trace fails from the default state, if it does.
+    fn failure(&self, commands: &[CommandWrapper<S, C>]) -> Option<Failure> {
+        let mut state = S::default();
+        match panics::catch(|| run_commands(commands, &mut state, &[], &mut ())) {
+            Ok(report) => Failure::of(&report),
+            Err(panic) => Some(Failure {
+                message: panic.to_string(),
+                cause: Cause {
+                    kind: "panic",
+                    command: panic.step.as_ref().map(|(_, label)| name(label)),
+                    location: panic.location.clone(),
+                },
+            }),
+        }
+    }
+
+    /// Drops commands, then replaces commands with their
+    /// [`Command::shrink`](crate::Command::shrink) variants, the way
+    /// scenarios shrink (see [`Sequence`] and [`Structural`]), keeping
+    /// only candidates that still fail with `cause`.
+    fn minimize(
+        &self,
+        commands: Vec<CommandWrapper<S, C>>,
+        cause: &Cause,
+    ) -> Vec<CommandWrapper<S, C>> {
+        let strategies = commands
+            .into_iter()
+            .map(|command| Structural::new(Just(command).boxed()).boxed())
+            .collect();
+        let mut tree = Sequence::fixed(strategies, 0)
+            .new_tree(&mut TestRunner::deterministic())
+            .expect("fixed commands always generate");
+        let mut shrunk = tree.current();
+        let mut progress = tree.simplify();
+        while progress {
+            let candidate = tree.current();
+            if self.failure(&candidate).is_some_and(|f| f.cause == *cause) {
+                shrunk = candidate;
+                progress = tree.simplify();
+            } else {
+                progress = tree.complicate();
+            }
+        }
+        shrunk
+    }
+}
+
+/// How a trace fails.
+struct Failure {
+    message: String,
+    cause: Cause,
+}
+
+/// What a failure is and where it occurs, which shrinking preserves so
+/// that the trace does not drift to a different bug. Unlike the message,
+/// it leaves out the step's index and parameters, which shrinking changes.
+#[derive(Debug, PartialEq, Eq)]
+struct Cause {
+    kind: &'static str,
+    /// Name of the failing command, if a command failed.
+    command: Option<String>,
+    /// Source location of a panic.
+    location: Option<String>,
+}
+
+impl Failure {
+    /// Describes the first problem in a report, like
+    /// [`ExecutionReport::failure_message`].
+    fn of(report: &ExecutionReport) -> Option<Self> {
+        let (kind, command) = if let Some(broken) = &report.broken_invariant {
+            ("invariant", Some(name(&broken.label)))
+        } else if let Some((step, _)) = report.failures().next() {
+            ("error", Some(name(&step.label)))
+        } else {
+            ("unconfirmed effect", None)
+        };
+        Some(Self {
+            message: report.failure_message()?,
+            cause: Cause {
+                kind,
+                command,
+                location: None,
+            },
+        })
+    }
+}
+
+/// Returns the name of the command a label belongs to.
+fn name(label: &str) -> String {
+    command_name(label).to_string()
+}
+
+/// Reads the labels of a trace: a JSON array if the file ends in `.json`,
+/// else one label per line.
+fn load_labels(path: &Path) -> Result<Vec<String>, String> {
+    let error = |e: &dyn std::fmt::Display| format!("{}: {}", path.display(), e);
+    if is_json(path) {
+        let contents = fs::read_to_string(path).map_err(|e| error(&e))?;
+        serde_json::from_str(&contents).map_err(|e| error(&e))
+    } else {
+        regression::load(path).map_err(|e| error(&e))
+    }
+}
+
+/// Writes the labels of a trace in the format its extension calls for.
+fn save_labels(path: &Path, labels: &[String]) -> Result<(), String> {
+    let contents = if is_json(path) {
+        serde_json::to_string_pretty(labels).map_err(|e| e.to_string())?
+    } else {
+        labels.iter().map(|label| format!("{}\n", label)).collect()
+    };
+    fs::write(path, contents).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Returns true if the file ends in `.json`.
+fn is_json(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "json")
+}
+
+/// Generates the `main` of a trace replay binary (see [`cli`](crate::cli)).
+///
+/// Takes the state type, the test context (an `Arc`), and the commands
+/// like [`scenario!`](crate::scenario): command types, rebuilt with
+/// [`Command::parse`](crate::Command::parse), and fixed commands in
+/// parentheses, recognized by their exact label.
+///
+/// # Examples
+///
+/// In `src/bin/madhouse.rs`:
+///
+/// ```
+/// use madhouse::{cli_main, Command, CommandWrapper, State, TestContext};
+/// use proptest::prelude::*;
+/// use std::sync::Arc;
+///
+/// #[derive(Debug, Default)]
+/// struct Counter { value: u64 }
+/// impl State for Counter {}
+///
+/// #[derive(Debug, Clone, Default)]
+/// struct Ctx {}
+/// impl TestContext for Ctx {}
+///
+/// struct Inc(u64);
+/// impl Command<Counter, Ctx> for Inc {
+///     fn check(&self, _state: &Counter) -> bool { true }
+///     fn apply(&self, state: &mut Counter) { state.value += self.0; }
+///     fn label(&self) -> String { format!("INC({})", self.0) }
+///     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Counter, Ctx>> {
+///         (1..10u64).prop_map(|n| CommandWrapper::new(Inc(n)))
+///     }
+///     fn parse(label: &str, _ctx: Arc<Ctx>) -> Option<CommandWrapper<Counter, Ctx>> {
+///         let n = label.strip_prefix("INC(")?.strip_suffix(')')?.parse().ok()?;
+///         Some(CommandWrapper::new(Inc(n)))
+///     }
+/// }
+///
+/// cli_main!(Counter, Arc::new(Ctx::default()), Inc, (Inc(100)));
+/// ```
+#[macro_export]
+macro_rules! cli_main {
+    (@cmds [$($calls:tt)*] $cmd:ident $(, $($rest:tt)*)?) => {
+        $crate::cli_main!(@cmds [$($calls)*.command::<$cmd>()] $($($rest)*)?)
+    };
+
+    (@cmds [$($calls:tt)*] $cmd:expr $(, $($rest:tt)*)?) => {
+        $crate::cli_main!(
+            @cmds [$($calls)*.fixed($crate::CommandWrapper::new($cmd))]
+            $($($rest)*)?
+        )
+    };
+
+    (@cmds [$($calls:tt)*]) => {
+        $($calls)*
+    };
+
+    ($state:ty, $test_context:expr, $($cmds:tt)+) => {
+        fn main() -> ::std::process::ExitCode {
+            let registry = $crate::cli_main!(
+                @cmds [$crate::registry::CommandRegistry::<$state, _>::new($test_context)]
+                $($cmds)+
+            );
+            $crate::cli::Cli::new(registry).main()
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Command, CommandError};
+    use proptest::strategy::{Just, Strategy};
+    use std::sync::Arc;
+
+    #[derive(Debug, Default)]
+    struct Balance(u64);
+    impl State for Balance {}
+
+    #[derive(Debug, Clone, Default)]
+    struct Ctx;
+    impl TestContext for Ctx {}
+
+    struct Deposit(u64);
+    impl Command<Balance, Ctx> for Deposit {
+        fn check(&self, _state: &Balance) -> bool {
+            true
+        }
+        fn apply(&self, state: &mut Balance) {
+            state.0 += self.0;
+        }
+        fn label(&self) -> String {
+            format!("DEPOSIT({})", self.0)
+        }
+        fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Balance, Ctx>> {
+            Just(CommandWrapper::new(Deposit(1)))
+        }
+        fn parse(label: &str, _ctx: Arc<Ctx>) -> Option<CommandWrapper<Balance, Ctx>> {
+            let amount = label.strip_prefix("DEPOSIT(")?.strip_suffix(')')?;
+            Some(CommandWrapper::new(Deposit(amount.parse().ok()?)))
+        }
+        fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+            Box::new((self.0 > 0).then_some(Deposit(self.0 / 2)).into_iter())
+        }
+    }
+
+    /// Fails once the balance exceeds 10.
+    struct Audit;
+    impl Command<Balance, Ctx> for Audit {
+        fn check(&self, _state: &Balance) -> bool {
+            true
+        }
+        fn apply(&self, _state: &mut Balance) {}
+        fn try_apply(&self, state: &mut Balance) -> Result<(), CommandError> {
+            match state.0 {
+                0..=10 => Ok(()),
+                balance => Err(CommandError::new(format!("balance {}", balance))),
+            }
+        }
+        fn label(&self) -> String {
+            "AUDIT".to_string()
+        }
+        fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Balance, Ctx>> {
+            Just(CommandWrapper::new(Audit))
+        }
+    }
+
+    /// Fails once the balance exceeds 40.
+    struct Limit;
+    impl Command<Balance, Ctx> for Limit {
+        fn check(&self, _state: &Balance) -> bool {
+            true
+        }
+        fn apply(&self, _state: &mut Balance) {}
+        fn try_apply(&self, state: &mut Balance) -> Result<(), CommandError> {
+            match state.0 {
+                0..=40 => Ok(()),
+                balance => Err(CommandError::new(format!("over limit: {}", balance))),
+            }
+        }
+        fn label(&self) -> String {
+            "LIMIT".to_string()
+        }
+        fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Balance, Ctx>> {
+            Just(CommandWrapper::new(Limit))
+        }
+    }
+
+    #[test]
+    fn test_shrink_keeps_the_original_failure() {
+        let cli = Cli::new(
+            CommandRegistry::new(Arc::new(Ctx))
+                .command::<Deposit>()
+                .fixed(CommandWrapper::new(Audit))
+                .fixed(CommandWrapper::new(Limit)),
+        );
+        let commands = vec![
+            CommandWrapper::new(Deposit(45)),
+            CommandWrapper::new(Limit),
+            CommandWrapper::new(Audit),
+        ];
+        let failure = cli.failure(&commands).unwrap();
+
+        // DEPOSIT(22) would pass LIMIT and fail AUDIT instead.
+        let shrunk = cli.minimize(commands, &failure.cause);
+        let labels: Vec<String> = shrunk.iter().map(|c| c.command.label()).collect();
+        assert_eq!(labels, ["DEPOSIT(45)", "LIMIT"]);
+        assert_eq!(
+            cli.failure(&shrunk).unwrap().message,
+            "command 2 (LIMIT) failed: over limit: 45"
+        );
+    }
+
+    #[test]
+    fn test_shrink_drops_and_shrinks_commands_while_the_trace_fails() {
+        let dir = std::env::temp_dir().join(format!("madhouse-cli-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let trace = dir.join("trace.json");
+        fs::write(
+            &trace,
+            r#"["DEPOSIT(5)", "AUDIT", "DEPOSIT(40)", "DEPOSIT(3)", "AUDIT"]"#,
+        )
+        .unwrap();
+        let cli = Cli::new(
+            CommandRegistry::new(Arc::new(Ctx))
+                .command::<Deposit>()
+                .fixed(CommandWrapper::new(Audit)),
+        );
+        let args = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+
+        let replayed = cli.run(&args(&["replay", trace.to_str().unwrap()]));
+        assert!(replayed.unwrap_err().contains("command 5 (AUDIT) failed"));
+
+        let out = dir.join("min.txt");
+        let shrunk = cli
+            .run(&args(&[
+                "shrink",
+                trace.to_str().unwrap(),
+                "--out",
+                out.to_str().unwrap(),
+            ]))
+            .unwrap();
+        assert!(shrunk.contains("from 5 to 2 command(s)"));
+        assert_eq!(regression::load(&out).unwrap(), ["DEPOSIT(20)", "AUDIT"]);
+        assert_eq!(
+            cli.run(&args(&["replay", out.to_str().unwrap()]))
+                .unwrap_err(),
+            format!("{}: command 2 (AUDIT) failed: balance 20", out.display())
+        );
+
+        fs::write(dir.join("unknown.txt"), "WITHDRAW(1)\n").unwrap();
+        let stats = cli.run(&args(&["stats", dir.to_str().unwrap()])).unwrap();
+        assert!(stats.starts_with("3 trace(s), 8 command(s), length 1..=5"));
+        assert!(stats.contains("\n  DEPOSIT   4\n  WITHDRAW  1\n"));
+        assert!(stats.ends_with("no command parses label `WITHDRAW(1)`"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,186 @@
+//! A virtual clock owned by the executor.
+//!
+//! Systems with timeouts are tested deterministically by reading time from
+//! a clock the test controls rather than sleeping in `apply`. Every
+//! execution of a sequence starts a [`VirtualClock`] at zero on the
+//! executing thread; commands read it with [`VirtualClock::now`], and
+//! [`AdvanceTime`], or any command calling [`VirtualClock::advance`], moves
+//! it forward. Hand `VirtualClock::now` to the system under test as its
+//! time source. Outside an execution, the clock stays at zero.
+//!
+//! For timers kept in the model itself, see [`timer`](crate::timer).
+//!
+//! # Examples
+//!
+//! ```
+//! use madhouse::clock::{AdvanceTime, VirtualClock};
+//! use madhouse::timer::ClockContext;
+//! use madhouse::{execute_commands, Command, CommandWrapper, State, TestContext};
+//! use proptest::prelude::*;
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//!
+//! #[derive(Debug, Default)]
+//! struct Session { opened: Option<Duration> }
+//! impl State for Session {}
+//!
+//! #[derive(Debug, Clone, Default)]
+//! struct Ctx {}
+//! impl TestContext for Ctx {}
+//! impl ClockContext for Ctx {
+//!     fn max_advance(&self) -> Duration { Duration::from_secs(60) }
+//! }
+//!
+//! struct Open;
+//! impl Command<Session, Ctx> for Open {
+//!     fn check(&self, state: &Session) -> bool { state.opened.is_none() }
+//!     fn apply(&self, state: &mut Session) { state.opened = Some(VirtualClock::now()); }
+//!     fn label(&self) -> String { "OPEN".to_string() }
+//!     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Session, Ctx>> {
+//!         Just(CommandWrapper::new(Open))
+//!     }
+//! }
+//!
+//! let ctx = Arc::new(Ctx::default());
+//! let commands = vec![
+//!     CommandWrapper::new(AdvanceTime::new(ctx.clone(), Duration::from_secs(5))),
+//!     CommandWrapper::new(Open),
+//! ];
+//! let mut session = Session::default();
+//! execute_commands(&commands, &mut session);
+//!
+//! assert_eq!(session.opened, Some(Duration::from_secs(5)));
+//! assert_eq!(VirtualClock::now(), Duration::ZERO);
+//! ```
+
+use crate::timer::ClockContext;
+use crate::{Command, CommandError, CommandWrapper, State};
+use proptest::prelude::Strategy;
+use std::cell::Cell;
+use std::sync::Arc;
+use std::time::Duration;
+
+thread_local! {
+    /// Time of the execution running on this thread, if one is.
+    static NOW: Cell<Option<Duration>> = const { Cell::new(None) };
+}
+
+/// The virtual clock of the sequence executing on this thread.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VirtualClock;
+
+impl VirtualClock {
+    /// Returns the virtual time elapsed since the execution started.
+    pub fn now() -> Duration {
+        NOW.with(|now| now.get()).unwrap_or_default()
+    }
+
+    /// Moves the clock ahead by `by`. Does nothing outside an execution.
+    pub fn advance(by: Duration) {
+        NOW.with(|now| now.set(now.get().map(|now| now.saturating_add(by))));
+    }
+}
+
+/// Starts the clock of one execution at zero, restoring that of an
+/// enclosing execution when dropped.
+pub(crate) struct Scope {
+    outer: Option<Duration>,
+}
+
+impl Scope {
+    pub(crate) fn enter() -> Self {
+        let outer = NOW.with(|now| now.replace(Some(Duration::ZERO)));
+        Self { outer }
+    }
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        NOW.with(|now| now.set(self.outer));
+    }
+}
+
+/// Command that advances the [`VirtualClock`].
+///
+/// The context's [`ClockContext::max_advance`] bounds each step, and
+/// [`ClockContext::advance_sut`] is called first, for systems keeping
+/// their own mock clock.
+pub struct AdvanceTime<C> {
+    ctx: Arc<C>,
+    /// How far to advance.
+    pub by: Duration,
+}
+
+impl<C> AdvanceTime<C> {
+    /// Creates a command advancing the clock by `by`.
+    pub fn new(ctx: Arc<C>, by: Duration) -> Self {
+        Self { ctx, by }
+    }
+}
+
+impl<S, C> Command<S, C> for AdvanceTime<C>
+where
+    S: State,
+    C: ClockContext + 'static,
+{
+    fn check(&self, _state: &S) -> bool {
+        true
+    }
+
+    fn apply(&self, state: &mut S) {
+        if let Err(err) = self.try_apply(state) {
+            panic!("{}: {}", Command::<S, C>::label(self), err);
+        }
+    }
+
+    fn try_apply(&self, _state: &mut S) -> Result<(), CommandError> {
+        self.ctx.advance_sut(self.by)?;
+        VirtualClock::advance(self.by);
+        Ok(())
+    }
+
+    fn label(&self) -> String {
+        format!("ADVANCE_TIME({}ms)", self.by.as_millis())
+    }
+
+    /// Advances by 1ms up to the context's `max_advance`, shrinking toward
+    /// short steps.
+    fn build(ctx: Arc<C>) -> impl Strategy<Value = CommandWrapper<S, C>> {
+        let max = ctx.max_advance().as_millis().max(1) as u64;
+        (1..=max).prop_map(move |ms| {
+            CommandWrapper::new(AdvanceTime::new(ctx.clone(), Duration::from_millis(ms)))
+        })
+    }
+
+    fn parse(label: &str, ctx: Arc<C>) -> Option<CommandWrapper<S, C>> {
+        let inner = label.strip_prefix("ADVANCE_TIME(")?.strip_suffix("ms)")?;
+        let ms = inner.parse().ok()?;
+        Some(CommandWrapper::new(AdvanceTime::new(
+            ctx,
+            Duration::from_millis(ms),
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_each_scope_starts_its_own_clock() {
+        let secs = Duration::from_secs;
+        VirtualClock::advance(secs(1));
+        assert_eq!(VirtualClock::now(), Duration::ZERO);
+
+        let outer = Scope::enter();
+        VirtualClock::advance(secs(2));
+        {
+            let _inner = Scope::enter();
+            VirtualClock::advance(secs(3));
+            assert_eq!(VirtualClock::now(), secs(3));
+        }
+        assert_eq!(VirtualClock::now(), secs(2));
+        drop(outer);
+        assert_eq!(VirtualClock::now(), Duration::ZERO);
+    }
+}
@@ -0,0 +1,639 @@
+//! Run configuration for scenarios.
+//!
+//! [`Config`] gathers every knob of a [`Scenario`](crate::Scenario) run in
+//! one place. It can be built in code, passed to
+//! [`scenario!`](crate::scenario) as `config = ...`, and is always
+//! overlaid with the environment (see [`Config::with_env`]) so CI can tune
+//! runs without code changes.
+
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// How command sequences are generated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Mode {
+    /// One command per strategy, in the order they were added.
+    #[default]
+    Normal,
+    /// Commands drawn pseudorandomly from all strategies, by weight.
+    Random,
+    /// Like [`Random`](Self::Random), but each case first disables a random
+    /// subset of the strategies, keeping at least one, and draws from the
+    /// others only (swarm testing). Bugs that need some command to stay
+    /// absent for a long stretch are hidden by uniform mixing. Markov
+    /// transitions are ignored.
+    Swarm,
+    /// Every sequence of up to `max_len` commands from all strategies,
+    /// shortest first, instead of sampled cases. Sequences whose last
+    /// command was skipped by `check` (or that failed) are not extended.
+    /// Each command is drawn anew from its strategy.
+    Exhaustive {
+        /// Length of the longest sequences run.
+        max_len: usize,
+    },
+    /// The first `prefix` strategies contribute one command each, in order,
+    /// as in [`Normal`](Self::Normal); the commands after them are drawn
+    /// from the remaining strategies as in [`Random`](Self::Random). The
+    /// setup and the random part shrink as separate phases.
+    Mixed {
+        /// Number of strategies run in order before the random part.
+        prefix: usize,
+    },
+}
+
+/// Longest sequences of [`Mode::Exhaustive`] set by `MADHOUSE=exhaustive`.
+pub const DEFAULT_EXHAUSTIVE_LEN: usize = 3;
+
+/// How much a run prints.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Only failures, through the panic message.
+    Quiet,
+    /// Seed, per-case traces, shrink diffs and saved regression files.
+    #[default]
+    Normal,
+    /// Also the final model state of every case.
+    Verbose,
+}
+
+impl Verbosity {
+    /// Reads `MADHOUSE_VERBOSITY` (`quiet`, `normal` or `verbose`),
+    /// defaulting to normal.
+    pub fn from_env() -> Self {
+        env_with("MADHOUSE_VERBOSITY", parse_verbosity).unwrap_or_default()
+    }
+}
+
+/// How each run's report is printed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    /// Human-readable, ANSI-colored summary.
+    #[default]
+    Text,
+    /// One JSON document per run, on a single line (see
+    /// [`ExecutionReport::to_json`](crate::ExecutionReport::to_json)).
+    Json,
+}
+
+impl Format {
+    /// Reads `MADHOUSE_FORMAT` (`text` or `json`), defaulting to text.
+    pub fn from_env() -> Self {
+        env_with("MADHOUSE_FORMAT", parse_format).unwrap_or_default()
+    }
+}
+
+/// One of several disjoint slices of a run's cases, for splitting a long
+/// campaign across CI machines.
+///
+/// Shard `k/n` executes the cases whose index is `k - 1` modulo `n`. Every
+/// case is still generated from the seed, so the shards of a campaign
+/// sharing a seed together execute each case exactly once. In exhaustive
+/// mode, every sequence is extended, whether its last command was skipped
+/// or not, so that all shards enumerate the same sequences.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::config::Shard;
+///
+/// let shard = Shard::new(2, 3);
+/// let cases: Vec<u32> = (0..9).filter(|&case| shard.contains(case)).collect();
+/// assert_eq!(cases, [1, 4, 7]);
+/// assert_eq!(shard.to_string(), "2/3");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shard {
+    index: u32,
+    count: u32,
+}
+
+impl Shard {
+    /// Creates shard `index` (1-based) of `count`.
+    ///
+    /// # Panics
+    ///
+    /// Panics unless `1 <= index <= count`.
+    pub fn new(index: u32, count: u32) -> Self {
+        assert!(
+            (1..=count).contains(&index),
+            "shard must be between 1/{} and {}/{}, got {}/{}",
+            count,
+            count,
+            count,
+            index,
+            count
+        );
+        Self { index, count }
+    }
+
+    /// Returns the 1-based index of the shard.
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// Returns the number of shards.
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Returns true if the shard executes case `case` (0-based).
+    pub fn contains(&self, case: u32) -> bool {
+        case % self.count == self.index - 1
+    }
+}
+
+/// Prints `k/n`, as `MADHOUSE_SHARD` takes it.
+impl Display for Shard {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}/{}", self.index, self.count)
+    }
+}
+
+/// Configuration of a scenario run.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::config::{Config, Mode};
+///
+/// let config = Config {
+///     mode: Mode::Random,
+///     cases: 50,
+///     seq_len: 5..40,
+///     ..Config::default()
+/// };
+/// assert_eq!(config.max_shrink_iters, 0);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    /// Generation mode. Env: `MADHOUSE=1` for random, `MADHOUSE=0` for
+    /// normal, `MADHOUSE=swarm` for swarm, `MADHOUSE=exhaustive` (or e.g.
+    /// `exhaustive:4`) for exhaustive, `MADHOUSE=mixed:3` for mixed with a
+    /// prefix of 3.
+    pub mode: Mode,
+    /// Number of cases to run. Env: `PROPTEST_CASES`.
+    pub cases: u32,
+    /// Keep running cases until this much time has passed, instead of
+    /// [`cases`](Self::cases) of them. The case running when it runs out
+    /// still completes. Env: `MADHOUSE_TIME_BUDGET`, in seconds or with a
+    /// unit, e.g. `90s`, `15m` or `1h`.
+    pub time_budget: Option<Duration>,
+    /// Range of sequence lengths in random and swarm modes. Env: `MADHOUSE_SEQ_LEN`,
+    /// e.g. `5..40`, `100..=200`, or `150` for exactly 150 commands.
+    pub seq_len: Range<usize>,
+    /// Shrink iterations per failing case. Env: `PROPTEST_MAX_SHRINK_ITERS`.
+    pub max_shrink_iters: u32,
+    /// Seed cases are generated from; random if `None`. Env: `MADHOUSE_SEED`.
+    pub seed: Option<u64>,
+    /// Output level. Env: `MADHOUSE_VERBOSITY`, one of `quiet`, `normal`,
+    /// `verbose`.
+    pub verbosity: Verbosity,
+    /// Report format. Env: `MADHOUSE_FORMAT`, `text` or `json`.
+    pub format: Format,
+    /// Distinct failures to collect before stopping; 0 for no limit. Env:
+    /// `MADHOUSE_MAX_FAILURES`.
+    pub max_failures: usize,
+    /// Indices of the only cases to execute. Env: `MADHOUSE_ONLY_CASES`,
+    /// e.g. `3,17`.
+    pub only_cases: Option<Vec<u32>>,
+    /// Slice of the cases to execute (see [`Shard`]); all if `None`. Env:
+    /// `MADHOUSE_SHARD`, e.g. `2/4`.
+    pub shard: Option<Shard>,
+    /// Directory a JUnit XML report is written to after each scenario run
+    /// (see [`junit`](crate::junit)). Env: `MADHOUSE_JUNIT_DIR`.
+    pub junit_dir: Option<PathBuf>,
+    /// Directory an HTML report of every scenario run by the test binary
+    /// is written to (see [`html`](crate::html)). Env: `MADHOUSE_HTML_DIR`.
+    pub html_dir: Option<PathBuf>,
+    /// URL a [`Webhook`](crate::notify::Webhook) notifier posts the first
+    /// failure and the end of each run to. Env: `MADHOUSE_WEBHOOK`.
+    pub webhook: Option<String>,
+    /// Re-record golden traces instead of checking them (see
+    /// [`golden`](crate::golden)). Env: `MADHOUSE_BLESS=1`.
+    pub bless: bool,
+    /// Steps (and heartbeats) each report keeps, dropping the earliest; all
+    /// if `None`. Reports count the dropped ones in
+    /// [`ExecutionReport::dropped_steps`](crate::ExecutionReport::dropped_steps);
+    /// coverage and report assertions only see the kept steps. Env: `MADHOUSE_TRACE_LIMIT`.
+    pub trace_limit: Option<usize>,
+    /// File every step of the latest case is streamed to, one JSON object
+    /// per line. Env: `MADHOUSE_TRACE_FILE`.
+    pub trace_file: Option<PathBuf>,
+    /// File listing quarantined traces and cases, which are skipped or
+    /// not reported (see [`quarantine`](crate::quarantine)). Env:
+    /// `MADHOUSE_QUARANTINE`.
+    pub quarantine: Option<PathBuf>,
+    /// Record how the failing step changed the state, as a diff of its
+    /// `Debug` output before and after (see
+    /// [`ExecutionReport::state_change`](crate::ExecutionReport::state_change)).
+    /// Costs one `Debug` rendering per step. Env: `MADHOUSE_STATE_DIFF=0`
+    /// to disable.
+    pub state_diff: bool,
+    /// Attach the labels of the commands executed before a panicking step
+    /// to its panic, so the message shows the trace that led to it. Costs
+    /// one `catch_unwind` per step. Env: `MADHOUSE_PANIC_TRACE=0` to
+    /// disable.
+    pub panic_trace: bool,
+    /// Record the commands applied without error whose `apply()` left the
+    /// state's [`fingerprint`](crate::State::fingerprint) unchanged (see
+    /// [`ExecutionReport::no_ops`](crate::ExecutionReport::no_ops)), which
+    /// usually points at a broken command or a vacuous test. Costs two
+    /// fingerprints per step. Env: `MADHOUSE_NO_OPS=1`.
+    pub no_ops: bool,
+    /// Count the distinct states the generated cases reach, and how many
+    /// new ones each window of applied commands finds, in the summary (see
+    /// [`saturation`](crate::saturation)). Costs one fingerprint per step.
+    /// Env: `MADHOUSE_SATURATION=1`.
+    pub saturation: bool,
+    /// Stop generating cases once a window of
+    /// [`saturation::WINDOW`](crate::saturation::WINDOW) applied commands
+    /// finds at most this many new states (see
+    /// [`saturation`](crate::saturation)); never if `None`. Counts states as
+    /// [`saturation`](Self::saturation) does. Env:
+    /// `MADHOUSE_SATURATION_THRESHOLD`.
+    pub saturation_threshold: Option<usize>,
+    /// Fail the run, listing the most skipped commands, if fewer than this
+    /// share of the commands its generated cases selected passed `check()`;
+    /// never if `None`. Env: `MADHOUSE_MIN_EXECUTED_RATIO`, e.g. `0.5`.
+    pub min_executed_ratio: Option<f64>,
+    /// Reject and regenerate generated cases in which fewer than this many
+    /// commands passed `check()`, instead of letting them pass vacuously.
+    /// Env: `MADHOUSE_REQUIRE_EXECUTED`.
+    pub require_executed: usize,
+    /// File the state transition graph of a run's generated cases is
+    /// written to, in Graphviz DOT format (see [`graph`](crate::graph)).
+    /// Env: `MADHOUSE_GRAPH_FILE`.
+    pub graph_file: Option<PathBuf>,
+    /// CSV file every command applied by a generated case is appended to,
+    /// with its duration (see [`timings`](crate::timings)). Env:
+    /// `MADHOUSE_TIMINGS_CSV`.
+    pub timings_csv: Option<PathBuf>,
+    /// CSV file each scenario appends, at the end of a run, the skip rate of
+    /// every command `check()` rejected to (see
+    /// [`Skips`](crate::coverage::Skips)). Env: `MADHOUSE_SKIPS_CSV`.
+    pub skips_csv: Option<PathBuf>,
+    /// Directory generated cases reaching new states or command sequences
+    /// are saved to, and random runs replay mutations from (see
+    /// [`corpus`](crate::corpus)). Env: `MADHOUSE_CORPUS_DIR`.
+    pub corpus_dir: Option<PathBuf>,
+    /// Make random mode favor the commands that led to new states in
+    /// earlier cases (see [`guidance`](crate::guidance)). Env:
+    /// `MADHOUSE_GUIDED=1`.
+    pub guided: bool,
+    /// Print a Mermaid sequence diagram of every failing case after
+    /// shrinking, with the state after each command (see
+    /// [`mermaid`](crate::mermaid)). Costs one `Debug` rendering (or
+    /// projection) per step. Env: `MADHOUSE_MERMAID=1`.
+    pub mermaid: bool,
+    /// Print every failing case after shrinking as a standalone `#[test]`
+    /// running the same commands (see [`testgen`](crate::testgen)). Env:
+    /// `MADHOUSE_REGRESSION_TEST=1`.
+    pub regression_test: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            mode: Mode::Normal,
+            cases: 1,
+            time_budget: None,
+            seq_len: 1..16,
+            max_shrink_iters: 0,
+            seed: None,
+            verbosity: Verbosity::Normal,
+            format: Format::Text,
+            max_failures: 1,
+            only_cases: None,
+            shard: None,
+            junit_dir: None,
+            html_dir: None,
+            webhook: None,
+            bless: false,
+            trace_limit: None,
+            trace_file: None,
+            quarantine: None,
+            state_diff: true,
+            panic_trace: true,
+            no_ops: false,
+            saturation: false,
+            saturation_threshold: None,
+            min_executed_ratio: None,
+            require_executed: 0,
+            graph_file: None,
+            timings_csv: None,
+            skips_csv: None,
+            corpus_dir: None,
+            guided: false,
+            mermaid: false,
+            regression_test: false,
+        }
+    }
+}
+
+impl Config {
+    /// Returns the default configuration overlaid with the environment.
+    pub fn from_env() -> Self {
+        Self::default().with_env()
+    }
+
+    /// Overrides fields with the environment variables that are set.
+    ///
+    /// Unparsable values are reported on stderr and ignored.
+    pub fn with_env(mut self) -> Self {
+        if let Some(mode) = env_with("MADHOUSE", parse_mode) {
+            self.mode = mode;
+        }
+        if let Some(cases) = env_parse("PROPTEST_CASES") {
+            self.cases = cases;
+        }
+        if let Some(budget) = env_with("MADHOUSE_TIME_BUDGET", parse_duration) {
+            self.time_budget = Some(budget);
+        }
+        if let Some(seq_len) = env_with("MADHOUSE_SEQ_LEN", parse_range) {
+            self.seq_len = seq_len;
+        }
+        if let Some(iters) = env_parse("PROPTEST_MAX_SHRINK_ITERS") {
+            self.max_shrink_iters = iters;
+        }
+        if let Some(seed) = env_parse("MADHOUSE_SEED") {
+            self.seed = Some(seed);
+        }
+        if let Some(verbosity) = env_with("MADHOUSE_VERBOSITY", parse_verbosity) {
+            self.verbosity = verbosity;
+        }
+        if let Some(format) = env_with("MADHOUSE_FORMAT", parse_format) {
+            self.format = format;
+        }
+        if let Some(max_failures) = env_parse("MADHOUSE_MAX_FAILURES") {
+            self.max_failures = max_failures;
+        }
+        if let Some(only_cases) = env_with("MADHOUSE_ONLY_CASES", parse_cases) {
+            self.only_cases = Some(only_cases);
+        }
+        if let Some(shard) = env_with("MADHOUSE_SHARD", parse_shard) {
+            self.shard = Some(shard);
+        }
+        if let Some(dir) = env_with("MADHOUSE_JUNIT_DIR", |value| {
+            (!value.is_empty()).then(|| PathBuf::from(value))
+        }) {
+            self.junit_dir = Some(dir);
+        }
+        if let Some(dir) = env_with("MADHOUSE_HTML_DIR", |value| {
+            (!value.is_empty()).then(|| PathBuf::from(value))
+        }) {
+            self.html_dir = Some(dir);
+        }
+        if let Some(url) = env_with("MADHOUSE_WEBHOOK", |value| {
+            (!value.is_empty()).then(|| value.to_string())
+        }) {
+            self.webhook = Some(url);
+        }
+        if let Some(bless) = env_parse::<u8>("MADHOUSE_BLESS") {
+            self.bless = bless == 1;
+        }
+        if let Some(limit) = env_parse("MADHOUSE_TRACE_LIMIT") {
+            self.trace_limit = Some(limit);
+        }
+        if let Some(path) = env_with("MADHOUSE_TRACE_FILE", |value| {
+            (!value.is_empty()).then(|| PathBuf::from(value))
+        }) {
+            self.trace_file = Some(path);
+        }
+        if let Some(path) = env_with("MADHOUSE_QUARANTINE", |value| {
+            (!value.is_empty()).then(|| PathBuf::from(value))
+        }) {
+            self.quarantine = Some(path);
+        }
+        if let Some(state_diff) = env_parse::<u8>("MADHOUSE_STATE_DIFF") {
+            self.state_diff = state_diff == 1;
+        }
+        if let Some(panic_trace) = env_parse::<u8>("MADHOUSE_PANIC_TRACE") {
+            self.panic_trace = panic_trace == 1;
+        }
+        if let Some(no_ops) = env_parse::<u8>("MADHOUSE_NO_OPS") {
+            self.no_ops = no_ops == 1;
+        }
+        if let Some(saturation) = env_parse::<u8>("MADHOUSE_SATURATION") {
+            self.saturation = saturation == 1;
+        }
+        if let Some(threshold) = env_parse("MADHOUSE_SATURATION_THRESHOLD") {
+            self.saturation_threshold = Some(threshold);
+        }
+        if let Some(ratio) = env_parse("MADHOUSE_MIN_EXECUTED_RATIO") {
+            self.min_executed_ratio = Some(ratio);
+        }
+        if let Some(required) = env_parse("MADHOUSE_REQUIRE_EXECUTED") {
+            self.require_executed = required;
+        }
+        if let Some(path) = env_with("MADHOUSE_GRAPH_FILE", |value| {
+            (!value.is_empty()).then(|| PathBuf::from(value))
+        }) {
+            self.graph_file = Some(path);
+        }
+        if let Some(path) = env_with("MADHOUSE_TIMINGS_CSV", |value| {
+            (!value.is_empty()).then(|| PathBuf::from(value))
+        }) {
+            self.timings_csv = Some(path);
+        }
+        if let Some(path) = env_with("MADHOUSE_SKIPS_CSV", |value| {
+            (!value.is_empty()).then(|| PathBuf::from(value))
+        }) {
+            self.skips_csv = Some(path);
+        }
+        if let Some(dir) = env_with("MADHOUSE_CORPUS_DIR", |value| {
+            (!value.is_empty()).then(|| PathBuf::from(value))
+        }) {
+            self.corpus_dir = Some(dir);
+        }
+        if let Some(guided) = env_parse::<u8>("MADHOUSE_GUIDED") {
+            self.guided = guided == 1;
+        }
+        if let Some(mermaid) = env_parse::<u8>("MADHOUSE_MERMAID") {
+            self.mermaid = mermaid == 1;
+        }
+        if let Some(regression_test) = env_parse::<u8>("MADHOUSE_REGRESSION_TEST") {
+            self.regression_test = regression_test == 1;
+        }
+        self
+    }
+}
+
+/// Reads an environment variable with `parse`, warning about bad values.
+fn env_with<T>(name: &str, parse: impl Fn(&str) -> Option<T>) -> Option<T> {
+    lookup_with(|name| std::env::var(name).ok(), name, parse)
+}
+
+/// Reads the variable `name` from `lookup` with `parse`, warning about bad
+/// values.
+pub(crate) fn lookup_with<T>(
+    lookup: impl Fn(&str) -> Option<String>,
+    name: &str,
+    parse: impl Fn(&str) -> Option<T>,
+) -> Option<T> {
+    let value = lookup(name)?;
+    let parsed = parse(value.trim());
+    if parsed.is_none() {
+        eprintln!("madhouse: ignoring invalid {}={}", name, value);
+    }
+    parsed
+}
+
+pub(crate) fn env_parse<T: FromStr>(name: &str) -> Option<T> {
+    env_with(name, |value| value.parse().ok())
+}
+
+/// Reads `MADHOUSE_PANIC_TRACE` for executions run without a [`Config`],
+/// defaulting to on.
+pub(crate) fn panic_trace_from_env() -> bool {
+    env_parse::<u8>("MADHOUSE_PANIC_TRACE").is_none_or(|panic_trace| panic_trace == 1)
+}
+
+/// Reads `MADHOUSE_NO_OPS` for executions run without a [`Config`],
+/// defaulting to off.
+pub(crate) fn no_ops_from_env() -> bool {
+    env_parse::<u8>("MADHOUSE_NO_OPS") == Some(1)
+}
+
+/// Parses `a..b`, `a..=b`, or `n` for exactly `n`.
+fn parse_range(value: &str) -> Option<Range<usize>> {
+    let range = match value.split_once("..") {
+        Some((start, end)) => {
+            let start = start.trim().parse().ok()?;
+            match end.strip_prefix('=') {
+                Some(last) => start..last.trim().parse::<usize>().ok()?.checked_add(1)?,
+                None => start..end.trim().parse().ok()?,
+            }
+        }
+        None => {
+            let len: usize = value.trim().parse().ok()?;
+            len..len.checked_add(1)?
+        }
+    };
+    (!range.is_empty()).then_some(range)
+}
+
+/// Parses seconds, optionally followed by `s`, `m` or `h`.
+fn parse_duration(value: &str) -> Option<Duration> {
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(at) => value.split_at(at),
+        None => (value, "s"),
+    };
+    let seconds = match unit.trim() {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        _ => return None,
+    };
+    Some(Duration::from_secs(
+        number.parse::<u64>().ok()?.checked_mul(seconds)?,
+    ))
+}
+
+/// Parses `1` (random), `swarm`, `exhaustive[:max_len]`, `mixed:prefix`,
+/// or any other number (normal).
+fn parse_mode(value: &str) -> Option<Mode> {
+    let lower = value.to_ascii_lowercase();
+    if lower == "swarm" {
+        return Some(Mode::Swarm);
+    }
+    if let Some(rest) = lower.strip_prefix("exhaustive") {
+        let max_len = match rest.strip_prefix(':') {
+            Some(max_len) => max_len.trim().parse().ok()?,
+            None if rest.is_empty() => DEFAULT_EXHAUSTIVE_LEN,
+            None => return None,
+        };
+        return Some(Mode::Exhaustive { max_len });
+    }
+    if let Some(prefix) = lower.strip_prefix("mixed:") {
+        let prefix = prefix.trim().parse().ok()?;
+        return Some(Mode::Mixed { prefix });
+    }
+    match value.parse::<u8>().ok()? {
+        1 => Some(Mode::Random),
+        _ => Some(Mode::Normal),
+    }
+}
+
+fn parse_verbosity(value: &str) -> Option<Verbosity> {
+    match value.to_ascii_lowercase().as_str() {
+        "quiet" | "0" => Some(Verbosity::Quiet),
+        "normal" | "1" => Some(Verbosity::Normal),
+        "verbose" | "2" => Some(Verbosity::Verbose),
+        _ => None,
+    }
+}
+
+fn parse_format(value: &str) -> Option<Format> {
+    match value.to_ascii_lowercase().as_str() {
+        "text" => Some(Format::Text),
+        "json" => Some(Format::Json),
+        _ => None,
+    }
+}
+
+/// Parses a comma-separated list of case indices.
+fn parse_cases(value: &str) -> Option<Vec<u32>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|case| !case.is_empty())
+        .map(|case| case.parse().ok())
+        .collect()
+}
+
+/// Parses `k/n`, with `1 <= k <= n`.
+fn parse_shard(value: &str) -> Option<Shard> {
+    let (index, count) = value.split_once('/')?;
+    let (index, count) = (index.trim().parse().ok()?, count.trim().parse().ok()?);
+    (1..=count)
+        .contains(&index)
+        .then(|| Shard::new(index, count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parsers() {
+        assert_eq!(parse_range("5..40"), Some(5..40));
+        assert_eq!(parse_range("4..4"), None);
+        assert_eq!(parse_range("100..=200"), Some(100..201));
+        assert_eq!(parse_range("150"), Some(150..151));
+        assert_eq!(parse_range("4..=3"), None);
+        assert_eq!(parse_range("many"), None);
+        assert_eq!(parse_duration("90"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_duration("15m"), Some(Duration::from_secs(900)));
+        assert_eq!(parse_duration("1h"), Some(Duration::from_secs(3600)));
+        assert_eq!(parse_duration("1.5h"), None);
+        assert_eq!(parse_duration("m"), None);
+        assert_eq!(parse_mode("1"), Some(Mode::Random));
+        assert_eq!(parse_mode("0"), Some(Mode::Normal));
+        assert_eq!(parse_mode("Swarm"), Some(Mode::Swarm));
+        assert_eq!(
+            parse_mode("exhaustive"),
+            Some(Mode::Exhaustive {
+                max_len: DEFAULT_EXHAUSTIVE_LEN
+            })
+        );
+        assert_eq!(
+            parse_mode("Exhaustive:5"),
+            Some(Mode::Exhaustive { max_len: 5 })
+        );
+        assert_eq!(parse_mode("exhaustively"), None);
+        assert_eq!(parse_mode("Mixed:2"), Some(Mode::Mixed { prefix: 2 }));
+        assert_eq!(parse_mode("mixed"), None);
+        assert_eq!(parse_verbosity("Verbose"), Some(Verbosity::Verbose));
+        assert_eq!(parse_verbosity("loud"), None);
+        assert_eq!(parse_format("JSON"), Some(Format::Json));
+        assert_eq!(parse_cases("3, 17,"), Some(vec![3, 17]));
+        assert_eq!(parse_cases("3,x"), None);
+        assert_eq!(parse_shard("2/4"), Some(Shard::new(2, 4)));
+        assert_eq!(parse_shard("0/4"), None);
+        assert_eq!(parse_shard("5/4"), None);
+        assert_eq!(parse_shard("2"), None);
+    }
+}
@@ -0,0 +1,117 @@
+//! Sequencing constraints on generated commands.
+//!
+//! Rules like "no withdrawal before a deposit" can be written in
+//! [`Command::check`](crate::Command::check), but then most generated
+//! commands are skipped and the run is wasted on no-ops. [`Constraints`]
+//! states such rules by command name (see
+//! [`command_name`](crate::report::command_name)) and enforces them on the
+//! generated sequence itself, before it runs. Set them with
+//! [`Scenario::constraints`](crate::Scenario::constraints); since every
+//! shrunk sequence goes through them as well, shrinking never produces a
+//! sequence breaking them.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Ordering and count rules, by command name.
+///
+/// A sequence is made to follow them by dropping the commands breaking them,
+/// and by moving the last command that must be last to the end.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::constraints::Constraints;
+///
+/// let constraints = Constraints::new()
+///     .never_before("WITHDRAW", "DEPOSIT")
+///     .at_most("DEPOSIT", 2)
+///     .last("CLOSE");
+///
+/// let sequence = vec!["WITHDRAW", "DEPOSIT", "CLOSE", "DEPOSIT", "DEPOSIT", "WITHDRAW"];
+/// assert_eq!(
+///     constraints.apply(sequence, |name| name.to_string()),
+///     vec!["DEPOSIT", "DEPOSIT", "WITHDRAW", "CLOSE"]
+/// );
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Constraints {
+    /// Commands, and the commands that must have run before them.
+    before: BTreeMap<String, BTreeSet<String>>,
+    at_most: BTreeMap<String, usize>,
+    last: BTreeSet<String>,
+}
+
+impl Constraints {
+    /// Creates constraints allowing every sequence.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allows command `later` only once command `earlier` has run.
+    pub fn never_before(mut self, later: impl Into<String>, earlier: impl Into<String>) -> Self {
+        self.before
+            .entry(later.into())
+            .or_default()
+            .insert(earlier.into());
+        self
+    }
+
+    /// Allows at most `count` commands named `name` per sequence.
+    pub fn at_most(mut self, name: impl Into<String>, count: usize) -> Self {
+        self.at_most.insert(name.into(), count);
+        self
+    }
+
+    /// Allows command `name` only at the end of a sequence, at most once.
+    pub fn last(mut self, name: impl Into<String>) -> Self {
+        self.last.insert(name.into());
+        self
+    }
+
+    /// Returns true if no constraint was set.
+    pub fn is_empty(&self) -> bool {
+        self.before.is_empty() && self.at_most.is_empty() && self.last.is_empty()
+    }
+
+    /// Returns `commands` without the ones breaking the constraints, names
+    /// given by `name`.
+    ///
+    /// Commands are kept in order, except that of the commands that must be
+    /// last, only the latest one is kept, at the end.
+    pub fn apply<T>(&self, commands: Vec<T>, name: impl Fn(&T) -> String) -> Vec<T> {
+        if self.is_empty() {
+            return commands;
+        }
+        let mut kept = Vec::with_capacity(commands.len());
+        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+        let mut last = None;
+        for command in commands {
+            let name = name(&command);
+            let count = counts.get(&name).copied().unwrap_or(0);
+            if self.at_most.get(&name).is_some_and(|&max| count >= max) {
+                continue;
+            }
+            if self.last.contains(&name) {
+                last = Some((name, command));
+            } else if self.ready(&name, &counts) {
+                counts.insert(name, count + 1);
+                kept.push(command);
+            }
+        }
+        if let Some((name, command)) = last {
+            if self.ready(&name, &counts) {
+                kept.push(command);
+            }
+        }
+        kept
+    }
+
+    /// Returns true if the commands `name` must follow have run.
+    fn ready(&self, name: &str, counts: &BTreeMap<String, usize>) -> bool {
+        self.before.get(name).is_none_or(|earlier| {
+            earlier
+                .iter()
+                .all(|earlier| counts.get(earlier).is_some_and(|&n| n > 0))
+        })
+    }
+}
@@ -0,0 +1,163 @@
+//! Building test contexts from the environment or a configuration file.
+//!
+//! Contexts carry the parameters of the system under test: node counts,
+//! endpoints, credentials. Built in code, changing one means recompiling.
+//! [`TestContext::from_env`](crate::TestContext::from_env) builds a context
+//! from environment variables instead, reading them with [`var`] and
+//! [`list`]; with the `toml` feature, [`load`] reads the whole context from
+//! the TOML file named by `MADHOUSE_CONTEXT_FILE`, so CI can tune a
+//! scenario without touching its code.
+//!
+//! # Examples
+//!
+//! ```
+//! use madhouse::context::{list, var};
+//! use madhouse::TestContext;
+//!
+//! #[derive(Debug, Clone)]
+//! struct Cluster { miners: usize, endpoints: Vec<String> }
+//!
+//! impl Default for Cluster {
+//!     fn default() -> Self {
+//!         Self { miners: 3, endpoints: vec!["127.0.0.1:20443".to_string()] }
+//!     }
+//! }
+//!
+//! impl TestContext for Cluster {
+//!     fn from_env() -> Self {
+//!         let defaults = Self::default();
+//!         Self {
+//!             miners: var("CLUSTER_MINERS").unwrap_or(defaults.miners),
+//!             endpoints: list("CLUSTER_ENDPOINTS").unwrap_or(defaults.endpoints),
+//!         }
+//!     }
+//! }
+//!
+//! std::env::set_var("CLUSTER_ENDPOINTS", "10.0.0.1:20443, 10.0.0.2:20443");
+//! let cluster = Cluster::from_env();
+//! assert_eq!(cluster.miners, 3);
+//! assert_eq!(cluster.endpoints, ["10.0.0.1:20443", "10.0.0.2:20443"]);
+//! ```
+
+use crate::config::lookup_with;
+#[cfg(feature = "toml")]
+use serde::de::DeserializeOwned;
+#[cfg(feature = "toml")]
+use std::path::Path;
+use std::str::FromStr;
+
+/// Parses the environment variable `name`, or returns `None` if it is
+/// unset. An invalid value is reported on stderr and ignored, like those
+/// of the `MADHOUSE_*` variables.
+pub fn var<T: FromStr>(name: &str) -> Option<T> {
+    var_in(environment, name)
+}
+
+/// Splits the environment variable `name` on commas, trimming each item
+/// and dropping empty ones, or returns `None` if it is unset.
+pub fn list(name: &str) -> Option<Vec<String>> {
+    list_in(environment, name)
+}
+
+fn environment(name: &str) -> Option<String> {
+    std::env::var(name).ok()
+}
+
+/// [`var`], reading variables from `lookup`.
+fn var_in<T: FromStr>(lookup: impl Fn(&str) -> Option<String>, name: &str) -> Option<T> {
+    lookup_with(lookup, name, |value| value.parse().ok())
+}
+
+/// [`list`], reading variables from `lookup`.
+fn list_in(lookup: impl Fn(&str) -> Option<String>, name: &str) -> Option<Vec<String>> {
+    let value = lookup(name)?;
+    Some(
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(String::from)
+            .collect(),
+    )
+}
+
+/// Deserializes a context from TOML, with the [`toml`](::toml) crate.
+///
+/// # Errors
+///
+/// Fails, naming the line and column, if `toml` is not valid, or if it
+/// does not deserialize into `T`.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::context::from_toml_str;
+/// use serde_json::{json, Value};
+///
+/// let context: Value = from_toml_str(r#"
+///     miners = 3
+///
+///     [signer]
+///     endpoints = ["10.0.0.1:30000", "10.0.0.2:30000"]
+///     key = 'secret' # from the CI vault
+/// "#).unwrap();
+/// assert_eq!(context["signer"]["endpoints"][1], json!("10.0.0.2:30000"));
+/// ```
+#[cfg(feature = "toml")]
+pub fn from_toml_str<T: DeserializeOwned>(toml: &str) -> Result<T, String> {
+    ::toml::from_str(toml).map_err(|err| err.to_string())
+}
+
+/// Reads and deserializes a context from the TOML file at `path`.
+///
+/// # Errors
+///
+/// Fails if the file cannot be read, or as [`from_toml_str`] does; the
+/// message names the file.
+#[cfg(feature = "toml")]
+pub fn from_file<T: DeserializeOwned>(path: impl AsRef<Path>) -> Result<T, String> {
+    let path = path.as_ref();
+    std::fs::read_to_string(path)
+        .map_err(|err| err.to_string())
+        .and_then(|toml| from_toml_str(&toml))
+        .map_err(|err| format!("cannot load context from {}: {}", path.display(), err))
+}
+
+/// Builds a context from the TOML file named by `MADHOUSE_CONTEXT_FILE`,
+/// or with [`TestContext::from_env`](crate::TestContext::from_env) if it
+/// is unset.
+///
+/// Fields missing from the file must have serde defaults, e.g. through
+/// `#[serde(default)]` on the context.
+///
+/// # Panics
+///
+/// Panics if the file cannot be loaded, rather than testing against a
+/// context other than the one asked for.
+#[cfg(feature = "toml")]
+pub fn load<C: crate::TestContext + Default + DeserializeOwned>() -> C {
+    match std::env::var_os("MADHOUSE_CONTEXT_FILE") {
+        Some(path) => from_file(path).unwrap_or_else(|err| panic!("{}", err)),
+        None => C::from_env(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_lists_are_trimmed_and_invalid_vars_ignored() {
+        let vars = HashMap::from([
+            ("LIST", " a:1 ,, b:2,"),
+            ("COUNT", "three"),
+            ("PORT", " 80 "),
+        ]);
+        let lookup = |name: &str| vars.get(name).map(|value| value.to_string());
+        assert_eq!(list_in(lookup, "LIST").unwrap(), ["a:1", "b:2"]);
+        assert_eq!(list_in(lookup, "UNSET"), None);
+        assert_eq!(var_in::<usize>(lookup, "COUNT"), None);
+        assert_eq!(var_in::<u16>(lookup, "PORT"), Some(80));
+    }
+}
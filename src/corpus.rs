@@ -0,0 +1,242 @@
+//! Mutating recorded traces for corpus-based generation.
+//!
+//! A scenario can mix fresh sequences with mutated copies of recorded
+//! traces (see [`Scenario::corpus`](crate::Scenario::corpus)), in the spirit
+//! of a coverage-guided fuzzer's corpus scheduling: interesting sequences
+//! found earlier are kept and perturbed instead of rediscovered from
+//! scratch.
+//!
+//! With [`Config::corpus_dir`](crate::Config::corpus_dir) set (or
+//! `MADHOUSE_CORPUS_DIR`), a scenario also keeps the [`Corpus`] itself: a
+//! generated case that passes and reaches a state fingerprint, or a
+//! command-name n-gram, that no entry reached before is saved to the
+//! directory, and random runs replay mutations of the saved entries. Kept
+//! in CI's cache, the directory accumulates interesting sequences across
+//! runs, like AFL's queue. Entries are trace files whose comments list the
+//! fingerprints of the states they reached:
+//!
+//! ```text
+//! # madhouse corpus
+//! # state 5f1c0d7e9a3b2c41
+//! # state 0b6e2f8d4c1a9e37
+//! INC(3)
+//! RESET
+//! ```
+
+use crate::regression;
+use crate::report::command_name;
+use std::collections::HashSet;
+use std::fmt::Debug;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Longest command-name n-gram tracked by a [`Corpus`].
+pub const NGRAM: usize = 3;
+
+/// Probability of replaying a mutated corpus entry in random modes, unless
+/// set with [`Scenario::corpus`](crate::Scenario::corpus).
+pub const DEFAULT_PROBABILITY: f64 = 0.25;
+
+/// A single edit applied to a recorded trace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mutation {
+    /// Replay the trace unchanged.
+    Keep,
+    /// Remove the command at the position.
+    Remove,
+    /// Insert a fresh command before the position.
+    Insert,
+    /// Replace the command at the position with a fresh one.
+    Replace,
+    /// Swap the command at the position with the next one.
+    Swap,
+    /// Repeat the command at the position.
+    Duplicate,
+}
+
+impl Mutation {
+    /// All mutations, in the order they shrink toward.
+    pub const ALL: [Mutation; 6] = [
+        Mutation::Keep,
+        Mutation::Remove,
+        Mutation::Insert,
+        Mutation::Replace,
+        Mutation::Swap,
+        Mutation::Duplicate,
+    ];
+
+    /// Applies the mutation at `pos` (wrapped to the trace length).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use madhouse::corpus::Mutation;
+    ///
+    /// let trace = ["A", "B", "C"];
+    /// assert_eq!(Mutation::Remove.apply(&trace, 1, "X"), ["A", "C"]);
+    /// assert_eq!(Mutation::Insert.apply(&trace, 4, "X"), ["A", "X", "B", "C"]);
+    /// assert_eq!(Mutation::Swap.apply(&trace, 2, "X"), ["A", "B", "C"]);
+    /// ```
+    pub fn apply<T: Clone + Debug>(self, trace: &[T], pos: usize, fresh: T) -> Vec<T> {
+        let mut out = trace.to_vec();
+        if out.is_empty() {
+            if matches!(self, Mutation::Insert | Mutation::Replace) {
+                out.push(fresh);
+            }
+            return out;
+        }
+        let pos = pos % out.len();
+        match self {
+            Mutation::Keep => {}
+            Mutation::Remove => {
+                out.remove(pos);
+            }
+            Mutation::Insert => out.insert(pos, fresh),
+            Mutation::Replace => out[pos] = fresh,
+            Mutation::Swap => {
+                if pos + 1 < out.len() {
+                    out.swap(pos, pos + 1);
+                }
+            }
+            Mutation::Duplicate => {
+                let repeated = out[pos].clone();
+                out.insert(pos, repeated);
+            }
+        }
+        out
+    }
+}
+
+/// Traces persisted in a directory because they reached new states or new
+/// sequences of commands.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::corpus::Corpus;
+///
+/// let dir = std::env::temp_dir().join(format!("madhouse-corpus-doc-{}", std::process::id()));
+/// let labels = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+///
+/// let mut corpus = Corpus::open(&dir).unwrap();
+/// assert!(corpus.add(&labels(&["INC(1)", "RESET"]), &[1, 0]).unwrap().is_some());
+/// // Same commands, same states: nothing new.
+/// assert!(corpus.add(&labels(&["INC(2)", "RESET"]), &[1, 0]).unwrap().is_none());
+/// assert!(corpus.is_interesting(&labels(&["INC(2)", "RESET"]), &[3]));
+/// assert!(corpus.is_interesting(&labels(&["RESET", "INC(2)"]), &[0]));
+///
+/// assert_eq!(Corpus::open(&dir).unwrap().traces(), corpus.traces());
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Corpus {
+    dir: PathBuf,
+    traces: Vec<Vec<String>>,
+    states: HashSet<u64>,
+    ngrams: HashSet<Vec<String>>,
+}
+
+impl Corpus {
+    /// Loads the entries in `dir`, in file name order. A missing directory
+    /// yields an empty corpus.
+    pub fn open(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let mut corpus = Self {
+            dir: dir.into(),
+            ..Self::default()
+        };
+        let mut paths: Vec<PathBuf> = match fs::read_dir(&corpus.dir) {
+            Ok(entries) => entries.filter_map(|e| e.ok().map(|e| e.path())).collect(),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err),
+        };
+        paths.retain(|path| path.extension().is_some_and(|ext| ext == "txt"));
+        paths.sort();
+        for path in paths {
+            let contents = fs::read_to_string(&path)?;
+            let states: Vec<u64> = contents
+                .lines()
+                .filter_map(|line| line.trim().strip_prefix("# state "))
+                .filter_map(|hex| u64::from_str_radix(hex.trim(), 16).ok())
+                .collect();
+            corpus.record(regression::load(&path)?, &states);
+        }
+        Ok(corpus)
+    }
+
+    /// Returns the directory of the corpus.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the labels of every entry, loaded ones first.
+    pub fn traces(&self) -> &[Vec<String>] {
+        &self.traces
+    }
+
+    /// Returns true if a trace with `labels`, reaching the states
+    /// fingerprinted by `states`, would add a state or an n-gram of
+    /// command names to the corpus.
+    pub fn is_interesting(&self, labels: &[String], states: &[u64]) -> bool {
+        states.iter().any(|state| !self.states.contains(state))
+            || ngrams(labels).any(|ngram| !self.ngrams.contains(&ngram))
+    }
+
+    /// Saves the trace to the directory if it is interesting, returning
+    /// the path of the new entry.
+    pub fn add(&mut self, labels: &[String], states: &[u64]) -> io::Result<Option<PathBuf>> {
+        if !self.is_interesting(labels, states) {
+            return Ok(None);
+        }
+        let mut distinct: Vec<u64> = states.to_vec();
+        distinct.sort_unstable();
+        distinct.dedup();
+        let mut contents = String::from("# madhouse corpus\n");
+        for state in &distinct {
+            contents.push_str(&format!("# state {:016x}\n", state));
+        }
+        for label in labels {
+            contents.push_str(label);
+            contents.push('\n');
+        }
+        let path = self
+            .dir
+            .join(format!("{:016x}.txt", regression::fingerprint(labels)));
+        fs::create_dir_all(&self.dir)?;
+        fs::write(&path, contents)?;
+        self.record(labels.to_vec(), &distinct);
+        Ok(Some(path))
+    }
+
+    fn record(&mut self, labels: Vec<String>, states: &[u64]) {
+        self.states.extend(states);
+        self.ngrams.extend(ngrams(&labels));
+        self.traces.push(labels);
+    }
+}
+
+/// The n-grams of command names in `labels`, of every length up to
+/// [`NGRAM`].
+fn ngrams(labels: &[String]) -> impl Iterator<Item = Vec<String>> + '_ {
+    let names: Vec<String> = labels
+        .iter()
+        .map(|label| command_name(label).to_string())
+        .collect();
+    (1..=NGRAM).flat_map(move |n| names.windows(n).map(<[String]>::to_vec).collect::<Vec<_>>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mutations() {
+        let trace = [1, 2, 3];
+        assert_eq!(Mutation::Keep.apply(&trace, 7, 9), vec![1, 2, 3]);
+        assert_eq!(Mutation::Replace.apply(&trace, 0, 9), vec![9, 2, 3]);
+        assert_eq!(Mutation::Swap.apply(&trace, 0, 9), vec![2, 1, 3]);
+        assert_eq!(Mutation::Duplicate.apply(&trace, 2, 9), vec![1, 2, 3, 3]);
+        assert_eq!(Mutation::Insert.apply(&[], 5, 9), vec![9]);
+        assert!(Mutation::Remove.apply(&[] as &[i32], 0, 9).is_empty());
+    }
+}
@@ -0,0 +1,385 @@
+//! Command coverage across the cases of a scenario run.
+//!
+//! Preconditions that are too strict silently starve commands: they keep
+//! being generated but never pass `check()`. [`Coverage`] counts, per command
+//! name, how often a command was generated, passed its precondition and
+//! executed successfully, and a scenario prints it as a table at the end of
+//! a run. [`Skips`] tells apart the commands whose preconditions are dead
+//! ends for the generation strategy: how often `check()` rejected each one,
+//! and why.
+
+use crate::report::{command_name, StepOutcome};
+use crate::timings;
+use crate::ExecutionReport;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter, Result as FmtResult, Write as _};
+
+/// Header row of the skip-rate CSV file (see [`Skips::rows`]).
+pub const SKIPS_HEADER: &str = "scenario,command,selected,skipped,rate,top_reason";
+
+/// Counters for one command name.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CommandCoverage {
+    /// Times the command appeared in a generated sequence.
+    pub generated: usize,
+    /// Times the executor selected it, i.e. called its `check()`.
+    pub selected: usize,
+    /// Times `check()` accepted it.
+    pub passed_check: usize,
+    /// Times it was applied without reporting an error.
+    pub executed: usize,
+    /// Times it was applied without error and left the state unchanged
+    /// (see [`Config::no_ops`](crate::Config::no_ops)).
+    pub no_ops: usize,
+}
+
+/// Per-command counters, keyed by command name (see
+/// [`command_name`](crate::report::command_name)).
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::coverage::Coverage;
+/// use madhouse::report::{ExecutionReport, StepOutcome, StepReport};
+/// use madhouse::SkipReason;
+/// use std::time::Duration;
+///
+/// let step = |label: &str, outcome| StepReport {
+///     index: 0,
+///     label: label.to_string(),
+///     group: String::new(),
+///     outcome,
+///     duration: Duration::ZERO,
+///     check_duration: Duration::ZERO,
+///     started: std::time::UNIX_EPOCH,
+///     finished: std::time::UNIX_EPOCH,
+/// };
+/// let report = ExecutionReport {
+///     steps: vec![step("INC(1)", StepOutcome::Executed), step("RESET", StepOutcome::Skipped(SkipReason::default()))],
+///     ..Default::default()
+/// };
+///
+/// let mut coverage = Coverage::default();
+/// coverage.record(["INC(1)", "RESET", "INC(2)"], &report);
+///
+/// assert_eq!(coverage.get("INC").unwrap().generated, 2);
+/// assert_eq!(coverage.get("INC").unwrap().executed, 1);
+/// assert_eq!(coverage.starved().collect::<Vec<_>>(), vec!["RESET"]);
+/// assert_eq!(coverage.executed_ratio(), Some(0.5));
+/// assert_eq!(coverage.most_skipped(), vec![("RESET", 1)]);
+/// assert_eq!(coverage.skip_rate("RESET"), Some(1.0));
+/// assert_eq!(coverage.skip_reasons("RESET"), vec![("check() returned false", 1)]);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Coverage {
+    /// Cases recorded.
+    pub cases: usize,
+    commands: BTreeMap<String, CommandCoverage>,
+    /// How often each reason was given for skipping each command.
+    reasons: BTreeMap<String, BTreeMap<String, usize>>,
+}
+
+impl Coverage {
+    /// Records one case: the labels of its generated sequence and the
+    /// report of its execution.
+    ///
+    /// Commands generated but never selected, because execution stopped
+    /// early, count as generated only.
+    pub fn record<'a>(
+        &mut self,
+        generated: impl IntoIterator<Item = &'a str>,
+        report: &ExecutionReport,
+    ) {
+        self.cases += 1;
+        for label in generated {
+            self.entry(label).generated += 1;
+        }
+        for step in &report.steps {
+            let entry = self.entry(&step.label);
+            entry.selected += 1;
+            if step.was_applied() {
+                entry.passed_check += 1;
+            }
+            if step.outcome == StepOutcome::Executed {
+                entry.executed += 1;
+            }
+            if let Some(reason) = step.skip_reason() {
+                *self
+                    .reasons
+                    .entry(command_name(&step.label).to_string())
+                    .or_default()
+                    .entry(reason.message().to_string())
+                    .or_default() += 1;
+            }
+        }
+        for no_op in &report.no_ops {
+            self.entry(&no_op.label).no_ops += 1;
+        }
+    }
+
+    /// Returns the counters of a command name.
+    pub fn get(&self, name: &str) -> Option<&CommandCoverage> {
+        self.commands.get(name)
+    }
+
+    /// Iterates over command names and counters, sorted by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &CommandCoverage)> {
+        self.commands.iter().map(|(name, c)| (name.as_str(), c))
+    }
+
+    /// Returns the commands that were generated but never passed `check()`.
+    pub fn starved(&self) -> impl Iterator<Item = &str> {
+        self.iter()
+            .filter(|(_, c)| c.generated > 0 && c.passed_check == 0)
+            .map(|(name, _)| name)
+    }
+
+    /// Returns the share of selected commands that passed `check()`, or
+    /// `None` if none was selected.
+    pub fn executed_ratio(&self) -> Option<f64> {
+        let (selected, passed) = self
+            .commands
+            .values()
+            .fold((0, 0), |(s, p), c| (s + c.selected, p + c.passed_check));
+        (selected > 0).then(|| passed as f64 / selected as f64)
+    }
+
+    /// Returns the commands `check()` rejected, with how often, most
+    /// rejected first.
+    pub fn most_skipped(&self) -> Vec<(&str, usize)> {
+        let mut skipped: Vec<(&str, usize)> = self
+            .iter()
+            .map(|(name, c)| (name, c.selected - c.passed_check))
+            .filter(|&(_, skipped)| skipped > 0)
+            .collect();
+        skipped.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        skipped
+    }
+
+    /// Returns the share of the selections of a command that `check()`
+    /// rejected, or `None` if it was never selected.
+    pub fn skip_rate(&self, name: &str) -> Option<f64> {
+        let c = self.get(name).filter(|c| c.selected > 0)?;
+        Some((c.selected - c.passed_check) as f64 / c.selected as f64)
+    }
+
+    /// Returns the reasons `check()` gave for rejecting a command, with how
+    /// often, most frequent first.
+    pub fn skip_reasons(&self, name: &str) -> Vec<(&str, usize)> {
+        let mut reasons: Vec<(&str, usize)> = self
+            .reasons
+            .get(name)
+            .into_iter()
+            .flatten()
+            .map(|(reason, &count)| (reason.as_str(), count))
+            .collect();
+        reasons.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        reasons
+    }
+
+    /// Returns the skip-rate table of the commands `check()` rejected.
+    pub fn skips(&self) -> Skips<'_> {
+        Skips { coverage: self }
+    }
+
+    /// Returns true if nothing was recorded.
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    fn entry(&mut self, label: &str) -> &mut CommandCoverage {
+        self.commands
+            .entry(command_name(label).to_string())
+            .or_default()
+    }
+}
+
+impl Display for Coverage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let width = self
+            .commands
+            .keys()
+            .map(String::len)
+            .chain(Some("COMMAND".len()))
+            .max()
+            .unwrap_or_default();
+        writeln!(f, "Command coverage ({} cases):", self.cases)?;
+        writeln!(
+            f,
+            "  {:<width$}  {:>9}  {:>12}  {:>8}",
+            "COMMAND", "GENERATED", "PASSED CHECK", "EXECUTED"
+        )?;
+        for (name, c) in self.iter() {
+            write!(
+                f,
+                "  {:<width$}  {:>9}  {:>12}  {:>8}",
+                name, c.generated, c.passed_check, c.executed
+            )?;
+            if c.generated > 0 && c.passed_check == 0 {
+                write!(f, "  <- never passed check()")?;
+            } else if c.executed > 0 && c.no_ops == c.executed {
+                write!(f, "  <- never changed the state")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// The commands `check()` rejected, by skip rate, with their most frequent
+/// reason.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::coverage::Coverage;
+/// use madhouse::report::{ExecutionReport, StepOutcome, StepReport};
+/// use madhouse::SkipReason;
+/// use std::time::Duration;
+///
+/// let step = |label: &str, outcome| StepReport {
+///     index: 0,
+///     label: label.to_string(),
+///     group: String::new(),
+///     outcome,
+///     duration: Duration::ZERO,
+///     check_duration: Duration::ZERO,
+///     started: std::time::UNIX_EPOCH,
+///     finished: std::time::UNIX_EPOCH,
+/// };
+/// let skipped = || StepOutcome::Skipped(SkipReason::new("no funds"));
+/// let report = ExecutionReport {
+///     steps: vec![
+///         step("DEPOSIT(5)", StepOutcome::Executed),
+///         step("WITHDRAW(3)", skipped()),
+///         step("WITHDRAW(1)", StepOutcome::Executed),
+///         step("WITHDRAW(9)", skipped()),
+///     ],
+///     ..Default::default()
+/// };
+/// let mut coverage = Coverage::default();
+/// coverage.record(["DEPOSIT(5)", "WITHDRAW(3)", "WITHDRAW(1)", "WITHDRAW(9)"], &report);
+///
+/// assert_eq!(
+///     coverage.skips().to_string(),
+///     concat!(
+///         "Skipped by check() (1 cases):\n",
+///         "  COMMAND   SELECTED  SKIPPED    RATE  TOP REASON\n",
+///         "  WITHDRAW         3        2   66.7%  no funds (2)\n",
+///     )
+/// );
+/// let mut csv = String::new();
+/// coverage.skips().rows(&mut csv, "tests::bank");
+/// assert_eq!(csv, "tests::bank,WITHDRAW,3,2,0.667,no funds\n");
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Skips<'a> {
+    coverage: &'a Coverage,
+}
+
+impl<'a> Skips<'a> {
+    /// Iterates over the rejected commands with their counters, highest
+    /// skip rate first.
+    pub fn iter(&self) -> impl Iterator<Item = (&'a str, &'a CommandCoverage)> {
+        let mut skipped: Vec<_> = self
+            .coverage
+            .iter()
+            .filter(|(_, c)| c.selected > c.passed_check)
+            .collect();
+        let rate = |c: &CommandCoverage| (c.selected - c.passed_check) as f64 / c.selected as f64;
+        skipped.sort_by(|a, b| rate(b.1).total_cmp(&rate(a.1)).then(a.0.cmp(b.0)));
+        skipped.into_iter()
+    }
+
+    /// Returns true if `check()` never rejected a command.
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    /// Appends to `out` one CSV row per rejected command of `scenario`:
+    /// its name, selections, skips, skip rate and most frequent reason.
+    pub fn rows(&self, out: &mut String, scenario: &str) {
+        for (name, c) in self.iter() {
+            let reason = self.coverage.skip_reasons(name);
+            let _ = writeln!(
+                out,
+                "{},{},{},{},{:.3},{}",
+                timings::field(scenario),
+                timings::field(name),
+                c.selected,
+                c.selected - c.passed_check,
+                self.coverage.skip_rate(name).unwrap_or_default(),
+                timings::field(reason.first().map_or("", |(reason, _)| reason))
+            );
+        }
+    }
+}
+
+impl Display for Skips<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let width = self
+            .iter()
+            .map(|(name, _)| name.len())
+            .chain(Some("COMMAND".len()))
+            .max()
+            .unwrap_or_default();
+        writeln!(f, "Skipped by check() ({} cases):", self.coverage.cases)?;
+        writeln!(
+            f,
+            "  {:<width$}  {:>8}  {:>7}  {:>6}  TOP REASON",
+            "COMMAND", "SELECTED", "SKIPPED", "RATE"
+        )?;
+        for (name, c) in self.iter() {
+            let reason = match self.coverage.skip_reasons(name).first() {
+                Some((reason, count)) => format!("{} ({})", reason, count),
+                None => String::new(),
+            };
+            writeln!(
+                f,
+                "  {:<width$}  {:>8}  {:>7}  {:>5.1}%  {}",
+                name,
+                c.selected,
+                c.selected - c.passed_check,
+                self.coverage.skip_rate(name).unwrap_or_default() * 100.0,
+                reason
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::StepReport;
+    use crate::SkipReason;
+    use std::time::Duration;
+
+    #[test]
+    fn test_table_flags_starved_commands() {
+        let report = ExecutionReport {
+            steps: vec![StepReport {
+                index: 0,
+                label: "RESET".to_string(),
+                group: String::new(),
+                outcome: StepOutcome::Skipped(SkipReason::default()),
+                duration: Duration::ZERO,
+                check_duration: Duration::ZERO,
+                started: std::time::UNIX_EPOCH,
+                finished: std::time::UNIX_EPOCH,
+            }],
+            ..Default::default()
+        };
+        let mut coverage = Coverage::default();
+        coverage.record(["RESET"], &report);
+
+        assert_eq!(
+            coverage.to_string(),
+            concat!(
+                "Command coverage (1 cases):\n",
+                "  COMMAND  GENERATED  PASSED CHECK  EXECUTED\n",
+                "  RESET            1             0         0  <- never passed check()\n",
+            )
+        );
+    }
+}
@@ -0,0 +1,354 @@
+//! Diffs of command traces and of states.
+//!
+//! [`TraceDiff`] compares the original failing trace with the shrunk one, so
+//! it is clear which commands the shrinker dropped and which it simplified.
+//! [`StateDiff`] compares the state before and after a failing step.
+
+use crate::report::command_name;
+use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
+
+/// One aligned row of a [`TraceDiff`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffLine {
+    /// The command appears unchanged in both traces.
+    Same(String),
+    /// The command was dropped from the original trace.
+    Removed(String),
+    /// The command only appears in the second trace.
+    Added(String),
+    /// Same command name, different parameters (original, shrunk).
+    Changed(String, String),
+}
+
+/// Alignment of two command traces, rendered side by side via `Display`.
+///
+/// Rows are prefixed with ` ` (same), `-` (removed), `+` (added), or `~`
+/// (changed parameters).
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::diff::{DiffLine, TraceDiff};
+///
+/// let original = ["INC(37)", "RESET", "INC(45)", "SMALL_INC(9)"];
+/// let shrunk = ["INC(45)", "SMALL_INC(1)"];
+/// let diff = TraceDiff::new(&original, &shrunk);
+///
+/// assert_eq!(
+///     diff.lines,
+///     vec![
+///         DiffLine::Removed("INC(37)".to_string()),
+///         DiffLine::Removed("RESET".to_string()),
+///         DiffLine::Same("INC(45)".to_string()),
+///         DiffLine::Changed("SMALL_INC(9)".to_string(), "SMALL_INC(1)".to_string()),
+///     ]
+/// );
+/// println!("{}", diff);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceDiff {
+    /// Aligned rows, in trace order.
+    pub lines: Vec<DiffLine>,
+}
+
+enum Op {
+    Keep(usize, usize),
+    Remove(usize),
+    Add(usize),
+}
+
+/// Aligns `a` and `b` along their longest common subsequence under `eq`.
+fn align<T>(a: &[T], b: &[T], eq: impl Fn(&T, &T) -> bool) -> Vec<Op> {
+    let (n, m) = (a.len(), b.len());
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if eq(&a[i], &b[j]) {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut ops = Vec::with_capacity(n.max(m));
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if eq(&a[i], &b[j]) {
+            ops.push(Op::Keep(i, j));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            ops.push(Op::Remove(i));
+            i += 1;
+        } else {
+            ops.push(Op::Add(j));
+            j += 1;
+        }
+    }
+    ops.extend((i..n).map(Op::Remove));
+    ops.extend((j..m).map(Op::Add));
+    ops
+}
+
+impl TraceDiff {
+    /// Diffs two traces of command labels.
+    ///
+    /// Identical labels are aligned first. Within the gaps left between
+    /// them, commands with the same name but different parameters (e.g.
+    /// `INC(9)` vs `INC(1)`) are paired up as changes.
+    pub fn new<T: AsRef<str>>(original: &[T], shrunk: &[T]) -> Self {
+        let a: Vec<&str> = original.iter().map(AsRef::as_ref).collect();
+        let b: Vec<&str> = shrunk.iter().map(AsRef::as_ref).collect();
+        Self {
+            lines: diff_lines(&a, &b, command_name),
+        }
+    }
+
+    /// Returns true if both traces are identical.
+    pub fn is_identical(&self) -> bool {
+        self.lines
+            .iter()
+            .all(|line| matches!(line, DiffLine::Same(_)))
+    }
+}
+
+/// Aligns `a` and `b`, pairing the lines left unmatched in each gap as
+/// changes when they have the same `key`.
+fn diff_lines(a: &[&str], b: &[&str], key: impl Fn(&str) -> &str) -> Vec<DiffLine> {
+    let mut lines = Vec::with_capacity(a.len().max(b.len()));
+    let mut removed = Vec::new();
+    let mut added = Vec::new();
+    for op in align(a, b, |x, y| x == y) {
+        match op {
+            Op::Remove(i) => removed.push(a[i]),
+            Op::Add(j) => added.push(b[j]),
+            Op::Keep(i, _) => {
+                push_gap(&mut lines, &removed, &added, &key);
+                removed.clear();
+                added.clear();
+                lines.push(DiffLine::Same(a[i].to_string()));
+            }
+        }
+    }
+    push_gap(&mut lines, &removed, &added, &key);
+    lines
+}
+
+fn push_gap(
+    lines: &mut Vec<DiffLine>,
+    removed: &[&str],
+    added: &[&str],
+    key: &impl Fn(&str) -> &str,
+) {
+    for op in align(removed, added, |x, y| key(x) == key(y)) {
+        lines.push(match op {
+            Op::Keep(i, j) => DiffLine::Changed(removed[i].to_string(), added[j].to_string()),
+            Op::Remove(i) => DiffLine::Removed(removed[i].to_string()),
+            Op::Add(j) => DiffLine::Added(added[j].to_string()),
+        });
+    }
+}
+
+impl Display for TraceDiff {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let width = self
+            .lines
+            .iter()
+            .map(|line| match line {
+                DiffLine::Same(l) | DiffLine::Removed(l) | DiffLine::Changed(l, _) => l.len(),
+                DiffLine::Added(_) => 0,
+            })
+            .chain(std::iter::once("Original".len()))
+            .max()
+            .unwrap_or_default();
+
+        writeln!(f, "  {:<width$} | Shrunk", "Original")?;
+        for line in &self.lines {
+            let (mark, left, right) = match line {
+                DiffLine::Same(l) => (' ', l.as_str(), l.as_str()),
+                DiffLine::Removed(l) => ('-', l.as_str(), ""),
+                DiffLine::Added(r) => ('+', "", r.as_str()),
+                DiffLine::Changed(l, r) => ('~', l.as_str(), r.as_str()),
+            };
+            let row = format!("{} {:<width$} | {}", mark, left, right);
+            writeln!(f, "{}", row.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+/// Line diff of the pretty `Debug` output (`{:#?}`) of two states.
+///
+/// Fields changed in place (same name, new value) are paired up as
+/// changes. Rendered via `Display` like a unified diff: changed lines are
+/// prefixed with `-` and `+`, and unchanged lines more than two lines away
+/// from a change are elided as `...`, so a change deep inside a large state
+/// stands out.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::diff::StateDiff;
+///
+/// #[derive(Debug)]
+/// struct Chain { height: u64, miners: Vec<&'static str>, fees: u64 }
+///
+/// let before = Chain { height: 7, miners: vec!["alice"], fees: 0 };
+/// let after = Chain { height: 8, miners: vec!["alice", "bob"], fees: 0 };
+/// let diff = StateDiff::between(&before, &after);
+///
+/// assert!(!diff.is_empty());
+/// let rendered = diff.to_string();
+/// assert!(rendered.contains("-     height: 7,\n+     height: 8,"));
+/// assert!(rendered.contains("+         \"bob\","));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateDiff {
+    /// Aligned lines, in output order.
+    pub lines: Vec<DiffLine>,
+}
+
+impl StateDiff {
+    /// Lines of unchanged context shown around each change.
+    const CONTEXT: usize = 2;
+
+    /// Diffs two renderings of a state, line by line.
+    pub fn new(before: &str, after: &str) -> Self {
+        let a: Vec<&str> = before.lines().collect();
+        let b: Vec<&str> = after.lines().collect();
+        Self {
+            lines: diff_lines(&a, &b, field_name),
+        }
+    }
+
+    /// Diffs the pretty `Debug` output of two values.
+    pub fn between<T: Debug + ?Sized>(before: &T, after: &T) -> Self {
+        Self::new(&format!("{:#?}", before), &format!("{:#?}", after))
+    }
+
+    /// Returns true if nothing changed.
+    pub fn is_empty(&self) -> bool {
+        self.lines
+            .iter()
+            .all(|line| matches!(line, DiffLine::Same(_)))
+    }
+}
+
+/// `    height: 7,` -> `    height`, so a field keeps its identity when its
+/// value changes.
+fn field_name(line: &str) -> &str {
+    line.split_once(": ").map_or(line, |(name, _)| name)
+}
+
+impl Display for StateDiff {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let changed: Vec<usize> = self
+            .lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| !matches!(line, DiffLine::Same(_)))
+            .map(|(i, _)| i)
+            .collect();
+        let near_change = |i: usize| changed.iter().any(|&c| c.abs_diff(i) <= Self::CONTEXT);
+
+        let mut elided = false;
+        for (i, line) in self.lines.iter().enumerate() {
+            match line {
+                DiffLine::Same(l) if near_change(i) => {
+                    writeln!(f, "  {}", l)?;
+                    elided = false;
+                }
+                DiffLine::Same(_) => {
+                    if !elided {
+                        writeln!(f, "  ...")?;
+                        elided = true;
+                    }
+                }
+                DiffLine::Removed(l) => writeln!(f, "- {}", l)?,
+                DiffLine::Added(l) => writeln!(f, "+ {}", l)?,
+                DiffLine::Changed(l, r) => {
+                    writeln!(f, "- {}", l)?;
+                    writeln!(f, "+ {}", r)?;
+                }
+            }
+            if !matches!(line, DiffLine::Same(_)) {
+                elided = false;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identical_traces() {
+        let diff = TraceDiff::new(&["A", "B"], &["A", "B"]);
+        assert!(diff.is_identical());
+        assert_eq!(diff.lines.len(), 2);
+    }
+
+    #[test]
+    fn test_added_and_removed() {
+        let diff = TraceDiff::new(&["A", "B", "C"], &["A", "D", "C"]);
+        assert_eq!(
+            diff.lines,
+            vec![
+                DiffLine::Same("A".to_string()),
+                DiffLine::Removed("B".to_string()),
+                DiffLine::Added("D".to_string()),
+                DiffLine::Same("C".to_string()),
+            ]
+        );
+        assert!(!diff.is_identical());
+    }
+
+    #[test]
+    fn test_empty_shrunk_trace() {
+        let empty: [&str; 0] = [];
+        let diff = TraceDiff::new(&["A(1)", "B"], &empty);
+        assert_eq!(
+            diff.lines,
+            vec![
+                DiffLine::Removed("A(1)".to_string()),
+                DiffLine::Removed("B".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_state_diff_pairs_changed_fields_and_elides_context() {
+        let before = "State {\n    a: 1,\n    b: 2,\n    c: 3,\n    d: 4,\n    e: 5,\n}";
+        let after = "State {\n    a: 1,\n    b: 2,\n    c: 3,\n    d: 4,\n    e: 6,\n}";
+        let diff = StateDiff::new(before, after);
+
+        assert_eq!(
+            diff.to_string(),
+            concat!(
+                "  ...\n",
+                "      c: 3,\n",
+                "      d: 4,\n",
+                "-     e: 5,\n",
+                "+     e: 6,\n",
+                "  }\n",
+            )
+        );
+        assert!(StateDiff::new(before, before).is_empty());
+    }
+
+    #[test]
+    fn test_render_side_by_side() {
+        let diff = TraceDiff::new(&["INC(30)", "RESET", "INC(2)"], &["INC(1)", "INC(2)"]);
+        let rendered = diff.to_string();
+        let expected = concat!(
+            "  Original | Shrunk\n",
+            "~ INC(30)  | INC(1)\n",
+            "- RESET    |\n",
+            "  INC(2)   | INC(2)\n",
+        );
+        assert_eq!(rendered, expected);
+    }
+}
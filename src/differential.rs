@@ -0,0 +1,259 @@
+//! Differential testing of two models of the same state type.
+//!
+//! When a state has two implementations behind it, e.g. an old and a new
+//! engine selected by a field, the same commands should take both to
+//! equal states. [`execute_differential`] runs a sequence on a model and
+//! applies every command the model accepted to a reference as well,
+//! comparing the two after each one; the first divergence panics, naming
+//! the step and diffing the two states. Inside a scenario (see
+//! [`Scenario::differential`](crate::Scenario::differential)), that panic
+//! fails the case and the sequence is shrunk like any other failure.
+//!
+//! # Examples
+//!
+//! ```
+//! use madhouse::differential::execute_differential;
+//! use madhouse::{Command, CommandWrapper, State, TestContext};
+//! use proptest::prelude::*;
+//! use std::panic::AssertUnwindSafe;
+//! use std::sync::Arc;
+//!
+//! #[derive(Debug, Default)]
+//! struct Ledger { balance: u8, saturating: bool }
+//! impl State for Ledger {}
+//!
+//! #[derive(Debug, Clone, Default)]
+//! struct Ctx {}
+//! impl TestContext for Ctx {}
+//!
+//! struct Deposit(u8);
+//! impl Command<Ledger, Ctx> for Deposit {
+//!     fn check(&self, _state: &Ledger) -> bool { true }
+//!     fn apply(&self, state: &mut Ledger) {
+//!         state.balance = if state.saturating {
+//!             state.balance.saturating_add(self.0)
+//!         } else {
+//!             state.balance.wrapping_add(self.0)
+//!         };
+//!     }
+//!     fn label(&self) -> String { format!("DEPOSIT({})", self.0) }
+//!     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Ledger, Ctx>> {
+//!         any::<u8>().prop_map(|n| CommandWrapper::new(Deposit(n)))
+//!     }
+//! }
+//!
+//! let commands = vec![
+//!     CommandWrapper::new(Deposit(100)),
+//!     CommandWrapper::new(Deposit(200)),
+//! ];
+//! let mut old = Ledger::default();
+//! let mut new = Ledger { saturating: true, ..Ledger::default() };
+//! let panic = std::panic::catch_unwind(AssertUnwindSafe(|| {
+//!     execute_differential(&commands, &mut old, &mut new, |a, b| a.balance == b.balance)
+//! }))
+//! .unwrap_err();
+//! let message = panic.downcast_ref::<String>().unwrap();
+//! assert!(message.contains("models diverge after DEPOSIT(200) (- model, + reference)"));
+//! ```
+
+use crate::diff::StateDiff;
+use crate::observer::Observer;
+use crate::sut::Harness;
+use crate::TestContext;
+use crate::{run_commands, CommandWrapper, ExecutionReport, State, StepOutcome, StepReport};
+use std::borrow::BorrowMut;
+use std::marker::PhantomData;
+
+/// Recreates the reference before each scenario case.
+type Init<R> = Box<dyn Fn() -> R>;
+
+/// Observer applying every command the model applied to a reference, and
+/// panicking at the first divergence.
+pub(crate) struct Lockstep<S: State, C: TestContext, R, F> {
+    reference: R,
+    eq: F,
+    init: Option<Init<R>>,
+    command: Option<CommandWrapper<S, C>>,
+    _state: PhantomData<fn(&S)>,
+}
+
+impl<S, C, R, F> Lockstep<S, C, R, F>
+where
+    S: State,
+    C: TestContext,
+    R: BorrowMut<S>,
+    F: Fn(&S, &S) -> bool,
+{
+    pub(crate) fn new(reference: R, eq: F) -> Self {
+        Self {
+            reference,
+            eq,
+            init: None,
+            command: None,
+            _state: PhantomData,
+        }
+    }
+
+    /// Creates an observer whose reference is recreated by `init` when
+    /// reset.
+    pub(crate) fn resetting(init: impl Fn() -> R + 'static, eq: F) -> Self {
+        let reference = init();
+        Self {
+            init: Some(Box::new(init)),
+            ..Self::new(reference, eq)
+        }
+    }
+}
+
+impl<S, C, R, F> Observer<S, C> for Lockstep<S, C, R, F>
+where
+    S: State,
+    C: TestContext,
+    R: BorrowMut<S>,
+    F: Fn(&S, &S) -> bool,
+{
+    fn on_selected(&mut self, _index: usize, command: &CommandWrapper<S, C>) {
+        self.command = Some(command.clone());
+    }
+
+    fn on_skipped(&mut self, step: &StepReport) {
+        let Some(command) = self.command.take() else {
+            return;
+        };
+        if command.command.check(self.reference.borrow()) {
+            panic!(
+                "models diverge at {}: check() rejected it on the model, \
+                 accepted it on the reference",
+                step.label
+            );
+        }
+    }
+
+    fn on_after_apply(&mut self, step: &StepReport, state: &S) {
+        let Some(command) = self.command.take() else {
+            return;
+        };
+        // The step already failed on the model.
+        if !matches!(step.outcome, StepOutcome::Executed) {
+            return;
+        }
+        let reference = self.reference.borrow_mut();
+        if !command.command.check(reference) {
+            panic!(
+                "models diverge at {}: check() accepted it on the model, \
+                 rejected it on the reference",
+                step.label
+            );
+        }
+        if let Err(err) = command.command.try_apply(reference) {
+            panic!("reference failed to apply {}: {}", step.label, err);
+        }
+        if !(self.eq)(state, reference) {
+            panic!(
+                "models diverge after {} (- model, + reference):\n{}",
+                step.label,
+                StateDiff::between(state, &*reference)
+            );
+        }
+    }
+}
+
+impl<S, C, R, F> Harness<S, C> for Lockstep<S, C, R, F>
+where
+    S: State,
+    C: TestContext,
+    R: BorrowMut<S>,
+    F: Fn(&S, &S) -> bool,
+{
+    fn reset(&mut self) {
+        self.command = None;
+        if let Some(init) = &self.init {
+            self.reference = init();
+        }
+    }
+}
+
+/// Executes a sequence of commands on `model` and on `reference`,
+/// comparing the two with `eq` after every command the model applied.
+///
+/// Behaves like [`execute_commands_with`](crate::observer::execute_commands_with)
+/// otherwise.
+///
+/// # Panics
+///
+/// Panics at the first divergence: if the two disagree on a command's
+/// `check()`, if the reference fails to apply a command the model applied,
+/// or if `eq` returns false afterwards.
+pub fn execute_differential<S: State, C: TestContext>(
+    commands: &[CommandWrapper<S, C>],
+    model: &mut S,
+    reference: &mut S,
+    eq: impl Fn(&S, &S) -> bool,
+) -> ExecutionReport {
+    run_commands(commands, model, &[], &mut Lockstep::new(reference, eq))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{panics, Command};
+    use proptest::prelude::{Just, Strategy};
+    use std::sync::Arc;
+
+    #[derive(Debug, Default)]
+    struct Queue {
+        items: Vec<u8>,
+        capacity: Option<usize>,
+    }
+
+    impl State for Queue {}
+
+    #[derive(Debug, Clone, Default)]
+    struct Ctx {}
+
+    impl TestContext for Ctx {}
+
+    struct Push(u8);
+
+    impl Command<Queue, Ctx> for Push {
+        fn check(&self, state: &Queue) -> bool {
+            state
+                .capacity
+                .is_none_or(|capacity| state.items.len() < capacity)
+        }
+
+        fn apply(&self, state: &mut Queue) {
+            state.items.push(self.0);
+        }
+
+        fn label(&self) -> String {
+            format!("PUSH({})", self.0)
+        }
+
+        fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Queue, Ctx>> {
+            Just(CommandWrapper::new(Push(1)))
+        }
+    }
+
+    #[test]
+    fn test_preconditions_must_agree() {
+        let commands: Vec<_> = (1..=3).map(|n| CommandWrapper::new(Push(n))).collect();
+        let eq = |a: &Queue, b: &Queue| a.items == b.items;
+
+        let report =
+            execute_differential(&commands, &mut Queue::default(), &mut Queue::default(), eq);
+        assert_eq!(report.executed().count(), 3);
+
+        let bounded = Queue {
+            capacity: Some(2),
+            ..Queue::default()
+        };
+        let panic = panics::catch(|| {
+            execute_differential(&commands, &mut Queue::default(), &mut { bounded }, eq)
+        })
+        .unwrap_err();
+        assert!(panic.message.starts_with(
+            "models diverge at PUSH(3): check() accepted it on the model, rejected it on the reference"
+        ));
+    }
+}
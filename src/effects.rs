@@ -0,0 +1,224 @@
+//! Effects that only become observable a few steps later.
+//!
+//! In asynchronous systems, and blockchains in particular, a command's
+//! effect often lands later: a submitted transaction shows up blocks after
+//! it was sent. A command declares such an effect with [`expect`], and the
+//! later command (or heartbeat) that observes it calls [`confirm`].
+//! Expectations still pending when the sequence ends are reported in
+//! [`ExecutionReport::unconfirmed`](crate::ExecutionReport::unconfirmed)
+//! and fail the case.
+//!
+//! Expectations are tracked per execution, on the executing thread; outside
+//! an execution, [`expect`] does nothing and [`confirm`] returns false.
+//!
+//! # Examples
+//!
+//! ```
+//! use madhouse::effects;
+//! use madhouse::{execute_commands, Command, CommandWrapper, State, TestContext};
+//! use proptest::prelude::*;
+//! use std::sync::Arc;
+//!
+//! #[derive(Debug, Default)]
+//! struct Chain { mempool: Vec<u64>, height: u64 }
+//! impl State for Chain {}
+//!
+//! #[derive(Debug, Clone, Default)]
+//! struct Ctx {}
+//! impl TestContext for Ctx {}
+//!
+//! struct Submit(u64);
+//! impl Command<Chain, Ctx> for Submit {
+//!     fn check(&self, _state: &Chain) -> bool { true }
+//!     fn apply(&self, state: &mut Chain) {
+//!         state.mempool.push(self.0);
+//!         effects::expect(format!("tx {} mined", self.0));
+//!     }
+//!     fn label(&self) -> String { format!("SUBMIT({})", self.0) }
+//!     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Chain, Ctx>> {
+//!         any::<u64>().prop_map(|tx| CommandWrapper::new(Submit(tx)))
+//!     }
+//! }
+//!
+//! struct Mine;
+//! impl Command<Chain, Ctx> for Mine {
+//!     fn check(&self, _state: &Chain) -> bool { true }
+//!     fn apply(&self, state: &mut Chain) {
+//!         state.height += 1;
+//!         for tx in state.mempool.drain(..) {
+//!             assert!(effects::confirm(&format!("tx {} mined", tx)));
+//!         }
+//!     }
+//!     fn label(&self) -> String { "MINE".to_string() }
+//!     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Chain, Ctx>> {
+//!         Just(CommandWrapper::new(Mine))
+//!     }
+//! }
+//!
+//! let commands = vec![
+//!     CommandWrapper::new(Submit(1)),
+//!     CommandWrapper::new(Mine),
+//!     CommandWrapper::new(Submit(2)),
+//! ];
+//! let report = execute_commands(&commands, &mut Chain::default());
+//!
+//! let unconfirmed = &report.unconfirmed[0];
+//! assert_eq!((unconfirmed.key.as_str(), unconfirmed.index), ("tx 2 mined", 2));
+//! assert_eq!(
+//!     report.failure_message().unwrap(),
+//!     "expected effect never confirmed: tx 2 mined (expected by command 3 (SUBMIT(2)))"
+//! );
+//! ```
+
+use std::cell::RefCell;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+/// An effect declared by a command and not yet confirmed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expectation {
+    /// What is expected, as passed to [`expect`].
+    pub key: String,
+    /// Index of the command that declared it.
+    pub index: usize,
+    /// Label of the command that declared it.
+    pub label: String,
+}
+
+impl Display for Expectation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "{} (expected by command {} ({}))",
+            self.key,
+            self.index + 1,
+            self.label
+        )
+    }
+}
+
+#[derive(Default)]
+struct Pending {
+    step: Option<(usize, String)>,
+    expectations: Vec<Expectation>,
+}
+
+thread_local! {
+    static PENDING: RefCell<Option<Pending>> = const { RefCell::new(None) };
+}
+
+/// Declares that the executing command's effect must be confirmed later in
+/// the sequence.
+///
+/// The same key may be pending several times; each [`confirm`] settles
+/// one.
+pub fn expect(key: impl Into<String>) {
+    PENDING.with(|p| {
+        if let Some(pending) = p.borrow_mut().as_mut() {
+            let (index, label) = pending.step.clone().unwrap_or_default();
+            pending.expectations.push(Expectation {
+                key: key.into(),
+                index,
+                label,
+            });
+        }
+    });
+}
+
+/// Confirms the earliest pending expectation for `key`, returning false if
+/// none is pending.
+pub fn confirm(key: &str) -> bool {
+    PENDING.with(|p| {
+        let mut p = p.borrow_mut();
+        let Some(pending) = p.as_mut() else {
+            return false;
+        };
+        match pending.expectations.iter().position(|e| e.key == key) {
+            Some(position) => {
+                pending.expectations.remove(position);
+                true
+            }
+            None => false,
+        }
+    })
+}
+
+/// Returns true if an expectation for `key` is pending.
+pub fn is_pending(key: &str) -> bool {
+    PENDING.with(|p| {
+        p.borrow()
+            .as_ref()
+            .is_some_and(|pending| pending.expectations.iter().any(|e| e.key == key))
+    })
+}
+
+/// Marks the step now executing on this thread, for the expectations it
+/// declares.
+pub(crate) fn set_step(index: usize, label: &str) {
+    PENDING.with(|p| {
+        if let Some(pending) = p.borrow_mut().as_mut() {
+            pending.step = Some((index, label.to_string()));
+        }
+    });
+}
+
+/// Tracks the expectations of one execution until finished or dropped,
+/// then restores those of an enclosing execution.
+pub(crate) struct Scope {
+    outer: Option<Pending>,
+}
+
+impl Scope {
+    pub(crate) fn enter() -> Self {
+        let outer = PENDING.with(|p| p.borrow_mut().replace(Pending::default()));
+        Self { outer }
+    }
+
+    /// Ends the execution, returning the expectations still pending.
+    pub(crate) fn finish(self) -> Vec<Expectation> {
+        PENDING.with(|p| {
+            p.borrow_mut()
+                .as_mut()
+                .map(|pending| std::mem::take(&mut pending.expectations))
+                .unwrap_or_default()
+        })
+    }
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        let outer = self.outer.take();
+        PENDING.with(|p| *p.borrow_mut() = outer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nested_scopes_track_their_own_expectations() {
+        expect("ignored outside a scope");
+        assert!(!is_pending("ignored outside a scope"));
+
+        let outer = Scope::enter();
+        set_step(0, "SUBMIT");
+        expect("mined");
+        expect("mined");
+        {
+            let inner = Scope::enter();
+            assert!(!confirm("mined"));
+            expect("inner");
+            assert_eq!(inner.finish().len(), 1);
+        }
+        assert!(confirm("mined"));
+        assert!(is_pending("mined"));
+
+        let unconfirmed = outer.finish();
+        assert_eq!(unconfirmed.len(), 1);
+        assert_eq!(
+            unconfirmed[0].to_string(),
+            "mined (expected by command 1 (SUBMIT))"
+        );
+        assert!(!is_pending("mined"));
+    }
+}
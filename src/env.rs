@@ -0,0 +1,193 @@
+//! Reproducible randomness for commands.
+//!
+//! A command drawing from `thread_rng` in `apply` behaves differently on
+//! every run, so a failing sequence may not fail again when replayed.
+//! Commands needing randomness (a random payload, a random peer to gossip
+//! to) draw it from the [`Env`] handed to
+//! [`Command::apply_with`](crate::Command::apply_with) instead.
+//!
+//! Each step's RNG is seeded from the case's seed, derived from the run's
+//! seed and the case number, chained with the index and label of every
+//! command selected up to and including it. Two cases drawing the same
+//! commands still get different values, while rerunning the seed, pinning
+//! the case, or replaying the sequence from a regression file (which
+//! records the case's seed) hands every step the same values. Executions
+//! outside a scenario start from seed zero.
+
+use crate::gen;
+use proptest::test_runner::TestRng;
+use std::cell::Cell;
+
+thread_local! {
+    /// Seed of the step executing on this thread, if an execution runs.
+    static SEED: Cell<Option<u64>> = const { Cell::new(None) };
+    /// Seed of the case executing on this thread, if a scenario runs one.
+    static CASE: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
+/// What the executor hands a command along with the state.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::{execute_commands, Command, CommandWrapper, Env, State, TestContext};
+/// use proptest::prelude::*;
+/// use std::sync::Arc;
+///
+/// #[derive(Debug, Default)]
+/// struct Mempool { txs: Vec<u64> }
+/// impl State for Mempool {}
+///
+/// #[derive(Debug, Clone, Default)]
+/// struct Ctx {}
+/// impl TestContext for Ctx {}
+///
+/// struct SubmitRandom;
+/// impl Command<Mempool, Ctx> for SubmitRandom {
+///     fn check(&self, _state: &Mempool) -> bool { true }
+///     fn apply(&self, state: &mut Mempool) { self.apply_with(state, &mut Env::current()) }
+///     fn apply_with(&self, state: &mut Mempool, env: &mut Env) {
+///         state.txs.push(env.rng().gen());
+///     }
+///     fn label(&self) -> String { "SUBMIT_RANDOM".to_string() }
+///     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Mempool, Ctx>> {
+///         Just(CommandWrapper::new(SubmitRandom))
+///     }
+/// }
+///
+/// let commands = vec![CommandWrapper::new(SubmitRandom); 2];
+/// let (mut first, mut second) = (Mempool::default(), Mempool::default());
+/// execute_commands(&commands, &mut first);
+/// execute_commands(&commands, &mut second);
+///
+/// assert_eq!(first.txs, second.txs);
+/// assert_ne!(first.txs[0], first.txs[1]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Env {
+    seed: u64,
+    rng: TestRng,
+}
+
+impl Env {
+    /// Creates an environment whose RNG is seeded with `seed`.
+    pub fn from_seed(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: gen::rng(seed),
+        }
+    }
+
+    /// Returns the environment of the step executing on this thread.
+    ///
+    /// Every call during a step starts the same RNG stream. Outside an
+    /// execution, the seed is zero.
+    pub fn current() -> Self {
+        Self::from_seed(SEED.with(Cell::get).unwrap_or_default())
+    }
+
+    /// Returns the seed of the RNG.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Returns the RNG, seeded for this step.
+    pub fn rng(&mut self) -> &mut TestRng {
+        &mut self.rng
+    }
+}
+
+/// Marks step `index` (`label`) as executing on this thread, chaining both
+/// into the seed.
+pub(crate) fn set_step(index: usize, label: &str) {
+    SEED.with(|seed| {
+        if let Some(previous) = seed.get() {
+            seed.set(Some(gen::stable_hash(&(previous, index as u64, label))));
+        }
+    });
+}
+
+/// Returns the seed of the step executing on this thread, for a worker
+/// thread applying the step to [`adopt`].
+pub(crate) fn step_seed() -> Option<u64> {
+    SEED.with(Cell::get)
+}
+
+/// Marks this thread as executing the step seeded with `seed`, taken from
+/// [`step_seed`] on the thread the step runs on.
+pub(crate) fn adopt(seed: Option<u64>) {
+    SEED.with(|current| current.set(seed));
+}
+
+/// Runs `f`, a case of a scenario, with executions starting from `seed`.
+pub(crate) fn with_case_seed<T>(seed: u64, f: impl FnOnce() -> T) -> T {
+    struct Restore(Option<u64>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            CASE.with(|case| case.set(self.0));
+        }
+    }
+    let _restore = Restore(CASE.with(|case| case.replace(Some(seed))));
+    f()
+}
+
+/// Seeds the steps of one execution, restoring the seed of an enclosing
+/// execution when dropped.
+pub(crate) struct Scope {
+    outer: Option<u64>,
+}
+
+impl Scope {
+    pub(crate) fn enter() -> Self {
+        let start = CASE.with(Cell::get).unwrap_or_default();
+        let outer = SEED.with(|seed| seed.replace(Some(start)));
+        Self { outer }
+    }
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        SEED.with(|seed| seed.set(self.outer));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::RngCore;
+
+    fn draws(labels: &[&str]) -> Vec<u64> {
+        let _scope = Scope::enter();
+        labels
+            .iter()
+            .enumerate()
+            .map(|(index, label)| {
+                set_step(index, label);
+                Env::current().rng().next_u64()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_step_seeds_depend_on_the_labels_so_far() {
+        let first = draws(&["A", "B", "A"]);
+        assert_eq!(first, draws(&["A", "B", "A"]));
+        assert_ne!(first[0], first[2]);
+        assert_eq!(draws(&["A", "C"])[0], first[0]);
+        assert_ne!(draws(&["A", "C"])[1], first[1]);
+        assert_eq!(Env::current().seed(), 0);
+    }
+
+    #[test]
+    fn test_case_seeds_change_the_draws_of_a_trace() {
+        let trace = ["A", "A"];
+        let case = |seed| with_case_seed(seed, || draws(&trace));
+
+        assert_eq!(case(1), case(1));
+        assert_ne!(case(1), case(2));
+        assert_ne!(case(1)[0], case(2)[0]);
+        assert_eq!(draws(&trace), case(0));
+        // The index tells repeated labels apart.
+        assert_ne!(case(1)[0], case(1)[1]);
+    }
+}
@@ -0,0 +1,364 @@
+//! Exhaustive exploration of the reachable states of a pure model.
+//!
+//! Random and exhaustive sequence generation run the same states over and
+//! over. For a model whose state is `Hash + Eq + Clone` (no handles to a
+//! real system), [`Explorer`] instead walks the graph of reachable states
+//! from the initial one, visiting each state once: every command whose
+//! `check` accepts a state is applied to a copy of it, and states seen
+//! before are not expanded again. Breadth-first search reports the
+//! shortest path to the first invariant violation, failed command or
+//! panic, which makes madhouse a lightweight model checker.
+//!
+//! # Examples
+//!
+//! ```
+//! use madhouse::explore::Explorer;
+//! use madhouse::{Command, CommandWrapper, InvariantViolation, State, TestContext};
+//! use proptest::prelude::*;
+//! use std::sync::Arc;
+//!
+//! #[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+//! struct Jugs { small: u8, big: u8 }
+//! impl State for Jugs {
+//!     fn invariants(&self) -> Result<(), InvariantViolation> {
+//!         match self.big {
+//!             4 => Err(InvariantViolation::new("4 gallons measured")),
+//!             _ => Ok(()),
+//!         }
+//!     }
+//! }
+//!
+//! #[derive(Debug, Clone, Default)]
+//! struct Ctx {}
+//! impl TestContext for Ctx {}
+//!
+//! #[derive(Debug, Clone, Copy)]
+//! enum Jug { FillSmall, FillBig, EmptySmall, EmptyBig, SmallToBig, BigToSmall }
+//! impl Command<Jugs, Ctx> for Jug {
+//!     fn check(&self, _state: &Jugs) -> bool { true }
+//!     fn apply(&self, s: &mut Jugs) {
+//!         match self {
+//!             Jug::FillSmall => s.small = 3,
+//!             Jug::FillBig => s.big = 5,
+//!             Jug::EmptySmall => s.small = 0,
+//!             Jug::EmptyBig => s.big = 0,
+//!             Jug::SmallToBig => {
+//!                 let poured = s.small.min(5 - s.big);
+//!                 s.small -= poured;
+//!                 s.big += poured;
+//!             }
+//!             Jug::BigToSmall => {
+//!                 let poured = s.big.min(3 - s.small);
+//!                 s.big -= poured;
+//!                 s.small += poured;
+//!             }
+//!         }
+//!     }
+//!     fn label(&self) -> String { format!("{:?}", self) }
+//!     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Jugs, Ctx>> {
+//!         Just(CommandWrapper::new(Jug::FillBig))
+//!     }
+//! }
+//!
+//! use Jug::*;
+//! let commands = [FillSmall, FillBig, EmptySmall, EmptyBig, SmallToBig, BigToSmall]
+//!     .map(CommandWrapper::new);
+//! let explored = Explorer::new(commands).explore();
+//!
+//! let violation = explored.violation.unwrap();
+//! assert_eq!(
+//!     violation.path,
+//!     ["FillBig", "BigToSmall", "EmptySmall", "BigToSmall", "FillBig", "BigToSmall"]
+//! );
+//! assert_eq!(violation.message, "invariant violated: 4 gallons measured");
+//! ```
+
+use crate::{panics, CommandWrapper, State, TestContext};
+use std::collections::{HashSet, VecDeque};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::hash::Hash;
+
+/// A visited state, with the node and command it was reached by, and its
+/// depth.
+type Node<S> = (S, Option<(usize, usize)>, usize);
+
+/// Order in which states are expanded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Search {
+    /// Nearest states first; violations are found by a shortest path.
+    #[default]
+    BreadthFirst,
+    /// Deepest states first; finds long paths sooner, with less memory in
+    /// the frontier.
+    DepthFirst,
+}
+
+/// Walks the reachable states of a model, applying every enabled command to
+/// every state once.
+pub struct Explorer<S: State, C: TestContext> {
+    commands: Vec<CommandWrapper<S, C>>,
+    initial: Option<S>,
+    search: Search,
+    max_depth: Option<usize>,
+    max_states: Option<usize>,
+}
+
+/// A path from the initial state to a failure.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// Labels of the commands leading to the failure, the failing one last;
+    /// empty if the initial state breaks an invariant.
+    pub path: Vec<String>,
+    /// What went wrong.
+    pub message: String,
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}", self.message)?;
+        for (i, label) in self.path.iter().enumerate() {
+            write!(f, "\n  {}. {}", i + 1, label)?;
+        }
+        Ok(())
+    }
+}
+
+/// What an exploration found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateSpace {
+    /// Distinct states visited, the initial one included.
+    pub states: usize,
+    /// Commands applied, including those reaching a state seen before.
+    pub transitions: usize,
+    /// Length of the longest path explored.
+    pub depth: usize,
+    /// Whether every reachable state was visited, rather than stopping at a
+    /// limit or a violation.
+    pub complete: bool,
+    /// The first failure found.
+    pub violation: Option<Violation>,
+}
+
+impl Display for StateSpace {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "{} states, {} transitions, depth {}{}",
+            self.states,
+            self.transitions,
+            self.depth,
+            if self.complete { "" } else { " (incomplete)" }
+        )?;
+        if let Some(violation) = &self.violation {
+            write!(f, "\nViolation: {}", violation)?;
+        }
+        Ok(())
+    }
+}
+
+impl<S, C> Explorer<S, C>
+where
+    S: State + Default + Clone + Hash + Eq,
+    C: TestContext,
+{
+    /// Creates an explorer applying `commands`, from `S::default()`.
+    pub fn new(commands: impl IntoIterator<Item = CommandWrapper<S, C>>) -> Self {
+        Self {
+            commands: commands.into_iter().collect(),
+            initial: None,
+            search: Search::default(),
+            max_depth: None,
+            max_states: None,
+        }
+    }
+
+    /// Starts from `state` instead of `S::default()`.
+    pub fn initial(mut self, state: S) -> Self {
+        self.initial = Some(state);
+        self
+    }
+
+    /// Sets the search order (default breadth-first).
+    pub fn search(mut self, search: Search) -> Self {
+        self.search = search;
+        self
+    }
+
+    /// Does not expand states more than `depth` commands away from the
+    /// initial state.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Stops once `states` distinct states were visited.
+    pub fn max_states(mut self, states: usize) -> Self {
+        self.max_states = Some(states);
+        self
+    }
+
+    /// Explores the state space until every reachable state was visited, a
+    /// limit was hit, or a failure was found.
+    pub fn explore(self) -> StateSpace {
+        let initial = self.initial.clone().unwrap_or_default();
+        let mut space = StateSpace {
+            states: 1,
+            transitions: 0,
+            depth: 0,
+            complete: false,
+            violation: None,
+        };
+        if let Err(violation) = initial.invariants() {
+            space.violation = Some(Violation {
+                path: Vec::new(),
+                message: format!("invariant violated: {}", violation),
+            });
+            return space;
+        }
+
+        let mut nodes: Vec<Node<S>> = vec![(initial.clone(), None, 0)];
+        let mut seen: HashSet<S> = HashSet::from([initial]);
+        let mut frontier = VecDeque::from([0]);
+        let mut limited = false;
+        while let Some(node) = match self.search {
+            Search::BreadthFirst => frontier.pop_front(),
+            Search::DepthFirst => frontier.pop_back(),
+        } {
+            let depth = nodes[node].2;
+            if self.max_depth.is_some_and(|max| depth >= max) {
+                limited = true;
+                continue;
+            }
+            for (index, command) in self.commands.iter().enumerate() {
+                let mut state = nodes[node].0.clone();
+                if !command.command.check(&state) {
+                    continue;
+                }
+                space.transitions += 1;
+                space.depth = space.depth.max(depth + 1);
+                let failure = match panics::catch(|| command.command.try_apply(&mut state)) {
+                    Err(panic) => Some(format!("panicked: {}", panic.message)),
+                    Ok(Err(err)) => Some(format!("command failed: {}", err)),
+                    Ok(Ok(())) => state
+                        .invariants()
+                        .err()
+                        .map(|violation| format!("invariant violated: {}", violation)),
+                };
+                if let Some(message) = failure {
+                    let mut path = path(&nodes, node, &self.commands);
+                    path.push(command.command.label());
+                    space.violation = Some(Violation { path, message });
+                    return space;
+                }
+                if seen.contains(&state) {
+                    continue;
+                }
+                if self.max_states.is_some_and(|max| nodes.len() >= max) {
+                    return space;
+                }
+                seen.insert(state.clone());
+                nodes.push((state, Some((node, index)), depth + 1));
+                frontier.push_back(nodes.len() - 1);
+                space.states = nodes.len();
+            }
+        }
+        space.complete = !limited;
+        space
+    }
+}
+
+/// Returns the labels of the commands leading to `node`.
+fn path<S: State, C: TestContext>(
+    nodes: &[Node<S>],
+    mut node: usize,
+    commands: &[CommandWrapper<S, C>],
+) -> Vec<String> {
+    let mut labels = Vec::new();
+    while let Some((parent, command)) = nodes[node].1 {
+        labels.push(commands[command].command.label());
+        node = parent;
+    }
+    labels.reverse();
+    labels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Command, InvariantViolation};
+    use proptest::prelude::*;
+    use std::sync::Arc;
+
+    #[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+    struct Dial {
+        value: u8,
+    }
+
+    impl State for Dial {
+        fn invariants(&self) -> Result<(), InvariantViolation> {
+            match self.value {
+                7 => Err(InvariantViolation::new("seven")),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Default)]
+    struct Ctx {}
+
+    impl TestContext for Ctx {}
+
+    /// Adds to the dial, modulo 10.
+    struct Add(u8);
+
+    impl Command<Dial, Ctx> for Add {
+        fn check(&self, _state: &Dial) -> bool {
+            true
+        }
+
+        fn apply(&self, state: &mut Dial) {
+            state.value = (state.value + self.0) % 10;
+        }
+
+        fn label(&self) -> String {
+            format!("ADD({})", self.0)
+        }
+
+        fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Dial, Ctx>> {
+            Just(CommandWrapper::new(Add(1)))
+        }
+    }
+
+    #[test]
+    fn test_breadth_first_finds_the_shortest_path() {
+        let commands = || [Add(1), Add(3)].map(CommandWrapper::new);
+
+        let explored = Explorer::new(commands()).explore();
+        let violation = explored.violation.unwrap();
+        assert_eq!(violation.path, ["ADD(1)", "ADD(3)", "ADD(3)"]);
+        assert_eq!(violation.message, "invariant violated: seven");
+        assert!(!explored.complete);
+
+        // Depth-first expands the latest state reached first.
+        let explored = Explorer::new(commands())
+            .search(Search::DepthFirst)
+            .explore();
+        assert_eq!(
+            explored.violation.unwrap().path,
+            ["ADD(3)", "ADD(3)", "ADD(1)"]
+        );
+
+        let explored = Explorer::new([Add(2)].map(CommandWrapper::new))
+            .initial(Dial { value: 1 })
+            .explore();
+        assert_eq!(explored.violation.unwrap().path.len(), 3);
+
+        // Even values only: each is visited once.
+        let explored = Explorer::new([Add(2)].map(CommandWrapper::new)).explore();
+        assert_eq!(explored.to_string(), "5 states, 5 transitions, depth 5");
+        assert!(explored.complete);
+
+        let explored = Explorer::new(commands()).max_depth(1).explore();
+        assert_eq!((explored.states, explored.complete), (3, false));
+    }
+}
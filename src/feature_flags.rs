@@ -0,0 +1,276 @@
+//! Toggling SUT feature flags mid-run.
+//!
+//! [`ToggleFeature`] flips one of the flags listed by the context's
+//! [`FeatureCapabilities`] on the system under test, and mirrors the new
+//! value into the model's [`FeatureFlags`] so other commands' preconditions
+//! can react to it.
+//!
+//! # Examples
+//!
+//! ```
+//! use madhouse::feature_flags::{
+//!     FeatureCapabilities, FeatureFlagState, FeatureFlags, ToggleFeature,
+//! };
+//! use madhouse::{scenario, Command, CommandError, CommandWrapper, State, TestContext};
+//! use proptest::prelude::*;
+//! use std::sync::Arc;
+//!
+//! #[derive(Debug, Default)]
+//! struct Node {
+//!     flags: FeatureFlags,
+//!     fast_syncs: u64,
+//! }
+//! impl State for Node {}
+//! impl FeatureFlagState for Node {
+//!     fn feature_flags(&self) -> &FeatureFlags { &self.flags }
+//!     fn feature_flags_mut(&mut self) -> &mut FeatureFlags { &mut self.flags }
+//! }
+//!
+//! #[derive(Debug, Clone, Default)]
+//! struct Ctx {}
+//! impl TestContext for Ctx {}
+//! impl FeatureCapabilities for Ctx {
+//!     fn features(&self) -> Vec<String> {
+//!         vec!["fast_sync".to_string()]
+//!     }
+//!     fn set_feature(&self, _name: &str, _enabled: bool) -> Result<(), CommandError> {
+//!         // e.g. POST /admin/features/{name}
+//!         Ok(())
+//!     }
+//! }
+//!
+//! struct FastSync;
+//! impl Command<Node, Ctx> for FastSync {
+//!     fn check(&self, state: &Node) -> bool {
+//!         state.flags.is_enabled("fast_sync")
+//!     }
+//!     fn apply(&self, state: &mut Node) { state.fast_syncs += 1; }
+//!     fn label(&self) -> String { "FAST_SYNC".to_string() }
+//!     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Node, Ctx>> {
+//!         Just(CommandWrapper::new(FastSync))
+//!     }
+//! }
+//!
+//! let ctx = Arc::new(Ctx::default());
+//! scenario![ctx, ToggleFeature: 1, FastSync];
+//! ```
+
+use crate::gen::pick;
+use crate::{Command, CommandError, CommandWrapper, State, TestContext};
+use proptest::prelude::{any, Strategy};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Model-side record of SUT feature flags.
+///
+/// Flags that were never set are reported as disabled.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct FeatureFlags {
+    flags: BTreeMap<String, bool>,
+}
+
+impl FeatureFlags {
+    /// Returns true if the flag is currently enabled.
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.flags.get(name).copied().unwrap_or(false)
+    }
+
+    /// Records the value of a flag.
+    pub fn set(&mut self, name: impl Into<String>, enabled: bool) {
+        self.flags.insert(name.into(), enabled);
+    }
+
+    /// Iterates over all recorded flags, in name order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, bool)> {
+        self.flags
+            .iter()
+            .map(|(name, enabled)| (name.as_str(), *enabled))
+    }
+}
+
+/// State that tracks the SUT's feature flags.
+pub trait FeatureFlagState: State {
+    /// Returns the recorded flags.
+    fn feature_flags(&self) -> &FeatureFlags;
+
+    /// Returns the recorded flags for modification.
+    fn feature_flags_mut(&mut self) -> &mut FeatureFlags;
+}
+
+/// Context describing which SUT feature flags can be toggled, and how.
+pub trait FeatureCapabilities: TestContext {
+    /// Names of the flags [`ToggleFeature`] may flip.
+    fn features(&self) -> Vec<String>;
+
+    /// Sets a flag on the system under test.
+    fn set_feature(&self, name: &str, enabled: bool) -> Result<(), CommandError>;
+}
+
+/// Command that enables or disables a named SUT feature flag.
+///
+/// Only applies when it would change the flag, so generated sequences do
+/// not waste steps on no-op toggles.
+pub struct ToggleFeature<C> {
+    ctx: Arc<C>,
+    /// Name of the flag.
+    pub name: String,
+    /// Value to set.
+    pub enabled: bool,
+}
+
+impl<C> ToggleFeature<C> {
+    /// Creates a toggle for a specific flag.
+    pub fn new(ctx: Arc<C>, name: impl Into<String>, enabled: bool) -> Self {
+        Self {
+            ctx,
+            name: name.into(),
+            enabled,
+        }
+    }
+}
+
+impl<S, C> Command<S, C> for ToggleFeature<C>
+where
+    S: FeatureFlagState,
+    C: FeatureCapabilities + 'static,
+{
+    fn check(&self, state: &S) -> bool {
+        state.feature_flags().is_enabled(&self.name) != self.enabled
+    }
+
+    fn apply(&self, state: &mut S) {
+        if let Err(err) = self.try_apply(state) {
+            panic!("{}: {}", Command::<S, C>::label(self), err);
+        }
+    }
+
+    fn try_apply(&self, state: &mut S) -> Result<(), CommandError> {
+        self.ctx.set_feature(&self.name, self.enabled)?;
+        state
+            .feature_flags_mut()
+            .set(self.name.clone(), self.enabled);
+        Ok(())
+    }
+
+    fn label(&self) -> String {
+        let value = if self.enabled { "on" } else { "off" };
+        format!("TOGGLE_FEATURE({}={})", self.name, value)
+    }
+
+    /// Picks one of the context's features and a target value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the context lists no features.
+    fn build(ctx: Arc<C>) -> impl Strategy<Value = CommandWrapper<S, C>> {
+        (pick(ctx.features()), any::<bool>()).prop_map(move |(name, enabled)| {
+            CommandWrapper::new(ToggleFeature::new(ctx.clone(), name, enabled))
+        })
+    }
+
+    fn parse(label: &str, ctx: Arc<C>) -> Option<CommandWrapper<S, C>> {
+        let inner = label.strip_prefix("TOGGLE_FEATURE(")?.strip_suffix(')')?;
+        let (name, value) = inner.rsplit_once('=')?;
+        let enabled = match value {
+            "on" => true,
+            "off" => false,
+            _ => return None,
+        };
+        Some(CommandWrapper::new(ToggleFeature::new(ctx, name, enabled)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execute_commands;
+    use std::sync::Mutex;
+
+    #[derive(Debug, Default)]
+    struct Node {
+        flags: FeatureFlags,
+    }
+
+    impl State for Node {}
+
+    impl FeatureFlagState for Node {
+        fn feature_flags(&self) -> &FeatureFlags {
+            &self.flags
+        }
+
+        fn feature_flags_mut(&mut self) -> &mut FeatureFlags {
+            &mut self.flags
+        }
+    }
+
+    #[derive(Debug, Clone, Default)]
+    struct Ctx {
+        sut: Arc<Mutex<Vec<(String, bool)>>>,
+    }
+
+    impl TestContext for Ctx {}
+
+    impl FeatureCapabilities for Ctx {
+        fn features(&self) -> Vec<String> {
+            vec!["a".to_string(), "b".to_string()]
+        }
+
+        fn set_feature(&self, name: &str, enabled: bool) -> Result<(), CommandError> {
+            if name == "broken" {
+                return Err(CommandError::new("unknown feature"));
+            }
+            self.sut.lock().unwrap().push((name.to_string(), enabled));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_toggle_updates_sut_and_model() {
+        let ctx = Arc::new(Ctx::default());
+        let commands: Vec<CommandWrapper<Node, Ctx>> = vec![
+            CommandWrapper::new(ToggleFeature::new(ctx.clone(), "a", true)),
+            CommandWrapper::new(ToggleFeature::new(ctx.clone(), "a", true)),
+            CommandWrapper::new(ToggleFeature::new(ctx.clone(), "b", false)),
+            CommandWrapper::new(ToggleFeature::new(ctx.clone(), "a", false)),
+        ];
+        let mut state = Node::default();
+
+        let report = execute_commands(&commands, &mut state);
+
+        // Redundant toggles are skipped.
+        assert_eq!(
+            report.skipped().map(|s| s.index).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert_eq!(
+            *ctx.sut.lock().unwrap(),
+            vec![("a".to_string(), true), ("a".to_string(), false)]
+        );
+        assert!(!state.flags.is_enabled("a"));
+        assert_eq!(report.steps[0].label, "TOGGLE_FEATURE(a=on)");
+    }
+
+    #[test]
+    fn test_failed_toggle_leaves_model_unchanged() {
+        let ctx = Arc::new(Ctx::default());
+        let commands: Vec<CommandWrapper<Node, Ctx>> =
+            vec![CommandWrapper::new(ToggleFeature::new(ctx, "broken", true))];
+        let mut state = Node::default();
+
+        let report = execute_commands(&commands, &mut state);
+
+        assert!(!report.is_ok());
+        assert!(!state.flags.is_enabled("broken"));
+    }
+
+    #[test]
+    fn test_parse_round_trips_label() {
+        let ctx = Arc::new(Ctx::default());
+        let parsed: CommandWrapper<Node, Ctx> =
+            ToggleFeature::parse("TOGGLE_FEATURE(a=off)", ctx.clone()).unwrap();
+        assert_eq!(parsed.command.label(), "TOGGLE_FEATURE(a=off)");
+        assert!(
+            <ToggleFeature<Ctx> as Command<Node, Ctx>>::parse("TOGGLE_FEATURE(a)", ctx).is_none()
+        );
+    }
+}
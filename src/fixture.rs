@@ -0,0 +1,236 @@
+//! Expensive resources shared by the commands of a scenario.
+//!
+//! Some commands need a resource that is slow to create, like a funded
+//! wallet or a running node. Creating it in `setup` costs every case even
+//! when no command uses it, and a `OnceCell` static leaks it into every
+//! later test. A [`Fixture`] is created on first use by any command, shared
+//! by all later ones, and torn down when the [`Scenario`](crate::Scenario)
+//! using it ends, whether it passed or failed. Keep fixtures in the test
+//! context and hand them to commands from [`Command::build`](crate::Command::build).
+//!
+//! Fixtures are torn down by the scenario running on the thread that first
+//! used them; outside a scenario, they live until
+//! [`reset`](Fixture::reset) or until the last handle is dropped.
+//!
+//! # Examples
+//!
+//! ```
+//! use madhouse::fixture::Fixture;
+//! use madhouse::{scenario, Command, CommandWrapper, State, TestContext};
+//! use proptest::prelude::*;
+//! use std::sync::atomic::{AtomicUsize, Ordering};
+//! use std::sync::Arc;
+//!
+//! static WALLETS: AtomicUsize = AtomicUsize::new(0);
+//!
+//! #[derive(Debug)]
+//! struct Wallet { balance: u64 }
+//!
+//! #[derive(Debug, Default)]
+//! struct Ledger { spent: u64 }
+//! impl State for Ledger {}
+//!
+//! #[derive(Debug, Clone)]
+//! struct Ctx { wallet: Fixture<Wallet> }
+//! impl TestContext for Ctx {}
+//!
+//! struct Spend(Fixture<Wallet>);
+//! impl Command<Ledger, Ctx> for Spend {
+//!     fn check(&self, state: &Ledger) -> bool { state.spent < self.0.get().balance }
+//!     fn apply(&self, state: &mut Ledger) { state.spent += 1; }
+//!     fn label(&self) -> String { "SPEND".to_string() }
+//!     fn build(ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Ledger, Ctx>> {
+//!         Just(CommandWrapper::new(Spend(ctx.wallet.clone())))
+//!     }
+//! }
+//!
+//! let wallet = Fixture::new(|| {
+//!     WALLETS.fetch_add(1, Ordering::SeqCst);
+//!     Wallet { balance: 100 }
+//! })
+//! .teardown(|_wallet| {
+//!     WALLETS.fetch_sub(1, Ordering::SeqCst);
+//! });
+//! let ctx = Arc::new(Ctx { wallet: wallet.clone() });
+//! scenario![ctx, Spend];
+//!
+//! assert!(!wallet.is_initialized());
+//! assert_eq!(WALLETS.load(Ordering::SeqCst), 0);
+//! ```
+
+use std::cell::RefCell;
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+type Init<T> = Box<dyn Fn() -> T + Send + Sync>;
+type Teardown<T> = Box<dyn Fn(&T) + Send + Sync>;
+/// Tears down one created fixture.
+type Release = Box<dyn FnOnce()>;
+
+/// A lazily created resource, shared by the clones of this handle.
+pub struct Fixture<T> {
+    inner: Arc<Inner<T>>,
+}
+
+struct Inner<T> {
+    value: Mutex<Option<Arc<T>>>,
+    init: Init<T>,
+    teardown: Option<Teardown<T>>,
+}
+
+thread_local! {
+    /// Teardowns of the fixtures created while a scenario runs, if one does.
+    static CREATED: RefCell<Option<Vec<Release>>> = const { RefCell::new(None) };
+}
+
+impl<T: 'static> Fixture<T> {
+    /// Creates a fixture built by `init` on first use.
+    pub fn new(init: impl Fn() -> T + Send + Sync + 'static) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                value: Mutex::new(None),
+                init: Box::new(init),
+                teardown: None,
+            }),
+        }
+    }
+
+    /// Sets the function releasing the resource when the scenario ends.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the fixture was already cloned.
+    pub fn teardown(mut self, teardown: impl Fn(&T) + Send + Sync + 'static) -> Self {
+        Arc::get_mut(&mut self.inner)
+            .expect("set the teardown before cloning the fixture")
+            .teardown = Some(Box::new(teardown));
+        self
+    }
+
+    /// Returns the resource, creating it first if needed.
+    pub fn get(&self) -> Arc<T> {
+        let mut value = self.lock();
+        if let Some(value) = value.as_ref() {
+            return Arc::clone(value);
+        }
+        let created = Arc::new((self.inner.init)());
+        *value = Some(Arc::clone(&created));
+        drop(value);
+        let fixture = self.clone();
+        CREATED.with(|c| {
+            if let Some(created) = c.borrow_mut().as_mut() {
+                created.push(Box::new(move || fixture.reset()));
+            }
+        });
+        created
+    }
+
+    /// Returns true if the resource was created and not torn down since.
+    pub fn is_initialized(&self) -> bool {
+        self.lock().is_some()
+    }
+
+    /// Tears the resource down, if created; the next [`get`](Self::get)
+    /// creates it again.
+    pub fn reset(&self) {
+        let value = self.lock().take();
+        if let (Some(value), Some(teardown)) = (value, &self.inner.teardown) {
+            teardown(&value);
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Option<Arc<T>>> {
+        // A panic while creating the resource leaves it uncreated.
+        self.inner
+            .value
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl<T> Clone for Fixture<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<T: Debug> Debug for Fixture<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self.inner.value.try_lock().as_deref() {
+            Ok(Some(value)) => f.debug_tuple("Fixture").field(value).finish(),
+            Ok(None) => f.write_str("Fixture(<uninitialized>)"),
+            Err(_) => f.write_str("Fixture(<locked>)"),
+        }
+    }
+}
+
+impl<T> Drop for Inner<T> {
+    fn drop(&mut self) {
+        let value = self.value.get_mut().map(Option::take).unwrap_or_default();
+        if let (Some(value), Some(teardown)) = (value, &self.teardown) {
+            teardown(&value);
+        }
+    }
+}
+
+/// Collects the fixtures created on this thread until dropped, then tears
+/// them down, newest first.
+pub(crate) struct Scope {
+    outer: Option<Vec<Release>>,
+}
+
+impl Scope {
+    pub(crate) fn enter() -> Self {
+        let outer = CREATED.with(|c| c.borrow_mut().replace(Vec::new()));
+        Self { outer }
+    }
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        let outer = self.outer.take();
+        let created = CREATED.with(|c| std::mem::replace(&mut *c.borrow_mut(), outer));
+        for teardown in created.into_iter().flatten().rev() {
+            teardown();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_fixtures_are_created_once_and_torn_down_with_their_scope() {
+        let created = Arc::new(AtomicUsize::new(0));
+        let torn_down = Arc::new(AtomicUsize::new(0));
+        let fixture = {
+            let created = Arc::clone(&created);
+            let torn_down = Arc::clone(&torn_down);
+            Fixture::new(move || created.fetch_add(1, Ordering::SeqCst) + 1).teardown(move |_| {
+                torn_down.fetch_add(1, Ordering::SeqCst);
+            })
+        };
+        assert_eq!(format!("{:?}", fixture), "Fixture(<uninitialized>)");
+
+        {
+            let _scope = Scope::enter();
+            assert_eq!(*fixture.clone().get(), 1);
+            assert_eq!(*fixture.get(), 1);
+            assert_eq!(format!("{:?}", fixture), "Fixture(1)");
+            assert_eq!(torn_down.load(Ordering::SeqCst), 0);
+        }
+        assert!(!fixture.is_initialized());
+        assert_eq!(torn_down.load(Ordering::SeqCst), 1);
+
+        // Outside a scenario, the fixture lives until reset.
+        assert_eq!(*fixture.get(), 2);
+        drop(Scope::enter());
+        assert!(fixture.is_initialized());
+        fixture.reset();
+        assert_eq!(torn_down.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,486 @@
+//! Coverage-guided fuzzing of command sequences, with the `fuzz` feature.
+//!
+//! Proptest samples sequences at random; a coverage-guided fuzzer such as
+//! libFuzzer (through `cargo fuzz`) mutates its inputs towards new code
+//! paths of the system under test instead, and finds different bugs. A
+//! [`Fuzzer`] derives a sequence from the fuzzer's raw bytes: each command
+//! takes one byte choosing among the registered commands, then up to
+//! [`COMMAND_BYTES`] bytes that drive the command's own proptest strategy,
+//! so the strategies written for [`scenario!`](crate::scenario) serve as
+//! they are. The [`fuzz_scenario!`](crate::fuzz_scenario) macro does so in
+//! a libFuzzer entry point, and [`Commands`] implements [`Arbitrary`] for
+//! targets taking structured input.
+//!
+//! A failing step or a broken invariant panics, which is what libFuzzer
+//! reports as a crash; so do commands that panic themselves.
+//!
+//! # Examples
+//!
+//! In `fuzz/fuzz_targets/counter.rs`, generated by `cargo fuzz init`:
+//!
+//! ```
+//! use madhouse::{fuzz_scenario, Command, CommandWrapper, State, TestContext};
+//! use proptest::prelude::*;
+//! use std::sync::Arc;
+//!
+//! #[derive(Debug, Default)]
+//! struct Counter { value: u64 }
+//! impl State for Counter {}
+//!
+//! #[derive(Debug, Clone, Default)]
+//! struct Ctx {}
+//! impl TestContext for Ctx {}
+//!
+//! struct Inc(u64);
+//! impl Command<Counter, Ctx> for Inc {
+//!     fn check(&self, _state: &Counter) -> bool { true }
+//!     fn apply(&self, state: &mut Counter) { state.value += self.0; }
+//!     fn label(&self) -> String { format!("INC({})", self.0) }
+//!     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Counter, Ctx>> {
+//!         (1..10u64).prop_map(|n| CommandWrapper::new(Inc(n)))
+//!     }
+//! }
+//!
+//! struct Reset;
+//! impl Command<Counter, Ctx> for Reset {
+//!     fn check(&self, state: &Counter) -> bool { state.value > 0 }
+//!     fn apply(&self, state: &mut Counter) { state.value = 0; }
+//!     fn label(&self) -> String { "RESET".to_string() }
+//!     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Counter, Ctx>> {
+//!         Just(CommandWrapper::new(Reset))
+//!     }
+//! }
+//!
+//! // With `#![no_main]`, this is the body of
+//! // `libfuzzer_sys::fuzz_target!(|data: &[u8]| { ... });`.
+//! let fuzz = |data: &[u8]| {
+//!     let ctx = Arc::new(Ctx::default());
+//!     fuzz_scenario!(data, ctx, Counter::default(), Inc, Reset);
+//! };
+//! fuzz(b"\x00\x05\x01");
+//! ```
+
+use crate::scenario::CommandStrategy;
+use crate::{run_commands, Command, CommandWrapper, ExecutionReport, State, TestContext};
+pub use arbitrary;
+use arbitrary::{Arbitrary, Unstructured};
+use proptest::strategy::{Just, Strategy, ValueTree};
+use proptest::test_runner::{Config as ProptestConfig, RngAlgorithm, TestRng, TestRunner};
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::sync::Arc;
+
+/// Bytes of fuzzer input each command's strategy draws from at most.
+///
+/// A strategy asking for more reads zeros, as it does once the input is
+/// exhausted.
+pub const COMMAND_BYTES: usize = 32;
+
+/// Derives command sequences from fuzzer input and executes them.
+///
+/// # Examples
+///
+/// ```
+/// # use madhouse::{Command, CommandWrapper, State, TestContext};
+/// # use proptest::prelude::*;
+/// # use std::sync::Arc;
+/// # #[derive(Debug, Default)]
+/// # struct Counter { value: u64 }
+/// # impl State for Counter {}
+/// # #[derive(Debug, Clone, Default)]
+/// # struct Ctx {}
+/// # impl TestContext for Ctx {}
+/// # struct Inc(u64);
+/// # impl Command<Counter, Ctx> for Inc {
+/// #     fn check(&self, _state: &Counter) -> bool { true }
+/// #     fn apply(&self, state: &mut Counter) { state.value += self.0; }
+/// #     fn label(&self) -> String { format!("INC({})", self.0) }
+/// #     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Counter, Ctx>> {
+/// #         (1..10u64).prop_map(|n| CommandWrapper::new(Inc(n)))
+/// #     }
+/// # }
+/// use arbitrary::Unstructured;
+/// use madhouse::fuzz::Fuzzer;
+///
+/// let fuzzer = Fuzzer::new(Arc::new(Ctx::default()))
+///     .command::<Inc>()
+///     .fixed(CommandWrapper::new(Inc(100)));
+///
+/// // One byte picks the command, the next ones feed its strategy.
+/// let commands = fuzzer.commands(&mut Unstructured::new(&[1])).unwrap();
+/// assert_eq!(format!("{:?}", commands), "[INC(100)]");
+///
+/// let mut counter = Counter::default();
+/// let report = fuzzer.run(&[0, 0, 0, 0, 0, 0, 0, 0, 128], &mut counter);
+/// assert_eq!(report.steps[0].label, "INC(5)");
+/// assert_eq!(counter.value, 5);
+/// ```
+pub struct Fuzzer<S: State, C: TestContext> {
+    ctx: Arc<C>,
+    strategies: Vec<CommandStrategy<S, C>>,
+    max_len: usize,
+}
+
+impl<S: State + 'static, C: TestContext + 'static> Fuzzer<S, C> {
+    /// Creates a fuzzer without commands, for sequences of at most 100.
+    pub fn new(ctx: Arc<C>) -> Self {
+        Self {
+            ctx,
+            strategies: Vec::new(),
+            max_len: 100,
+        }
+    }
+
+    /// Returns the test context.
+    pub fn context(&self) -> Arc<C> {
+        Arc::clone(&self.ctx)
+    }
+
+    /// Registers the command type `Cmd`, generated by its
+    /// [`Command::build`] strategy.
+    pub fn command<Cmd: Command<S, C> + 'static>(self) -> Self {
+        let strategy = Cmd::build(self.context());
+        self.strategy(strategy)
+    }
+
+    /// Registers a command strategy.
+    pub fn strategy<T>(mut self, strategy: T) -> Self
+    where
+        T: Strategy<Value = CommandWrapper<S, C>> + 'static,
+    {
+        self.strategies.push(strategy.boxed());
+        self
+    }
+
+    /// Registers a command that is always generated as-is.
+    pub fn fixed(self, command: CommandWrapper<S, C>) -> Self {
+        self.strategy(Just(command))
+    }
+
+    /// Caps the length of the derived sequences.
+    pub fn max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+
+    /// Derives a sequence from `u`, taking commands until the input is
+    /// exhausted or the sequence reaches its maximum length.
+    ///
+    /// Fails if no command is registered, or if a strategy rejects the
+    /// values its bytes produce too often.
+    pub fn commands(
+        &self,
+        u: &mut Unstructured<'_>,
+    ) -> arbitrary::Result<Vec<CommandWrapper<S, C>>> {
+        if self.strategies.is_empty() {
+            return Err(arbitrary::Error::EmptyChoose);
+        }
+        let mut commands = Vec::new();
+        while !u.is_empty() && commands.len() < self.max_len {
+            let strategy = u.choose(&self.strategies)?;
+            let bytes = u.bytes(COMMAND_BYTES.min(u.len()))?;
+            commands.push(generate(strategy, bytes)?);
+        }
+        Ok(commands)
+    }
+
+    /// Derives a sequence from `data` and executes it on `state`.
+    ///
+    /// Inputs a sequence cannot be derived from execute nothing.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a command fails or breaks an invariant.
+    pub fn run(&self, data: &[u8], state: &mut S) -> ExecutionReport {
+        match self.commands(&mut Unstructured::new(data)) {
+            Ok(commands) => execute(&commands, state),
+            Err(_) => ExecutionReport::default(),
+        }
+    }
+}
+
+/// Test contexts whose commands are registered for fuzzing, so that
+/// [`Commands`] can be derived with [`Arbitrary`].
+pub trait Registry<S: State + 'static>: TestContext + Default + 'static {
+    /// Registers the commands to fuzz on `fuzzer`.
+    fn register(fuzzer: Fuzzer<S, Self>) -> Fuzzer<S, Self>;
+}
+
+/// A sequence of the commands registered by `C`, as structured fuzzer
+/// input.
+///
+/// # Examples
+///
+/// ```
+/// # use madhouse::{Command, CommandWrapper, State, TestContext};
+/// # use proptest::prelude::*;
+/// # use std::sync::Arc;
+/// # #[derive(Debug, Default)]
+/// # struct Counter { value: u64 }
+/// # impl State for Counter {}
+/// # #[derive(Debug, Clone, Default)]
+/// # struct Ctx {}
+/// # impl TestContext for Ctx {}
+/// # struct Inc(u64);
+/// # impl Command<Counter, Ctx> for Inc {
+/// #     fn check(&self, _state: &Counter) -> bool { true }
+/// #     fn apply(&self, state: &mut Counter) { state.value += self.0; }
+/// #     fn label(&self) -> String { format!("INC({})", self.0) }
+/// #     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Counter, Ctx>> {
+/// #         (1..10u64).prop_map(|n| CommandWrapper::new(Inc(n)))
+/// #     }
+/// # }
+/// use arbitrary::{Arbitrary, Unstructured};
+/// use madhouse::fuzz::{Commands, Fuzzer, Registry};
+///
+/// impl Registry<Counter> for Ctx {
+///     fn register(fuzzer: Fuzzer<Counter, Self>) -> Fuzzer<Counter, Self> {
+///         fuzzer.command::<Inc>()
+///     }
+/// }
+///
+/// // The body of `fuzz_target!(|commands: Commands<Counter, Ctx>| { ... });`.
+/// let fuzz = |commands: Commands<Counter, Ctx>| {
+///     commands.run(&mut Counter::default());
+/// };
+/// let mut u = Unstructured::new(&[0, 0, 0, 0, 0]);
+/// fuzz(Commands::arbitrary(&mut u).unwrap());
+/// ```
+pub struct Commands<S: State, C: TestContext>(pub Vec<CommandWrapper<S, C>>);
+
+impl<S: State, C: TestContext> Commands<S, C> {
+    /// Executes the sequence on `state`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a command fails or breaks an invariant.
+    pub fn run(&self, state: &mut S) -> ExecutionReport {
+        execute(&self.0, state)
+    }
+}
+
+impl<'a, S: State + 'static, C: Registry<S>> Arbitrary<'a> for Commands<S, C> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        C::register(Fuzzer::new(Arc::new(C::default())))
+            .commands(u)
+            .map(Self)
+    }
+}
+
+/// Prints the labels, which is how libFuzzer shows a crashing input.
+impl<S: State, C: TestContext> Debug for Commands<S, C> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_list().entries(&self.0).finish()
+    }
+}
+
+/// Builds a command from `strategy`, with `bytes` as its randomness.
+fn generate<S: State, C: TestContext>(
+    strategy: &CommandStrategy<S, C>,
+    bytes: &[u8],
+) -> arbitrary::Result<CommandWrapper<S, C>> {
+    let config = ProptestConfig {
+        rng_algorithm: RngAlgorithm::PassThrough,
+        max_local_rejects: 16,
+        ..ProptestConfig::default()
+    };
+    let rng = TestRng::from_seed(RngAlgorithm::PassThrough, bytes);
+    let mut runner = TestRunner::new_with_rng(config, rng);
+    strategy
+        .new_tree(&mut runner)
+        .map(|tree| tree.current())
+        .map_err(|_| arbitrary::Error::IncorrectFormat)
+}
+
+/// Executes `commands` silently, panicking on the first problem.
+fn execute<S: State, C: TestContext>(
+    commands: &[CommandWrapper<S, C>],
+    state: &mut S,
+) -> ExecutionReport {
+    let report = run_commands(commands, state, &[], &mut ());
+    if let Some(message) = report.failure_message() {
+        panic!("{}", message);
+    }
+    report
+}
+
+/// Fuzzes a scenario from raw fuzzer bytes, as the body of a libFuzzer
+/// entry point (see [`fuzz`](crate::fuzz)).
+///
+/// Takes the input bytes, the test context (an `Arc`), an expression for
+/// the initial state, and the commands like [`scenario!`](crate::scenario):
+/// command types, generated by their `build` strategy, and fixed commands
+/// in parentheses. Returns the
+/// [`ExecutionReport`](crate::ExecutionReport).
+///
+/// # Examples
+///
+/// ```
+/// # use madhouse::{fuzz_scenario, Command, CommandWrapper, State, TestContext};
+/// # use proptest::prelude::*;
+/// # use std::sync::Arc;
+/// # #[derive(Debug, Default)]
+/// # struct Counter { value: u64 }
+/// # impl State for Counter {}
+/// # #[derive(Debug, Clone, Default)]
+/// # struct Ctx {}
+/// # impl TestContext for Ctx {}
+/// # struct Inc(u64);
+/// # impl Command<Counter, Ctx> for Inc {
+/// #     fn check(&self, _state: &Counter) -> bool { true }
+/// #     fn apply(&self, state: &mut Counter) { state.value += self.0; }
+/// #     fn label(&self) -> String { format!("INC({})", self.0) }
+/// #     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Counter, Ctx>> {
+/// #         (1..10u64).prop_map(|n| CommandWrapper::new(Inc(n)))
+/// #     }
+/// # }
+/// let ctx = Arc::new(Ctx::default());
+/// let report = fuzz_scenario!(&[1, 7, 0], ctx, Counter::default(), Inc, (Inc(100)));
+/// assert_eq!(report.steps[0].label, "INC(100)");
+/// ```
+#[macro_export]
+macro_rules! fuzz_scenario {
+    (@cmds [$($calls:tt)*] $cmd:ident $(, $($rest:tt)*)?) => {
+        $crate::fuzz_scenario!(@cmds [$($calls)*.command::<$cmd>()] $($($rest)*)?)
+    };
+
+    (@cmds [$($calls:tt)*] $cmd:expr $(, $($rest:tt)*)?) => {
+        $crate::fuzz_scenario!(
+            @cmds [$($calls)*.fixed($crate::CommandWrapper::new($cmd))]
+            $($($rest)*)?
+        )
+    };
+
+    (@cmds [$($calls:tt)*]) => {
+        $($calls)*
+    };
+
+    ($data:expr, $test_context:expr, $state:expr, $($cmds:tt)+) => {
+        $crate::fuzz_scenario!(
+            @cmds [$crate::fuzz::Fuzzer::new($test_context.clone())]
+            $($cmds)+
+        )
+        .run($data, &mut $state)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::InvariantViolation;
+
+    #[derive(Debug, Default)]
+    struct Stack {
+        items: Vec<u8>,
+    }
+
+    impl State for Stack {
+        fn invariants(&self) -> Result<(), InvariantViolation> {
+            match self.items.len() {
+                0..=2 => Ok(()),
+                len => Err(InvariantViolation::new(format!("{} items", len))),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Default)]
+    struct Ctx {}
+
+    impl TestContext for Ctx {}
+
+    struct Push(u8);
+
+    impl Command<Stack, Ctx> for Push {
+        fn check(&self, _state: &Stack) -> bool {
+            true
+        }
+
+        fn apply(&self, state: &mut Stack) {
+            state.items.push(self.0);
+        }
+
+        fn label(&self) -> String {
+            format!("PUSH({})", self.0)
+        }
+
+        fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Stack, Ctx>> {
+            (0..=255u8).prop_map(|n| CommandWrapper::new(Push(n)))
+        }
+    }
+
+    struct Pop;
+
+    impl Command<Stack, Ctx> for Pop {
+        fn check(&self, state: &Stack) -> bool {
+            !state.items.is_empty()
+        }
+
+        fn apply(&self, state: &mut Stack) {
+            state.items.pop();
+        }
+
+        fn label(&self) -> String {
+            "POP".to_string()
+        }
+
+        fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Stack, Ctx>> {
+            Just(CommandWrapper::new(Pop))
+        }
+    }
+
+    impl Registry<Stack> for Ctx {
+        fn register(fuzzer: Fuzzer<Stack, Self>) -> Fuzzer<Stack, Self> {
+            fuzzer.command::<Push>().command::<Pop>().max_len(3)
+        }
+    }
+
+    #[test]
+    fn test_bytes_drive_the_choice_and_the_strategy_of_each_command() {
+        // A choice byte, then the bytes of the command's strategy.
+        let input = |commands: &[(u8, u8)]| -> Vec<u8> {
+            commands
+                .iter()
+                .flat_map(|&(choice, value)| {
+                    let mut bytes = vec![0; COMMAND_BYTES + 1];
+                    bytes[..2].copy_from_slice(&[choice, value]);
+                    bytes
+                })
+                .collect()
+        };
+        let labels = |data: &[u8]| {
+            let commands = Commands::<Stack, Ctx>::arbitrary(&mut Unstructured::new(data)).unwrap();
+            format!("{:?}", commands)
+        };
+
+        assert_eq!(labels(&[]), "[]");
+        assert_eq!(labels(&[0]), "[PUSH(0)]");
+        assert_eq!(labels(&[0, 7]), "[PUSH(7)]");
+        assert_eq!(labels(&[1, 7]), "[POP]");
+        assert_eq!(
+            labels(&input(&[(0, 5), (1, 0), (0, 6)])),
+            "[PUSH(5), POP, PUSH(6)]"
+        );
+        // At most 3 commands.
+        assert_eq!(
+            labels(&input(&[(0, 1), (0, 2), (0, 3), (0, 4)])),
+            "[PUSH(1), PUSH(2), PUSH(3)]"
+        );
+
+        let empty = Fuzzer::<Stack, Ctx>::new(Arc::new(Ctx::default()));
+        assert!(empty.commands(&mut Unstructured::new(&[0])).is_err());
+        assert!(empty.run(&[0], &mut Stack::default()).steps.is_empty());
+
+        let ctx = Arc::new(Ctx::default());
+        let data = input(&[(0, 0), (0, 0)]);
+        let report = fuzz_scenario!(&data, ctx, Stack::default(), (Push(1)), Pop);
+        assert_eq!(report.executed().count(), 2);
+        let overflow = std::panic::catch_unwind(|| {
+            let data = input(&[(0, 0), (0, 0), (0, 0)]);
+            fuzz_scenario!(&data, ctx, Stack::default(), (Push(1)), Pop);
+        });
+        let message = *overflow.unwrap_err().downcast::<String>().unwrap();
+        assert_eq!(
+            message,
+            "invariant violated after command 3 (PUSH(1)): 3 items"
+        );
+    }
+}
@@ -0,0 +1,458 @@
+//! Portable, explicitly seeded generation helpers.
+//!
+//! Everything in this module produces identical results on every OS and
+//! architecture. Integers are hashed as fixed-width little-endian bytes,
+//! seeds are expanded with a fixed algorithm, and the RNG is proptest's
+//! ChaCha implementation. Nothing here uses `std`'s `DefaultHasher`, whose
+//! algorithm is unspecified and may change between Rust releases.
+//!
+//! A seed or hash recorded on one machine (e.g. linux/x86 CI) therefore
+//! replays identically on any other (e.g. a developer's mac).
+
+use proptest::prelude::Strategy;
+use proptest::strategy::{BoxedStrategy, Union};
+use proptest::test_runner::{RngAlgorithm, TestRng};
+use std::fmt::Debug;
+use std::hash::{Hash, Hasher};
+use std::ops::{Bound, RangeBounds};
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// A 64-bit FNV-1a hasher with a platform-independent integer encoding.
+///
+/// Unlike the default `Hasher` methods, which feed integers in native byte
+/// order and `usize`/`isize` at native width, this hasher always writes
+/// integers as little-endian bytes and widens `usize`/`isize` to 64 bits.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::gen::StableHasher;
+/// use std::hash::{Hash, Hasher};
+///
+/// let mut hasher = StableHasher::new();
+/// "madhouse".hash(&mut hasher);
+/// 42usize.hash(&mut hasher);
+/// let digest = hasher.finish();
+///
+/// // Same input, same digest, on every platform.
+/// let mut again = StableHasher::new();
+/// "madhouse".hash(&mut again);
+/// 42u64.hash(&mut again);
+/// assert_eq!(digest, again.finish());
+/// ```
+#[derive(Debug, Clone)]
+pub struct StableHasher {
+    state: u64,
+}
+
+impl StableHasher {
+    /// Creates a hasher with the standard FNV-1a offset basis.
+    pub const fn new() -> Self {
+        Self {
+            state: FNV_OFFSET_BASIS,
+        }
+    }
+}
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Hasher for StableHasher {
+    fn finish(&self) -> u64 {
+        self.state
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.state ^= u64::from(*byte);
+            self.state = self.state.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn write_u8(&mut self, n: u8) {
+        self.write(&[n]);
+    }
+
+    fn write_u16(&mut self, n: u16) {
+        self.write(&n.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, n: u32) {
+        self.write(&n.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        self.write(&n.to_le_bytes());
+    }
+
+    fn write_u128(&mut self, n: u128) {
+        self.write(&n.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, n: usize) {
+        self.write_u64(n as u64);
+    }
+
+    fn write_i8(&mut self, n: i8) {
+        self.write_u8(n as u8);
+    }
+
+    fn write_i16(&mut self, n: i16) {
+        self.write_u16(n as u16);
+    }
+
+    fn write_i32(&mut self, n: i32) {
+        self.write_u32(n as u32);
+    }
+
+    fn write_i64(&mut self, n: i64) {
+        self.write_u64(n as u64);
+    }
+
+    fn write_i128(&mut self, n: i128) {
+        self.write_u128(n as u128);
+    }
+
+    fn write_isize(&mut self, n: isize) {
+        self.write_i64(n as i64);
+    }
+}
+
+/// Hashes a value with [`StableHasher`].
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::gen::stable_hash;
+///
+/// assert_eq!(stable_hash(&7usize), stable_hash(&7u64));
+/// assert_ne!(stable_hash("A"), stable_hash("B"));
+/// ```
+pub fn stable_hash<T: Hash + ?Sized>(value: &T) -> u64 {
+    let mut hasher = StableHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Expands a 64-bit seed into the 32 bytes expected by the ChaCha RNG.
+///
+/// Uses SplitMix64 and little-endian encoding, so the expansion does not
+/// depend on the host.
+pub fn seed_bytes(seed: u64) -> [u8; 32] {
+    let mut state = seed;
+    let mut bytes = [0u8; 32];
+    for chunk in bytes.chunks_exact_mut(8) {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        chunk.copy_from_slice(&z.to_le_bytes());
+    }
+    bytes
+}
+
+/// Creates a ChaCha-based proptest RNG from a 64-bit seed.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::gen::rng;
+/// use proptest::prelude::RngCore;
+///
+/// assert_eq!(rng(42).next_u64(), rng(42).next_u64());
+/// ```
+pub fn rng(seed: u64) -> TestRng {
+    TestRng::from_seed(RngAlgorithm::ChaCha, &seed_bytes(seed))
+}
+
+/// Creates a strategy that picks one of the given items.
+///
+/// The index is drawn as a `u64`, so the choice for a given RNG state is the
+/// same on 32-bit and 64-bit targets. Shrinks toward the first item.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::gen::pick;
+/// use proptest::prelude::*;
+///
+/// proptest!(|(color in pick(vec!["red", "green", "blue"]))| {
+///     assert!(["red", "green", "blue"].contains(&color));
+/// });
+/// ```
+///
+/// # Panics
+///
+/// Panics if `items` is empty.
+pub fn pick<T: Clone + Debug>(items: impl Into<Vec<T>>) -> impl Strategy<Value = T> {
+    let items = items.into();
+    assert!(!items.is_empty(), "gen::pick requires at least one item");
+    (0..items.len() as u64).prop_map(move |idx| items[idx as usize].clone())
+}
+
+/// Creates a strategy interleaving the values of two strategies, each
+/// keeping its own order.
+///
+/// `len` is the total number of values the two produce; every interleaving
+/// of them can be drawn. Shrinks toward all values of `first`, then all
+/// values of `second`. [`prop_interleave!`](crate::prop_interleave) builds
+/// both sides from lists of strategies.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::gen::interleave;
+/// use proptest::prelude::*;
+///
+/// let alice = Just(vec!["a1", "a2"]);
+/// let bob = Just(vec!["b1"]);
+///
+/// proptest!(|(merged in interleave(alice, bob, 3))| {
+///     assert!([
+///         vec!["a1", "a2", "b1"],
+///         vec!["a1", "b1", "a2"],
+///         vec!["b1", "a1", "a2"],
+///     ]
+///     .contains(&merged));
+/// });
+/// ```
+pub fn interleave<T: Debug>(
+    first: impl Strategy<Value = Vec<T>>,
+    second: impl Strategy<Value = Vec<T>>,
+    len: usize,
+) -> impl Strategy<Value = Vec<T>> {
+    // At each position, true takes the next value of `second` if any left.
+    let picks = proptest::collection::vec(proptest::bool::ANY, len);
+    (first, second, picks).prop_map(|(first, second, picks)| {
+        let mut merged = Vec::with_capacity(first.len() + second.len());
+        let mut first = first.into_iter().peekable();
+        let mut second = second.into_iter().peekable();
+        for pick in picks {
+            let next = match (pick, first.peek().is_some()) {
+                (true, _) | (false, false) => second.next().or_else(|| first.next()),
+                (false, true) => first.next(),
+            };
+            merged.extend(next);
+        }
+        merged.extend(first);
+        merged.extend(second);
+        merged
+    })
+}
+
+/// Default share of [`edgy`] draws that are boundary values.
+pub const DEFAULT_EDGE_BIAS: f64 = 0.5;
+
+/// Integers [`edgy`] can generate.
+pub trait EdgeInt: Copy + Ord + Debug + 'static {
+    /// Smallest value of the type.
+    const MIN: Self;
+    /// Largest value of the type.
+    const MAX: Self;
+
+    /// Returns the boundary values within `lo..=hi`, ascending.
+    fn edges(lo: Self, hi: Self) -> Vec<Self>;
+
+    /// Returns `self + 1`, saturating.
+    fn succ(self) -> Self;
+
+    /// Returns `self - 1`, saturating.
+    fn pred(self) -> Self;
+
+    /// Returns a uniform strategy over `lo..=hi`.
+    fn uniform(lo: Self, hi: Self) -> BoxedStrategy<Self>;
+}
+
+macro_rules! edge_int {
+    ($($t:ty),*) => {$(
+        impl EdgeInt for $t {
+            const MIN: Self = <$t>::MIN;
+            const MAX: Self = <$t>::MAX;
+
+            fn edges(lo: Self, hi: Self) -> Vec<Self> {
+                let mut edges = vec![lo, lo.saturating_add(1), hi.saturating_sub(1), hi, 0];
+                #[allow(unused_comparisons)]
+                let signed = <$t>::MIN < 0;
+                if signed {
+                    edges.push((0 as $t).saturating_sub(1));
+                }
+                for bit in 0..<$t>::BITS {
+                    let Some(power) = (1 as $t).checked_shl(bit).filter(|&p| p > 0) else {
+                        continue;
+                    };
+                    edges.push(power);
+                    if signed {
+                        edges.push((0 as $t).saturating_sub(power));
+                    }
+                }
+                edges.retain(|edge| (lo..=hi).contains(edge));
+                edges.sort_unstable();
+                edges.dedup();
+                edges
+            }
+
+            fn succ(self) -> Self {
+                self.saturating_add(1)
+            }
+
+            fn pred(self) -> Self {
+                self.saturating_sub(1)
+            }
+
+            fn uniform(lo: Self, hi: Self) -> BoxedStrategy<Self> {
+                (lo..=hi).boxed()
+            }
+        }
+    )*};
+}
+
+edge_int!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
+/// Creates a strategy over `range` biased toward its boundary values.
+///
+/// Uniform ranges rarely hit the values where systems break. Half of the
+/// draws here are edges: the range bounds and their neighbors, zero, `-1`,
+/// and powers of two (and their negations) within the range. The other
+/// half are uniform. See [`edgy_with`] to change the ratio.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::gen::edgy;
+/// use proptest::prelude::*;
+///
+/// proptest!(|(amount in edgy(1..=1_000u64))| {
+///     assert!((1..=1_000).contains(&amount));
+/// });
+/// ```
+///
+/// # Panics
+///
+/// Panics if `range` is empty.
+pub fn edgy<T: EdgeInt>(range: impl RangeBounds<T>) -> BoxedStrategy<T> {
+    edgy_with(range, DEFAULT_EDGE_BIAS)
+}
+
+/// Creates a strategy over `range` drawing boundary values with
+/// probability `bias` (clamped to `0.0..=1.0`), uniform values otherwise.
+///
+/// # Panics
+///
+/// Panics if `range` is empty.
+pub fn edgy_with<T: EdgeInt>(range: impl RangeBounds<T>, bias: f64) -> BoxedStrategy<T> {
+    let lo = match range.start_bound() {
+        Bound::Included(&lo) => lo,
+        Bound::Excluded(&lo) if lo < T::MAX => lo.succ(),
+        Bound::Excluded(_) => panic!("gen::edgy requires a non-empty range"),
+        Bound::Unbounded => T::MIN,
+    };
+    let hi = match range.end_bound() {
+        Bound::Included(&hi) => hi,
+        Bound::Excluded(&hi) if hi > T::MIN => hi.pred(),
+        Bound::Excluded(_) => panic!("gen::edgy requires a non-empty range"),
+        Bound::Unbounded => T::MAX,
+    };
+    assert!(lo <= hi, "gen::edgy requires a non-empty range");
+
+    // Integer weights keep the choice portable.
+    let edge_weight = (bias.clamp(0.0, 1.0) * 1000.0).round() as u32;
+    match edge_weight {
+        0 => T::uniform(lo, hi),
+        1000 => pick(T::edges(lo, hi)).boxed(),
+        _ => Union::new_weighted(vec![
+            (edge_weight, pick(T::edges(lo, hi)).boxed()),
+            (1000 - edge_weight, T::uniform(lo, hi)),
+        ])
+        .boxed(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::RngCore;
+    use proptest::strategy::ValueTree;
+    use proptest::test_runner::{Config, TestRunner};
+
+    #[test]
+    fn test_fnv1a_reference_vectors() {
+        assert_eq!(StableHasher::new().finish(), 0xcbf2_9ce4_8422_2325);
+
+        let mut hasher = StableHasher::new();
+        hasher.write(b"a");
+        assert_eq!(hasher.finish(), 0xaf63_dc4c_8601_ec8c);
+
+        let mut hasher = StableHasher::new();
+        hasher.write(b"foobar");
+        assert_eq!(hasher.finish(), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn test_integers_hash_as_little_endian() {
+        let mut bytes = StableHasher::new();
+        bytes.write(&[0x01, 0x02, 0x03, 0x04]);
+
+        assert_eq!(stable_hash(&0x0403_0201u32), bytes.finish());
+        assert_eq!(stable_hash(&3usize), stable_hash(&3u64));
+        assert_eq!(stable_hash(&-3isize), stable_hash(&-3i64));
+    }
+
+    #[test]
+    fn test_rng_is_pinned() {
+        // Guards against accidental changes to seed expansion or algorithm,
+        // which would silently invalidate every recorded seed.
+        let mut rng = rng(0);
+        let draws: Vec<u64> = (0..3).map(|_| rng.next_u64()).collect();
+        assert_eq!(
+            draws,
+            [
+                0xd1e7_f859_c1fe_3186,
+                0x547f_d235_7bcc_56d5,
+                0x3ec9_f510_b1a1_bea5
+            ]
+        );
+        assert_eq!(seed_bytes(0)[..8], 0xe220_a839_7b1d_cdafu64.to_le_bytes());
+    }
+
+    #[test]
+    fn test_pick_is_reproducible() {
+        let items = vec!["a", "b", "c", "d", "e"];
+        let draw = |seed| {
+            let mut runner = TestRunner::new_with_rng(Config::default(), rng(seed));
+            (0..16)
+                .map(|_| pick(items.clone()).new_tree(&mut runner).unwrap().current())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(draw(7), draw(7));
+    }
+
+    #[test]
+    fn test_edgy_hits_boundaries() {
+        assert_eq!(
+            i8::edges(-5, 100),
+            vec![-5, -4, -2, -1, 0, 1, 2, 4, 8, 16, 32, 64, 99, 100]
+        );
+        assert_eq!(u8::edges(0, u8::MAX).last(), Some(&u8::MAX));
+
+        let mut runner = TestRunner::new_with_rng(Config::default(), rng(3));
+        let strategy = edgy(0..1_000_000u64);
+        let draws: Vec<u64> = (0..200)
+            .map(|_| strategy.new_tree(&mut runner).unwrap().current())
+            .collect();
+        assert!(draws.iter().all(|&d| d < 1_000_000));
+        assert!(draws.contains(&0) && draws.contains(&999_999));
+
+        let all_edges = edgy_with(10..=20u32, 1.0);
+        for _ in 0..50 {
+            let draw = all_edges.new_tree(&mut runner).unwrap().current();
+            assert!([10, 11, 16, 19, 20].contains(&draw), "{}", draw);
+        }
+    }
+}
@@ -0,0 +1,238 @@
+//! Golden traces for conformance testing.
+//!
+//! A golden trace is a command sequence together with what happened at each
+//! step when it was recorded: the outcome and a digest of the model state
+//! after the command. [`Scenario::conform_to_file`](crate::Scenario::conform_to_file)
+//! (or [`conform!`](crate::conform)) re-executes the sequence and fails on
+//! the first step that diverges, which catches unintended behavior changes
+//! in the layer between commands and the system under test.
+//!
+//! The file format extends regression files with tab-separated columns:
+//! label, outcome (`executed`, `failed` or `skipped`) and the state digest
+//! (`-` for skipped steps). A file of bare labels is recorded on its first
+//! run; `MADHOUSE_BLESS=1` re-records an existing one.
+//!
+//! ```text
+//! # madhouse golden trace
+//! INC(3)  executed  5a0c3e0c1b8e7a51
+//! RESET  executed  af63bd4c8601b7df
+//! RESET  skipped  -
+//! ```
+
+use crate::gen::stable_hash;
+use crate::{Observer, State, StepOutcome, StepReport, TestContext};
+use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Digest of a state: a stable hash of its `Debug` representation.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::golden::digest;
+///
+/// assert_eq!(digest(&vec![1, 2]), digest(&vec![1, 2]));
+/// assert_ne!(digest(&vec![1, 2]), digest(&vec![2, 1]));
+/// ```
+pub fn digest(state: &dyn Debug) -> u64 {
+    stable_hash(&format!("{:?}", state))
+}
+
+/// What happened at one step of a trace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GoldenStep {
+    /// Label of the command.
+    pub label: String,
+    /// `executed`, `failed` or `skipped`.
+    pub outcome: String,
+    /// Digest of the state after the command; `None` if it was skipped.
+    pub digest: Option<u64>,
+}
+
+impl Display for GoldenStep {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self.digest {
+            Some(digest) => write!(f, "{}\t{}\t{:016x}", self.label, self.outcome, digest),
+            None => write!(f, "{}\t{}\t-", self.label, self.outcome),
+        }
+    }
+}
+
+/// A golden trace file: labels, and the steps recorded for them if any.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GoldenTrace {
+    /// Command labels, in order.
+    pub labels: Vec<String>,
+    /// Recorded steps; empty if the file only lists labels.
+    pub steps: Vec<GoldenStep>,
+}
+
+impl GoldenTrace {
+    /// Parses a golden trace.
+    ///
+    /// Returns `None` if a line has recorded columns but they are malformed,
+    /// or if only some lines have them.
+    pub fn parse(contents: &str) -> Option<Self> {
+        let mut trace = Self::default();
+        let lines = contents
+            .lines()
+            .map(|line| line.trim_end_matches('\r'))
+            .filter(|line| !line.trim().is_empty() && !line.starts_with('#'));
+        for line in lines {
+            let mut columns = line.split('\t');
+            let label = columns.next()?.trim().to_string();
+            trace.labels.push(label.clone());
+            let Some(outcome) = columns.next() else {
+                continue;
+            };
+            let digest = match columns.next()? {
+                "-" => None,
+                hex => Some(u64::from_str_radix(hex, 16).ok()?),
+            };
+            trace.steps.push(GoldenStep {
+                label,
+                outcome: outcome.to_string(),
+                digest,
+            });
+        }
+        (trace.steps.is_empty() || trace.steps.len() == trace.labels.len()).then_some(trace)
+    }
+
+    /// Reads a golden trace file.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        Self::parse(&contents)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed golden trace"))
+    }
+
+    /// Writes recorded steps as a golden trace file.
+    pub fn save(path: impl AsRef<Path>, steps: &[GoldenStep]) -> io::Result<()> {
+        let mut contents = String::from("# madhouse golden trace\n");
+        for step in steps {
+            contents.push_str(&format!("{}\n", step));
+        }
+        fs::write(path, contents)
+    }
+
+    /// Returns true if the file has recorded steps to conform to.
+    pub fn is_recorded(&self) -> bool {
+        !self.steps.is_empty()
+    }
+
+    /// Compares executed steps to the recorded ones and returns the first
+    /// divergence.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use madhouse::golden::{GoldenStep, GoldenTrace};
+    ///
+    /// let trace = GoldenTrace::parse("A\texecuted\t00000000000000ff\nB\tskipped\t-\n").unwrap();
+    /// let actual = vec![GoldenStep {
+    ///     label: "A".to_string(),
+    ///     outcome: "executed".to_string(),
+    ///     digest: Some(0xff),
+    /// }];
+    ///
+    /// let divergence = trace.first_divergence(&actual).unwrap();
+    /// assert_eq!(divergence.index, 1);
+    /// assert!(divergence.actual.is_none());
+    /// ```
+    pub fn first_divergence(&self, actual: &[GoldenStep]) -> Option<Divergence> {
+        let len = self.steps.len().max(actual.len());
+        (0..len)
+            .map(|index| Divergence {
+                index,
+                expected: self.steps.get(index).cloned(),
+                actual: actual.get(index).cloned(),
+            })
+            .find(|d| d.expected != d.actual)
+    }
+}
+
+/// The first step at which an execution differs from its golden trace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// Index of the step (0-based).
+    pub index: usize,
+    /// Recorded step, `None` if the execution ran past the trace.
+    pub expected: Option<GoldenStep>,
+    /// Executed step, `None` if execution stopped before it.
+    pub actual: Option<GoldenStep>,
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let show = |step: &Option<GoldenStep>| match step {
+            Some(step) => step.to_string().replace('\t', " "),
+            None => "<no step>".to_string(),
+        };
+        write!(
+            f,
+            "golden trace diverged at step {}: expected `{}`, got `{}`",
+            self.index,
+            show(&self.expected),
+            show(&self.actual)
+        )
+    }
+}
+
+/// Observer recording the steps of an execution.
+#[derive(Default)]
+pub(crate) struct Recorder {
+    pub(crate) steps: Vec<GoldenStep>,
+}
+
+impl<S: State, C: TestContext> Observer<S, C> for Recorder {
+    fn on_skipped(&mut self, step: &StepReport) {
+        self.steps.push(GoldenStep {
+            label: step.label.clone(),
+            outcome: "skipped".to_string(),
+            digest: None,
+        });
+    }
+
+    fn on_after_apply(&mut self, step: &StepReport, state: &S) {
+        let outcome = match step.outcome {
+            StepOutcome::Failed(_) => "failed",
+            _ => "executed",
+        };
+        self.steps.push(GoldenStep {
+            label: step.label.clone(),
+            outcome: outcome.to_string(),
+            digest: Some(digest(state)),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_round_trips_and_rejects_partial_files() {
+        let steps = vec![
+            GoldenStep {
+                label: "INC(3)".to_string(),
+                outcome: "executed".to_string(),
+                digest: Some(0x5a0c),
+            },
+            GoldenStep {
+                label: "RESET".to_string(),
+                outcome: "skipped".to_string(),
+                digest: None,
+            },
+        ];
+        let text: String = steps.iter().map(|s| format!("{}\n", s)).collect();
+
+        let trace = GoldenTrace::parse(&text).unwrap();
+
+        assert_eq!(trace.steps, steps);
+        assert_eq!(trace.labels, vec!["INC(3)", "RESET"]);
+        assert!(!GoldenTrace::parse("INC(3)\nRESET\n").unwrap().is_recorded());
+        assert!(GoldenTrace::parse("INC(3)\texecuted\t0\nRESET\n").is_none());
+        assert!(GoldenTrace::parse("INC(3)\texecuted\tzz\n").is_none());
+    }
+}
@@ -0,0 +1,138 @@
+//! Graphviz export of the explored state transitions.
+//!
+//! [`StateGraph`] records, for every applied command, an edge from the
+//! fingerprint of the state before it to the fingerprint of the state after
+//! it (see [`State::fingerprint`]), labeled with the command. Across all the
+//! cases of a run, this pictures which regions of the state space the
+//! commands actually reach. A scenario writes it to
+//! [`Config::graph_file`](crate::Config::graph_file) in DOT format, to be
+//! rendered with e.g. `dot -Tsvg`.
+
+use crate::observer::Observer;
+use crate::{CommandWrapper, State, StepReport, TestContext};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Transitions between state fingerprints, and how often each was taken.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::graph::StateGraph;
+///
+/// let mut graph = StateGraph::default();
+/// graph.record(1, 2, "INC");
+/// graph.record(2, 1, "DEC");
+/// graph.record(1, 2, "INC");
+///
+/// assert_eq!(graph.nodes().count(), 2);
+/// assert_eq!(graph.edges().count(), 2);
+/// let dot = graph.to_string();
+/// assert!(dot.starts_with("digraph states {\n"));
+/// assert!(dot.contains(
+///     r#""0000000000000001" -> "0000000000000002" [label="INC (x2)"];"#
+/// ));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StateGraph {
+    nodes: BTreeSet<u64>,
+    edges: BTreeMap<(u64, u64, String), usize>,
+    /// Fingerprint of the state before the command being applied.
+    pending: Option<u64>,
+}
+
+impl StateGraph {
+    /// Records a transition from state `from` to state `to` by `label`.
+    pub fn record(&mut self, from: u64, to: u64, label: &str) {
+        self.nodes.insert(from);
+        self.nodes.insert(to);
+        *self.edges.entry((from, to, label.to_string())).or_default() += 1;
+    }
+
+    /// Iterates over the state fingerprints, in order.
+    pub fn nodes(&self) -> impl Iterator<Item = u64> + '_ {
+        self.nodes.iter().copied()
+    }
+
+    /// Iterates over the transitions and how often each was taken.
+    pub fn edges(&self) -> impl Iterator<Item = (u64, u64, &str, usize)> {
+        self.edges
+            .iter()
+            .map(|((from, to, label), count)| (*from, *to, label.as_str(), *count))
+    }
+
+    /// Writes the graph in DOT format to `path`.
+    pub fn write(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+}
+
+impl<S: State, C: TestContext> Observer<S, C> for StateGraph {
+    fn on_before_apply(&mut self, index: usize, command: &CommandWrapper<S, C>, state: &S) {
+        let _ = (index, command);
+        self.pending = Some(state.fingerprint());
+    }
+
+    fn on_after_apply(&mut self, step: &StepReport, state: &S) {
+        if let Some(from) = self.pending.take() {
+            self.record(from, state.fingerprint(), &step.label);
+        }
+    }
+}
+
+impl Display for StateGraph {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        writeln!(f, "digraph states {{")?;
+        writeln!(f, "  node [shape=box, fontname=monospace];")?;
+        for node in &self.nodes {
+            writeln!(f, "  \"{:016x}\" [label=\"{:08x}\"];", node, node >> 32)?;
+        }
+        for ((from, to, label), count) in &self.edges {
+            let label = escape(label);
+            if *count > 1 {
+                writeln!(
+                    f,
+                    "  \"{:016x}\" -> \"{:016x}\" [label=\"{} (x{})\"];",
+                    from, to, label, count
+                )?;
+            } else {
+                writeln!(
+                    f,
+                    "  \"{:016x}\" -> \"{:016x}\" [label=\"{}\"];",
+                    from, to, label
+                )?;
+            }
+        }
+        writeln!(f, "}}")
+    }
+}
+
+/// Escapes a label for a quoted DOT string.
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dot_output_escapes_labels() {
+        let mut graph = StateGraph::default();
+        graph.record(0x10, 0x10, r#"SAY("hi")"#);
+
+        assert_eq!(
+            graph.to_string(),
+            concat!(
+                "digraph states {\n",
+                "  node [shape=box, fontname=monospace];\n",
+                "  \"0000000000000010\" [label=\"00000000\"];\n",
+                "  \"0000000000000010\" -> \"0000000000000010\" [label=\"SAY(\\\"hi\\\")\"];\n",
+                "}\n",
+            )
+        );
+    }
+}
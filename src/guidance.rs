@@ -0,0 +1,167 @@
+//! Coverage-guided command generation.
+//!
+//! Random mode picks commands by their fixed weights, however often a
+//! command has already been seen to lead nowhere new. With guidance on
+//! (see [`Scenario::guided`](crate::Scenario::guided) or
+//! `MADHOUSE_GUIDED=1`), every command a generated case applies is scored
+//! by whether the state it led to was new, and later cases scale each
+//! command's weight by its score (see [`Guidance::factor`]). Commands that
+//! keep discovering states are drawn more often; the others never drop out
+//! entirely.
+//!
+//! What counts as a new state is up to a [`StateAbstraction`]: by default
+//! the state's [`fingerprint`](crate::State::fingerprint), or any coarser
+//! projection, such as a balance bucket or the set of active nodes, that
+//! ignores differences not worth exploring.
+
+use crate::observer::Observer;
+use crate::report::command_name;
+use crate::{State, StepReport, TestContext};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashSet};
+
+/// Smallest weight factor of a command, however rarely it found new
+/// states.
+pub const MIN_FACTOR: f64 = 0.1;
+
+/// Maps a state to the hash that identifies it for guidance.
+///
+/// Closures `Fn(&S) -> u64` are abstractions.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::guidance::{Fingerprint, StateAbstraction};
+/// use madhouse::State;
+///
+/// #[derive(Debug)]
+/// struct Account { balance: u64 }
+/// impl State for Account {}
+///
+/// // Balances in the same power of two are the same state.
+/// let bucket = |account: &Account| u64::from(account.balance.leading_zeros());
+/// assert_eq!(bucket.abstract_state(&Account { balance: 5 }), bucket.abstract_state(&Account { balance: 7 }));
+/// assert_ne!(Fingerprint.abstract_state(&Account { balance: 5 }), Fingerprint.abstract_state(&Account { balance: 7 }));
+/// ```
+pub trait StateAbstraction<S> {
+    /// Returns the hash of `state`'s abstraction.
+    fn abstract_state(&self, state: &S) -> u64;
+}
+
+/// The default abstraction: [`State::fingerprint`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Fingerprint;
+
+impl<S: State> StateAbstraction<S> for Fingerprint {
+    fn abstract_state(&self, state: &S) -> u64 {
+        state.fingerprint()
+    }
+}
+
+impl<S, F: Fn(&S) -> u64> StateAbstraction<S> for F {
+    fn abstract_state(&self, state: &S) -> u64 {
+        self(state)
+    }
+}
+
+/// How often applying each command led to a new abstract state.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::guidance::Guidance;
+///
+/// let mut guidance = Guidance::new();
+/// for state in [1, 2, 3] {
+///     guidance.record("DEPOSIT", state);
+/// }
+/// for _ in 0..3 {
+///     guidance.record("BALANCE", 3);
+/// }
+///
+/// assert_eq!(guidance.distinct(), 3);
+/// assert!(guidance.factor("DEPOSIT") > guidance.factor("NEVER_APPLIED"));
+/// assert!(guidance.factor("NEVER_APPLIED") > guidance.factor("BALANCE"));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Guidance {
+    seen: HashSet<u64>,
+    /// Applications and new states, per command name.
+    scores: BTreeMap<String, (u32, u32)>,
+}
+
+impl Guidance {
+    /// Creates guidance that has seen nothing yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records that applying command `name` led to the abstract `state`,
+    /// returning true if that state is new.
+    pub fn record(&mut self, name: &str, state: u64) -> bool {
+        let new = self.seen.insert(state);
+        let (applied, novel) = self.scores.entry(name.to_string()).or_default();
+        *applied += 1;
+        *novel += u32::from(new);
+        new
+    }
+
+    /// Returns the factor the weight of command `name` is scaled by: its
+    /// share of applications that led to a new state, counting one more
+    /// success and one more failure so that commands never applied get
+    /// one half, plus [`MIN_FACTOR`].
+    pub fn factor(&self, name: &str) -> f64 {
+        let (applied, novel) = self.scores.get(name).copied().unwrap_or_default();
+        MIN_FACTOR + f64::from(novel + 1) / f64::from(applied + 2)
+    }
+
+    /// Returns the number of distinct abstract states seen.
+    pub fn distinct(&self) -> usize {
+        self.seen.len()
+    }
+}
+
+/// Feeds the states commands lead to into shared guidance.
+pub(crate) struct Feedback<'a, S> {
+    abstraction: &'a dyn StateAbstraction<S>,
+    guidance: &'a RefCell<Guidance>,
+}
+
+impl<'a, S> Feedback<'a, S> {
+    pub(crate) fn new(
+        abstraction: &'a dyn StateAbstraction<S>,
+        guidance: &'a RefCell<Guidance>,
+    ) -> Self {
+        Self {
+            abstraction,
+            guidance,
+        }
+    }
+}
+
+impl<S: State, C: TestContext> Observer<S, C> for Feedback<'_, S> {
+    fn on_after_apply(&mut self, step: &StepReport, state: &S) {
+        let abstracted = self.abstraction.abstract_state(state);
+        self.guidance
+            .borrow_mut()
+            .record(command_name(&step.label), abstracted);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_factor_follows_the_share_of_new_states() {
+        let mut guidance = Guidance::new();
+        assert_eq!(guidance.factor("INC"), MIN_FACTOR + 0.5);
+
+        assert!(guidance.record("INC", 1));
+        assert!(guidance.record("INC", 2));
+        assert!(!guidance.record("RESET", 1));
+        assert_eq!(guidance.factor("INC"), MIN_FACTOR + 0.75);
+        assert_eq!(guidance.factor("RESET"), MIN_FACTOR + 1.0 / 3.0);
+        assert_eq!(guidance.distinct(), 2);
+    }
+}
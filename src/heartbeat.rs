@@ -0,0 +1,166 @@
+//! Heartbeat commands injected at fixed intervals.
+//!
+//! Heartbeats (keep-alives, metric scrapes) run alongside the generated
+//! sequence rather than as part of it. They are never generated, so they
+//! never participate in shrinking, and they are recorded separately in
+//! [`ExecutionReport::heartbeats`](crate::ExecutionReport::heartbeats).
+
+use crate::{Command, CommandWrapper, State, TestContext};
+use std::time::{Duration, Instant};
+
+/// How often a heartbeat fires.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeartbeatInterval {
+    /// After every N applied commands.
+    Steps(usize),
+    /// Whenever at least this much time has passed since it last fired.
+    Every(Duration),
+}
+
+/// A command injected by the executor at a fixed interval.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::{
+///     execute_commands_with_heartbeats, Command, CommandWrapper, Heartbeat, State,
+///     TestContext,
+/// };
+/// use proptest::prelude::*;
+/// use std::sync::Arc;
+///
+/// #[derive(Debug, Default)]
+/// struct Node { work: u64, pings: u64 }
+/// impl State for Node {}
+///
+/// #[derive(Debug, Clone, Default)]
+/// struct Ctx {}
+/// impl TestContext for Ctx {}
+///
+/// struct Work;
+/// impl Command<Node, Ctx> for Work {
+///     fn check(&self, _state: &Node) -> bool { true }
+///     fn apply(&self, state: &mut Node) { state.work += 1; }
+///     fn label(&self) -> String { "WORK".to_string() }
+///     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Node, Ctx>> {
+///         Just(CommandWrapper::new(Work))
+///     }
+/// }
+///
+/// struct Ping;
+/// impl Command<Node, Ctx> for Ping {
+///     fn check(&self, _state: &Node) -> bool { true }
+///     fn apply(&self, state: &mut Node) { state.pings += 1; }
+///     fn label(&self) -> String { "PING".to_string() }
+///     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Node, Ctx>> {
+///         Just(CommandWrapper::new(Ping))
+///     }
+/// }
+///
+/// let commands: Vec<_> = (0..5).map(|_| CommandWrapper::new(Work)).collect();
+/// let heartbeats = [Heartbeat::every_steps(2, Ping)];
+/// let mut state = Node::default();
+///
+/// let report = execute_commands_with_heartbeats(&commands, &mut state, &heartbeats);
+/// assert_eq!(state.pings, 2);
+/// assert_eq!(report.executed().count(), 5);
+/// assert_eq!(report.heartbeats.len(), 2);
+/// ```
+pub struct Heartbeat<S: State, C: TestContext> {
+    /// The injected command.
+    pub command: CommandWrapper<S, C>,
+    /// How often the command fires.
+    pub interval: HeartbeatInterval,
+}
+
+impl<S: State, C: TestContext> Heartbeat<S, C> {
+    /// Creates a heartbeat that fires after every `n` applied commands.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is zero.
+    pub fn every_steps<Cmd: Command<S, C> + 'static>(n: usize, cmd: Cmd) -> Self {
+        assert!(n > 0, "heartbeat step interval must be positive");
+        Self {
+            command: CommandWrapper::new(cmd),
+            interval: HeartbeatInterval::Steps(n),
+        }
+    }
+
+    /// Creates a heartbeat that fires once `period` has elapsed since it
+    /// last fired (or since execution started).
+    pub fn every<Cmd: Command<S, C> + 'static>(period: Duration, cmd: Cmd) -> Self {
+        Self {
+            command: CommandWrapper::new(cmd),
+            interval: HeartbeatInterval::Every(period),
+        }
+    }
+}
+
+impl<S: State, C: TestContext> Clone for Heartbeat<S, C> {
+    fn clone(&self) -> Self {
+        Self {
+            command: self.command.clone(),
+            interval: self.interval,
+        }
+    }
+}
+
+/// Tracks when a heartbeat is next due during one execution.
+pub(crate) struct HeartbeatClock {
+    interval: HeartbeatInterval,
+    applied: usize,
+    last: Instant,
+}
+
+impl HeartbeatClock {
+    pub(crate) fn new(interval: HeartbeatInterval) -> Self {
+        Self {
+            interval,
+            applied: 0,
+            last: Instant::now(),
+        }
+    }
+
+    /// Advances the clock after a selected command and returns true if the
+    /// heartbeat should fire now.
+    pub(crate) fn tick(&mut self, applied: bool) -> bool {
+        if applied {
+            self.applied += 1;
+        }
+        let due = match self.interval {
+            HeartbeatInterval::Steps(n) => self.applied >= n,
+            HeartbeatInterval::Every(period) => self.last.elapsed() >= period,
+        };
+        if due {
+            self.applied = 0;
+            self.last = Instant::now();
+        }
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_step_clock_counts_applied_commands_only() {
+        let mut clock = HeartbeatClock::new(HeartbeatInterval::Steps(2));
+        let fired: Vec<bool> = [true, false, true, true, true, false]
+            .into_iter()
+            .map(|applied| clock.tick(applied))
+            .collect();
+
+        assert_eq!(fired, vec![false, false, true, false, true, false]);
+    }
+
+    #[test]
+    fn test_time_clock() {
+        let mut clock = HeartbeatClock::new(HeartbeatInterval::Every(Duration::from_millis(20)));
+        assert!(!clock.tick(true));
+        std::thread::sleep(Duration::from_millis(25));
+        assert!(clock.tick(false));
+        assert!(!clock.tick(true));
+    }
+}
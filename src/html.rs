@@ -0,0 +1,301 @@
+//! Self-contained HTML reports, for triaging long fuzzing runs.
+//!
+//! With [`Config::html_dir`](crate::Config::html_dir) set (or
+//! `MADHOUSE_HTML_DIR`), every scenario run adds a section to one HTML file
+//! per test binary invocation, named after the binary: whether it passed,
+//! the shrunk trace of each distinct failure, a chart of command coverage
+//! and a histogram of `apply()` durations. The file is rewritten after each
+//! scenario, so it is complete whenever the binary exits, and it loads no
+//! external assets.
+
+use crate::coverage::Coverage;
+use crate::junit::escape;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Scenarios reported so far by this process, per report file.
+static REPORTS: Mutex<BTreeMap<PathBuf, Vec<ScenarioReport>>> = Mutex::new(BTreeMap::new());
+
+/// Counts of durations, in power-of-two buckets of nanoseconds.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Histogram {
+    buckets: BTreeMap<u32, usize>,
+}
+
+impl Histogram {
+    /// Counts `duration` in the smallest bucket holding it.
+    pub fn record(&mut self, duration: Duration) {
+        let nanos = duration.as_nanos().max(1);
+        let bucket = u128::BITS - (nanos - 1).leading_zeros();
+        *self.buckets.entry(bucket).or_default() += 1;
+    }
+
+    /// Iterates over the non-empty buckets, as their upper bound and count,
+    /// shortest first.
+    pub fn iter(&self) -> impl Iterator<Item = (Duration, usize)> + '_ {
+        self.buckets.iter().map(|(&bucket, &count)| {
+            let nanos = 1u64.checked_shl(bucket).unwrap_or(u64::MAX);
+            (Duration::from_nanos(nanos), count)
+        })
+    }
+
+    /// Returns true if nothing was recorded.
+    pub fn is_empty(&self) -> bool {
+        self.buckets.is_empty()
+    }
+}
+
+/// A distinct failure found by a scenario run, after shrinking.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FailedCase {
+    /// Index of the case that failed first this way.
+    pub case: u32,
+    /// Failure message.
+    pub message: String,
+    /// Labels of the shrunk sequence.
+    pub labels: Vec<String>,
+}
+
+/// What one scenario run reports.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScenarioReport {
+    /// Scenario name, usually the test's name.
+    pub name: String,
+    /// Seed of the run.
+    pub seed: u64,
+    /// Cases executed.
+    pub cases: u32,
+    /// Wall-clock time of the run, shrinking included.
+    pub duration: Duration,
+    /// Distinct failures, in the order they were found.
+    pub failures: Vec<FailedCase>,
+    /// Command coverage of the generated cases.
+    pub coverage: Coverage,
+    /// Durations of the commands applied by the generated cases.
+    pub timings: Histogram,
+}
+
+/// The scenarios run by one test binary invocation.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::html::{FailedCase, HtmlReport, ScenarioReport};
+///
+/// let report = HtmlReport {
+///     title: "counter_tests".to_string(),
+///     scenarios: vec![ScenarioReport {
+///         name: "tests::counter".to_string(),
+///         cases: 10,
+///         failures: vec![FailedCase {
+///             case: 3,
+///             message: "balance < 0".to_string(),
+///             labels: vec!["INC(1)".to_string(), "DEC(2)".to_string()],
+///         }],
+///         ..Default::default()
+///     }],
+/// };
+///
+/// let html = report.to_html();
+/// assert!(html.starts_with("<!DOCTYPE html>"));
+/// assert!(html.contains(r#"<td class="fail">FAIL</td>"#));
+/// assert!(html.contains("<pre>balance &lt; 0</pre>"));
+/// assert!(html.contains("<li>DEC(2)</li>"));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HtmlReport {
+    /// Page title, usually the test binary's name.
+    pub title: String,
+    /// Scenarios in the order they finished.
+    pub scenarios: Vec<ScenarioReport>,
+}
+
+impl HtmlReport {
+    /// Renders the report as a standalone HTML page.
+    pub fn to_html(&self) -> String {
+        let mut out = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        let _ = writeln!(out, "<title>madhouse: {}</title>", escape(&self.title));
+        out.push_str(STYLE);
+        out.push_str("</head>\n<body>\n");
+        let _ = writeln!(out, "<h1>{}</h1>", escape(&self.title));
+
+        out.push_str("<table>\n<tr><th>Scenario</th><th>Result</th><th>Cases</th><th>Seed</th><th>Time</th></tr>\n");
+        for (i, scenario) in self.scenarios.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "<tr><td><a href=\"#s{}\">{}</a></td>{}<td>{}</td><td>{}</td><td>{:.2?}</td></tr>",
+                i,
+                escape(&scenario.name),
+                result(scenario),
+                scenario.cases,
+                scenario.seed,
+                scenario.duration
+            );
+        }
+        out.push_str("</table>\n");
+
+        for (i, scenario) in self.scenarios.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "<section id=\"s{}\">\n<h2>{}</h2>",
+                i,
+                escape(&scenario.name)
+            );
+            for failure in &scenario.failures {
+                let _ = writeln!(
+                    out,
+                    "<h3>Failure in case {}</h3>\n<pre>{}</pre>\n<ol>",
+                    failure.case,
+                    escape(&failure.message)
+                );
+                for label in &failure.labels {
+                    let _ = writeln!(out, "<li>{}</li>", escape(label));
+                }
+                out.push_str("</ol>\n");
+            }
+            if !scenario.coverage.is_empty() {
+                out.push_str("<h3>Command coverage</h3>\n");
+                let rows = scenario
+                    .coverage
+                    .iter()
+                    .map(|(name, c)| (name.to_string(), [c.generated, c.passed_check, c.executed]));
+                chart(&mut out, ["generated", "passed check", "executed"], rows);
+            }
+            let skips = scenario.coverage.skips();
+            if !skips.is_empty() {
+                out.push_str("<h3>Skipped by check()</h3>\n");
+                let rows = skips
+                    .iter()
+                    .map(|(name, c)| (name.to_string(), [c.selected, c.selected - c.passed_check]));
+                chart(&mut out, ["selected", "skipped"], rows);
+            }
+            if !scenario.timings.is_empty() {
+                out.push_str("<h3>apply() durations</h3>\n");
+                let rows = scenario
+                    .timings
+                    .iter()
+                    .map(|(bound, count)| (format!("&le; {:.2?}", bound), [count]));
+                chart(&mut out, ["commands"], rows);
+            }
+            out.push_str("</section>\n");
+        }
+        out.push_str("</body>\n</html>\n");
+        out
+    }
+
+    /// Writes the report to `path`, creating its directory.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, self.to_html())
+    }
+}
+
+/// Adds `scenario` to the report of this test binary in `dir`, rewriting
+/// it, and returns its path.
+pub(crate) fn record(dir: &Path, scenario: ScenarioReport) -> io::Result<PathBuf> {
+    let title = std::env::current_exe()
+        .ok()
+        .and_then(|exe| Some(exe.file_stem()?.to_str()?.to_string()))
+        .unwrap_or_else(|| "madhouse".to_string());
+    let path = dir.join(format!("{}.html", title));
+    let mut reports = REPORTS.lock().unwrap_or_else(|e| e.into_inner());
+    let scenarios = reports.entry(path.clone()).or_default();
+    scenarios.push(scenario);
+    let report = HtmlReport {
+        title,
+        scenarios: scenarios.clone(),
+    };
+    report.write(&path)?;
+    Ok(path)
+}
+
+fn result(scenario: &ScenarioReport) -> &'static str {
+    if scenario.failures.is_empty() {
+        r#"<td class="pass">PASS</td>"#
+    } else {
+        r#"<td class="fail">FAIL</td>"#
+    }
+}
+
+/// Renders rows of counts as horizontal bars, scaled to the largest count.
+/// Row names are HTML already.
+fn chart<const N: usize>(
+    out: &mut String,
+    series: [&str; N],
+    rows: impl Iterator<Item = (String, [usize; N])>,
+) {
+    let rows: Vec<_> = rows.collect();
+    let max = rows
+        .iter()
+        .flat_map(|(_, counts)| counts.iter().copied())
+        .max()
+        .unwrap_or_default()
+        .max(1);
+    out.push_str("<table class=\"chart\">\n");
+    for (name, counts) in rows {
+        let _ = write!(out, "<tr><td>{}</td><td>", name);
+        for (i, (count, title)) in counts.iter().zip(series).enumerate() {
+            let _ = write!(
+                out,
+                "<div class=\"bar b{}\" style=\"width:{:.1}%\" title=\"{}\">{}</div>",
+                i,
+                100.0 * *count as f64 / max as f64,
+                title,
+                count
+            );
+        }
+        out.push_str("</td></tr>\n");
+    }
+    out.push_str("</table>\n");
+}
+
+const STYLE: &str = "<style>
+body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; margin-bottom: 1em; }
+th, td { padding: 0.2em 0.6em; text-align: left; vertical-align: top; }
+.pass { color: #1a7f37; font-weight: bold; }
+.fail { color: #cf222e; font-weight: bold; }
+pre { background: #f6f8fa; padding: 0.5em; white-space: pre-wrap; }
+.chart td:last-child { width: 60em; }
+.bar { font-size: 0.8em; min-width: 2em; margin: 1px 0; white-space: nowrap; }
+.b0 { background: #b6d4fe; }
+.b1 { background: #a3cfbb; }
+.b2 { background: #ffe69c; }
+</style>
+";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_buckets_by_powers_of_two() {
+        let mut histogram = Histogram::default();
+        for nanos in [0, 1, 3, 4, 5, 1000] {
+            histogram.record(Duration::from_nanos(nanos));
+        }
+        let buckets: Vec<_> = histogram
+            .iter()
+            .map(|(bound, count)| (bound.as_nanos(), count))
+            .collect();
+        assert_eq!(buckets, [(1, 2), (4, 2), (8, 1), (1024, 1)]);
+
+        let report = HtmlReport {
+            title: "t".to_string(),
+            scenarios: vec![ScenarioReport {
+                timings: histogram,
+                ..Default::default()
+            }],
+        };
+        assert!(report.to_html().contains(
+            "<tr><td>&le; 1.02µs</td><td><div class=\"bar b0\" style=\"width:50.0%\" title=\"commands\">1</div></td></tr>"
+        ));
+    }
+}
@@ -0,0 +1,158 @@
+//! JUnit XML reports, for CI systems that display test results natively.
+//!
+//! With [`Config::junit_dir`](crate::Config::junit_dir) set (or
+//! `MADHOUSE_JUNIT_DIR`), every scenario run writes one `<testsuite>` file,
+//! with one `<testcase>` per generated case. Failures carry the shrunk
+//! command trace.
+
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// One generated case of a scenario run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JUnitCase {
+    /// Case name, e.g. `case 3`.
+    pub name: String,
+    /// Time spent running the case, including shrinking.
+    pub time: Duration,
+    /// Failure message, if the case failed.
+    pub failure: Option<String>,
+}
+
+/// All cases of one scenario run.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::junit::{JUnitCase, JUnitSuite};
+/// use std::time::Duration;
+///
+/// let suite = JUnitSuite {
+///     name: "tests::counter".to_string(),
+///     cases: vec![JUnitCase {
+///         name: "case 0".to_string(),
+///         time: Duration::from_millis(1500),
+///         failure: Some("minimal failing input: [INC(1) & RESET]".to_string()),
+///     }],
+/// };
+///
+/// let xml = suite.to_xml();
+/// assert!(xml.contains(r#"<testsuite name="tests::counter" tests="1" failures="1" time="1.500">"#));
+/// assert!(xml.contains("INC(1) &amp; RESET"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JUnitSuite {
+    /// Suite name, usually the test's name.
+    pub name: String,
+    /// Cases in execution order.
+    pub cases: Vec<JUnitCase>,
+}
+
+impl JUnitSuite {
+    /// Renders the suite as a JUnit XML document.
+    pub fn to_xml(&self) -> String {
+        let failures = self.cases.iter().filter(|c| c.failure.is_some()).count();
+        let total: Duration = self.cases.iter().map(|c| c.time).sum();
+
+        let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        let _ = writeln!(
+            out,
+            r#"<testsuite name="{}" tests="{}" failures="{}" time="{:.3}">"#,
+            escape(&self.name),
+            self.cases.len(),
+            failures,
+            total.as_secs_f64()
+        );
+        for case in &self.cases {
+            let _ = write!(
+                out,
+                r#"  <testcase classname="{}" name="{}" time="{:.3}""#,
+                escape(&self.name),
+                escape(&case.name),
+                case.time.as_secs_f64()
+            );
+            match &case.failure {
+                None => out.push_str("/>\n"),
+                Some(message) => {
+                    let first_line = message.lines().next().unwrap_or_default();
+                    let _ = writeln!(
+                        out,
+                        ">\n    <failure message=\"{}\">{}</failure>\n  </testcase>",
+                        escape(first_line),
+                        escape(message)
+                    );
+                }
+            }
+        }
+        out.push_str("</testsuite>\n");
+        out
+    }
+
+    /// Writes the suite to `dir`, named after the suite, and returns the
+    /// file path.
+    pub fn write(&self, dir: &Path) -> io::Result<PathBuf> {
+        let file: String = self
+            .name
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        fs::create_dir_all(dir)?;
+        let path = dir.join(format!("{}.xml", file));
+        fs::write(&path, self.to_xml())?;
+        Ok(path)
+    }
+}
+
+/// Escapes text for XML (and HTML) attributes and content.
+pub(crate) fn escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            '\n' | '\t' | '\r' => out.push(c),
+            c if c.is_control() => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_passing_cases_are_self_closing() {
+        let suite = JUnitSuite {
+            name: "s".to_string(),
+            cases: vec![JUnitCase {
+                name: "case 0".to_string(),
+                time: Duration::ZERO,
+                failure: None,
+            }],
+        };
+
+        assert_eq!(
+            suite.to_xml(),
+            concat!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+                "<testsuite name=\"s\" tests=\"1\" failures=\"0\" time=\"0.000\">\n",
+                "  <testcase classname=\"s\" name=\"case 0\" time=\"0.000\"/>\n",
+                "</testsuite>\n",
+            )
+        );
+    }
+}
@@ -29,13 +29,24 @@
 //!
 //! - **Normal**: Commands run in specified order.
 //! - **Random**: Commands chosen pseudorandomly (when MADHOUSE=1).
+//! - **Swarm**: Like random, from a random subset of the commands per case
+//!   (when MADHOUSE=swarm).
+//! - **Exhaustive**: Every sequence up to a length (when MADHOUSE=exhaustive).
 //!
 //! ## Features
 //!
 //! - Trait-based command design
 //! - Self-validating commands
+//! - Global state invariants checked after every command ([`State::invariants`])
+//! - Per-test invariants checked after every command ([`execute_commands_checked`])
+//! - Fallible commands via `try_apply`, collected in an [`ExecutionReport`]
 //! - Timing information
 //! - Test case shrinking
+//! - Failing sequences saved and replayed verbatim ([`replay!`])
+//! - Portable, seed-stable generation helpers ([`gen`])
+//! - Virtual-time timers fired by an `AdvanceClock` command ([`timer`])
+//! - Async commands (`async` feature)
+//! - Failure hooks capturing SUT artifacts (`artifacts` feature)
 //!
 //! ## Example
 //!
@@ -87,21 +98,114 @@
 //! // Manual execution.
 //! let mut state = MyState::default();
 //! let commands = vec![CommandWrapper::new(IncrementCommand)];
-//! let executed = execute_commands(&commands, &mut state);
+//! let report = execute_commands(&commands, &mut state);
+//! assert_eq!(report.executed().count(), 1);
 //! assert_eq!(state.last_mined_block, 1);
 //! ```
 
+pub mod actors;
+#[cfg(feature = "artifacts")]
+pub mod artifacts;
+#[cfg(feature = "async")]
+pub mod async_command;
+#[cfg(feature = "bench")]
+pub mod bench;
+pub mod cancel;
+pub mod checkpoint;
+#[cfg(feature = "cli")]
+pub mod cli;
+pub mod clock;
+pub mod config;
+pub mod constraints;
+pub mod context;
+pub mod corpus;
+pub mod coverage;
+pub mod diff;
+pub mod differential;
+pub mod effects;
+pub mod env;
+pub mod explore;
+pub mod feature_flags;
+pub mod fixture;
+#[cfg(feature = "fuzz")]
+pub mod fuzz;
+pub mod gen;
+pub mod golden;
+pub mod graph;
+pub mod guidance;
+pub mod heartbeat;
+pub mod html;
+pub mod junit;
+#[cfg(feature = "loom")]
+pub mod loom;
+pub mod markov;
+pub mod mermaid;
+pub mod nemesis;
+pub mod net;
+pub mod notify;
+pub mod observer;
+pub mod panics;
+pub mod predicates;
+pub mod process;
+#[cfg(feature = "json")]
+pub mod projection;
+pub mod quarantine;
+#[cfg(feature = "serde")]
+pub mod registry;
+pub mod regression;
+pub mod report;
+pub mod saturation;
+pub mod scaffold;
+pub mod scenario;
+pub mod schedule;
+pub mod sequence;
+pub mod shrink;
+#[cfg(feature = "tracing")]
+mod spans;
+pub mod stats;
+pub mod summary;
+pub mod sut;
+pub mod testgen;
+pub mod timer;
+pub mod timings;
+pub mod tla;
+mod trace;
+mod watchdog;
+
+#[cfg(feature = "async")]
+pub use async_command::{
+    execute_commands_async, AsyncCommand, AsyncCommandWrapper, ErasedAsyncCommand,
+};
+pub use checkpoint::execute_commands_checkpointed;
+pub use config::{Config, Format, Mode, Verbosity};
+pub use env::Env;
+pub use heartbeat::{Heartbeat, HeartbeatInterval};
+#[cfg(feature = "derive")]
+pub use madhouse_derive::Command;
+pub use observer::{execute_commands_with, Observer};
+pub use report::{BrokenInvariant, ExecutionReport, NoOp, StateChange, StepOutcome, StepReport};
+pub use scenario::{CommandSet, Scenario};
+
+/// Paths used by `#[derive(Command)]` and [`commands!`]; not public API.
+#[doc(hidden)]
+pub mod __private {
+    pub use proptest;
+}
+
 use proptest::prelude::Strategy;
-use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::any::Any;
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant, SystemTime};
+use trace::Trace;
 
 /// System state being tested.
 ///
 /// # Examples
 ///
 /// ```
-/// use madhouse::State;
+/// use madhouse::{InvariantViolation, State};
 ///
 /// #[derive(Debug, Default)]
 /// struct CounterState {
@@ -110,9 +214,75 @@ use std::time::Instant;
 ///     increment_count: u64,
 /// }
 ///
-/// impl State for CounterState {}
+/// impl State for CounterState {
+///     fn invariants(&self) -> Result<(), InvariantViolation> {
+///         if self.value > self.max_reached {
+///             return Err(InvariantViolation::new("value above max_reached"));
+///         }
+///         Ok(())
+///     }
+/// }
 /// ```
-pub trait State: Debug {}
+pub trait State: Debug {
+    /// Checks properties that must hold after every applied command.
+    ///
+    /// The executor calls this after each applied command (and heartbeat)
+    /// and stops at the first violation, recording the index and label of
+    /// the command that broke it. The default accepts every state.
+    fn invariants(&self) -> Result<(), InvariantViolation> {
+        Ok(())
+    }
+
+    /// Identifies the state, to count the distinct states a run reaches
+    /// (see [`saturation`]).
+    ///
+    /// The default hashes the `Debug` output. Override it to hash only the
+    /// fields that matter, or if `Debug` includes values that differ
+    /// between equivalent states (timestamps, ids).
+    fn fingerprint(&self) -> u64 {
+        gen::stable_hash(&format!("{:?}", self))
+    }
+}
+
+/// Error returned by [`State::invariants`] when the state is inconsistent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvariantViolation {
+    message: String,
+}
+
+impl InvariantViolation {
+    /// Creates a new violation with the given message.
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+        }
+    }
+
+    /// Returns the violation message.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl Display for InvariantViolation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for InvariantViolation {}
+
+impl From<String> for InvariantViolation {
+    fn from(message: String) -> Self {
+        Self::new(message)
+    }
+}
+
+impl From<&str> for InvariantViolation {
+    fn from(message: &str) -> Self {
+        Self::new(message)
+    }
+}
 
 /// Test configuration.
 ///
@@ -129,7 +299,19 @@ pub trait State: Debug {}
 ///
 /// impl TestContext for CounterContext {}
 /// ```
-pub trait TestContext: Debug + Clone {}
+pub trait TestContext: Debug + Clone {
+    /// Builds the context from environment variables, so CI can tune it
+    /// without recompiling (see [`context`]).
+    ///
+    /// The default ignores the environment and returns
+    /// [`Default::default`].
+    fn from_env() -> Self
+    where
+        Self: Sized + Default,
+    {
+        Self::default()
+    }
+}
 
 /// Commands in the stateful testing framework.
 ///
@@ -198,15 +380,146 @@ pub trait Command<S: State, C: TestContext> {
     /// * `state` - Current state to check against.
     fn check(&self, state: &S) -> bool;
 
+    /// Checks if the command can be applied, explaining why not.
+    ///
+    /// The executor calls this method instead of [`check`](Self::check)
+    /// and reports the reason of each skipped command, so preconditions
+    /// that never hold do not go unnoticed. The default delegates to
+    /// `check` and gives no specific reason; commands overriding it should
+    /// keep `check` consistent.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use madhouse::{Command, CommandWrapper, SkipReason, State, TestContext};
+    /// use proptest::prelude::*;
+    /// use std::sync::Arc;
+    ///
+    /// #[derive(Debug, Default)]
+    /// struct Counter { value: u64 }
+    /// impl State for Counter {}
+    ///
+    /// #[derive(Debug, Clone, Default)]
+    /// struct Ctx {}
+    /// impl TestContext for Ctx {}
+    ///
+    /// struct Reset;
+    /// impl Command<Counter, Ctx> for Reset {
+    ///     fn check(&self, state: &Counter) -> bool { self.check_detailed(state).is_ok() }
+    ///     fn check_detailed(&self, state: &Counter) -> Result<(), SkipReason> {
+    ///         match state.value {
+    ///             0 => Err(SkipReason::new("counter already zero")),
+    ///             _ => Ok(()),
+    ///         }
+    ///     }
+    ///     fn apply(&self, state: &mut Counter) { state.value = 0; }
+    ///     fn label(&self) -> String { "RESET".to_string() }
+    ///     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Counter, Ctx>> {
+    ///         Just(CommandWrapper::new(Reset))
+    ///     }
+    /// }
+    ///
+    /// let report = madhouse::execute_commands_silent(&[CommandWrapper::new(Reset)], &mut Counter::default());
+    /// assert_eq!(report.steps[0].skip_reason().unwrap().message(), "counter already zero");
+    /// ```
+    ///
+    /// # Arguments
+    /// * `state` - Current state to check against.
+    fn check_detailed(&self, state: &S) -> Result<(), SkipReason> {
+        if self.check(state) {
+            Ok(())
+        } else {
+            Err(SkipReason::default())
+        }
+    }
+
     /// Applies the command to the state, modifying it.
     ///
     /// # Arguments
     /// * `state` - State to modify.
     fn apply(&self, state: &mut S);
 
+    /// Applies the command to the state, drawing randomness from `env`.
+    ///
+    /// Commands needing randomness override this instead of reaching for
+    /// `thread_rng`, and implement `apply` as
+    /// `self.apply_with(state, &mut Env::current())`; the [`Env`] RNG is
+    /// seeded from the case, so replays draw the same values (see
+    /// [`env`]). The default delegates to `apply`.
+    ///
+    /// # Arguments
+    /// * `state` - State to modify.
+    /// * `env` - Environment of the executing step.
+    fn apply_with(&self, state: &mut S, env: &mut Env) {
+        let _ = env;
+        self.apply(state);
+    }
+
+    /// Applies the command to the state, reporting failures as errors.
+    ///
+    /// Commands that drive real systems (network calls, process spawning)
+    /// can override this instead of panicking inside `apply`. The executor
+    /// always calls this method; the default delegates to `apply_with`.
+    ///
+    /// # Arguments
+    /// * `state` - State to modify.
+    fn try_apply(&self, state: &mut S) -> Result<(), CommandError> {
+        self.apply_with(state, &mut Env::current());
+        Ok(())
+    }
+
+    /// Returns how long applying the command may take, if limited.
+    ///
+    /// A command that takes longer fails its step with a
+    /// [`CommandError`] naming the limit. Since a running command cannot be
+    /// stopped, it fails once it returns; wrapped with
+    /// [`CommandWrapper::detachable`], it runs on a worker thread and fails
+    /// as soon as the limit is reached, even if it hangs (e.g. waiting on
+    /// an external process that never answers). The default is no limit.
+    fn timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Releases resources the command acquired when it was applied.
+    ///
+    /// Called once for every command that was applied (successfully, with
+    /// an error or with a panic), in reverse order, after the sequence ran,
+    /// including when a later command panicked. Commands that start processes or open
+    /// connections stop them here. The default does nothing.
+    ///
+    /// # Arguments
+    /// * `state` - State after the sequence ran.
+    fn cleanup(&self, state: &mut S) {
+        let _ = state;
+    }
+
     /// Returns a human-readable label for the command.
     fn label(&self) -> String;
 
+    /// Returns the group the command is reported under, so that summaries
+    /// of models with many commands can aggregate them by subsystem (see
+    /// [`ExecutionReport::groups`]).
+    ///
+    /// The default is the module path of the implementing type.
+    fn group(&self) -> String {
+        report::module_group(std::any::type_name::<Self>()).to_string()
+    }
+
+    /// Returns how often random mode picks the command, relative to the
+    /// other commands of the scenario.
+    ///
+    /// Used when the command is added without a weight of its own, e.g.
+    /// `Inc` rather than `Inc: 5` in [`scenario!`], or with
+    /// [`Scenario::add`]. It is read once, from the scenario's context,
+    /// when the scenario is built. The default is 1.
+    fn weight(ctx: &C) -> u32
+    where
+        Self: Sized,
+    {
+        let _ = ctx;
+        1
+    }
+
     /// Builds a proptest strategy for generating instances of this command.
     ///
     /// # Arguments
@@ -214,6 +527,193 @@ pub trait Command<S: State, C: TestContext> {
     fn build(ctx: Arc<C>) -> impl Strategy<Value = CommandWrapper<S, C>>
     where
         Self: Sized;
+
+    /// Rebuilds a command from one of its labels, for replaying a saved
+    /// failing sequence (see [`Scenario::replay_from_file`]).
+    ///
+    /// Returns `None` if the label was not produced by this command. The
+    /// default never recognizes a label, so commands have to opt in.
+    ///
+    /// # Arguments
+    /// * `label` - Label previously returned by [`Command::label`].
+    /// * `ctx` - Test context used to parameterize the command.
+    fn parse(label: &str, ctx: Arc<C>) -> Option<CommandWrapper<S, C>>
+    where
+        Self: Sized,
+    {
+        let _ = (label, ctx);
+        None
+    }
+
+    /// Returns simpler variants of this command, simplest first, for
+    /// shrinking a failing sequence.
+    ///
+    /// Shrinking tries these once the strategy's own shrinking is done,
+    /// keeping the first variant that still fails and then shrinking that
+    /// one in turn. Commands with many fields (a transaction, a config
+    /// change) can drop or reset fields structurally this way. The default
+    /// returns no variants.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use madhouse::{Command, CommandWrapper, State, TestContext};
+    /// use proptest::prelude::*;
+    /// use std::sync::Arc;
+    ///
+    /// #[derive(Debug, Default)]
+    /// struct Ledger { total: u64 }
+    /// impl State for Ledger {}
+    ///
+    /// #[derive(Debug, Clone, Default)]
+    /// struct Ctx {}
+    /// impl TestContext for Ctx {}
+    ///
+    /// #[derive(Clone)]
+    /// struct Transfer { amounts: Vec<u64>, memo: Option<String> }
+    ///
+    /// impl Command<Ledger, Ctx> for Transfer {
+    ///     fn check(&self, _state: &Ledger) -> bool { true }
+    ///     fn apply(&self, state: &mut Ledger) { state.total += self.amounts.iter().sum::<u64>(); }
+    ///     fn label(&self) -> String { format!("TRANSFER({:?}, {:?})", self.amounts, self.memo) }
+    ///     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Ledger, Ctx>> {
+    ///         Just(CommandWrapper::new(Transfer { amounts: vec![1, 2], memo: None }))
+    ///     }
+    ///     fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+    ///         let mut variants = Vec::new();
+    ///         if self.memo.is_some() {
+    ///             variants.push(Transfer { memo: None, ..self.clone() });
+    ///         }
+    ///         // Drop one transfer leg at a time.
+    ///         for i in 0..self.amounts.len() {
+    ///             let mut amounts = self.amounts.clone();
+    ///             amounts.remove(i);
+    ///             variants.push(Transfer { amounts, ..self.clone() });
+    ///         }
+    ///         Box::new(variants.into_iter())
+    ///     }
+    /// }
+    ///
+    /// let transfer = Transfer { amounts: vec![5, 7], memo: Some("rent".into()) };
+    /// let variants: Vec<String> = transfer.shrink().map(|t| t.label()).collect();
+    /// assert_eq!(variants[0], "TRANSFER([5, 7], None)");
+    /// ```
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>>
+    where
+        Self: Sized + 'static,
+    {
+        Box::new(std::iter::empty())
+    }
+}
+
+/// Error reported by a command that failed to apply.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::CommandError;
+///
+/// let err = CommandError::new("connection refused");
+/// assert_eq!(err.to_string(), "connection refused");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandError {
+    message: String,
+}
+
+impl CommandError {
+    /// Creates a new error with the given message.
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+        }
+    }
+
+    /// Returns the error message.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl Display for CommandError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for CommandError {}
+
+impl From<String> for CommandError {
+    fn from(message: String) -> Self {
+        Self::new(message)
+    }
+}
+
+impl From<&str> for CommandError {
+    fn from(message: &str) -> Self {
+        Self::new(message)
+    }
+}
+
+impl From<std::io::Error> for CommandError {
+    fn from(err: std::io::Error) -> Self {
+        Self::new(err.to_string())
+    }
+}
+
+/// Why a command's precondition rejected it (see
+/// [`Command::check_detailed`]).
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::SkipReason;
+///
+/// assert_eq!(SkipReason::new("no funds").to_string(), "no funds");
+/// assert_eq!(SkipReason::default().to_string(), "check() returned false");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkipReason {
+    message: String,
+}
+
+impl SkipReason {
+    /// Creates a new reason with the given message.
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+        }
+    }
+
+    /// Returns the reason message.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+/// The reason of commands that only implement [`Command::check`].
+impl Default for SkipReason {
+    fn default() -> Self {
+        Self::new("check() returned false")
+    }
+}
+
+impl Display for SkipReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl From<String> for SkipReason {
+    fn from(message: String) -> Self {
+        Self::new(message)
+    }
+}
+
+impl From<&str> for SkipReason {
+    fn from(message: &str) -> Self {
+        Self::new(message)
+    }
 }
 
 /// Wrapper for command trait objects.
@@ -251,12 +751,30 @@ pub trait Command<S: State, C: TestContext> {
 /// let cmd = IncrementCmd;
 /// let wrapper = CommandWrapper::new(cmd);
 /// assert_eq!(wrapper.command.label(), "INCREMENT");
+/// assert!(wrapper.downcast_ref::<IncrementCmd>().is_some());
 /// ```
 pub struct CommandWrapper<S: State, C: TestContext> {
     /// The wrapped command trait object.
     pub command: Arc<dyn Command<S, C>>,
+    /// The same command, for [`downcast_ref`](Self::downcast_ref).
+    any: Arc<dyn Any>,
+    /// Produces the command's [`Command::shrink`] variants, wrapped.
+    shrinker: Arc<Shrinker<S, C>>,
+    /// Type name of the command, for [`testgen`].
+    type_name: &'static str,
+    /// The command type's [`Command::parse`], for [`testgen`].
+    parse: LabelParser<S, C>,
+    /// Applies the command on a worker thread under its timeout, if built
+    /// with [`detachable`](Self::detachable).
+    detached: Option<Arc<Detached<S>>>,
 }
 
+type Shrinker<S, C> = dyn Fn() -> Box<dyn Iterator<Item = CommandWrapper<S, C>>>;
+
+type LabelParser<S, C> = fn(&str, Arc<C>) -> Option<CommandWrapper<S, C>>;
+
+type Detached<S> = dyn Fn(&mut S, Duration) -> watchdog::Outcome;
+
 impl<S: State, C: TestContext> CommandWrapper<S, C> {
     /// Creates a new command wrapper for the given command.
     ///
@@ -264,16 +782,114 @@ impl<S: State, C: TestContext> CommandWrapper<S, C> {
     ///
     /// * `cmd` - The command to wrap.
     pub fn new<Cmd: Command<S, C> + 'static>(cmd: Cmd) -> Self {
+        let command = Arc::new(cmd);
+        let shrunk = Arc::clone(&command);
+        // A closure rather than `CommandWrapper::new` itself: it captures
+        // nothing, so the iterator is 'static without bounding `S` and `C`.
+        Self {
+            any: Arc::clone(&command) as Arc<dyn Any>,
+            command,
+            shrinker: Arc::new(move || Box::new(shrunk.shrink().map(|c| CommandWrapper::new(c)))),
+            type_name: std::any::type_name::<Cmd>(),
+            parse: Cmd::parse,
+            detached: None,
+        }
+    }
+
+    /// Creates a command wrapper whose [`Command::timeout`] is enforced on
+    /// a worker thread.
+    ///
+    /// The command is applied on its own thread, handed the state. If it
+    /// is still running at its limit, its step fails at once, naming the
+    /// limit, instead of the whole run hanging, and execution stops there:
+    /// the thread is left running with the state it was handed, and the
+    /// caller gets a default state back. That thread is never joined; it
+    /// ends when the command returns or the process exits, so each
+    /// timed-out step, including reruns while shrinking, leaves one behind.
+    /// Commands built with [`new`](Self::new) fail their step only once
+    /// they return.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use madhouse::{execute_commands_silent, Command, CommandWrapper, State, TestContext};
+    /// use proptest::prelude::*;
+    /// use std::sync::Arc;
+    /// use std::time::Duration;
+    ///
+    /// #[derive(Debug, Default)]
+    /// struct Node { height: u64 }
+    /// impl State for Node {}
+    ///
+    /// #[derive(Debug, Clone, Default)]
+    /// struct Ctx {}
+    /// impl TestContext for Ctx {}
+    ///
+    /// struct Mine;
+    /// impl Command<Node, Ctx> for Mine {
+    ///     fn check(&self, _state: &Node) -> bool { true }
+    ///     fn apply(&self, _state: &mut Node) {
+    ///         std::thread::sleep(Duration::from_secs(60)); // The node never answers.
+    ///     }
+    ///     fn label(&self) -> String { "MINE".to_string() }
+    ///     fn timeout(&self) -> Option<Duration> { Some(Duration::from_millis(10)) }
+    ///     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Node, Ctx>> {
+    ///         Just(CommandWrapper::detachable(Mine))
+    ///     }
+    /// }
+    ///
+    /// let commands = vec![CommandWrapper::detachable(Mine); 2];
+    /// let report = execute_commands_silent(&commands, &mut Node::default());
+    /// assert_eq!(report.steps.len(), 1);
+    /// assert_eq!(
+    ///     report.failure_message().unwrap(),
+    ///     "command 1 (MINE) failed: timed out: still running after its limit of 10ms, \
+    ///      left running detached"
+    /// );
+    /// ```
+    pub fn detachable<Cmd>(cmd: Cmd) -> Self
+    where
+        Cmd: Command<S, C> + Send + Sync + 'static,
+        S: Send + Default + 'static,
+    {
+        let command = Arc::new(cmd);
+        let shrunk = Arc::clone(&command);
+        let detached = Arc::clone(&command);
         Self {
-            command: Arc::new(cmd),
+            any: Arc::clone(&command) as Arc<dyn Any>,
+            command,
+            shrinker: Arc::new(move || {
+                Box::new(shrunk.shrink().map(|c| CommandWrapper::detachable(c)))
+            }),
+            type_name: std::any::type_name::<Cmd>(),
+            parse: Cmd::parse,
+            detached: Some(Arc::new(move |state: &mut S, limit| {
+                let command = Arc::clone(&detached);
+                watchdog::detach(limit, state, move |state| command.try_apply(state))
+            })),
         }
     }
+
+    /// Returns the wrapped command's [`Command::shrink`] variants.
+    pub fn shrink(&self) -> Box<dyn Iterator<Item = CommandWrapper<S, C>>> {
+        (self.shrinker)()
+    }
+
+    /// Returns the wrapped command if it is a `T`.
+    pub fn downcast_ref<T: 'static>(&self) -> Option<&T> {
+        self.any.downcast_ref()
+    }
 }
 
 impl<S: State, C: TestContext> Clone for CommandWrapper<S, C> {
     fn clone(&self) -> Self {
         Self {
             command: Arc::clone(&self.command),
+            any: Arc::clone(&self.any),
+            shrinker: Arc::clone(&self.shrinker),
+            type_name: self.type_name,
+            parse: self.parse,
+            detached: self.detached.clone(),
         }
     }
 }
@@ -314,7 +930,7 @@ macro_rules! prop_allof {
     ($first:expr, $($rest:expr),+ $(,)?) => {
         {
             let first_strat = $first.prop_map(|val| vec![val]);
-            let rest_strat = prop_allof!($($rest),+);
+            let rest_strat = $crate::prop_allof!($($rest),+);
 
             (first_strat, rest_strat).prop_map(|(mut first_vec, rest_vec)| {
                 first_vec.extend(rest_vec);
@@ -324,20 +940,97 @@ macro_rules! prop_allof {
     };
 }
 
-/// Executes a sequence of commands and returns those executed.
+/// Creates a strategy that returns a tuple of the values of all provided
+/// strategies, each keeping its own type.
+///
+/// Unlike [`prop_allof!`], the strategies need not produce the same type,
+/// so a command sequence can be drawn together with auxiliary data, e.g.
+/// the configuration it runs under. Each value shrinks on its own. Up to
+/// 12 strategies are supported, as by proptest's tuple strategies; a single
+/// strategy yields a one-element tuple.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::{prop_allof, prop_allof_t};
+/// use proptest::prelude::*;
+///
+/// #[derive(Debug, Clone)]
+/// struct NetConfig { miners: u8 }
+///
+/// let combined = prop_allof_t![
+///     prop_allof![Just("START_MINER"), Just("MINE")],
+///     (1..=4u8).prop_map(|miners| NetConfig { miners }),
+///     any::<bool>(),
+/// ];
+///
+/// proptest!(|((commands, config, _flag) in combined)| {
+///     assert_eq!(commands, vec!["START_MINER", "MINE"]);
+///     assert!((1..=4).contains(&config.miners));
+/// });
+/// ```
+#[macro_export]
+macro_rules! prop_allof_t {
+    ($($strat:expr),+ $(,)?) => {
+        ($($strat,)+)
+    };
+}
+
+/// Creates a strategy returning the values of two lists of strategies,
+/// each list in order, randomly interleaved.
+///
+/// Each side is built as with [`prop_allof!`], so every actor's commands
+/// keep their order while the interleaving between actors varies. Shrinks
+/// toward all of the first list, then all of the second (see
+/// [`gen::interleave`]).
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::prop_interleave;
+/// use proptest::prelude::*;
+///
+/// let merged = prop_interleave![[Just("a1"), Just("a2")], [Just("b1"), Just("b2")]];
+///
+/// proptest!(|(v in merged)| {
+///     let of = |actor: char| v.iter().filter(|s| s.starts_with(actor)).cloned().collect::<Vec<_>>();
+///     assert_eq!(of('a'), ["a1", "a2"]);
+///     assert_eq!(of('b'), ["b1", "b2"]);
+/// });
+/// ```
+#[macro_export]
+macro_rules! prop_interleave {
+    ([$($first:expr),+ $(,)?], [$($second:expr),+ $(,)?] $(,)?) => {
+        $crate::gen::interleave(
+            $crate::prop_allof!($($first),+),
+            $crate::prop_allof!($($second),+),
+            [$(stringify!($first)),+, $(stringify!($second)),+].len(),
+        )
+    };
+}
+
+/// Executes a sequence of commands and reports what happened.
 ///
 /// This function:
 /// 1. Filters commands based on check() method.
-/// 2. Applies each valid command to the state.
+/// 2. Applies each valid command to the state via try_apply().
 /// 3. Measures execution time.
-/// 4. Prints a summary of selected and executed commands.
+/// 4. Prints a summary of selected and executed commands, unless
+///    `MADHOUSE_VERBOSITY=quiet`; `verbose` also prints the final state.
+///    Use [`execute_commands_silent`] to print nothing regardless.
+///
+/// Errors returned by `try_apply` do not stop execution; they are collected
+/// in the report so the caller can decide whether they are fatal. After
+/// each applied command, [`State::invariants`] is checked; execution stops at
+/// the first violation, which is recorded in
+/// [`ExecutionReport::broken_invariant`].
 ///
 /// # Arguments
 /// * `commands` - Slice of commands to potentially execute.
 /// * `state` - Mutable state that commands will modify.
 ///
 /// # Returns
-/// A vector of references to commands that were executed.
+/// An [`ExecutionReport`] with one entry per selected command.
 ///
 /// # Examples
 ///
@@ -377,116 +1070,479 @@ macro_rules! prop_allof {
 ///     CommandWrapper::new(IncrementCommand(5)),
 /// ];
 ///
-/// let executed = execute_commands(&commands, &mut state);
-/// assert_eq!(executed.len(), 2);
+/// let report = execute_commands(&commands, &mut state);
+/// assert_eq!(report.executed().count(), 2);
+/// assert!(report.is_ok());
 /// assert_eq!(state.value, 8);
 /// ```
-pub fn execute_commands<'a, S: State, C: TestContext>(
-    commands: &'a [CommandWrapper<S, C>],
+pub fn execute_commands<S: State, C: TestContext>(
+    commands: &[CommandWrapper<S, C>],
     state: &mut S,
-) -> Vec<&'a CommandWrapper<S, C>> {
-    let mut executed = Vec::with_capacity(commands.len());
-    let mut execution_times = Vec::with_capacity(commands.len());
-
-    // ANSI color codes.
-    let yellow = "\x1b[33m";
-    let green = "\x1b[32m";
-    let reset = "\x1b[0m";
-
-    for cmd in commands {
-        if cmd.command.check(state) {
-            let start = Instant::now();
-            cmd.command.apply(state);
-            let duration = start.elapsed();
-            executed.push(cmd);
-            execution_times.push(duration);
-        }
-    }
-
-    println!("Selected:");
-    for (i, cmd) in commands.iter().enumerate() {
-        println!("{:02}. {}{}{}", i + 1, yellow, cmd.command.label(), reset);
-    }
-
-    println!("Executed:");
-    for (i, (cmd, time)) in executed.iter().zip(execution_times.iter()).enumerate() {
-        println!(
-            "{:02}. {}{}{} ({:.2?})",
-            i + 1,
-            green,
-            cmd.command.label(),
-            reset,
-            time
-        );
-    }
-
-    executed
+) -> ExecutionReport {
+    execute_commands_with_heartbeats(commands, state, &[])
 }
 
-/// Macro for running stateful tests.
+/// Executes a sequence of commands, injecting heartbeat commands at fixed
+/// intervals.
 ///
-/// While commands execute in the order specified in the macro,
-/// their generation depends on several factors:
+/// Behaves like [`execute_commands`]. After each selected command, every
+/// heartbeat that is due (see [`HeartbeatInterval`]) is checked and applied.
+/// Heartbeats are recorded in [`ExecutionReport::heartbeats`], not in
+/// [`ExecutionReport::steps`], so step indices always match the selected
+/// sequence.
 ///
-/// - In normal mode: Commands run in the order provided, but proptest strategies
-///   may generate different values across runs unless the same seed is used.
-/// - With MADHOUSE=1: The command sequence is permutated randomly.
-/// - With PROPTEST_MAX_SHRINK_ITERS: Failed tests can be shrunk to minimal cases.
+/// # Arguments
+/// * `commands` - Slice of commands to potentially execute.
+/// * `state` - Mutable state that commands will modify.
+/// * `heartbeats` - Commands to inject at fixed intervals.
+pub fn execute_commands_with_heartbeats<S: State, C: TestContext>(
+    commands: &[CommandWrapper<S, C>],
+    state: &mut S,
+    heartbeats: &[Heartbeat<S, C>],
+) -> ExecutionReport {
+    let report = run_commands(commands, state, heartbeats, &mut ());
+    print_summary(&report, state);
+    report
+}
+
+/// Executes a sequence of commands like [`execute_commands`], checking
+/// `invariant` on the state after each applied command.
 ///
-/// The framework honors any PROPTEST environment variables. By default,
-/// the scenario runs with 1 test case and 0 shrink iterations to accommodate
-/// heavyweight non-deterministic test setups found in complex systems.
+/// For properties a test needs that do not belong in
+/// [`State::invariants`], e.g. ones tied to a single test's setup. The
+/// closure is evaluated after the state's own invariants; execution stops at
+/// the first violation of either, which is recorded in
+/// [`ExecutionReport::broken_invariant`].
 ///
 /// # Arguments
-///
-/// * `test_context` - Test context for creating commands.
-/// * `command1, command2, ...` - Either command types (e.g., `Inc`) or
-///   fixed command instances (e.g., `(Inc { amount: 3 })`). Note that
-///   expressions must be wrapped in parentheses.
+/// * `commands` - Slice of commands to potentially execute.
+/// * `state` - Mutable state that commands will modify.
+/// * `invariant` - Check run on the state after every applied command.
 ///
 /// # Examples
 ///
 /// ```
-/// use madhouse::{
-///     execute_commands, prop_allof, Command, CommandWrapper, State,
-///     TestContext, scenario
-/// };
-/// use proptest::prelude::Just;
-/// use proptest::strategy::Strategy;
+/// use madhouse::{execute_commands_checked, Command, CommandWrapper, State, TestContext};
+/// use proptest::prelude::*;
 /// use std::sync::Arc;
 ///
-/// // Define your application state.
 /// #[derive(Debug, Default)]
-/// struct AppState {
-///     counter: u64,
-/// }
-/// impl State for AppState {}
+/// struct Counter { value: u64 }
+/// impl State for Counter {}
 ///
-/// // Define your test context.
 /// #[derive(Debug, Clone, Default)]
-/// struct AppContext {}
-/// impl TestContext for AppContext {}
+/// struct Ctx {}
+/// impl TestContext for Ctx {}
 ///
-/// // Define some commands.
-/// struct IncrementCommand {
-///     amount: u64,
-/// }
-/// impl Command<AppState, AppContext> for IncrementCommand {
-///     fn check(&self, _state: &AppState) -> bool { true }
-///     fn apply(&self, state: &mut AppState) { state.counter += self.amount; }
-///     fn label(&self) -> String { format!("INCREMENT({})", self.amount) }
-///     fn build(_ctx: Arc<AppContext>) -> impl Strategy<Value = CommandWrapper<AppState, AppContext>> {
-///         (1..=5u64).prop_map(|n| CommandWrapper::new(IncrementCommand { amount: n }))
+/// struct Inc(u64);
+/// impl Command<Counter, Ctx> for Inc {
+///     fn check(&self, _state: &Counter) -> bool { true }
+///     fn apply(&self, state: &mut Counter) { state.value += self.0; }
+///     fn label(&self) -> String { format!("INC({})", self.0) }
+///     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Counter, Ctx>> {
+///         Just(CommandWrapper::new(Inc(1)))
 ///     }
 /// }
 ///
-/// struct ResetCommand;
-/// impl Command<AppState, AppContext> for ResetCommand {
-///     fn check(&self, state: &AppState) -> bool { state.counter > 0 }
-///     fn apply(&self, state: &mut AppState) { state.counter = 0; }
-///     fn label(&self) -> String { "RESET".to_string() }
-///     fn build(_ctx: Arc<AppContext>) -> impl Strategy<Value = CommandWrapper<AppState, AppContext>> {
-///         Just(CommandWrapper::new(ResetCommand))
+/// let commands = vec![
+///     CommandWrapper::new(Inc(2)),
+///     CommandWrapper::new(Inc(3)),
+///     CommandWrapper::new(Inc(4)),
+/// ];
+/// let mut state = Counter::default();
+///
+/// let report = execute_commands_checked(&commands, &mut state, |state| {
+///     if state.value % 2 == 1 {
+///         return Err(format!("odd value {}", state.value).into());
+///     }
+///     Ok(())
+/// });
+///
+/// let broken = report.broken_invariant.unwrap();
+/// assert_eq!((broken.index, broken.label.as_str()), (1, "INC(3)"));
+/// assert_eq!(broken.violation.message(), "odd value 5");
+/// assert_eq!(report.steps.len(), 2);
+/// ```
+pub fn execute_commands_checked<S: State, C: TestContext>(
+    commands: &[CommandWrapper<S, C>],
+    state: &mut S,
+    invariant: impl Fn(&S) -> Result<(), InvariantViolation>,
+) -> ExecutionReport {
+    let report = run_commands_traced(
+        commands,
+        state,
+        &[],
+        &mut (),
+        &mut Trace::default(),
+        Some(&invariant),
+    );
+    print_summary(&report, state);
+    report
+}
+
+/// Prints the summary of a report, and the final state if verbose, as
+/// `MADHOUSE_VERBOSITY` and `MADHOUSE_FORMAT` ask.
+fn print_summary<S: State>(report: &ExecutionReport, state: &S) {
+    let verbosity = config::Verbosity::from_env();
+    let format = config::Format::from_env();
+    if verbosity >= config::Verbosity::Normal {
+        report.print(format, state);
+    }
+    if verbosity >= config::Verbosity::Verbose && format == config::Format::Text {
+        println!("Final state: {:#?}", state);
+    }
+}
+
+/// Executes a sequence of commands like [`execute_commands`], without
+/// printing anything.
+///
+/// For callers running many sequences, e.g. thousands of cases in CI, that
+/// inspect the returned [`ExecutionReport`] themselves.
+///
+/// # Arguments
+/// * `commands` - Slice of commands to potentially execute.
+/// * `state` - Mutable state that commands will modify.
+pub fn execute_commands_silent<S: State, C: TestContext>(
+    commands: &[CommandWrapper<S, C>],
+    state: &mut S,
+) -> ExecutionReport {
+    run_commands(commands, state, &[], &mut ())
+}
+
+/// Executes commands and heartbeats without printing the summary.
+pub(crate) fn run_commands<S: State, C: TestContext>(
+    commands: &[CommandWrapper<S, C>],
+    state: &mut S,
+    heartbeats: &[Heartbeat<S, C>],
+    observer: &mut impl Observer<S, C>,
+) -> ExecutionReport {
+    run_commands_traced(
+        commands,
+        state,
+        heartbeats,
+        observer,
+        &mut Trace::default(),
+        None,
+    )
+}
+
+/// Check on the state, run after each applied command along with
+/// [`State::invariants`].
+type Invariant<'a, S> = dyn Fn(&S) -> Result<(), InvariantViolation> + 'a;
+
+/// Executes commands and heartbeats, recording the steps in `trace` and
+/// checking `invariant`, if any, after each applied one.
+pub(crate) fn run_commands_traced<S: State, C: TestContext>(
+    commands: &[CommandWrapper<S, C>],
+    state: &mut S,
+    heartbeats: &[Heartbeat<S, C>],
+    observer: &mut impl Observer<S, C>,
+    trace: &mut Trace,
+    invariant: Option<&Invariant<'_, S>>,
+) -> ExecutionReport {
+    #[cfg(feature = "tracing")]
+    let span = spans::run(commands.len());
+    let mut report = ExecutionReport::default();
+    let effects = effects::Scope::enter();
+    let labels = stats::Scope::enter();
+    let _env = env::Scope::enter();
+    let processes = process::Scope::enter();
+    let _clock = clock::Scope::enter();
+    let mut guard = Cleanup {
+        state,
+        applied: Vec::new(),
+    };
+    let state = &mut *guard.state;
+    let mut clocks: Vec<_> = heartbeats
+        .iter()
+        .map(|hb| heartbeat::HeartbeatClock::new(hb.interval))
+        .collect();
+
+    'steps: for (index, cmd) in commands.iter().enumerate() {
+        if trace.is_cancelled() {
+            report.cancelled = true;
+            break;
+        }
+        // Pushed first so that a command panicking in `apply` is cleaned up.
+        guard.applied.push((index, cmd));
+        let before = trace.before_step(&report, state);
+        let fingerprint = trace.fingerprint(state);
+        let (step, abandoned) = trace.guard(
+            &report,
+            index,
+            || cmd.command.label(),
+            || run_step(index, cmd, state, observer),
+        );
+        if abandoned {
+            // The state is with the command's worker: nothing is left to
+            // check or clean up.
+            guard.applied.clear();
+            trace.step(&mut report, step);
+            break;
+        }
+        let applied = step.was_applied();
+        if !applied {
+            guard.applied.pop();
+        }
+        let broken = applied
+            .then(|| check_step(&step, state, invariant))
+            .flatten();
+        let failed = broken.is_some() || step.error().is_some();
+        trace.after_step(&mut report, &step, failed, before, state);
+        trace.after_apply(&mut report, &step, fingerprint, state);
+        trace.step(&mut report, step);
+        if broken.is_some() {
+            report.broken_invariant = broken;
+            break;
+        }
+
+        for (hb, clock) in heartbeats.iter().zip(clocks.iter_mut()) {
+            if clock.tick(applied) {
+                guard.applied.push((index, &hb.command));
+                let before = trace.before_step(&report, state);
+                let (step, abandoned) = trace.guard(
+                    &report,
+                    index,
+                    || hb.command.command.label(),
+                    || run_step(index, &hb.command, state, &mut ()),
+                );
+                if abandoned {
+                    guard.applied.clear();
+                    trace.heartbeat(&mut report, step);
+                    break 'steps;
+                }
+                if !step.was_applied() {
+                    guard.applied.pop();
+                }
+                let broken = step
+                    .was_applied()
+                    .then(|| check_step(&step, state, invariant))
+                    .flatten();
+                let failed = broken.is_some() || step.error().is_some();
+                trace.after_step(&mut report, &step, failed, before, state);
+                trace.heartbeat(&mut report, step);
+                if broken.is_some() {
+                    report.broken_invariant = broken;
+                    break 'steps;
+                }
+            }
+        }
+    }
+
+    // A cancelled run did not get the chance to confirm its effects.
+    let unconfirmed = effects.finish();
+    if !report.cancelled {
+        report.unconfirmed = unconfirmed;
+    }
+    report.labels = labels.finish();
+    // Cleaned up first, so that the processes it kills are reported.
+    drop(guard);
+    report.processes = processes.finish();
+    trace.finish(&mut report);
+    #[cfg(feature = "tracing")]
+    spans::finish_run(&span, &report);
+    report
+}
+
+/// Calls [`Command::cleanup`] on the applied commands, in reverse order,
+/// when dropped.
+struct Cleanup<'a, S: State, C: TestContext> {
+    state: &'a mut S,
+    applied: Vec<(usize, &'a CommandWrapper<S, C>)>,
+}
+
+impl<S: State, C: TestContext> Drop for Cleanup<'_, S, C> {
+    fn drop(&mut self) {
+        panics::run_all(self.applied.iter().rev(), |(index, cmd)| {
+            let label = format!("{} cleanup", cmd.command.label());
+            panics::set_step(Some((*index, &label)));
+            cmd.command.cleanup(self.state);
+        });
+        panics::set_step(None);
+    }
+}
+
+/// Evaluates the state invariants after `step` was applied.
+pub(crate) fn check_invariants<S: State>(step: &StepReport, state: &S) -> Option<BrokenInvariant> {
+    state.invariants().err().map(|violation| BrokenInvariant {
+        index: step.index,
+        label: step.label.clone(),
+        violation,
+    })
+}
+
+/// Evaluates the state invariants, then `invariant`, after `step` was
+/// applied.
+fn check_step<S: State>(
+    step: &StepReport,
+    state: &S,
+    invariant: Option<&Invariant<'_, S>>,
+) -> Option<BrokenInvariant> {
+    check_invariants(step, state).or_else(|| {
+        let violation = invariant?(state).err()?;
+        Some(BrokenInvariant {
+            index: step.index,
+            label: step.label.clone(),
+            violation,
+        })
+    })
+}
+
+/// Checks and applies a single command, timing `try_apply`.
+///
+/// Also returns whether the command was left running on a worker thread
+/// with the state, which ends the execution.
+fn run_step<S: State, C: TestContext>(
+    index: usize,
+    cmd: &CommandWrapper<S, C>,
+    state: &mut S,
+    observer: &mut impl Observer<S, C>,
+) -> (StepReport, bool) {
+    let label = cmd.command.label();
+    #[cfg(feature = "tracing")]
+    let span = spans::step(index, &label, &cmd.command.group());
+    panics::set_step(Some((index, &label)));
+    effects::set_step(index, &label);
+    env::set_step(index, &label);
+    observer.on_selected(index, cmd);
+    let started = SystemTime::now();
+    let start = Instant::now();
+    let verdict = cmd.command.check_detailed(state);
+    let check_duration = start.elapsed();
+    let applies = verdict.is_ok();
+    let mut abandoned = false;
+    let (outcome, duration) = if let Err(reason) = verdict {
+        (StepOutcome::Skipped(reason), Duration::ZERO)
+    } else {
+        observer.on_before_apply(index, cmd, state);
+        let start = Instant::now();
+        let timeout = cmd.command.timeout();
+        let result = match (timeout, &cmd.detached) {
+            (Some(limit), Some(detached)) => match detached(state, limit) {
+                watchdog::Outcome::Returned(result) => result,
+                watchdog::Outcome::Abandoned(err) => {
+                    abandoned = true;
+                    Err(err)
+                }
+            },
+            _ => cmd.command.try_apply(state),
+        };
+        let duration = start.elapsed();
+        match result {
+            Ok(()) => match timeout.filter(|&limit| duration > limit) {
+                Some(limit) => (
+                    StepOutcome::Failed(CommandError::new(format!(
+                        "timed out: took {:?}, limit {:?}",
+                        duration, limit
+                    ))),
+                    duration,
+                ),
+                None => (StepOutcome::Executed, duration),
+            },
+            Err(err) => (StepOutcome::Failed(err), duration),
+        }
+    };
+    let step = StepReport {
+        index,
+        label,
+        group: cmd.command.group(),
+        outcome,
+        duration,
+        check_duration,
+        started,
+        finished: SystemTime::now(),
+    };
+    #[cfg(feature = "tracing")]
+    spans::finish_step(&span, &step);
+    if applies {
+        observer.on_after_apply(&step, state);
+    } else {
+        observer.on_skipped(&step);
+    }
+    panics::set_step(None);
+    (step, abandoned)
+}
+
+/// Macro for running stateful tests.
+///
+/// While commands execute in the order specified in the macro,
+/// their generation depends on several factors:
+///
+/// - In normal mode: Commands run in the order provided, but proptest strategies
+///   may generate different values across runs unless the same seed is used.
+/// - With MADHOUSE=1: The command sequence is permutated randomly.
+/// - With PROPTEST_MAX_SHRINK_ITERS: Failed tests can be shrunk to minimal cases.
+///
+/// The framework honors any PROPTEST environment variables. By default,
+/// the scenario runs with 1 test case and 0 shrink iterations to accommodate
+/// heavyweight non-deterministic test setups found in complex systems. Pass
+/// `config = ...` to tune a scenario (see [`Config`]); environment variables
+/// still take precedence.
+///
+/// # Arguments
+///
+/// * `key = value, ...` - Optional scenario options, each calling the
+///   [`Scenario`] method of the same name (e.g.,
+///   `assert_report = |report| ...`). `require_executed >= N` rejects and
+///   regenerates cases in which fewer than `N` commands passed `check()`
+///   (see [`Scenario::require_executed`]).
+/// * `test_context` - Test context for creating commands.
+/// * `command1, command2, ...` - Either command types (e.g., `Inc`) or
+///   fixed command instances (e.g., `(Inc { amount: 3 })`). Note that
+///   expressions must be wrapped in parentheses. Each may be followed by a
+///   weight (e.g., `Inc: 5`) used when picking commands in MADHOUSE=1 mode;
+///   the default weight is the one the command declares
+///   ([`Command::weight`], 1 unless overridden). Commands generic over the
+///   state, like [`nemesis::Kill`], need an explicit weight, as their state
+///   type cannot be inferred from `weight`. `#[phase]` before a command
+///   starts a phase of one command per following entry, in order, and
+///   `#[phase(5..40)]` one of 5 to 39 commands drawn from them, whatever the
+///   mode (see [`Scenario::ordered_phase`]). `include set` adds the commands
+///   of a [`CommandSet`] built with [`commands!`].
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::{
+///     execute_commands, prop_allof, Command, CommandWrapper, Config, Mode, State,
+///     TestContext, scenario
+/// };
+/// use proptest::prelude::Just;
+/// use proptest::strategy::Strategy;
+/// use std::sync::Arc;
+///
+/// // Define your application state.
+/// #[derive(Debug, Default)]
+/// struct AppState {
+///     counter: u64,
+/// }
+/// impl State for AppState {}
+///
+/// // Define your test context.
+/// #[derive(Debug, Clone, Default)]
+/// struct AppContext {}
+/// impl TestContext for AppContext {}
+///
+/// // Define some commands.
+/// struct IncrementCommand {
+///     amount: u64,
+/// }
+/// impl Command<AppState, AppContext> for IncrementCommand {
+///     fn check(&self, _state: &AppState) -> bool { true }
+///     fn apply(&self, state: &mut AppState) { state.counter += self.amount; }
+///     fn label(&self) -> String { format!("INCREMENT({})", self.amount) }
+///     fn build(_ctx: Arc<AppContext>) -> impl Strategy<Value = CommandWrapper<AppState, AppContext>> {
+///         (1..=5u64).prop_map(|n| CommandWrapper::new(IncrementCommand { amount: n }))
+///     }
+/// }
+///
+/// struct ResetCommand;
+/// impl Command<AppState, AppContext> for ResetCommand {
+///     fn check(&self, state: &AppState) -> bool { state.counter > 0 }
+///     fn apply(&self, state: &mut AppState) { state.counter = 0; }
+///     fn label(&self) -> String { "RESET".to_string() }
+///     fn build(_ctx: Arc<AppContext>) -> impl Strategy<Value = CommandWrapper<AppState, AppContext>> {
+///         Just(CommandWrapper::new(ResetCommand))
 ///     }
 /// }
 ///
@@ -498,231 +1554,2465 @@ pub fn execute_commands<'a, S: State, C: TestContext>(
 ///     ResetCommand,
 ///     (IncrementCommand { amount: 42 })
 /// ];
+///
+/// // In MADHOUSE=1 mode, pick IncrementCommand 5 times as often as ResetCommand.
+/// scenario![ctx, IncrementCommand: 5, ResetCommand: 1];
+///
+/// // Tune the run per scenario; environment variables still take precedence.
+/// scenario![
+///     config = Config {
+///         mode: Mode::Random,
+///         cases: 10,
+///         seq_len: 2..8,
+///         max_shrink_iters: 100,
+///         ..Config::default()
+///     },
+///     ctx,
+///     IncrementCommand,
+///     ResetCommand
+/// ];
+///
+/// // Assert over the executed trace at the end of every case.
+/// scenario![
+///     assert_report = |report| {
+///         assert!(report.count("RESET") <= report.count("INCREMENT"));
+///     },
+///     ctx,
+///     IncrementCommand,
+///     ResetCommand
+/// ];
+///
+/// // Regenerate cases that execute fewer than two commands.
+/// scenario![require_executed >= 2, ctx, (IncrementCommand { amount: 1 }), ResetCommand];
+///
+/// // Reset first, then a random middle of 3 to 9 commands, then reset again.
+/// scenario![
+///     ctx,
+///     #[phase] (ResetCommand),
+///     #[phase(3..10)] IncrementCommand: 5, ResetCommand,
+///     #[phase] (ResetCommand)
+/// ];
 /// ```
 #[macro_export]
 macro_rules! scenario {
-    ($test_context:expr, $($cmd:tt),+ $(,)?) => {
+    (@opts $finish:tt [$($opts:tt)*] require_executed >= $value:expr, $($rest:tt)+) => {
+        $crate::scenario!(@opts $finish [$($opts)* .require_executed($value)] $($rest)+)
+    };
+
+    (@opts $finish:tt [$($opts:tt)*] $key:ident = $value:expr, $($rest:tt)+) => {
+        $crate::scenario!(@opts $finish [$($opts)* .$key($value)] $($rest)+)
+    };
+
+    // Options are applied after the commands, which fix the state type
+    // that closures like `teardown = |state| ...` are inferred against.
+    (@opts [$($finish:tt)*] [$($opts:tt)*] $test_context:expr, $($cmds:tt)+) => {
         {
             let test_context = $test_context.clone();
-            let config = proptest::test_runner::Config {
-                cases: 1,
-                max_shrink_iters: 0,
-                ..Default::default()
-            };
-
-            // Use MADHOUSE env var to determine test mode.
-            let use_madhouse = std::env::var("MADHOUSE") == Ok("1".into());
-
-            if use_madhouse {
-                proptest::proptest!(config, |(commands in proptest::collection::vec(
-                    proptest::prop_oneof![
-                        $(scenario!(@to_strategy test_context.clone(), $cmd)),+
-                    ],
-                    1..16,
-                ))| {
-                    println!("\n=== New Test Run (MADHOUSE mode) ===\n");
-                    let mut state = <_ as std::default::Default>::default();
-                    execute_commands(&commands, &mut state);
-                });
-            } else {
-                proptest::proptest!(config, |(commands in prop_allof![
-                    $(scenario!(@to_strategy test_context.clone(), $cmd)),+
-                ])| {
-                    println!("\n=== New Test Run (deterministic mode) ===\n");
-                    let mut state = <_ as std::default::Default>::default();
-                    execute_commands(&commands, &mut state);
-                });
-            }
+            $crate::scenario!(
+                @cmds test_context [$($opts)* $($finish)*]
+                [$crate::Scenario::new(test_context.clone()).source_file(file!())]
+                $($cmds)+
+            )
         }
     };
 
-    (@to_strategy $ctx:expr, $cmd:ident) => {
-        $cmd::build($ctx)
+    (@cmds $ctx:ident $finish:tt [$($calls:tt)*] #[phase] $($rest:tt)*) => {
+        $crate::scenario!(@cmds $ctx $finish [$($calls)*.ordered_phase()] $($rest)*)
+    };
+
+    (@cmds $ctx:ident $finish:tt [$($calls:tt)*] #[phase($len:expr)] $($rest:tt)*) => {
+        $crate::scenario!(@cmds $ctx $finish [$($calls)*.random_phase($len)] $($rest)*)
+    };
+
+    (@cmds $ctx:ident $finish:tt [$($calls:tt)*] include $set:expr $(, $($rest:tt)*)?) => {
+        $crate::scenario!(@cmds $ctx $finish [$($calls)*.include(&$set)] $($($rest)*)?)
+    };
+
+    (@cmds $ctx:ident $finish:tt [$($calls:tt)*] $cmd:tt : $weight:expr $(, $($rest:tt)*)?) => {
+        $crate::scenario!(
+            @cmds $ctx $finish
+            [$crate::scenario!(@add $ctx, [$($calls)*], $cmd, $weight)]
+            $($($rest)*)?
+        )
+    };
+
+    (@cmds $ctx:ident $finish:tt [$($calls:tt)*] $cmd:tt $(, $($rest:tt)*)?) => {
+        $crate::scenario!(
+            @cmds $ctx $finish
+            [$crate::scenario!(@add $ctx, [$($calls)*], $cmd)]
+            $($($rest)*)?
+        )
+    };
+
+    (@cmds $ctx:ident [$($finish:tt)*] [$($calls:tt)*]) => {
+        $($calls)*$($finish)*
     };
 
-    (@to_strategy $ctx:expr, $cmd:expr) => {
-        proptest::prelude::Just(CommandWrapper::new($cmd))
+    (@add $ctx:ident, [$($calls:tt)*], $cmd:ident, $weight:expr) => {
+        $($calls)*
+            .weighted($weight, $cmd::build(::std::sync::Arc::clone(&$ctx)))
+            .parser($cmd::parse)
+    };
+
+    (@add $ctx:ident, [$($calls:tt)*], $cmd:expr, $weight:expr) => {
+        $($calls)*.fixed($weight, $crate::CommandWrapper::new($cmd))
+    };
+
+    (@add $ctx:ident, [$($calls:tt)*], $cmd:ident) => {
+        $($calls)*
+            .weighted($cmd::weight(&*$ctx), $cmd::build(::std::sync::Arc::clone(&$ctx)))
+            .parser($cmd::parse)
+    };
+
+    (@add $ctx:ident, [$($calls:tt)*], $cmd:expr) => {
+        $($calls)*.add_instance($cmd)
+    };
+
+    ($($args:tt)+) => {
+        $crate::scenario!(@opts [.run()] [] $($args)+)
     };
 }
 
-/// Common imports for working with madhouse scenarios.
+/// Re-runs a failing sequence saved by [`scenario!`], verbatim.
+///
+/// Takes the path of a file under `madhouse-regressions/` followed by the
+/// same arguments as [`scenario!`]. Labels are turned back into commands
+/// with [`Command::parse`]; fixed commands such as `(ResetCommand)` are
+/// recognized by their exact label. See [`Scenario::replay_from_file`].
+///
+/// # Examples
 ///
-/// Import everything needed for scenario testing with a single use statement:
 /// ```
-/// use madhouse::prelude::*;
+/// use madhouse::{replay, Command, CommandWrapper, State, TestContext};
+/// use proptest::prelude::*;
+/// use std::sync::Arc;
+///
+/// #[derive(Debug, Default)]
+/// struct Counter { value: u64 }
+/// impl State for Counter {}
+///
+/// #[derive(Debug, Clone, Default)]
+/// struct Ctx {}
+/// impl TestContext for Ctx {}
+///
+/// struct Inc { amount: u64 }
+/// impl Command<Counter, Ctx> for Inc {
+///     fn check(&self, _state: &Counter) -> bool { true }
+///     fn apply(&self, state: &mut Counter) { state.value += self.amount; }
+///     fn label(&self) -> String { format!("INC({})", self.amount) }
+///     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Counter, Ctx>> {
+///         (1..10u64).prop_map(|amount| CommandWrapper::new(Inc { amount }))
+///     }
+///     fn parse(label: &str, _ctx: Arc<Ctx>) -> Option<CommandWrapper<Counter, Ctx>> {
+///         let amount = label.strip_prefix("INC(")?.strip_suffix(')')?.parse().ok()?;
+///         Some(CommandWrapper::new(Inc { amount }))
+///     }
+/// }
+///
+/// # let dir = std::env::temp_dir().join(format!("madhouse-replay-doc-{}", std::process::id()));
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// # let path = dir.join("counter.txt");
+/// # std::fs::write(&path, "# saved by a failing run\nINC(3)\nINC(7)\n").unwrap();
+/// let ctx = Arc::new(Ctx::default());
+/// replay![
+///     path,
+///     assert_report = |report| assert_eq!(report.executed().count(), 2),
+///     ctx,
+///     Inc
+/// ];
+/// # std::fs::remove_dir_all(&dir).unwrap();
 /// ```
-pub mod prelude {
-    pub use crate::{
-        execute_commands, prop_allof, scenario, Command, CommandWrapper, State, TestContext,
+#[macro_export]
+macro_rules! replay {
+    ($path:expr, $($args:tt)+) => {
+        $crate::scenario!(@opts [.replay_from_file($path)] [] $($args)+)
     };
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use proptest::prelude::Just;
-
-    #[derive(Clone, Debug, Default)]
-    struct MyState {
-        last_mined_block: u64,
-    }
-
-    impl State for MyState {}
-
-    #[derive(Debug, Clone, Default)]
-    struct MyContext {}
+/// Migrates a proptest regression file into madhouse regression files.
+///
+/// Takes the path of a proptest regression file (typically under
+/// `proptest-regressions/`) followed by the same arguments as
+/// [`scenario!`], and evaluates to the paths written. See
+/// [`Scenario::import_proptest_regressions`].
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::{import_regressions, regression, Command, CommandWrapper, State, TestContext};
+/// use proptest::prelude::*;
+/// use std::sync::Arc;
+///
+/// #[derive(Debug, Default)]
+/// struct Counter { value: u64 }
+/// impl State for Counter {}
+///
+/// #[derive(Debug, Clone, Default)]
+/// struct Ctx {}
+/// impl TestContext for Ctx {}
+///
+/// struct Inc { amount: u64 }
+/// impl Command<Counter, Ctx> for Inc {
+///     fn check(&self, _state: &Counter) -> bool { true }
+///     fn apply(&self, state: &mut Counter) { state.value += self.amount; }
+///     fn label(&self) -> String { format!("INC({})", self.amount) }
+///     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Counter, Ctx>> {
+///         (1..10u64).prop_map(|amount| CommandWrapper::new(Inc { amount }))
+///     }
+///     fn parse(label: &str, _ctx: Arc<Ctx>) -> Option<CommandWrapper<Counter, Ctx>> {
+///         let amount = label.strip_prefix("INC(")?.strip_suffix(')')?.parse().ok()?;
+///         Some(CommandWrapper::new(Inc { amount }))
+///     }
+/// }
+///
+/// # let dir = std::env::temp_dir().join(format!("madhouse-import-doc-{}", std::process::id()));
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// # let path = dir.join("counter.txt");
+/// # std::fs::write(&path, "cc 1f # shrinks to commands = [INC(3), INC(7)]\n").unwrap();
+/// # let regressions = dir.join("madhouse-regressions");
+/// let ctx = Arc::new(Ctx::default());
+/// let paths = import_regressions![path, regressions_dir = &regressions, ctx, Inc];
+///
+/// assert_eq!(regression::load(&paths[0]).unwrap(), vec!["INC(3)", "INC(7)"]);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[macro_export]
+macro_rules! import_regressions {
+    ($path:expr, $($args:tt)+) => {
+        $crate::scenario!(@opts [.import_proptest_regressions($path)] [] $($args)+)
+    };
+}
 
-    impl TestContext for MyContext {}
+/// Checks a saved sequence against its golden trace.
+///
+/// Takes the golden trace file path first, then the same arguments as
+/// [`scenario!`]. See [`Scenario::conform_to_file`] and [`golden`].
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::{conform, Command, CommandWrapper, State, TestContext};
+/// use proptest::prelude::*;
+/// use std::sync::Arc;
+///
+/// #[derive(Debug, Default)]
+/// struct Counter { value: u64 }
+/// impl State for Counter {}
+///
+/// #[derive(Debug, Clone, Default)]
+/// struct Ctx {}
+/// impl TestContext for Ctx {}
+///
+/// struct Inc { amount: u64 }
+/// impl Command<Counter, Ctx> for Inc {
+///     fn check(&self, _state: &Counter) -> bool { true }
+///     fn apply(&self, state: &mut Counter) { state.value += self.amount; }
+///     fn label(&self) -> String { format!("INC({})", self.amount) }
+///     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Counter, Ctx>> {
+///         (1..10u64).prop_map(|amount| CommandWrapper::new(Inc { amount }))
+///     }
+///     fn parse(label: &str, _ctx: Arc<Ctx>) -> Option<CommandWrapper<Counter, Ctx>> {
+///         let amount = label.strip_prefix("INC(")?.strip_suffix(')')?.parse().ok()?;
+///         Some(CommandWrapper::new(Inc { amount }))
+///     }
+/// }
+///
+/// # let dir = std::env::temp_dir().join(format!("madhouse-golden-doc-{}", std::process::id()));
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// # let path = dir.join("counter.golden");
+/// # std::fs::write(&path, "INC(3)\nINC(7)\n").unwrap();
+/// let ctx = Arc::new(Ctx::default());
+/// // The first run records the trace, later runs check against it.
+/// conform![path.clone(), ctx.clone(), Inc];
+/// conform![path, ctx, Inc];
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[macro_export]
+macro_rules! conform {
+    ($path:expr, $($args:tt)+) => {
+        $crate::scenario!(@opts [.conform_to_file($path)] [] $($args)+)
+    };
+}
 
-    struct TestCommand {
-        value: u32,
+/// Declares an enum of commands that implements [`Command`] itself.
+///
+/// Each variant wraps one command type, which must implement [`Command`]
+/// and `Clone`. The enum delegates every method to the wrapped command by
+/// `match`, and its `build` draws from the variants' strategies by weight
+/// (default 1). Inspecting a trace then comes down to an exhaustive `match`
+/// on [`CommandWrapper::downcast_ref`]. `From` is implemented for every
+/// variant type.
+///
+/// `commands![A, B: 2, (Reset)]`, without an enum, builds a reusable
+/// [`CommandSet`] from the same entries as [`scenario!`] takes instead;
+/// scenarios add it with `include set`.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::{commands, scenario, Command, CommandWrapper, State, TestContext};
+/// use proptest::prelude::*;
+/// use std::sync::Arc;
+///
+/// #[derive(Debug, Default)]
+/// struct Counter { value: u64 }
+/// impl State for Counter {}
+///
+/// #[derive(Debug, Clone, Default)]
+/// struct Ctx {}
+/// impl TestContext for Ctx {}
+///
+/// #[derive(Clone)]
+/// struct Inc { amount: u64 }
+/// impl Command<Counter, Ctx> for Inc {
+///     fn check(&self, _state: &Counter) -> bool { true }
+///     fn apply(&self, state: &mut Counter) { state.value += self.amount; }
+///     fn label(&self) -> String { format!("INC({})", self.amount) }
+///     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Counter, Ctx>> {
+///         (1..5u64).prop_map(|amount| CommandWrapper::new(Inc { amount }))
+///     }
+/// }
+///
+/// #[derive(Clone)]
+/// struct Reset;
+/// impl Command<Counter, Ctx> for Reset {
+///     fn check(&self, state: &Counter) -> bool { state.value > 0 }
+///     fn apply(&self, state: &mut Counter) { state.value = 0; }
+///     fn label(&self) -> String { "RESET".to_string() }
+///     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Counter, Ctx>> {
+///         Just(CommandWrapper::new(Reset))
+///     }
+/// }
+///
+/// commands! {
+///     /// Every counter command.
+///     pub enum CounterCommand: Command<Counter, Ctx> {
+///         Inc(Inc): 3,
+///         Reset(Reset),
+///     }
+/// }
+///
+/// let ctx = Arc::new(Ctx::default());
+/// assert_eq!(<CounterCommand as Command<Counter, Ctx>>::weight(&ctx), 4);
+/// scenario![
+///     assert_report = |report| assert!(report.count("INC") <= 1),
+///     ctx,
+///     CounterCommand
+/// ];
+///
+/// let wrapper = CommandWrapper::new(CounterCommand::from(Reset));
+/// match wrapper.downcast_ref::<CounterCommand>().unwrap() {
+///     CounterCommand::Inc(inc) => assert!(inc.amount > 0),
+///     CounterCommand::Reset(_) => {}
+/// }
+/// ```
+#[macro_export]
+macro_rules! commands {
+    (
+        $(#[$meta:meta])*
+        $vis:vis enum $name:ident: Command<$state:ty, $ctx:ty> {
+            $($variant:ident($command:ty) $(: $weight:expr)?),+ $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis enum $name {
+            $(
+                #[allow(missing_docs)]
+                $variant($command),
+            )+
+        }
+
+        $(
+            impl ::std::convert::From<$command> for $name {
+                fn from(command: $command) -> Self {
+                    Self::$variant(command)
+                }
+            }
+        )+
+
+        impl $crate::Command<$state, $ctx> for $name {
+            fn check(&self, state: &$state) -> bool {
+                match self {
+                    $(Self::$variant(c) => $crate::Command::<$state, $ctx>::check(c, state),)+
+                }
+            }
+
+            fn check_detailed(
+                &self,
+                state: &$state,
+            ) -> ::std::result::Result<(), $crate::SkipReason> {
+                match self {
+                    $(Self::$variant(c) => $crate::Command::<$state, $ctx>::check_detailed(c, state),)+
+                }
+            }
+
+            fn apply(&self, state: &mut $state) {
+                match self {
+                    $(Self::$variant(c) => $crate::Command::<$state, $ctx>::apply(c, state),)+
+                }
+            }
+
+            fn apply_with(&self, state: &mut $state, env: &mut $crate::Env) {
+                match self {
+                    $(Self::$variant(c) => $crate::Command::<$state, $ctx>::apply_with(c, state, env),)+
+                }
+            }
+
+            fn try_apply(&self, state: &mut $state) -> ::std::result::Result<(), $crate::CommandError> {
+                match self {
+                    $(Self::$variant(c) => $crate::Command::<$state, $ctx>::try_apply(c, state),)+
+                }
+            }
+
+            fn timeout(&self) -> ::std::option::Option<::std::time::Duration> {
+                match self {
+                    $(Self::$variant(c) => $crate::Command::<$state, $ctx>::timeout(c),)+
+                }
+            }
+
+            fn cleanup(&self, state: &mut $state) {
+                match self {
+                    $(Self::$variant(c) => $crate::Command::<$state, $ctx>::cleanup(c, state),)+
+                }
+            }
+
+            fn label(&self) -> ::std::string::String {
+                match self {
+                    $(Self::$variant(c) => $crate::Command::<$state, $ctx>::label(c),)+
+                }
+            }
+
+            fn group(&self) -> ::std::string::String {
+                match self {
+                    $(Self::$variant(c) => $crate::Command::<$state, $ctx>::group(c),)+
+                }
+            }
+
+            /// The sum of the variants' weights.
+            fn weight(ctx: &$ctx) -> u32 {
+                0 $(+ $crate::commands!(@weight $command, $state, ctx $(, $weight)?))+
+            }
+
+            fn build(
+                ctx: ::std::sync::Arc<$ctx>,
+            ) -> impl $crate::__private::proptest::strategy::Strategy<
+                Value = $crate::CommandWrapper<$state, $ctx>,
+            > {
+                use $crate::__private::proptest::strategy::Strategy;
+                $crate::__private::proptest::strategy::Union::new_weighted(vec![$((
+                    $crate::commands!(@weight $command, $state, &*ctx $(, $weight)?),
+                    <$command as $crate::Command<$state, $ctx>>::build(ctx.clone())
+                        .prop_filter_map(
+                            concat!("strategy did not build a ", stringify!($command)),
+                            |wrapper| {
+                                wrapper
+                                    .downcast_ref::<$command>()
+                                    .map(|c| $crate::CommandWrapper::new(Self::$variant(c.clone())))
+                            },
+                        )
+                        .boxed(),
+                )),+])
+            }
+
+            fn parse(
+                label: &str,
+                ctx: ::std::sync::Arc<$ctx>,
+            ) -> ::std::option::Option<$crate::CommandWrapper<$state, $ctx>> {
+                $(
+                    if let Some(c) = <$command as $crate::Command<$state, $ctx>>::parse(label, ctx.clone())
+                        .as_ref()
+                        .and_then(|wrapper| wrapper.downcast_ref::<$command>())
+                    {
+                        return Some($crate::CommandWrapper::new(Self::$variant(c.clone())));
+                    }
+                )+
+                None
+            }
+
+            fn shrink(&self) -> ::std::boxed::Box<dyn ::std::iter::Iterator<Item = Self>> {
+                match self {
+                    $(Self::$variant(c) => ::std::boxed::Box::new(
+                        $crate::Command::<$state, $ctx>::shrink(c).map(Self::$variant),
+                    ),)+
+                }
+            }
+        }
+    };
+
+    (@weight $command:ty, $state:ty, $ctx:expr, $weight:expr) => { $weight };
+    (@weight $command:ty, $state:ty, $ctx:expr) => {
+        <$command as $crate::Command<$state, _>>::weight($ctx)
+    };
+
+    (@set [$($calls:tt)*] include $set:expr $(, $($rest:tt)*)?) => {
+        $crate::commands!(@set [$($calls)*.include(&$set)] $($($rest)*)?)
+    };
+
+    (@set [$($calls:tt)*] #[phase] $($rest:tt)*) => {
+        $crate::commands!(@set [$($calls)*.then(|scenario| scenario.ordered_phase())] $($rest)*)
+    };
+
+    (@set [$($calls:tt)*] #[phase($len:expr)] $($rest:tt)*) => {
+        $crate::commands!(
+            @set [$($calls)*.then(move |scenario| scenario.random_phase(::std::clone::Clone::clone(&$len)))]
+            $($rest)*
+        )
+    };
+
+    (@set [$($calls:tt)*] $cmd:tt $(: $weight:expr)? $(, $($rest:tt)*)?) => {
+        $crate::commands!(
+            @set [$($calls)*.then(move |scenario| {
+                #[allow(unused_variables)]
+                let ctx = scenario.context();
+                $crate::scenario!(@add ctx, [scenario], $cmd $(, $weight)?)
+            })]
+            $($($rest)*)?
+        )
+    };
+
+    (@set [$($calls:tt)*]) => {
+        $($calls)*
+    };
+
+    ($($entries:tt)+) => {
+        $crate::commands!(@set [$crate::CommandSet::new()] $($entries)+)
+    };
+}
+
+/// Common imports for working with madhouse scenarios.
+///
+/// Import everything needed for scenario testing with a single use statement:
+/// ```
+/// use madhouse::prelude::*;
+/// ```
+pub mod prelude {
+    pub use crate::{
+        commands, conform, execute_commands, execute_commands_checked, import_regressions,
+        prop_allof, prop_interleave, replay, scenario, Command, CommandError, CommandWrapper,
+        Config, ExecutionReport, Heartbeat, InvariantViolation, Mode, Scenario, State, TestContext,
+        Verbosity,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::Just;
+
+    #[derive(Clone, Debug, Default)]
+    struct MyState {
+        last_mined_block: u64,
+    }
+
+    impl State for MyState {}
+
+    #[derive(Debug, Clone, Default)]
+    struct MyContext {}
+
+    impl TestContext for MyContext {}
+
+    struct TestCommand {
+        value: u32,
+    }
+
+    impl Command<MyState, MyContext> for TestCommand {
+        fn check(&self, _state: &MyState) -> bool {
+            true
+        }
+
+        fn apply(&self, state: &mut MyState) {
+            state.last_mined_block += self.value as u64;
+        }
+
+        fn label(&self) -> String {
+            format!("TEST({})", self.value)
+        }
+
+        fn build(
+            _ctx: Arc<MyContext>,
+        ) -> impl Strategy<Value = CommandWrapper<MyState, MyContext>> {
+            Just(CommandWrapper::new(TestCommand { value: 1 }))
+        }
+    }
+
+    #[test]
+    fn test_command_wrapper() {
+        let cmd = TestCommand { value: 42 };
+        let wrapper = CommandWrapper::new(cmd);
+        let mut state = MyState::default();
+        assert!(wrapper.command.check(&state));
+
+        wrapper.command.apply(&mut state);
+
+        assert_eq!(state.last_mined_block, 42);
+        assert_eq!(format!("{:?}", wrapper), "TEST(42)");
+    }
+
+    #[test]
+    fn test_prop_allof_macro() {
+        use proptest::prelude::*;
+
+        // Test with one strategy.
+        let strat1 = Just(1);
+        let combined1 = prop_allof![strat1];
+
+        proptest!(|(v in combined1)| {
+            assert_eq!(v, vec![1]);
+        });
+
+        // Test with multiple strategies.
+        let strat2 = Just(2);
+        let strat3 = Just(3);
+        let combined2 = prop_allof![strat1, strat2, strat3];
+
+        proptest!(|(v in combined2)| {
+            assert_eq!(v, vec![1, 2, 3]);
+        });
+    }
+
+    #[test]
+    fn test_prop_interleave_macro() {
+        use proptest::prelude::*;
+
+        let merged = prop_interleave![[Just(1), Just(2), Just(3)], [Just(10), Just(20)]];
+        let seen = std::cell::RefCell::new(std::collections::HashSet::new());
+        proptest!(|(v in merged)| {
+            let (low, high): (Vec<i32>, Vec<i32>) = v.iter().partition(|&&n| n < 10);
+            assert_eq!(low, vec![1, 2, 3]);
+            assert_eq!(high, vec![10, 20]);
+            seen.borrow_mut().insert(v);
+        });
+        // 256 cases sample most of the 10 interleavings.
+        assert!(seen.borrow().len() > 5);
+    }
+
+    #[test]
+    fn test_execute_commands_empty() {
+        let commands: Vec<CommandWrapper<MyState, MyContext>> = vec![];
+        let mut state = MyState::default();
+
+        let report = execute_commands(&commands, &mut state);
+        assert_eq!(report.executed().count(), 0);
+    }
+
+    #[test]
+    fn test_execute_commands_all_rejected() {
+        struct RejectCommand;
+
+        impl Command<MyState, MyContext> for RejectCommand {
+            fn check(&self, _state: &MyState) -> bool {
+                false
+            }
+            fn apply(&self, _state: &mut MyState) {}
+            fn label(&self) -> String {
+                "REJECT".to_string()
+            }
+            fn build(
+                _ctx: Arc<MyContext>,
+            ) -> impl Strategy<Value = CommandWrapper<MyState, MyContext>> {
+                Just(CommandWrapper::new(RejectCommand))
+            }
+        }
+
+        let commands = vec![
+            CommandWrapper::new(RejectCommand),
+            CommandWrapper::new(RejectCommand),
+        ];
+        let mut state = MyState::default();
+
+        let report = execute_commands(&commands, &mut state);
+        assert_eq!(report.executed().count(), 0);
+    }
+
+    struct FailingCommand;
+
+    impl Command<MyState, MyContext> for FailingCommand {
+        fn check(&self, _state: &MyState) -> bool {
+            true
+        }
+        fn apply(&self, _state: &mut MyState) {}
+        fn try_apply(&self, _state: &mut MyState) -> Result<(), CommandError> {
+            Err(CommandError::new("unreachable endpoint"))
+        }
+        fn label(&self) -> String {
+            "FAIL".to_string()
+        }
+        fn group(&self) -> String {
+            "network".to_string()
+        }
+        fn build(
+            _ctx: Arc<MyContext>,
+        ) -> impl Strategy<Value = CommandWrapper<MyState, MyContext>> {
+            Just(CommandWrapper::new(FailingCommand))
+        }
+    }
+
+    #[test]
+    fn test_execute_commands_collects_errors() {
+        let commands = vec![
+            CommandWrapper::new(FailingCommand),
+            CommandWrapper::new(TestCommand { value: 2 }),
+        ];
+        let mut state = MyState::default();
+
+        let report = execute_commands(&commands, &mut state);
+
+        // The error does not stop later commands from running.
+        assert_eq!(report.executed().count(), 2);
+        assert_eq!(state.last_mined_block, 2);
+        let failures: Vec<_> = report.failures().collect();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].0.label, "FAIL");
+        assert_eq!(failures[0].1.message(), "unreachable endpoint");
+    }
+
+    #[test]
+    fn test_execute_commands_silent_reports_like_execute_commands() {
+        let commands = vec![
+            CommandWrapper::new(FailingCommand),
+            CommandWrapper::new(TestCommand { value: 2 }),
+        ];
+        let mut state = MyState::default();
+
+        let report = execute_commands_silent(&commands, &mut state);
+
+        assert_eq!(report.executed().count(), 2);
+        assert_eq!(report.failures().count(), 1);
+        assert_eq!(state.last_mined_block, 2);
+    }
+
+    struct SlowApplyCommand(u64);
+
+    impl Command<MyState, MyContext> for SlowApplyCommand {
+        fn check(&self, _state: &MyState) -> bool {
+            true
+        }
+        fn apply(&self, _state: &mut MyState) {
+            std::thread::sleep(Duration::from_millis(self.0));
+        }
+        fn timeout(&self) -> Option<Duration> {
+            Some(Duration::from_millis(20))
+        }
+        fn label(&self) -> String {
+            format!("SLOW_APPLY({})", self.0)
+        }
+        fn build(
+            _ctx: Arc<MyContext>,
+        ) -> impl Strategy<Value = CommandWrapper<MyState, MyContext>> {
+            Just(CommandWrapper::new(SlowApplyCommand(0)))
+        }
+    }
+
+    #[test]
+    fn test_commands_over_their_timeout_fail() {
+        let commands = vec![
+            CommandWrapper::new(SlowApplyCommand(0)),
+            CommandWrapper::new(SlowApplyCommand(30)),
+        ];
+        let mut state = MyState::default();
+
+        let report = execute_commands(&commands, &mut state);
+
+        let failures: Vec<_> = report.failures().collect();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].0.label, "SLOW_APPLY(30)");
+        assert!(failures[0].1.message().starts_with("timed out: took "));
+        assert!(failures[0].1.message().ends_with(", limit 20ms"));
+    }
+
+    #[test]
+    fn test_a_detached_command_still_running_ends_the_case() {
+        let commands = vec![
+            CommandWrapper::new(TestCommand { value: 1 }),
+            CommandWrapper::detachable(SlowApplyCommand(60_000)),
+            CommandWrapper::new(TestCommand { value: 2 }),
+        ];
+        let mut state = MyState::default();
+
+        let report = execute_commands(&commands, &mut state);
+
+        assert_eq!(report.steps.len(), 2);
+        assert_eq!(
+            report.failure_message().unwrap(),
+            "command 2 (SLOW_APPLY(60000)) failed: timed out: still running after its \
+             limit of 20ms, left running detached"
+        );
+        // The worker kept the state; nothing ran on the default left behind.
+        assert_eq!(state.last_mined_block, 0);
+    }
+
+    #[test]
+    fn test_steps_are_grouped_by_module_unless_overridden() {
+        let commands = vec![
+            CommandWrapper::new(TestCommand { value: 1 }),
+            CommandWrapper::new(FailingCommand),
+            CommandWrapper::new(TestCommand { value: 2 }),
+        ];
+        let mut state = MyState::default();
+
+        let report = execute_commands(&commands, &mut state);
+
+        assert_eq!(report.steps[0].group, "madhouse::tests");
+        let groups = report.groups();
+        assert_eq!(groups["madhouse::tests"].executed, 2);
+        assert_eq!(groups["network"].failed, 1);
+    }
+
+    struct SlowCheckCommand;
+
+    impl Command<MyState, MyContext> for SlowCheckCommand {
+        fn check(&self, _state: &MyState) -> bool {
+            std::thread::sleep(Duration::from_millis(5));
+            false
+        }
+
+        fn apply(&self, _state: &mut MyState) {}
+
+        fn label(&self) -> String {
+            "SLOW_CHECK".to_string()
+        }
+
+        fn build(
+            _ctx: Arc<MyContext>,
+        ) -> impl Strategy<Value = CommandWrapper<MyState, MyContext>> {
+            Just(CommandWrapper::new(SlowCheckCommand))
+        }
+    }
+
+    #[test]
+    fn test_check_time_is_measured_apart_from_apply() {
+        let commands = vec![
+            CommandWrapper::new(SlowCheckCommand),
+            CommandWrapper::new(TestCommand { value: 1 }),
+        ];
+        let mut state = MyState::default();
+
+        let report = execute_commands(&commands, &mut state);
+
+        assert!(!report.steps[0].was_applied());
+        assert!(report.steps[0].check_duration >= Duration::from_millis(5));
+        assert_eq!(report.steps[0].duration, Duration::ZERO);
+        let commands = report.commands();
+        assert!(commands["SLOW_CHECK"].check_duration >= Duration::from_millis(5));
+        assert!(report.check_duration() > report.apply_duration());
+    }
+
+    #[derive(Debug, Default)]
+    struct BoundedState {
+        value: u64,
+    }
+
+    impl State for BoundedState {
+        fn invariants(&self) -> Result<(), InvariantViolation> {
+            if self.value > 10 {
+                return Err(format!("value {} exceeds 10", self.value).into());
+            }
+            Ok(())
+        }
+    }
+
+    struct AddCommand(u64);
+
+    impl Command<BoundedState, MyContext> for AddCommand {
+        fn check(&self, _state: &BoundedState) -> bool {
+            true
+        }
+        fn apply(&self, state: &mut BoundedState) {
+            state.value += self.0;
+        }
+        fn label(&self) -> String {
+            format!("ADD({})", self.0)
+        }
+        fn build(
+            _ctx: Arc<MyContext>,
+        ) -> impl Strategy<Value = CommandWrapper<BoundedState, MyContext>> {
+            Just(CommandWrapper::new(AddCommand(1)))
+        }
+    }
+
+    #[test]
+    fn test_execute_commands_stops_at_broken_invariant() {
+        let commands = vec![
+            CommandWrapper::new(AddCommand(4)),
+            CommandWrapper::new(AddCommand(7)),
+            CommandWrapper::new(AddCommand(1)),
+        ];
+        let mut state = BoundedState::default();
+
+        let report = execute_commands(&commands, &mut state);
+
+        let broken = report.broken_invariant.as_ref().unwrap();
+        assert_eq!(broken.index, 1);
+        assert_eq!(broken.label, "ADD(7)");
+        assert_eq!(broken.violation.message(), "value 11 exceeds 10");
+        // Execution stops at the violation.
+        assert_eq!(report.steps.len(), 2);
+        assert_eq!(state.value, 11);
+    }
+
+    #[test]
+    fn test_execute_commands_checked_reports_first_violating_step() {
+        let commands = vec![
+            CommandWrapper::new(AddCommand(4)),
+            CommandWrapper::new(AddCommand(3)),
+            CommandWrapper::new(AddCommand(7)),
+        ];
+        let mut state = BoundedState::default();
+
+        let report = execute_commands_checked(&commands, &mut state, |state| {
+            if state.value > 5 {
+                return Err("value above 5".into());
+            }
+            Ok(())
+        });
+
+        let broken = report.broken_invariant.as_ref().unwrap();
+        assert_eq!((broken.index, broken.label.as_str()), (1, "ADD(3)"));
+        assert_eq!(broken.violation.message(), "value above 5");
+        assert_eq!(report.steps.len(), 2);
+
+        // The state's own invariants still come first.
+        let mut state = BoundedState { value: 4 };
+        let report =
+            execute_commands_checked(&commands[2..], &mut state, |_| Err("checked too".into()));
+        assert_eq!(
+            report.broken_invariant.unwrap().violation.message(),
+            "value 11 exceeds 10"
+        );
+    }
+
+    #[test]
+    fn test_commands_leaving_the_state_unchanged_are_recorded() {
+        let commands = vec![
+            CommandWrapper::new(AddCommand(4)),
+            CommandWrapper::new(AddCommand(0)),
+            CommandWrapper::new(AddCommand(3)),
+        ];
+        let config = Config {
+            no_ops: true,
+            ..Config::default()
+        };
+        let mut state = BoundedState::default();
+
+        let report = run_commands_traced(
+            &commands,
+            &mut state,
+            &[],
+            &mut (),
+            &mut Trace::new(&config),
+            None,
+        );
+
+        let no_ops: Vec<_> = report
+            .no_ops
+            .iter()
+            .map(|no_op| (no_op.index, no_op.label.as_str()))
+            .collect();
+        assert_eq!(no_ops, [(1, "ADD(0)")]);
+        assert!(report
+            .to_json(None)
+            .contains(r#""no_ops":[{"index":1,"label":"ADD(0)"}]"#));
+
+        let mut coverage = coverage::Coverage::default();
+        coverage.record(["ADD(4)", "ADD(0)", "ADD(3)"], &report);
+        assert_eq!(coverage.get("ADD").unwrap().no_ops, 1);
+    }
+
+    #[test]
+    fn test_state_change_of_the_failing_step_is_recorded() {
+        let commands = vec![
+            CommandWrapper::new(AddCommand(4)),
+            CommandWrapper::new(AddCommand(7)),
+        ];
+        let mut state = BoundedState::default();
+
+        let report = execute_commands(&commands, &mut state);
+
+        let change = report.state_change.as_ref().unwrap();
+        assert_eq!((change.index, change.label.as_str()), (1, "ADD(7)"));
+        assert_eq!(
+            change.diff.to_string(),
+            "  BoundedState {\n-     value: 4,\n+     value: 11,\n  }\n"
+        );
+    }
+
+    #[test]
+    fn test_failing_trace_is_drawn_with_state_notes() {
+        use crate::mermaid::{SequenceDiagram, StateNotes};
+
+        let commands = vec![
+            CommandWrapper::new(AddCommand(4)),
+            CommandWrapper::new(AddCommand(7)),
+        ];
+        let mut state = BoundedState::default();
+        let mut notes = StateNotes::new(|state: &BoundedState| format!("{:?}", state));
+
+        let report = execute_commands_with(&commands, &mut state, &mut notes);
+        let mut diagram = SequenceDiagram::new(&report);
+        notes.annotate(&mut diagram);
+
+        let rendered = diagram.to_string();
+        assert!(rendered.contains(
+            "madhouse->>P1: 2. ADD(7)\n    Note right of P1: BoundedState { value: 11 }\n"
+        ));
+        assert!(
+            rendered.ends_with("Note over madhouse,P1: invariant broken: value 11 exceeds 10\n")
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "invariant violated after command 2 (ADD(7))")]
+    fn test_scenario_fails_on_broken_invariant() {
+        let ctx = Arc::new(MyContext::default());
+        scenario![
+            persist_regressions = false,
+            ctx,
+            (AddCommand(4)),
+            (AddCommand(7))
+        ];
+    }
+
+    #[test]
+    #[should_panic(expected = "invariant violated after command 2 (ADD(7))")]
+    fn test_scenario_with_sequence_diagrams_still_fails() {
+        let ctx = Arc::new(MyContext::default());
+        scenario![
+            config = Config {
+                mermaid: true,
+                ..Config::default()
+            },
+            persist_regressions = false,
+            ctx,
+            (AddCommand(4)),
+            (AddCommand(7))
+        ];
+    }
+
+    #[test]
+    #[should_panic(expected = "unreachable endpoint")]
+    fn test_scenario_fails_on_command_error() {
+        let ctx = Arc::new(MyContext::default());
+        scenario![
+            persist_regressions = false,
+            ctx,
+            TestCommand,
+            FailingCommand
+        ];
+    }
+
+    struct Spawn {
+        id: u32,
+        stopped: Arc<std::sync::Mutex<Vec<u32>>>,
+    }
+
+    impl Command<MyState, MyContext> for Spawn {
+        fn check(&self, state: &MyState) -> bool {
+            state.last_mined_block < 3
+        }
+
+        fn apply(&self, state: &mut MyState) {
+            state.last_mined_block += 1;
+            assert!(self.id != 0, "spawn failed");
+        }
+
+        fn cleanup(&self, _state: &mut MyState) {
+            self.stopped.lock().unwrap().push(self.id);
+        }
+
+        fn label(&self) -> String {
+            format!("SPAWN({})", self.id)
+        }
+
+        fn build(
+            _ctx: Arc<MyContext>,
+        ) -> impl Strategy<Value = CommandWrapper<MyState, MyContext>> {
+            Just(CommandWrapper::new(TestCommand { value: 1 }))
+        }
+    }
+
+    #[test]
+    fn test_cleanup_runs_in_reverse_order_despite_panics() {
+        let stopped = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let spawn = |id| {
+            CommandWrapper::new(Spawn {
+                id,
+                stopped: Arc::clone(&stopped),
+            })
+        };
+        let mut state = MyState::default();
+
+        let commands = vec![spawn(1), spawn(2), spawn(3), spawn(4)];
+        run_commands(&commands, &mut state, &[], &mut ());
+        // SPAWN(4) was skipped.
+        assert_eq!(*stopped.lock().unwrap(), vec![3, 2, 1]);
+
+        stopped.lock().unwrap().clear();
+        let mut state = MyState::default();
+        let commands = vec![spawn(1), spawn(0), spawn(2)];
+        let panic = panics::catch(|| run_commands(&commands, &mut state, &[], &mut ()));
+        assert!(panic
+            .unwrap_err()
+            .to_string()
+            .contains("in step 1 (SPAWN(0))"));
+        assert_eq!(*stopped.lock().unwrap(), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_panics_carry_the_commands_executed_before_them() {
+        let stopped = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let commands: Vec<_> = [1, 2, 0]
+            .map(|id| {
+                CommandWrapper::new(Spawn {
+                    id,
+                    stopped: Arc::clone(&stopped),
+                })
+            })
+            .into_iter()
+            .collect();
+
+        let captured = panics::catch(|| {
+            execute_commands_silent(&commands, &mut MyState::default());
+        })
+        .unwrap_err();
+        assert_eq!(captured.trace, ["SPAWN(1)", "SPAWN(2)"]);
+        assert_eq!(captured.message, "spawn failed");
+
+        // Outside a scenario, the trace is raised in the panic message.
+        let payload = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            execute_commands_silent(&commands, &mut MyState::default());
+        }))
+        .unwrap_err();
+        assert_eq!(
+            payload.downcast_ref::<String>().unwrap(),
+            "panicked in step 2 (SPAWN(0)): spawn failed\n\
+             Executed before it:\n01. SPAWN(1)\n02. SPAWN(2)"
+        );
+    }
+}
+
+#[cfg(test)]
+mod scenario_tests {
+    use super::*;
+    use proptest::prelude::Just;
+    use std::sync::Arc;
+
+    #[derive(Debug, Default, Clone)]
+    struct MyState {
+        action_chronicle: Vec<String>,
+    }
+
+    impl State for MyState {}
+
+    #[derive(Debug, Clone, Default)]
+    struct MyContext {}
+
+    impl TestContext for MyContext {}
+
+    macro_rules! my_command {
+        ($name:ident, $label:expr) => {
+            struct $name;
+            impl Command<MyState, MyContext> for $name {
+                fn check(&self, _state: &MyState) -> bool {
+                    true
+                }
+                fn apply(&self, state: &mut MyState) {
+                    state.action_chronicle.push($label.to_string());
+                }
+                fn label(&self) -> String {
+                    $label.to_string()
+                }
+                fn build(
+                    _ctx: Arc<MyContext>,
+                ) -> impl Strategy<Value = CommandWrapper<MyState, MyContext>> {
+                    Just(CommandWrapper::new($name))
+                }
+            }
+        };
+    }
+
+    my_command!(A, "A");
+    my_command!(B, "B");
+    my_command!(C, "C");
+    my_command!(D, "D");
+    my_command!(E, "E");
+    my_command!(F, "F");
+
+    #[test]
+    fn run_scenario() {
+        let ctx = Arc::new(MyContext::default());
+        scenario![ctx, A, B, C, D, E, F];
+    }
+
+    #[test]
+    fn run_weighted_scenario() {
+        let ctx = Arc::new(MyContext::default());
+        scenario![ctx, A: 5, B, (C): 2, D: 1,];
+    }
+
+    #[test]
+    fn command_sets_are_included_where_listed() {
+        let ctx = Arc::new(MyContext::default());
+        let base = commands![A, B];
+        let extended = commands![include base, #[phase] C];
+        scenario![
+            assert_report = |report| {
+                let labels: Vec<&str> = report.steps.iter().map(|s| s.label.as_str()).collect();
+                assert_eq!(labels, ["D", "A", "B", "C", "E"]);
+            },
+            ctx,
+            D,
+            include extended,
+            E
+        ];
+        scenario![
+            assert_report = |report| assert_eq!(report.steps.len(), 4),
+            ctx,
+            include base,
+            include base
+        ];
+    }
+
+    #[test]
+    fn run_scenario_with_report_assertion() {
+        let ctx = Arc::new(MyContext::default());
+        scenario![
+            assert_report =
+                |report| { assert_eq!(report.count("A") + report.count("B"), report.steps.len()) },
+            ctx,
+            A,
+            B
+        ];
+    }
+
+    #[test]
+    fn setup_and_teardown_run_around_every_case() {
+        let log = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let (setup_log, teardown_log) = (log.clone(), log.clone());
+        let ctx = Arc::new(MyContext::default());
+        scenario![
+            config = Config {
+                cases: 2,
+                ..Config::default()
+            },
+            setup = move || setup_log.borrow_mut().push("setup".to_string()),
+            teardown = move |state| teardown_log
+                .borrow_mut()
+                .push(state.action_chronicle.join(",")),
+            ctx,
+            A,
+            B
+        ];
+        assert_eq!(*log.borrow(), vec!["setup", "A,B", "setup", "A,B"]);
+
+        // Teardown also runs when the case panics.
+        log.borrow_mut().clear();
+        let teardown_log = log.clone();
+        let scenario = Scenario::new(ctx)
+            .fixed(1, CommandWrapper::new(A))
+            .teardown(move |_| teardown_log.borrow_mut().push("teardown".to_string()))
+            .assert_report(|_| panic!("assertion failed"))
+            .persist_regressions(false)
+            .verbosity(Verbosity::Quiet);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| scenario.run()));
+        assert!(result.is_err());
+        assert_eq!(*log.borrow(), vec!["teardown"]);
+    }
+
+    #[test]
+    fn cancelled_runs_stop_between_commands_and_tear_down() {
+        use crate::cancel::CancellationToken;
+
+        struct Stop(CancellationToken);
+
+        impl Command<MyState, MyContext> for Stop {
+            fn check(&self, _state: &MyState) -> bool {
+                true
+            }
+
+            fn apply(&self, _state: &mut MyState) {
+                self.0.cancel();
+            }
+
+            fn label(&self) -> String {
+                "STOP".to_string()
+            }
+
+            fn build(
+                _ctx: Arc<MyContext>,
+            ) -> impl Strategy<Value = CommandWrapper<MyState, MyContext>> {
+                Just(CommandWrapper::new(Stop(CancellationToken::new())))
+            }
+        }
+
+        let log = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let teardown_log = log.clone();
+        let scenario = Scenario::new(Arc::new(MyContext::default()));
+        let token = scenario.cancellation_token();
+        scenario
+            .fixed(1, CommandWrapper::new(A))
+            .fixed(1, CommandWrapper::new(Stop(token)))
+            .fixed(1, CommandWrapper::new(B))
+            .cases(5)
+            .teardown(move |state| {
+                teardown_log
+                    .borrow_mut()
+                    .push(state.action_chronicle.join(","))
+            })
+            .assert_report(|_| panic!("asserted over a cancelled run"))
+            .verbosity(Verbosity::Quiet)
+            .run();
+
+        assert_eq!(*log.borrow(), vec!["A"]);
+    }
+
+    #[test]
+    fn phases_keep_their_order_and_lengths() {
+        let ctx = Arc::new(MyContext::default());
+        scenario![
+            config = Config {
+                mode: Mode::Random,
+                cases: 20,
+                seq_len: 1..2,
+                ..Config::default()
+            },
+            verbosity = Verbosity::Quiet,
+            assert_report = |report| {
+                let labels: Vec<&str> = report.steps.iter().map(|s| s.label.as_str()).collect();
+                let middle = &labels[3..labels.len() - 1];
+                assert_eq!(labels[..3], ["A", "B", "C"]);
+                assert!((3..6).contains(&middle.len()));
+                assert!(middle.iter().all(|label| ["D", "E"].contains(label)));
+                assert_eq!(labels.last(), Some(&"F"));
+            },
+            ctx,
+            A,
+            #[phase]
+            B,
+            C,
+            #[phase(3..6)]
+            D,
+            E,
+            #[phase]
+            F
+        ];
+    }
+
+    #[test]
+    fn mixed_mode_runs_a_prefix_in_order_then_random_commands() {
+        let ctx = Arc::new(MyContext::default());
+        scenario![
+            config = Config {
+                mode: Mode::Mixed { prefix: 2 },
+                cases: 20,
+                seq_len: 5..10,
+                ..Config::default()
+            },
+            verbosity = Verbosity::Quiet,
+            assert_report = |report| {
+                let labels: Vec<&str> = report.steps.iter().map(|s| s.label.as_str()).collect();
+                assert_eq!(labels[..2], ["A", "B"]);
+                assert!((5..10).contains(&(labels.len() - 2)));
+                assert!(labels[2..].iter().all(|label| ["C", "D"].contains(label)));
+            },
+            ctx,
+            A,
+            B,
+            C,
+            D
+        ];
+    }
+
+    #[test]
+    fn transitions_shape_random_sequences() {
+        use crate::markov::Transitions;
+
+        let ctx = Arc::new(MyContext::default());
+        scenario![
+            config = Config {
+                mode: Mode::Random,
+                cases: 20,
+                seq_len: 10..30,
+                ..Config::default()
+            },
+            verbosity = Verbosity::Quiet,
+            transitions = Transitions::new()
+                .after("A", "A", 0.0)
+                .after("A", "C", 0.0)
+                .after("B", "C", 10.0),
+            assert_report = |report| {
+                let labels: Vec<&str> = report.steps.iter().map(|s| s.label.as_str()).collect();
+                assert!(labels
+                    .windows(2)
+                    .all(|pair| pair[0] != "A" || pair[1] == "B"));
+            },
+            ctx,
+            A,
+            B,
+            C
+        ];
+    }
+
+    #[test]
+    fn commands_declare_their_own_weight() {
+        struct Heavy;
+
+        impl Command<MyState, MyContext> for Heavy {
+            fn check(&self, _state: &MyState) -> bool {
+                true
+            }
+            fn apply(&self, state: &mut MyState) {
+                state.action_chronicle.push("HEAVY".to_string());
+            }
+            fn label(&self) -> String {
+                "HEAVY".to_string()
+            }
+            fn weight(_ctx: &MyContext) -> u32 {
+                20
+            }
+            fn build(
+                _ctx: Arc<MyContext>,
+            ) -> impl Strategy<Value = CommandWrapper<MyState, MyContext>> {
+                Just(CommandWrapper::new(Heavy))
+            }
+        }
+
+        let counts = std::rc::Rc::new(std::cell::Cell::new((0, 0)));
+        let report_counts = counts.clone();
+        let ctx = Arc::new(MyContext::default());
+        scenario![
+            config = Config {
+                mode: Mode::Random,
+                cases: 20,
+                seq_len: 10..30,
+                ..Config::default()
+            },
+            verbosity = Verbosity::Quiet,
+            assert_report = move |report| {
+                let (heavy, a) = report_counts.get();
+                report_counts.set((heavy + report.count("HEAVY"), a + report.count("A")));
+            },
+            ctx,
+            A,
+            Heavy
+        ];
+        let (heavy, a) = counts.get();
+        assert!(heavy > 5 * a, "HEAVY ran {} times, A {} times", heavy, a);
+    }
+
+    #[test]
+    fn constraints_hold_in_generated_sequences() {
+        use crate::constraints::Constraints;
+
+        let ctx = Arc::new(MyContext::default());
+        scenario![
+            config = Config {
+                mode: Mode::Random,
+                cases: 20,
+                seq_len: 10..30,
+                ..Config::default()
+            },
+            verbosity = Verbosity::Quiet,
+            constraints = Constraints::new()
+                .never_before("B", "A")
+                .at_most("A", 3)
+                .last("C"),
+            assert_report = |report| {
+                let labels: Vec<&str> = report.steps.iter().map(|s| s.label.as_str()).collect();
+                let first_a = labels.iter().position(|l| *l == "A");
+                let first_b = labels.iter().position(|l| *l == "B");
+                assert!(first_b.is_none() || first_a < first_b, "{:?}", labels);
+                assert!(report.count("A") <= 3);
+                assert!(report.count("C") <= 1);
+                assert!(report.count("C") == 0 || labels.last() == Some(&"C"));
+            },
+            ctx,
+            A,
+            B,
+            C
+        ];
+    }
+
+    #[test]
+    fn saturated_runs_stop_early() {
+        #[derive(Debug, Default)]
+        struct Toggle {
+            on: bool,
+        }
+
+        impl State for Toggle {}
+
+        struct Flip;
+
+        impl Command<Toggle, MyContext> for Flip {
+            fn check(&self, _state: &Toggle) -> bool {
+                true
+            }
+
+            fn apply(&self, state: &mut Toggle) {
+                state.on = !state.on;
+            }
+
+            fn label(&self) -> String {
+                "FLIP".to_string()
+            }
+
+            fn build(
+                _ctx: Arc<MyContext>,
+            ) -> impl Strategy<Value = CommandWrapper<Toggle, MyContext>> {
+                Just(CommandWrapper::new(Flip))
+            }
+        }
+
+        let cases = std::rc::Rc::new(std::cell::Cell::new(0));
+        let counter = cases.clone();
+        let ctx = Arc::new(MyContext::default());
+        scenario![
+            config = Config {
+                mode: Mode::Random,
+                cases: 1000,
+                seq_len: 100..101,
+                saturation_threshold: Some(0),
+                ..Config::default()
+            },
+            verbosity = Verbosity::Quiet,
+            setup = move || counter.set(counter.get() + 1),
+            ctx,
+            Flip
+        ];
+        // Both states are found in the first 1000 commands (10 cases), and
+        // none in the next 1000.
+        assert_eq!(cases.get(), 20);
+    }
+
+    #[test]
+    fn exhaustive_mode_prunes_sequences_with_skipped_commands() {
+        #[derive(Debug, Default)]
+        struct Door {
+            open: bool,
+        }
+
+        impl State for Door {}
+
+        struct Open;
+
+        impl Command<Door, MyContext> for Open {
+            fn check(&self, state: &Door) -> bool {
+                !state.open
+            }
+
+            fn apply(&self, state: &mut Door) {
+                state.open = true;
+            }
+
+            fn label(&self) -> String {
+                "OPEN".to_string()
+            }
+
+            fn build(
+                _ctx: Arc<MyContext>,
+            ) -> impl Strategy<Value = CommandWrapper<Door, MyContext>> {
+                Just(CommandWrapper::new(Open))
+            }
+        }
+
+        struct Close;
+
+        impl Command<Door, MyContext> for Close {
+            fn check(&self, state: &Door) -> bool {
+                state.open
+            }
+
+            fn apply(&self, state: &mut Door) {
+                state.open = false;
+            }
+
+            fn label(&self) -> String {
+                "CLOSE".to_string()
+            }
+
+            fn build(
+                _ctx: Arc<MyContext>,
+            ) -> impl Strategy<Value = CommandWrapper<Door, MyContext>> {
+                Just(CommandWrapper::new(Close))
+            }
+        }
+
+        let runs = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let recorded = runs.clone();
+        let ctx = Arc::new(MyContext::default());
+        scenario![
+            config = Config {
+                mode: Mode::Exhaustive { max_len: 3 },
+                ..Config::default()
+            },
+            verbosity = Verbosity::Quiet,
+            assert_report = move |report| {
+                let labels: Vec<String> = report.executed().map(|s| s.label.clone()).collect();
+                recorded.borrow_mut().push(labels.join(" "));
+            },
+            ctx,
+            Open,
+            Close
+        ];
+
+        // Sequences starting with CLOSE, or repeating a command, stop
+        // growing at their first skipped command.
+        assert_eq!(
+            *runs.borrow(),
+            [
+                "OPEN",
+                "",
+                "OPEN",
+                "OPEN CLOSE",
+                "OPEN CLOSE OPEN",
+                "OPEN CLOSE"
+            ]
+        );
+    }
+
+    #[test]
+    fn swarm_mode_leaves_commands_out_of_some_cases() {
+        let runs = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let recorded = runs.clone();
+        let ctx = Arc::new(MyContext::default());
+        scenario![
+            config = Config {
+                mode: Mode::Swarm,
+                cases: 20,
+                seq_len: 30..31,
+                seed: Some(7),
+                ..Config::default()
+            },
+            verbosity = Verbosity::Quiet,
+            assert_report = move |report| {
+                let used: std::collections::BTreeSet<String> =
+                    report.executed().map(|s| s.label.clone()).collect();
+                recorded.borrow_mut().push(used);
+            },
+            ctx,
+            A,
+            B,
+            C
+        ];
+
+        // Thirty commands drawn from all three would almost surely use each.
+        let runs = runs.borrow();
+        assert_eq!(runs.len(), 20);
+        assert!(runs.iter().all(|used| !used.is_empty()));
+        assert!(runs.iter().filter(|used| used.len() < 3).count() >= 10);
+    }
+
+    #[test]
+    fn time_budget_replaces_the_number_of_cases() {
+        let cases = std::rc::Rc::new(std::cell::Cell::new(0));
+        let counted = cases.clone();
+        let ctx = Arc::new(MyContext::default());
+        let started = std::time::Instant::now();
+        scenario![
+            time_budget = std::time::Duration::from_millis(50),
+            verbosity = Verbosity::Quiet,
+            setup = move || {
+                counted.set(counted.get() + 1);
+                std::thread::sleep(std::time::Duration::from_millis(5));
+            },
+            ctx,
+            A,
+            B
+        ];
+
+        assert!(started.elapsed() >= std::time::Duration::from_millis(50));
+        assert!(cases.get() > 1);
+    }
+
+    #[test]
+    fn system_under_test_is_reset_and_compared_in_every_case() {
+        use crate::sut::{Mismatch, SystemUnderTest};
+
+        /// Records the labels it was applied, like the model.
+        struct Recorder(std::rc::Rc<std::cell::RefCell<Vec<String>>>);
+
+        impl SystemUnderTest<MyState, MyContext> for Recorder {
+            type Observation = Vec<String>;
+
+            fn apply(
+                &mut self,
+                command: &CommandWrapper<MyState, MyContext>,
+            ) -> Result<(), CommandError> {
+                self.0.borrow_mut().push(command.command.label());
+                Ok(())
+            }
+
+            fn observe(&mut self) -> Vec<String> {
+                self.0.borrow().clone()
+            }
+
+            fn compare(&self, model: &MyState, observed: &Vec<String>) -> Result<(), Mismatch> {
+                Mismatch::check(&model.action_chronicle, observed)
+            }
+
+            fn reset(&mut self) {
+                self.0.borrow_mut().clear();
+            }
+        }
+
+        let applied = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let ctx = Arc::new(MyContext::default());
+        scenario![
+            config = Config {
+                mode: Mode::Random,
+                cases: 5,
+                ..Config::default()
+            },
+            verbosity = Verbosity::Quiet,
+            system_under_test = Recorder(applied.clone()),
+            ctx,
+            A,
+            B
+        ];
+
+        assert!(!applied.borrow().is_empty());
+    }
+
+    #[test]
+    fn state_graph_is_written_to_the_graph_file() {
+        let path = std::env::temp_dir().join(format!("madhouse-graph-{}.dot", std::process::id()));
+        let ctx = Arc::new(MyContext::default());
+        scenario![
+            config = Config {
+                cases: 2,
+                graph_file: Some(path.clone()),
+                ..Config::default()
+            },
+            ctx,
+            A,
+            B
+        ];
+
+        let dot = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(dot.matches(" -> ").count(), 2);
+        assert!(dot.contains(r#"[label="A (x2)"];"#));
+        assert!(dot.contains(r#"[label="B (x2)"];"#));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn projections_are_evaluated_on_the_final_state() {
+        use crate::projection::Projections;
+        use serde_json::json;
+
+        let ctx = Arc::new(MyContext::default());
+        scenario![
+            projections = Projections::new().add("actions", |state: &MyState| json!(state
+                .action_chronicle
+                .len())),
+            assert_report = |report| {
+                assert_eq!(report.projections, Some(json!({"actions": 2})));
+                assert!(report
+                    .to_json(None)
+                    .contains(r#""projections":{"actions":2},"#));
+            },
+            ctx,
+            A,
+            B
+        ];
+    }
+
+    #[test]
+    fn run_scenario_with_heartbeat() {
+        let ctx = Arc::new(MyContext::default());
+        scenario![
+            heartbeat = Heartbeat::every_steps(1, F),
+            assert_report = |report| {
+                assert_eq!(report.heartbeats.len(), report.executed().count());
+                assert!(report.steps.iter().all(|step| step.label != "F"));
+            },
+            ctx,
+            A,
+            B,
+            C
+        ];
+    }
+
+    #[test]
+    #[should_panic(expected = "assertion")]
+    fn failing_report_assertion_fails_scenario() {
+        let ctx = Arc::new(MyContext::default());
+        scenario![
+            assert_report = |report| assert!(report.count("C") > report.count("D")),
+            persist_regressions = false,
+            ctx,
+            C,
+            D
+        ];
+    }
+
+    #[test]
+    fn max_failures_zero_runs_every_case() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+        struct Broken;
+        impl Command<MyState, MyContext> for Broken {
+            fn check(&self, _state: &MyState) -> bool {
+                true
+            }
+            fn apply(&self, _state: &mut MyState) {}
+            fn try_apply(&self, _state: &mut MyState) -> Result<(), CommandError> {
+                RUNS.fetch_add(1, Ordering::SeqCst);
+                Err(CommandError::new("broken"))
+            }
+            fn label(&self) -> String {
+                "BROKEN".to_string()
+            }
+            fn build(
+                _ctx: Arc<MyContext>,
+            ) -> impl Strategy<Value = CommandWrapper<MyState, MyContext>> {
+                Just(CommandWrapper::new(Broken))
+            }
+        }
+
+        let ctx = Arc::new(MyContext::default());
+        let result = std::panic::catch_unwind(|| {
+            Scenario::new(ctx.clone())
+                .strategy(Broken::build(ctx.clone()))
+                .cases(4)
+                .max_failures(0)
+                .persist_regressions(false)
+                .run();
+        });
+
+        // Every case ran instead of stopping at the first failure.
+        let payload = result.unwrap_err();
+        let message = payload.downcast_ref::<String>().unwrap();
+        assert!(message.contains("(BROKEN) failed: broken"), "{}", message);
+        if std::env::var("PROPTEST_CASES").is_err() {
+            assert!(RUNS.load(Ordering::SeqCst) >= 4);
+        }
     }
 
-    impl Command<MyState, MyContext> for TestCommand {
+    struct Num(u64);
+
+    impl Command<MyState, MyContext> for Num {
         fn check(&self, _state: &MyState) -> bool {
             true
         }
-
-        fn apply(&self, state: &mut MyState) {
-            state.last_mined_block += self.value as u64;
-        }
-
+        fn apply(&self, _state: &mut MyState) {}
         fn label(&self) -> String {
-            format!("TEST({})", self.value)
+            format!("NUM({})", self.0)
         }
-
         fn build(
             _ctx: Arc<MyContext>,
         ) -> impl Strategy<Value = CommandWrapper<MyState, MyContext>> {
-            Just(CommandWrapper::new(TestCommand { value: 1 }))
+            (0..1_000_000u64).prop_map(|n| CommandWrapper::new(Num(n)))
         }
     }
 
-    #[test]
-    fn test_command_wrapper() {
-        let cmd = TestCommand { value: 42 };
-        let wrapper = CommandWrapper::new(cmd);
-        let mut state = MyState::default();
-        assert!(wrapper.command.check(&state));
-
-        wrapper.command.apply(&mut state);
-
-        assert_eq!(state.last_mined_block, 42);
-        assert_eq!(format!("{:?}", wrapper), "TEST(42)");
+    /// Runs 3 cases from seed 7 and returns the labels executed in each.
+    fn seeded_traces(only_cases: Option<Vec<u32>>, shard: Option<(u32, u32)>) -> Vec<Vec<String>> {
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = seen.clone();
+        let ctx = Arc::new(MyContext::default());
+        let mut scenario = Scenario::new(ctx.clone())
+            .strategy(Num::build(ctx.clone()))
+            .strategy(Num::build(ctx))
+            .seed(7)
+            .cases(3)
+            .assert_report(move |report| {
+                let labels: Vec<_> = report.steps.iter().map(|s| s.label.clone()).collect();
+                sink.lock().unwrap().push(labels);
+            });
+        if let Some(only) = only_cases {
+            scenario = scenario.only_cases(only);
+        }
+        if let Some((index, count)) = shard {
+            scenario = scenario.shard(index, count);
+        }
+        scenario.run();
+        let traces = seen.lock().unwrap().clone();
+        traces
     }
 
     #[test]
-    fn test_prop_allof_macro() {
-        use proptest::prelude::*;
-
-        // Test with one strategy.
-        let strat1 = Just(1);
-        let combined1 = prop_allof![strat1];
+    fn same_seed_generates_same_cases() {
+        if std::env::var("MADHOUSE_SEED").is_err() {
+            assert_eq!(seeded_traces(None, None), seeded_traces(None, None));
+        }
+    }
 
-        proptest!(|(v in combined1)| {
-            assert_eq!(v, vec![1]);
-        });
+    #[test]
+    fn only_cases_runs_the_same_cases_as_a_full_run() {
+        if std::env::var("MADHOUSE_SEED").is_ok() || std::env::var("MADHOUSE_ONLY_CASES").is_ok() {
+            return;
+        }
+        let all = seeded_traces(None, None);
+        let pinned = seeded_traces(Some(vec![0, 2]), None);
+        assert_eq!(pinned, vec![all[0].clone(), all[2].clone()]);
+    }
 
-        // Test with multiple strategies.
-        let strat2 = Just(2);
-        let strat3 = Just(3);
-        let combined2 = prop_allof![strat1, strat2, strat3];
+    #[test]
+    fn shards_split_the_cases_of_a_full_run() {
+        if std::env::var("MADHOUSE_SEED").is_ok()
+            || std::env::var("MADHOUSE_ONLY_CASES").is_ok()
+            || std::env::var("MADHOUSE_SHARD").is_ok()
+        {
+            return;
+        }
+        let all = seeded_traces(None, None);
+        assert_eq!(
+            seeded_traces(None, Some((1, 2))),
+            vec![all[0].clone(), all[2].clone()]
+        );
+        assert_eq!(seeded_traces(None, Some((2, 2))), vec![all[1].clone()]);
+    }
 
-        proptest!(|(v in combined2)| {
-            assert_eq!(v, vec![1, 2, 3]);
-        });
+    #[test]
+    fn run_scenario_with_config() {
+        if std::env::var("MADHOUSE").is_ok() || std::env::var("MADHOUSE_SEQ_LEN").is_ok() {
+            return;
+        }
+        let ctx = Arc::new(MyContext::default());
+        let config = Config {
+            mode: Mode::Random,
+            cases: 5,
+            seq_len: 3..4,
+            verbosity: Verbosity::Quiet,
+            ..Config::default()
+        };
+        scenario![
+            config = config,
+            assert_report = |report| assert_eq!(report.steps.len(), 3),
+            ctx,
+            A,
+            B
+        ];
     }
 
     #[test]
-    fn test_execute_commands_empty() {
-        let commands: Vec<CommandWrapper<MyState, MyContext>> = vec![];
-        let mut state = MyState::default();
+    fn named_scenarios_label_failures_and_are_summarized() {
+        let dir = std::env::temp_dir().join(format!("madhouse-named-{}", std::process::id()));
+        let ctx = Arc::new(MyContext::default());
+        scenario![
+            name = "named passing",
+            config = Config {
+                cases: 3,
+                verbosity: Verbosity::Quiet,
+                ..Config::default()
+            },
+            ctx,
+            A
+        ];
+        let scenario = Scenario::new(ctx)
+            .name("Named failing")
+            .source_file(file!())
+            .fixed(1, CommandWrapper::new(A))
+            .assert_report(|_| panic!("boom"))
+            .regressions_dir(&dir)
+            .config(Config {
+                cases: 2,
+                ..Config::default()
+            })
+            .verbosity(Verbosity::Quiet);
+        let payload =
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| scenario.run())).unwrap_err();
+        let message = payload.downcast_ref::<String>().unwrap();
+        assert!(message.starts_with("Scenario Named failing: Test failed"));
 
-        let executed = execute_commands(&commands, &mut state);
-        assert!(executed.is_empty());
+        let outcomes = summary::outcomes();
+        let outcome = |name: &str| outcomes.iter().find(|o| o.name == name).unwrap();
+        assert!(outcome("named passing").passed());
+        assert_eq!(outcome("Named failing").failures, 1);
+        let saved: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        assert_eq!(saved.len(), 1);
+        assert!(saved[0].starts_with("lib-named-failing-"));
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 
     #[test]
-    fn test_execute_commands_all_rejected() {
-        struct RejectCommand;
-
-        impl Command<MyState, MyContext> for RejectCommand {
+    fn min_executed_ratio_fails_runs_that_mostly_skip() {
+        struct Locked;
+        impl Command<MyState, MyContext> for Locked {
             fn check(&self, _state: &MyState) -> bool {
                 false
             }
             fn apply(&self, _state: &mut MyState) {}
             fn label(&self) -> String {
-                "REJECT".to_string()
+                "LOCKED".to_string()
             }
             fn build(
                 _ctx: Arc<MyContext>,
             ) -> impl Strategy<Value = CommandWrapper<MyState, MyContext>> {
-                Just(CommandWrapper::new(RejectCommand))
+                Just(CommandWrapper::new(Locked))
             }
         }
 
-        let commands = vec![
-            CommandWrapper::new(RejectCommand),
-            CommandWrapper::new(RejectCommand),
+        let ctx = Arc::new(MyContext::default());
+        Scenario::new(ctx.clone())
+            .fixed(1, CommandWrapper::new(A))
+            .min_executed_ratio(1.0)
+            .verbosity(Verbosity::Quiet)
+            .run();
+
+        let scenario = Scenario::new(ctx)
+            .fixed(1, CommandWrapper::new(Locked))
+            .fixed(1, CommandWrapper::new(A))
+            .cases(1)
+            .seq_len(20..21)
+            .min_executed_ratio(0.9)
+            .verbosity(Verbosity::Quiet)
+            .persist_regressions(false);
+        let payload =
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| scenario.run())).unwrap_err();
+        let message = payload.downcast_ref::<String>().unwrap();
+        assert!(message.starts_with("Only "), "{}", message);
+        assert!(message.contains("(minimum 90.0%). Most skipped:\n  LOCKED: "));
+    }
+
+    #[test]
+    fn assert_trace_fails_cases_whose_labels_do_not_match() {
+        use crate::predicates::{contains, contains_subsequence};
+
+        let ctx = Arc::new(MyContext::default());
+        scenario![
+            assert_trace = contains_subsequence(["A", "C"]),
+            ctx,
+            A,
+            B,
+            C
         ];
-        let mut state = MyState::default();
 
-        let executed = execute_commands(&commands, &mut state);
-        assert!(executed.is_empty());
+        let scenario = Scenario::new(ctx)
+            .fixed(1, CommandWrapper::new(A))
+            .fixed(1, CommandWrapper::new(B))
+            .assert_trace(contains("B").and(!contains("A")))
+            .verbosity(Verbosity::Quiet)
+            .persist_regressions(false);
+        let payload =
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| scenario.run())).unwrap_err();
+        let message = payload.downcast_ref::<String>().unwrap();
+        assert!(
+            message.contains("trace does not satisfy `contains B and not contains A`"),
+            "{}",
+            message
+        );
     }
-}
 
-#[cfg(test)]
-mod scenario_tests {
-    use super::*;
-    use proptest::prelude::Just;
-    use std::sync::Arc;
+    #[test]
+    fn differential_fails_at_the_first_divergence() {
+        let ctx = Arc::new(MyContext::default());
+        let offset = || MyState {
+            action_chronicle: vec!["X".to_string()],
+        };
+        let scenario = || {
+            Scenario::new(ctx.clone())
+                .fixed(1, CommandWrapper::new(A))
+                .fixed(1, CommandWrapper::new(B))
+                .verbosity(Verbosity::Quiet)
+                .persist_regressions(false)
+        };
+        scenario()
+            .differential(offset, |a, b| {
+                b.action_chronicle[1..] == a.action_chronicle[..]
+            })
+            .run();
 
-    #[derive(Debug, Default, Clone)]
-    struct MyState {
-        action_chronicle: Vec<String>,
+        let diverging =
+            scenario().differential(offset, |a, b| a.action_chronicle == b.action_chronicle);
+        let payload =
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| diverging.run())).unwrap_err();
+        let message = payload.downcast_ref::<String>().unwrap();
+        assert!(
+            message.contains("models diverge after A (- model, + reference)"),
+            "{}",
+            message
+        );
     }
 
-    impl State for MyState {}
+    #[test]
+    fn require_executed_regenerates_vacuous_cases() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
 
-    #[derive(Debug, Clone, Default)]
-    struct MyContext {}
+        static CASES: AtomicUsize = AtomicUsize::new(0);
 
-    impl TestContext for MyContext {}
+        let ctx = Arc::new(MyContext::default());
+        scenario![
+            config = Config {
+                mode: Mode::Random,
+                cases: 20,
+                seq_len: 1..6,
+                verbosity: Verbosity::Quiet,
+                ..Config::default()
+            },
+            require_executed >= 3,
+            assert_report = |report| {
+                assert!(report.executed().count() >= 3);
+                CASES.fetch_add(1, Ordering::SeqCst);
+            },
+            ctx,
+            A
+        ];
+        if std::env::var("PROPTEST_CASES").is_err() {
+            assert_eq!(CASES.load(Ordering::SeqCst), 20);
+        }
 
-    macro_rules! my_command {
-        ($name:ident, $label:expr) => {
-            struct $name;
-            impl Command<MyState, MyContext> for $name {
-                fn check(&self, _state: &MyState) -> bool {
-                    true
-                }
-                fn apply(&self, state: &mut MyState) {
-                    state.action_chronicle.push($label.to_string());
-                }
-                fn label(&self) -> String {
-                    $label.to_string()
-                }
-                fn build(
-                    _ctx: Arc<MyContext>,
-                ) -> impl Strategy<Value = CommandWrapper<MyState, MyContext>> {
-                    Just(CommandWrapper::new($name))
-                }
-            }
+        let never = Scenario::new(ctx)
+            .fixed(1, CommandWrapper::new(A))
+            .mode(Mode::Random)
+            .seq_len(1..3)
+            .require_executed(3)
+            .verbosity(Verbosity::Quiet);
+        let payload =
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| never.run())).unwrap_err();
+        let message = payload.downcast_ref::<String>().unwrap();
+        assert!(message.contains("Too many global rejects"), "{}", message);
+    }
+
+    #[test]
+    fn scenario_takes_an_inline_config() {
+        if [
+            "MADHOUSE",
+            "MADHOUSE_SEQ_LEN",
+            "PROPTEST_CASES",
+            "MADHOUSE_TIME_BUDGET",
+        ]
+        .iter()
+        .any(|var| std::env::var(var).is_ok())
+        {
+            return;
+        }
+        let ctx = Arc::new(MyContext::default());
+        let cases = std::rc::Rc::new(std::cell::Cell::new(0));
+        let counted = cases.clone();
+        scenario![
+            config = Config {
+                mode: Mode::Random,
+                cases: 50,
+                seq_len: 1..64,
+                max_shrink_iters: 10,
+                verbosity: Verbosity::Quiet,
+                ..Config::default()
+            },
+            assert_report = move |report| {
+                assert!((1..64).contains(&report.steps.len()));
+                counted.set(counted.get() + 1);
+            },
+            ctx,
+            A,
+            B,
+            C
+        ];
+        assert_eq!(cases.get(), 50);
+    }
+
+    #[test]
+    fn corpus_traces_are_replayed_with_one_mutation() {
+        let ctx = Arc::new(MyContext::default());
+        Scenario::new(ctx)
+            .fixed(1, CommandWrapper::new(A))
+            .fixed(1, CommandWrapper::new(B))
+            .corpus(1.0, vec![vec!["B".to_string(); 4]])
+            .cases(20)
+            .verbosity(Verbosity::Quiet)
+            .assert_report(|report| {
+                let len = report.steps.len();
+                assert!((3..=5).contains(&len), "{:?}", report.steps);
+                assert!(report.count("B") >= len - 1);
+            })
+            .run();
+    }
+
+    #[cfg(feature = "artifacts")]
+    #[test]
+    fn failure_hook_captures_artifacts() {
+        let dir = std::env::temp_dir().join(format!("madhouse-hook-{}", std::process::id()));
+        let ctx = Arc::new(MyContext::default());
+        let scenario = Scenario::new(ctx)
+            .fixed(1, CommandWrapper::new(A))
+            .artifacts_dir(&dir)
+            .persist_regressions(false)
+            .verbosity(Verbosity::Quiet)
+            .assert_report(|_| panic!("dashboard shows an error"))
+            .on_failure(|_ctx, failure| {
+                let trace = format!("{}\n{}", failure.message, failure.labels.join("\n"));
+                failure.write("snapshot.txt", trace).map(|_| ())
+            });
+
+        assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| scenario.run())).is_err());
+
+        let case_dir = std::fs::read_dir(&dir)
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap()
+            .path();
+        let snapshot = std::fs::read_to_string(case_dir.join("snapshot.txt")).unwrap();
+        assert_eq!(snapshot, "dashboard shows an error\nA");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn junit_report_records_failing_trace() {
+        let dir = std::env::temp_dir().join(format!("madhouse-junit-{}", std::process::id()));
+        let ctx = Arc::new(MyContext::default());
+        let scenario = Scenario::new(ctx)
+            .fixed(1, CommandWrapper::new(A))
+            .junit_dir(&dir)
+            .persist_regressions(false)
+            .verbosity(Verbosity::Quiet)
+            .assert_report(|_| panic!("broken <invariant>"));
+
+        assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| scenario.run())).is_err());
+
+        let xml = std::fs::read_to_string(
+            dir.join("scenario_tests__junit_report_records_failing_trace.xml"),
+        )
+        .unwrap();
+        assert!(xml.contains(r#"failures="1""#), "{}", xml);
+        assert!(xml.contains(r#"<testcase classname="scenario_tests::junit_report_records_failing_trace" name="case 0""#));
+        assert!(xml.contains("broken &lt;invariant&gt;"));
+        assert!(xml.contains("minimal failing input: [\n    &quot;A&quot;,\n]"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn html_report_records_failing_trace_and_coverage() {
+        let dir = std::env::temp_dir().join(format!("madhouse-html-{}", std::process::id()));
+        let ctx = Arc::new(MyContext::default());
+        let scenario = Scenario::new(ctx)
+            .fixed(1, CommandWrapper::new(A))
+            .html_dir(&dir)
+            .persist_regressions(false)
+            .verbosity(Verbosity::Quiet)
+            .assert_report(|_| panic!("broken <invariant>"));
+
+        assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| scenario.run())).is_err());
+
+        let file = std::fs::read_dir(&dir).unwrap().next().unwrap().unwrap();
+        let html = std::fs::read_to_string(file.path()).unwrap();
+        assert!(html.contains("scenario_tests::html_report_records_failing_trace_and_coverage"));
+        assert!(html.contains(r#"<td class="fail">FAIL</td>"#), "{}", html);
+        assert!(html.contains("broken &lt;invariant&gt;"));
+        assert!(html.contains("<li>A</li>"));
+        assert!(html.contains("<h3>Command coverage</h3>"));
+        assert!(html.contains("<h3>apply() durations</h3>"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn timings_csv_has_a_row_per_applied_command_of_each_case() {
+        let dir = std::env::temp_dir().join(format!("madhouse-csv-{}", std::process::id()));
+        let path = dir.join("timings.csv");
+        let ctx = Arc::new(MyContext::default());
+        Scenario::new(ctx)
+            .fixed(1, CommandWrapper::new(A))
+            .fixed(1, CommandWrapper::new(B))
+            .config(Config {
+                cases: 2,
+                ..Config::default()
+            })
+            .timings_csv(&path)
+            .verbosity(Verbosity::Quiet)
+            .run();
+
+        let csv = std::fs::read_to_string(&path).unwrap();
+        let rows: Vec<Vec<&str>> = csv.lines().map(|row| row.split(',').collect()).collect();
+        assert_eq!(rows[0], timings::HEADER.split(',').collect::<Vec<_>>());
+        let cells: Vec<_> = rows[1..]
+            .iter()
+            .map(|row| (row[1], row[2], row[3]))
+            .collect();
+        assert_eq!(
+            cells,
+            [
+                ("0", "0", "A"),
+                ("0", "1", "B"),
+                ("1", "0", "A"),
+                ("1", "1", "B")
+            ]
+        );
+        assert!(rows[1..].iter().all(|row| {
+            row[0] == "scenario_tests::timings_csv_has_a_row_per_applied_command_of_each_case"
+                && row[4].parse::<u128>().is_ok()
+        }));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn guided_generation_favors_commands_reaching_new_states() {
+        let run = |guided: bool| {
+            let counts = std::rc::Rc::new(std::cell::Cell::new((0, 0)));
+            let recorded = counts.clone();
+            let ctx = Arc::new(MyContext::default());
+            let scenario = Scenario::new(ctx)
+                .fixed(1, CommandWrapper::new(A))
+                .fixed(1, CommandWrapper::new(B))
+                .config(Config {
+                    mode: Mode::Random,
+                    cases: 30,
+                    seq_len: 20..21,
+                    seed: Some(3),
+                    ..Config::default()
+                })
+                .assert_report(move |report| {
+                    let (a, b) = recorded.get();
+                    recorded.set((a + report.count("A"), b + report.count("B")));
+                })
+                .verbosity(Verbosity::Quiet);
+            let scenario = if guided {
+                // Only A changes the abstract state.
+                scenario.guided(|state: &MyState| {
+                    state.action_chronicle.iter().filter(|a| *a == "A").count() as u64
+                })
+            } else {
+                scenario
+            };
+            scenario.run();
+            counts.get()
+        };
+
+        let (a, b) = run(true);
+        let (unguided, _) = run(false);
+        assert_eq!(a + b, 600);
+        assert!(
+            a > unguided + 50,
+            "A {} times guided, {} unguided",
+            a,
+            unguided
+        );
+    }
+
+    #[test]
+    fn corpus_keeps_parseable_traces_reaching_new_states() {
+        let dir = std::env::temp_dir().join(format!("madhouse-corpus-{}", std::process::id()));
+        let run = |mode, with_c: bool| {
+            let ctx = Arc::new(MyContext::default());
+            let scenario = Scenario::new(ctx.clone())
+                .fixed(1, CommandWrapper::new(A))
+                .fixed(1, CommandWrapper::new(B));
+            let scenario = if with_c {
+                scenario.strategy(C::build(ctx))
+            } else {
+                scenario
+            };
+            scenario
+                .config(Config {
+                    mode,
+                    cases: 20,
+                    seq_len: 1..4,
+                    seed: Some(7),
+                    ..Config::default()
+                })
+                .persist_corpus(&dir)
+                .verbosity(Verbosity::Quiet)
+                .run();
+            corpus::Corpus::open(&dir).unwrap().traces().to_vec()
         };
+        let ab = ["A", "B"];
+
+        assert_eq!(run(Mode::Normal, false), [ab]);
+        // Nothing new, and C has no parser.
+        assert_eq!(run(Mode::Normal, false), [ab]);
+        assert_eq!(run(Mode::Normal, true), [ab]);
+
+        // Random runs replay mutations of the corpus and add to it.
+        let traces = run(Mode::Random, false);
+        assert!(traces.len() > 1);
+        assert!(traces.iter().any(|trace| *trace == ab));
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 
-    my_command!(A, "A");
-    my_command!(B, "B");
-    my_command!(C, "C");
-    my_command!(D, "D");
-    my_command!(E, "E");
-    my_command!(F, "F");
+    struct Recorder(std::rc::Rc<std::cell::RefCell<Vec<String>>>);
+
+    impl notify::Notifier for Recorder {
+        fn on_first_failure(&self, notice: &notify::FailureNotice) {
+            self.0
+                .borrow_mut()
+                .push(format!("failure {:?}", notice.labels));
+        }
+
+        fn on_campaign_end(&self, summary: &notify::CampaignSummary) {
+            self.0
+                .borrow_mut()
+                .push(format!("end {} failures", summary.failures));
+        }
+    }
 
     #[test]
-    fn run_scenario() {
+    fn notifiers_hear_first_failure_and_campaign_end() {
+        let events = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
         let ctx = Arc::new(MyContext::default());
-        scenario![ctx, A, B, C, D, E, F];
+        let scenario = Scenario::new(ctx)
+            .fixed(1, CommandWrapper::new(A))
+            .cases(3)
+            .max_failures(0)
+            .persist_regressions(false)
+            .verbosity(Verbosity::Quiet)
+            .notifier(Recorder(events.clone()))
+            .assert_report(|_| panic!("page me"));
+
+        assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| scenario.run())).is_err());
+
+        assert_eq!(
+            *events.borrow(),
+            vec![r#"failure ["A"]"#.to_string(), "end 1 failures".to_string()]
+        );
+    }
+
+    fn regression_file(name: &str, contents: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("madhouse-replay-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn replay_runs_saved_sequence_verbatim() {
+        let path = regression_file("verbatim.txt", "# saved\nB\nA\nB\n");
+        let ctx = Arc::new(MyContext::default());
+        replay![
+            path,
+            assert_report = |report| {
+                let labels: Vec<_> = report.steps.iter().map(|s| s.label.as_str()).collect();
+                assert_eq!(labels, vec!["B", "A", "B"]);
+            },
+            ctx,
+            (A),
+            (B)
+        ];
+    }
+
+    #[test]
+    fn conform_records_then_detects_divergence() {
+        let path = regression_file("conform.golden", "A\nB\n");
+        let ctx = Arc::new(MyContext::default());
+        conform![path.clone(), ctx.clone(), (A), (B)];
+
+        let recorded = std::fs::read_to_string(&path).unwrap();
+        assert!(recorded.contains("A\texecuted\t"), "{}", recorded);
+        conform![path.clone(), ctx.clone(), (A), (B)];
+
+        let tampered = recorded.replace("B\texecuted", "B\tskipped");
+        std::fs::write(&path, tampered).unwrap();
+        let diverged = std::panic::catch_unwind(|| conform![path, ctx, (A), (B)]).unwrap_err();
+        let message = diverged.downcast_ref::<String>().unwrap();
+        assert!(
+            message.contains("golden trace diverged at step 1"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "no command parses label `Z`")]
+    fn replay_rejects_unknown_label() {
+        let path = regression_file("unknown.txt", "A\nZ\n");
+        let ctx = Arc::new(MyContext::default());
+        replay![path, ctx, (A)];
     }
 }
 
@@ -823,4 +4113,103 @@ mod shrinking_scenario_tests {
             (IncrementCommand { amount: 42 })
         ];
     }
+
+    #[test]
+    fn panics_are_attributed_to_their_step() {
+        let ctx = Arc::new(CounterContext::default());
+        let scenario = Scenario::new(ctx)
+            .fixed(1, CommandWrapper::new(IncrementCommand { amount: 60 }))
+            .fixed(1, CommandWrapper::new(IncrementCommand { amount: 50 }))
+            .mode(Mode::Normal)
+            .persist_regressions(false)
+            .verbosity(Verbosity::Quiet);
+
+        let failure =
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| scenario.run())).unwrap_err();
+        let message = failure.downcast_ref::<String>().unwrap();
+        assert!(
+            message.contains("panicked in step 1 (INCREMENT(50)) at src/lib.rs:"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn quarantined_failures_do_not_fail_the_run() {
+        let labels = vec!["INCREMENT(60)".to_string(), "INCREMENT(50)".to_string()];
+        let path = std::env::temp_dir().join(format!("madhouse-quarantine-{}", std::process::id()));
+        let scenario = |quarantine: String| {
+            std::fs::write(&path, quarantine).unwrap();
+            let ctx = Arc::new(CounterContext::default());
+            Scenario::new(ctx)
+                .fixed(1, CommandWrapper::new(IncrementCommand { amount: 60 }))
+                .fixed(1, CommandWrapper::new(IncrementCommand { amount: 50 }))
+                .mode(Mode::Normal)
+                .seed(9)
+                .quarantine(&path)
+                .persist_regressions(false)
+                .verbosity(Verbosity::Quiet)
+                .run();
+        };
+
+        scenario(format!(
+            "# overflow\n{:016x}\n",
+            regression::fingerprint(&labels)
+        ));
+        scenario("seed 9 0\n".to_string());
+        let failure = std::panic::catch_unwind(|| scenario("seed 9 1\n".to_string()));
+        let _ = std::fs::remove_file(&path);
+        assert!(failure.is_err());
+    }
+
+    struct BulkIncrement {
+        amounts: Vec<u32>,
+    }
+
+    impl Command<CounterState, CounterContext> for BulkIncrement {
+        fn check(&self, _state: &CounterState) -> bool {
+            true
+        }
+
+        fn apply(&self, state: &mut CounterState) {
+            state.value += self.amounts.iter().sum::<u32>();
+            assert!(self.amounts.len() < 2, "too many legs");
+        }
+
+        fn label(&self) -> String {
+            format!("BULK({:?})", self.amounts)
+        }
+
+        fn build(
+            _ctx: Arc<CounterContext>,
+        ) -> impl Strategy<Value = CommandWrapper<CounterState, CounterContext>> {
+            proptest::strategy::Just(CommandWrapper::new(BulkIncrement {
+                amounts: vec![5, 6, 7, 8],
+            }))
+        }
+
+        fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+            let amounts = self.amounts.clone();
+            Box::new((0..amounts.len()).map(move |i| {
+                let mut amounts = amounts.clone();
+                amounts.remove(i);
+                BulkIncrement { amounts }
+            }))
+        }
+    }
+
+    #[test]
+    fn custom_shrinker_simplifies_command_structure() {
+        let ctx = Arc::new(CounterContext::default());
+        let scenario = Scenario::new(ctx.clone())
+            .strategy(BulkIncrement::build(ctx))
+            .max_shrink_iters(100)
+            .persist_regressions(false)
+            .verbosity(Verbosity::Quiet);
+
+        let failure =
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| scenario.run())).unwrap_err();
+        let message = failure.downcast_ref::<String>().unwrap();
+        assert!(message.contains("\"BULK([7, 8])\""), "{}", message);
+    }
 }
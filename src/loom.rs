@@ -0,0 +1,223 @@
+//! Exploring the thread interleavings of a case with
+//! [loom](https://docs.rs/loom), with the `loom` feature.
+//!
+//! Commands that spawn threads over shared atomics or locks may pass on
+//! every run and still race. Under [`loom::model`](::loom::model), such a
+//! case runs once per interleaving of its threads, so a lost update or a
+//! deadlock shows up deterministically. [`execute_model`] explores a single
+//! sequence; [`Scenario::loom`](crate::Scenario::loom) explores every case
+//! of a scenario, shrinking failing ones as usual.
+//!
+//! Only loom's primitives are modeled: the state must be built from those
+//! re-exported in [`sync`] and [`thread`], and created inside the model,
+//! which is why each interleaving starts from a fresh state. The test
+//! context is shared by all interleavings and must not hold any.
+//!
+//! # Examples
+//!
+//! ```
+//! use madhouse::loom::execute_model;
+//! use madhouse::loom::sync::atomic::{AtomicUsize, Ordering};
+//! use madhouse::loom::sync::Arc;
+//! use madhouse::loom::thread;
+//! use madhouse::{Command, CommandWrapper, State, TestContext};
+//! use proptest::prelude::*;
+//! use std::panic::AssertUnwindSafe;
+//!
+//! #[derive(Debug, Default)]
+//! struct Counter { hits: Arc<AtomicUsize> }
+//! impl State for Counter {}
+//!
+//! #[derive(Debug, Clone, Default)]
+//! struct Ctx {}
+//! impl TestContext for Ctx {}
+//!
+//! /// Two threads incrementing with a racy load then store.
+//! struct RacyHits;
+//! impl Command<Counter, Ctx> for RacyHits {
+//!     fn check(&self, _state: &Counter) -> bool { true }
+//!     fn apply(&self, state: &mut Counter) {
+//!         let before = state.hits.load(Ordering::SeqCst);
+//!         let threads: Vec<_> = (0..2)
+//!             .map(|_| {
+//!                 let hits = state.hits.clone();
+//!                 thread::spawn(move || {
+//!                     let n = hits.load(Ordering::SeqCst);
+//!                     hits.store(n + 1, Ordering::SeqCst);
+//!                 })
+//!             })
+//!             .collect();
+//!         for thread in threads {
+//!             thread.join().unwrap();
+//!         }
+//!         assert_eq!(state.hits.load(Ordering::SeqCst), before + 2, "lost update");
+//!     }
+//!     fn label(&self) -> String { "RACY_HITS".to_string() }
+//!     fn build(_ctx: std::sync::Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Counter, Ctx>> {
+//!         Just(CommandWrapper::new(RacyHits))
+//!     }
+//! }
+//!
+//! let commands = vec![CommandWrapper::new(RacyHits)];
+//! let panic = std::panic::catch_unwind(AssertUnwindSafe(|| {
+//!     execute_model(&commands, Counter::default)
+//! }))
+//! .unwrap_err();
+//! let message = panic.downcast_ref::<String>().unwrap();
+//! assert!(message.contains("lost update"));
+//! ```
+
+use crate::{run_commands, CommandWrapper, State, TestContext};
+pub use ::loom::{sync, thread};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+thread_local! {
+    /// Body of the model being explored on this thread.
+    static BODY: RefCell<Option<Rc<dyn Fn()>>> = const { RefCell::new(None) };
+}
+
+/// Runs `commands` on a state built by `init`, once per interleaving of
+/// the threads they spawn.
+///
+/// # Panics
+///
+/// Panics at the first interleaving in which a command fails, panics, or
+/// breaks an invariant.
+pub fn execute_model<S: State + 'static, C: TestContext + 'static>(
+    commands: &[CommandWrapper<S, C>],
+    init: impl Fn() -> S + 'static,
+) {
+    let commands = commands.to_vec();
+    explore(move || {
+        let mut state = init();
+        let report = run_commands(&commands, &mut state, &[], &mut ());
+        if let Some(message) = report.failure_message() {
+            panic!("{}", message);
+        }
+    });
+}
+
+/// Calls `body` once per interleaving loom explores.
+///
+/// Loom runs every modeled thread as a coroutine on the thread calling
+/// [`model`](::loom::model), so `body` need not be `Send`: it is owned by
+/// this thread, and the model looks it up there.
+pub(crate) fn explore(body: impl Fn() + 'static) {
+    let outer = BODY.with(|b| b.replace(Some(Rc::new(body))));
+    let explored = std::panic::catch_unwind(|| {
+        ::loom::model(|| {
+            let body = BODY.with(|b| b.borrow().clone());
+            body.expect("loom runs the model on the thread exploring it")();
+        })
+    });
+    BODY.with(|b| *b.borrow_mut() = outer);
+    if let Err(payload) = explored {
+        std::panic::resume_unwind(payload);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Command;
+    use proptest::prelude::{Just, Strategy};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Debug, Default)]
+    struct Hits(sync::Arc<sync::atomic::AtomicUsize>);
+
+    impl State for Hits {}
+
+    #[derive(Debug, Clone, Default)]
+    struct Ctx;
+
+    impl TestContext for Ctx {}
+
+    struct AtomicHits;
+
+    impl Command<Hits, Ctx> for AtomicHits {
+        fn check(&self, _state: &Hits) -> bool {
+            true
+        }
+
+        fn apply(&self, state: &mut Hits) {
+            let hits = state.0.clone();
+            let other = thread::spawn(move || hits.fetch_add(1, sync::atomic::Ordering::SeqCst));
+            state.0.fetch_add(1, sync::atomic::Ordering::SeqCst);
+            other.join().unwrap();
+        }
+
+        fn label(&self) -> String {
+            "ATOMIC_HITS".to_string()
+        }
+
+        fn build(_ctx: std::sync::Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Hits, Ctx>> {
+            Just(CommandWrapper::new(AtomicHits))
+        }
+    }
+
+    struct RacyHits;
+
+    impl Command<Hits, Ctx> for RacyHits {
+        fn check(&self, _state: &Hits) -> bool {
+            true
+        }
+
+        fn apply(&self, state: &mut Hits) {
+            let hits = state.0.clone();
+            let racy = move || {
+                let n = hits.load(sync::atomic::Ordering::SeqCst);
+                hits.store(n + 1, sync::atomic::Ordering::SeqCst);
+            };
+            let other = thread::spawn(racy.clone());
+            racy();
+            other.join().unwrap();
+        }
+
+        fn label(&self) -> String {
+            "RACY_HITS".to_string()
+        }
+
+        fn build(_ctx: std::sync::Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Hits, Ctx>> {
+            Just(CommandWrapper::new(RacyHits))
+        }
+    }
+
+    impl Hits {
+        fn count(&self) -> usize {
+            self.0.load(sync::atomic::Ordering::SeqCst)
+        }
+    }
+
+    #[test]
+    fn test_scenarios_explore_the_interleavings_of_every_case() {
+        let ctx = std::sync::Arc::new(Ctx);
+        let scenario = |command: CommandWrapper<Hits, Ctx>| {
+            crate::Scenario::new(ctx.clone())
+                .fixed(1, command)
+                .assert_report(|report| assert!(report.is_ok()))
+                .teardown(|hits: &mut Hits| assert_eq!(hits.count(), 2, "lost update"))
+                .verbosity(crate::Verbosity::Quiet)
+                .persist_regressions(false)
+                .loom(true)
+        };
+        scenario(CommandWrapper::new(AtomicHits)).run();
+
+        let racy = scenario(CommandWrapper::new(RacyHits));
+        let panic = crate::panics::catch(|| racy.run()).unwrap_err();
+        assert!(panic.message.contains("lost update"), "{}", panic.message);
+    }
+
+    #[test]
+    fn test_execute_model_explores_several_interleavings() {
+        static INTERLEAVINGS: AtomicUsize = AtomicUsize::new(0);
+
+        let commands = vec![CommandWrapper::new(AtomicHits); 2];
+        execute_model(&commands, || {
+            INTERLEAVINGS.fetch_add(1, Ordering::SeqCst);
+            Hits::default()
+        });
+        assert!(INTERLEAVINGS.load(Ordering::SeqCst) > 1);
+    }
+}
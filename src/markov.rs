@@ -0,0 +1,97 @@
+//! Markov-chain command generation.
+//!
+//! Random mode draws every command independently, by weight, which yields
+//! uniform noise rather than the sequences a system sees in operation,
+//! where e.g. a block commit is usually followed by a sortition.
+//! [`Transitions`] scales the weight of a command depending on the command
+//! before it, so random mode follows a Markov chain over command names (see
+//! [`command_name`](crate::report::command_name)). Set it with
+//! [`Scenario::transitions`](crate::Scenario::transitions); generation
+//! then goes through [`Sequence::markov`](crate::sequence::Sequence::markov).
+
+use proptest::prelude::RngCore;
+use std::collections::BTreeMap;
+
+/// Weight factors for a command following another, by command name.
+///
+/// Pairs without a factor keep the command's weight (factor 1). A factor of
+/// 0 rules a command out after another; if it rules out every command, the
+/// weights apply unscaled.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::markov::Transitions;
+///
+/// let transitions = Transitions::new()
+///     .after("SUBMIT_BLOCK_COMMIT", "SORTITION", 5.0)
+///     .after("SORTITION", "SORTITION", 0.0);
+///
+/// assert_eq!(transitions.factor("SUBMIT_BLOCK_COMMIT", "SORTITION"), 5.0);
+/// assert_eq!(transitions.factor("SORTITION", "SORTITION"), 0.0);
+/// assert_eq!(transitions.factor("SORTITION", "MINE"), 1.0);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Transitions {
+    factors: BTreeMap<(String, String), f64>,
+}
+
+impl Transitions {
+    /// Creates transitions leaving every weight unscaled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Scales the weight of command `next` by `factor` right after
+    /// command `previous`, replacing an earlier factor for the pair.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `factor` is negative or not finite.
+    pub fn after(
+        mut self,
+        previous: impl Into<String>,
+        next: impl Into<String>,
+        factor: f64,
+    ) -> Self {
+        assert!(
+            factor.is_finite() && factor >= 0.0,
+            "transition factor must be finite and non-negative, got {}",
+            factor
+        );
+        self.factors.insert((previous.into(), next.into()), factor);
+        self
+    }
+
+    /// Returns the factor of command `next` right after `previous`.
+    pub fn factor(&self, previous: &str, next: &str) -> f64 {
+        self.factors
+            .get(&(previous.to_string(), next.to_string()))
+            .copied()
+            .unwrap_or(1.0)
+    }
+
+    /// Returns true if no factor was set.
+    pub fn is_empty(&self) -> bool {
+        self.factors.is_empty()
+    }
+}
+
+/// Picks an index with probability proportional to `weights`, or `None` if
+/// they sum to zero.
+pub(crate) fn pick(weights: &[f64], rng: &mut impl RngCore) -> Option<usize> {
+    let total: f64 = weights.iter().sum();
+    if total <= 0.0 {
+        return None;
+    }
+    // 53 random bits, uniform in [0, 1).
+    let mut target = (rng.next_u64() >> 11) as f64 / (1u64 << 53) as f64 * total;
+    for (i, &weight) in weights.iter().enumerate() {
+        if target < weight {
+            return Some(i);
+        }
+        target -= weight;
+    }
+    // Rounding left the target past the end: take the last candidate.
+    weights.iter().rposition(|&weight| weight > 0.0)
+}
@@ -0,0 +1,339 @@
+//! Mermaid sequence diagrams of executed traces.
+//!
+//! Plain-text traces get mangled when pasted into issues and are hard to
+//! discuss in reviews. [`SequenceDiagram`] renders an [`ExecutionReport`] as
+//! a Mermaid `sequenceDiagram`, which GitHub draws from a fenced
+//! ```` ```mermaid ```` block: one arrow per applied command, towards the
+//! command's group, with errors, the broken invariant and unconfirmed
+//! effects as replies and notes. [`StateNotes`] records a short rendering of
+//! the state after every command, shown as a note next to it. With
+//! [`Config::mermaid`](crate::Config::mermaid) set, a scenario prints the
+//! diagram of every failing case after shrinking.
+
+use crate::observer::Observer;
+use crate::{ExecutionReport, State, StepReport, TestContext};
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+/// Notes longer than this many characters are cut.
+const MAX_NOTE: usize = 100;
+
+/// Participant sending the commands.
+const DRIVER: &str = "madhouse";
+
+/// A report drawn as a Mermaid sequence diagram.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::mermaid::SequenceDiagram;
+/// use madhouse::{BrokenInvariant, ExecutionReport, StepOutcome, StepReport};
+/// use std::time::Duration;
+///
+/// let step = |index: usize, label: &str| StepReport {
+///     index,
+///     label: label.to_string(),
+///     group: "counter".to_string(),
+///     outcome: StepOutcome::Executed,
+///     duration: Duration::ZERO,
+///     check_duration: Duration::ZERO,
+///     started: std::time::UNIX_EPOCH,
+///     finished: std::time::UNIX_EPOCH,
+/// };
+/// let report = ExecutionReport {
+///     steps: vec![step(0, "INC(3)"), step(1, "INC(9)")],
+///     broken_invariant: Some(BrokenInvariant {
+///         index: 1,
+///         label: "INC(9)".to_string(),
+///         violation: "value above 10".into(),
+///     }),
+///     ..ExecutionReport::default()
+/// };
+///
+/// let mut diagram = SequenceDiagram::new(&report);
+/// diagram.annotate(0, "value: 3");
+/// diagram.annotate(1, "value: 12");
+///
+/// assert_eq!(
+///     diagram.to_string(),
+///     "sequenceDiagram\n\
+///     \x20   participant madhouse\n\
+///     \x20   participant P1 as counter\n\
+///     \x20   madhouse->>P1: 1. INC(3)\n\
+///     \x20   Note right of P1: value: 3\n\
+///     \x20   madhouse->>P1: 2. INC(9)\n\
+///     \x20   Note right of P1: value: 12\n\
+///     \x20   Note over madhouse,P1: invariant broken: value above 10\n"
+/// );
+/// assert!(diagram.to_markdown().starts_with("```mermaid\nsequenceDiagram\n"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SequenceDiagram {
+    /// Participant labels, in order of first appearance.
+    participants: Vec<String>,
+    /// Earlier steps the report no longer holds.
+    dropped: usize,
+    entries: Vec<Entry>,
+    /// State notes by command index.
+    notes: BTreeMap<usize, String>,
+    /// Problems reported once the sequence ended.
+    trailer: Vec<String>,
+}
+
+/// One applied command or heartbeat.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    index: usize,
+    label: String,
+    participant: usize,
+    heartbeat: bool,
+    error: Option<String>,
+    broken: Option<String>,
+}
+
+impl SequenceDiagram {
+    /// Draws the applied commands and heartbeats of `report`.
+    ///
+    /// Skipped commands are left out; heartbeats follow the command they
+    /// ran after.
+    pub fn new(report: &ExecutionReport) -> Self {
+        let mut diagram = Self {
+            participants: Vec::new(),
+            dropped: report.dropped_steps,
+            entries: Vec::new(),
+            notes: BTreeMap::new(),
+            trailer: report
+                .unconfirmed
+                .iter()
+                .map(|expectation| format!("never confirmed: {}", expectation))
+                .collect(),
+        };
+        let mut heartbeats = report.heartbeats.iter().peekable();
+        for step in &report.steps {
+            while let Some(heartbeat) = heartbeats.next_if(|hb| hb.index < step.index) {
+                diagram.push(report, heartbeat, true);
+            }
+            diagram.push(report, step, false);
+            while let Some(heartbeat) = heartbeats.next_if(|hb| hb.index == step.index) {
+                diagram.push(report, heartbeat, true);
+            }
+        }
+        for heartbeat in heartbeats {
+            diagram.push(report, heartbeat, true);
+        }
+        diagram
+    }
+
+    /// Notes the state after command `index`, replacing an earlier note.
+    pub fn annotate(&mut self, index: usize, note: impl Into<String>) {
+        self.notes.insert(index, note.into());
+    }
+
+    /// Renders the diagram in a fenced block, ready to paste into Markdown.
+    pub fn to_markdown(&self) -> String {
+        format!("```mermaid\n{}```\n", self)
+    }
+
+    fn push(&mut self, report: &ExecutionReport, step: &StepReport, heartbeat: bool) {
+        if !step.was_applied() {
+            return;
+        }
+        let group = if step.group.is_empty() {
+            "model"
+        } else {
+            step.group.as_str()
+        };
+        let participant = match self.participants.iter().position(|p| p == group) {
+            Some(position) => position,
+            None => {
+                self.participants.push(group.to_string());
+                self.participants.len() - 1
+            }
+        };
+        let broken = report
+            .broken_invariant
+            .as_ref()
+            .filter(|broken| broken.index == step.index && broken.label == step.label)
+            .map(|broken| broken.violation.to_string());
+        self.entries.push(Entry {
+            index: step.index,
+            label: step.label.clone(),
+            participant,
+            heartbeat,
+            error: step.error().map(ToString::to_string),
+            broken,
+        });
+    }
+}
+
+impl Display for SequenceDiagram {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        writeln!(f, "sequenceDiagram")?;
+        writeln!(f, "    participant {}", DRIVER)?;
+        for (i, participant) in self.participants.iter().enumerate() {
+            writeln!(f, "    participant P{} as {}", i + 1, escape(participant))?;
+        }
+        if self.dropped > 0 {
+            writeln!(
+                f,
+                "    Note over {}: {} earlier steps dropped",
+                DRIVER, self.dropped
+            )?;
+        }
+        for entry in &self.entries {
+            let to = format!("P{}", entry.participant + 1);
+            if entry.heartbeat {
+                writeln!(
+                    f,
+                    "    {}-){}: heartbeat {}",
+                    DRIVER,
+                    to,
+                    escape(&entry.label)
+                )?;
+            } else {
+                writeln!(
+                    f,
+                    "    {}->>{}: {}. {}",
+                    DRIVER,
+                    to,
+                    entry.index + 1,
+                    escape(&entry.label)
+                )?;
+            }
+            if let Some(error) = &entry.error {
+                writeln!(f, "    {}--x{}: error: {}", to, DRIVER, escape(error))?;
+            }
+            if let Some(note) = self.notes.get(&entry.index).filter(|_| !entry.heartbeat) {
+                writeln!(f, "    Note right of {}: {}", to, escape(note))?;
+            }
+            if let Some(violation) = &entry.broken {
+                writeln!(
+                    f,
+                    "    Note over {},{}: invariant broken: {}",
+                    DRIVER,
+                    to,
+                    escape(violation)
+                )?;
+            }
+        }
+        for problem in &self.trailer {
+            writeln!(f, "    Note over {}: {}", DRIVER, escape(problem))?;
+        }
+        Ok(())
+    }
+}
+
+/// Cuts `text` to [`MAX_NOTE`] characters and encodes the characters that
+/// end or break a Mermaid statement.
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for (i, c) in text.chars().enumerate() {
+        if i == MAX_NOTE {
+            escaped.push('…');
+            break;
+        }
+        match c {
+            '#' => escaped.push_str("#35;"),
+            ';' => escaped.push_str("#59;"),
+            '<' => escaped.push_str("#60;"),
+            '>' => escaped.push_str("#62;"),
+            '\n' => escaped.push_str("<br/>"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Observer rendering the state after every applied command, for
+/// [`SequenceDiagram::annotate`].
+pub struct StateNotes<'a, S> {
+    render: Box<dyn Fn(&S) -> String + 'a>,
+    notes: Vec<(usize, String)>,
+}
+
+impl<'a, S> StateNotes<'a, S> {
+    /// Creates an observer rendering states with `render`.
+    pub fn new(render: impl Fn(&S) -> String + 'a) -> Self {
+        Self {
+            render: Box::new(render),
+            notes: Vec::new(),
+        }
+    }
+
+    /// Adds the recorded notes to `diagram`.
+    pub fn annotate(self, diagram: &mut SequenceDiagram) {
+        for (index, note) in self.notes {
+            diagram.annotate(index, note);
+        }
+    }
+}
+
+impl<S: State, C: TestContext> Observer<S, C> for StateNotes<'_, S> {
+    fn on_after_apply(&mut self, step: &StepReport, state: &S) {
+        self.notes.push((step.index, (self.render)(state)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effects::Expectation;
+    use crate::{SkipReason, StepOutcome};
+    use std::time::Duration;
+
+    fn step(index: usize, group: &str, outcome: StepOutcome) -> StepReport {
+        StepReport {
+            index,
+            label: format!("CMD({})", index),
+            group: group.to_string(),
+            outcome,
+            duration: Duration::ZERO,
+            check_duration: Duration::ZERO,
+            started: std::time::UNIX_EPOCH,
+            finished: std::time::UNIX_EPOCH,
+        }
+    }
+
+    #[test]
+    fn test_errors_heartbeats_and_unconfirmed_effects_are_drawn() {
+        let report = ExecutionReport {
+            steps: vec![
+                step(0, "miner", StepOutcome::Executed),
+                step(1, "miner", StepOutcome::Skipped(SkipReason::default())),
+                step(2, "", StepOutcome::Failed("bad; #1 <tx>".into())),
+            ],
+            heartbeats: vec![step(0, "clock", StepOutcome::Executed)],
+            dropped_steps: 4,
+            unconfirmed: vec![Expectation {
+                key: "tx mined".to_string(),
+                index: 0,
+                label: "CMD(0)".to_string(),
+            }],
+            ..ExecutionReport::default()
+        };
+
+        let mut diagram = SequenceDiagram::new(&report);
+        diagram.annotate(0, "x".repeat(MAX_NOTE + 1));
+
+        assert_eq!(
+            diagram.to_string(),
+            format!(
+                concat!(
+                    "sequenceDiagram\n",
+                    "    participant madhouse\n",
+                    "    participant P1 as miner\n",
+                    "    participant P2 as clock\n",
+                    "    participant P3 as model\n",
+                    "    Note over madhouse: 4 earlier steps dropped\n",
+                    "    madhouse->>P1: 1. CMD(0)\n",
+                    "    Note right of P1: {}…\n",
+                    "    madhouse-)P2: heartbeat CMD(0)\n",
+                    "    madhouse->>P3: 3. CMD(2)\n",
+                    "    P3--xmadhouse: error: bad#59; #35;1 #60;tx#62;\n",
+                    "    Note over madhouse: never confirmed: tx mined (expected by command 1 (CMD(0)))\n",
+                ),
+                "x".repeat(MAX_NOTE)
+            )
+        );
+    }
+}
@@ -0,0 +1,509 @@
+//! Fault-injection ("nemesis") commands.
+//!
+//! Jepsen-style tests mix faults into the workload: a node is killed and
+//! restarted, paused and resumed, loses messages, or sees its clock jump.
+//! This module ships these commands ready to use. The system under test is
+//! reached through a [`NemesisContext`], which lists the nodes faults can
+//! target and carries out each fault; the model tracks which nodes are down
+//! or paused in [`Faults`], through [`NemesisState`], so that `check` only
+//! restarts killed nodes and only resumes paused ones.
+//!
+//! Every fault still in place when a case ends is undone by the command's
+//! [`cleanup`](crate::Command::cleanup), so the next case starts from
+//! healthy nodes.
+//!
+//! # Examples
+//!
+//! ```
+//! use madhouse::nemesis::{Faults, Kill, NemesisContext, NemesisState, Restart};
+//! use madhouse::{scenario, Command, CommandError, CommandWrapper, State, TestContext};
+//! use proptest::prelude::*;
+//! use std::sync::{Arc, Mutex};
+//!
+//! #[derive(Debug, Default)]
+//! struct Cluster { faults: Faults, blocks: u64 }
+//! impl State for Cluster {}
+//! impl NemesisState for Cluster {
+//!     fn faults(&self) -> &Faults { &self.faults }
+//!     fn faults_mut(&mut self) -> &mut Faults { &mut self.faults }
+//! }
+//!
+//! #[derive(Debug, Clone, Default)]
+//! struct Ctx { log: Arc<Mutex<Vec<String>>> }
+//! impl TestContext for Ctx {}
+//! impl NemesisContext for Ctx {
+//!     fn nodes(&self) -> Vec<String> { vec!["miner-1".into(), "miner-2".into()] }
+//!     fn kill(&self, node: &str) -> Result<(), CommandError> {
+//!         self.log.lock().unwrap().push(format!("kill -9 {}", node));
+//!         Ok(())
+//!     }
+//!     fn restart(&self, node: &str) -> Result<(), CommandError> {
+//!         self.log.lock().unwrap().push(format!("start {}", node));
+//!         Ok(())
+//!     }
+//! }
+//!
+//! // The workload: mine a block on the first node, if it is up.
+//! struct Mine;
+//! impl Command<Cluster, Ctx> for Mine {
+//!     fn check(&self, state: &Cluster) -> bool { state.faults.is_up("miner-1") }
+//!     fn apply(&self, state: &mut Cluster) { state.blocks += 1; }
+//!     fn label(&self) -> String { "MINE".to_string() }
+//!     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Cluster, Ctx>> {
+//!         Just(CommandWrapper::new(Mine))
+//!     }
+//! }
+//!
+//! let ctx = Arc::new(Ctx::default());
+//! scenario![ctx, Mine, Kill: 1, Mine, Restart: 1, Mine];
+//!
+//! // Whatever ran, no node is left down.
+//! let log = ctx.log.lock().unwrap();
+//! assert_eq!(log.iter().filter(|l| l.starts_with("kill")).count(), log.len() / 2);
+//! ```
+
+use crate::{Command, CommandError, CommandWrapper, State, TestContext};
+use proptest::prelude::Strategy;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Faults currently in place, by node.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Faults {
+    killed: BTreeSet<String>,
+    paused: BTreeSet<String>,
+    clock_offsets: BTreeMap<String, Duration>,
+}
+
+impl Faults {
+    /// Returns true if `node` was killed and not restarted since.
+    pub fn is_killed(&self, node: &str) -> bool {
+        self.killed.contains(node)
+    }
+
+    /// Returns true if `node` was paused and not resumed since.
+    pub fn is_paused(&self, node: &str) -> bool {
+        self.paused.contains(node)
+    }
+
+    /// Returns true if `node` is neither killed nor paused.
+    pub fn is_up(&self, node: &str) -> bool {
+        !self.is_killed(node) && !self.is_paused(node)
+    }
+
+    /// Returns how far the clock of `node` was jumped ahead in total.
+    pub fn clock_offset(&self, node: &str) -> Duration {
+        self.clock_offsets.get(node).copied().unwrap_or_default()
+    }
+}
+
+/// State that tracks the [`Faults`] injected into the system under test.
+pub trait NemesisState: State {
+    /// Returns the faults in place.
+    fn faults(&self) -> &Faults;
+
+    /// Returns the faults in place for modification.
+    fn faults_mut(&mut self) -> &mut Faults;
+}
+
+/// Context carrying out faults on the system under test.
+///
+/// Only [`nodes`](Self::nodes) is required; each fault's method fails by
+/// default, so implement those of the commands the scenario uses.
+pub trait NemesisContext: TestContext {
+    /// Names of the nodes faults can target.
+    fn nodes(&self) -> Vec<String>;
+
+    /// Kills `node`, e.g. with `SIGKILL`.
+    fn kill(&self, node: &str) -> Result<(), CommandError> {
+        unsupported("kill", node)
+    }
+
+    /// Starts `node` again after it was killed.
+    fn restart(&self, node: &str) -> Result<(), CommandError> {
+        unsupported("restart", node)
+    }
+
+    /// Suspends `node` without killing it, e.g. with `SIGSTOP`.
+    fn pause(&self, node: &str) -> Result<(), CommandError> {
+        unsupported("pause", node)
+    }
+
+    /// Lets a paused `node` run again, e.g. with `SIGCONT`.
+    fn resume(&self, node: &str) -> Result<(), CommandError> {
+        unsupported("resume", node)
+    }
+
+    /// Drops the next `count` messages sent to `node`.
+    fn drop_messages(&self, node: &str, count: usize) -> Result<(), CommandError> {
+        let _ = count;
+        unsupported("drop_messages", node)
+    }
+
+    /// Moves the clock of `node` ahead by `by`.
+    fn jump_clock(&self, node: &str, by: Duration) -> Result<(), CommandError> {
+        let _ = by;
+        unsupported("jump_clock", node)
+    }
+
+    /// Most messages a single [`DropMessages`] drops (default 10).
+    fn max_dropped_messages(&self) -> usize {
+        10
+    }
+
+    /// Largest jump a single [`ClockJump`] makes (default one minute).
+    fn max_clock_jump(&self) -> Duration {
+        Duration::from_secs(60)
+    }
+}
+
+fn unsupported(fault: &str, node: &str) -> Result<(), CommandError> {
+    Err(CommandError::new(format!(
+        "cannot {} {}: NemesisContext::{} is not implemented",
+        fault, node, fault
+    )))
+}
+
+/// Strategy picking one of the context's nodes.
+///
+/// # Panics
+///
+/// Panics if the context has no nodes.
+fn node<C: NemesisContext>(ctx: &C) -> impl Strategy<Value = String> {
+    let nodes = ctx.nodes();
+    assert!(!nodes.is_empty(), "NemesisContext::nodes returned no nodes");
+    crate::gen::pick(nodes)
+}
+
+/// Defines a command taking a node, applicable when `check` holds.
+macro_rules! node_command {
+    (
+        $(#[$doc:meta])*
+        $name:ident, $label:literal, $fault:ident,
+        check = |$faults:ident, $node:ident| $check:expr,
+        apply = |$faults_mut:ident, $node_mut:ident| $apply:expr,
+        cleanup = $cleanup:expr $(,)?
+    ) => {
+        $(#[$doc])*
+        pub struct $name<C> {
+            ctx: Arc<C>,
+            /// The node the fault targets.
+            pub node: String,
+        }
+
+        impl<C> $name<C> {
+            /// Creates the command targeting `node`.
+            pub fn new(ctx: Arc<C>, node: impl Into<String>) -> Self {
+                Self {
+                    ctx,
+                    node: node.into(),
+                }
+            }
+        }
+
+        impl<S, C> Command<S, C> for $name<C>
+        where
+            S: NemesisState,
+            C: NemesisContext + 'static,
+        {
+            fn check(&self, state: &S) -> bool {
+                let ($faults, $node) = (state.faults(), self.node.as_str());
+                $check
+            }
+
+            fn apply(&self, state: &mut S) {
+                if let Err(err) = self.try_apply(state) {
+                    panic!("{}: {}", Command::<S, C>::label(self), err);
+                }
+            }
+
+            fn try_apply(&self, state: &mut S) -> Result<(), CommandError> {
+                self.ctx.$fault(&self.node)?;
+                let ($faults_mut, $node_mut) = (state.faults_mut(), self.node.clone());
+                $apply;
+                Ok(())
+            }
+
+            fn cleanup(&self, state: &mut S) {
+                let cleanup: fn(&C, &mut Faults, &str) = $cleanup;
+                cleanup(&self.ctx, state.faults_mut(), &self.node);
+            }
+
+            fn label(&self) -> String {
+                format!(concat!($label, "({})"), self.node)
+            }
+
+            fn build(ctx: Arc<C>) -> impl Strategy<Value = CommandWrapper<S, C>> {
+                node(ctx.as_ref())
+                    .prop_map(move |node| CommandWrapper::new($name::new(ctx.clone(), node)))
+            }
+
+            fn parse(label: &str, ctx: Arc<C>) -> Option<CommandWrapper<S, C>> {
+                let node = label
+                    .strip_prefix(concat!($label, "("))?
+                    .strip_suffix(')')?;
+                Some(CommandWrapper::new($name::new(ctx, node)))
+            }
+        }
+    };
+}
+
+node_command!(
+    /// Kills a running node.
+    Kill, "KILL", kill,
+    check = |faults, node| !faults.is_killed(node),
+    apply = |faults, node| {
+        faults.paused.remove(&node);
+        faults.killed.insert(node)
+    },
+    cleanup = |ctx, faults, node| {
+        if faults.killed.remove(node) {
+            let _ = ctx.restart(node);
+        }
+    },
+);
+
+node_command!(
+    /// Restarts a killed node.
+    Restart, "RESTART", restart,
+    check = |faults, node| faults.is_killed(node),
+    apply = |faults, node| faults.killed.remove(&node),
+    cleanup = |_, _, _| {},
+);
+
+node_command!(
+    /// Pauses a running node.
+    Pause, "PAUSE", pause,
+    check = |faults, node| faults.is_up(node),
+    apply = |faults, node| faults.paused.insert(node),
+    cleanup = |ctx, faults, node| {
+        if faults.paused.remove(node) {
+            let _ = ctx.resume(node);
+        }
+    },
+);
+
+node_command!(
+    /// Resumes a paused node.
+    Resume, "RESUME", resume,
+    check = |faults, node| faults.is_paused(node),
+    apply = |faults, node| faults.paused.remove(&node),
+    cleanup = |_, _, _| {},
+);
+
+/// Drops the next messages sent to a live node.
+pub struct DropMessages<C> {
+    ctx: Arc<C>,
+    /// The node whose messages are dropped.
+    pub node: String,
+    /// How many messages are dropped.
+    pub count: usize,
+}
+
+impl<C> DropMessages<C> {
+    /// Creates the command dropping `count` messages sent to `node`.
+    pub fn new(ctx: Arc<C>, node: impl Into<String>, count: usize) -> Self {
+        Self {
+            ctx,
+            node: node.into(),
+            count,
+        }
+    }
+}
+
+impl<S, C> Command<S, C> for DropMessages<C>
+where
+    S: NemesisState,
+    C: NemesisContext + 'static,
+{
+    fn check(&self, state: &S) -> bool {
+        !state.faults().is_killed(&self.node)
+    }
+
+    fn apply(&self, state: &mut S) {
+        if let Err(err) = self.try_apply(state) {
+            panic!("{}: {}", Command::<S, C>::label(self), err);
+        }
+    }
+
+    fn try_apply(&self, _state: &mut S) -> Result<(), CommandError> {
+        self.ctx.drop_messages(&self.node, self.count)
+    }
+
+    fn label(&self) -> String {
+        format!("DROP_MESSAGES({}, {})", self.node, self.count)
+    }
+
+    /// Drops 1 up to the context's `max_dropped_messages`, shrinking toward
+    /// one message.
+    fn build(ctx: Arc<C>) -> impl Strategy<Value = CommandWrapper<S, C>> {
+        let max = ctx.max_dropped_messages().max(1);
+        (node(ctx.as_ref()), 1..=max).prop_map(move |(node, count)| {
+            CommandWrapper::new(DropMessages::new(ctx.clone(), node, count))
+        })
+    }
+
+    fn parse(label: &str, ctx: Arc<C>) -> Option<CommandWrapper<S, C>> {
+        let inner = label.strip_prefix("DROP_MESSAGES(")?.strip_suffix(')')?;
+        let (node, count) = inner.rsplit_once(", ")?;
+        Some(CommandWrapper::new(DropMessages::new(
+            ctx,
+            node,
+            count.parse().ok()?,
+        )))
+    }
+}
+
+/// Moves the clock of a live node ahead.
+pub struct ClockJump<C> {
+    ctx: Arc<C>,
+    /// The node whose clock jumps.
+    pub node: String,
+    /// How far the clock jumps.
+    pub by: Duration,
+}
+
+impl<C> ClockJump<C> {
+    /// Creates the command moving the clock of `node` ahead by `by`.
+    pub fn new(ctx: Arc<C>, node: impl Into<String>, by: Duration) -> Self {
+        Self {
+            ctx,
+            node: node.into(),
+            by,
+        }
+    }
+}
+
+impl<S, C> Command<S, C> for ClockJump<C>
+where
+    S: NemesisState,
+    C: NemesisContext + 'static,
+{
+    fn check(&self, state: &S) -> bool {
+        !state.faults().is_killed(&self.node)
+    }
+
+    fn apply(&self, state: &mut S) {
+        if let Err(err) = self.try_apply(state) {
+            panic!("{}: {}", Command::<S, C>::label(self), err);
+        }
+    }
+
+    fn try_apply(&self, state: &mut S) -> Result<(), CommandError> {
+        self.ctx.jump_clock(&self.node, self.by)?;
+        *state
+            .faults_mut()
+            .clock_offsets
+            .entry(self.node.clone())
+            .or_default() += self.by;
+        Ok(())
+    }
+
+    fn label(&self) -> String {
+        format!("CLOCK_JUMP({}, {}ms)", self.node, self.by.as_millis())
+    }
+
+    /// Jumps 1ms up to the context's `max_clock_jump`, shrinking toward
+    /// short jumps.
+    fn build(ctx: Arc<C>) -> impl Strategy<Value = CommandWrapper<S, C>> {
+        let max = ctx.max_clock_jump().as_millis().max(1) as u64;
+        (node(ctx.as_ref()), 1..=max).prop_map(move |(node, ms)| {
+            CommandWrapper::new(ClockJump::new(ctx.clone(), node, Duration::from_millis(ms)))
+        })
+    }
+
+    fn parse(label: &str, ctx: Arc<C>) -> Option<CommandWrapper<S, C>> {
+        let inner = label.strip_prefix("CLOCK_JUMP(")?.strip_suffix("ms)")?;
+        let (node, ms) = inner.rsplit_once(", ")?;
+        Some(CommandWrapper::new(ClockJump::new(
+            ctx,
+            node,
+            Duration::from_millis(ms.parse().ok()?),
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execute_commands;
+    use std::sync::Mutex;
+
+    #[derive(Debug, Default)]
+    struct Cluster {
+        faults: Faults,
+    }
+
+    impl State for Cluster {}
+
+    impl NemesisState for Cluster {
+        fn faults(&self) -> &Faults {
+            &self.faults
+        }
+
+        fn faults_mut(&mut self) -> &mut Faults {
+            &mut self.faults
+        }
+    }
+
+    #[derive(Debug, Clone, Default)]
+    struct Ctx {
+        calls: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl TestContext for Ctx {}
+
+    impl NemesisContext for Ctx {
+        fn nodes(&self) -> Vec<String> {
+            vec!["a".to_string(), "b".to_string()]
+        }
+
+        fn pause(&self, node: &str) -> Result<(), CommandError> {
+            self.calls.lock().unwrap().push(format!("pause {}", node));
+            Ok(())
+        }
+
+        fn resume(&self, node: &str) -> Result<(), CommandError> {
+            self.calls.lock().unwrap().push(format!("resume {}", node));
+            Ok(())
+        }
+
+        fn jump_clock(&self, node: &str, by: Duration) -> Result<(), CommandError> {
+            let call = format!("jump {} {:?}", node, by);
+            self.calls.lock().unwrap().push(call);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_faults_are_tracked_and_undone_by_cleanup() {
+        let ctx = Arc::new(Ctx::default());
+        let ms = Duration::from_millis;
+        let commands: Vec<CommandWrapper<Cluster, Ctx>> = vec![
+            CommandWrapper::new(Pause::new(ctx.clone(), "a")),
+            CommandWrapper::new(Pause::new(ctx.clone(), "a")),
+            CommandWrapper::new(ClockJump::new(ctx.clone(), "b", ms(250))),
+            CommandWrapper::new(Pause::new(ctx.clone(), "b")),
+            CommandWrapper::new(Resume::new(ctx.clone(), "b")),
+            CommandWrapper::new(Kill::new(ctx.clone(), "a")),
+        ];
+        let mut cluster = Cluster::default();
+
+        let report = execute_commands(&commands, &mut cluster);
+
+        assert_eq!(report.executed().count(), 5);
+        assert_eq!(report.failures().count(), 1);
+        assert_eq!(report.steps[2].label, "CLOCK_JUMP(b, 250ms)");
+        assert_eq!(cluster.faults.clock_offset("b"), ms(250));
+        // The pause of `a` was undone once the sequence ended.
+        assert!(cluster.faults.is_up("a") && cluster.faults.is_up("b"));
+        assert_eq!(
+            *ctx.calls.lock().unwrap(),
+            ["pause a", "jump b 250ms", "pause b", "resume b", "resume a"]
+        );
+
+        let parsed = <ClockJump<Ctx> as Command<Cluster, Ctx>>::parse("CLOCK_JUMP(b, 7ms)", ctx);
+        assert_eq!(parsed.unwrap().command.label(), "CLOCK_JUMP(b, 7ms)");
+    }
+}
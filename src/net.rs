@@ -0,0 +1,838 @@
+//! Network fault commands: partitions, latency and dropped connections.
+//!
+//! Distributed state machines, like miners racing for sortition, only show
+//! their interesting behavior when the network misbehaves. This module
+//! ships commands injecting those faults between the endpoints a
+//! [`NetContext`] declares. The context carries each fault out, through OS
+//! tooling (`iptables`, `tc netem`) or an in-process [`Proxy`] placed in
+//! front of each endpoint; the model tracks the faults in place in
+//! [`Network`], through [`NetState`], so that `check` only heals partitioned
+//! links and commands can ask whether two endpoints can talk.
+//!
+//! Every fault still in place when a case ends is undone by the command's
+//! [`cleanup`](crate::Command::cleanup), so the next case starts from a
+//! healthy network.
+//!
+//! # Examples
+//!
+//! ```
+//! use madhouse::net::{Heal, NetContext, NetState, Network, Partition};
+//! use madhouse::{scenario, Command, CommandError, CommandWrapper, State, TestContext};
+//! use proptest::prelude::*;
+//! use std::sync::{Arc, Mutex};
+//!
+//! #[derive(Debug, Default)]
+//! struct Cluster { network: Network, gossiped: u64 }
+//! impl State for Cluster {}
+//! impl NetState for Cluster {
+//!     fn network(&self) -> &Network { &self.network }
+//!     fn network_mut(&mut self) -> &mut Network { &mut self.network }
+//! }
+//!
+//! #[derive(Debug, Clone, Default)]
+//! struct Ctx { cut: Arc<Mutex<usize>> }
+//! impl TestContext for Ctx {}
+//! impl NetContext for Ctx {
+//!     fn endpoints(&self) -> Vec<String> { vec!["miner-1".into(), "miner-2".into()] }
+//!     fn partition(&self, _a: &str, _b: &str) -> Result<(), CommandError> {
+//!         *self.cut.lock().unwrap() += 1;
+//!         Ok(())
+//!     }
+//!     fn heal(&self, _a: &str, _b: &str) -> Result<(), CommandError> {
+//!         *self.cut.lock().unwrap() -= 1;
+//!         Ok(())
+//!     }
+//! }
+//!
+//! // The workload: gossip a block, if the miners can talk.
+//! struct Gossip;
+//! impl Command<Cluster, Ctx> for Gossip {
+//!     fn check(&self, state: &Cluster) -> bool {
+//!         state.network.can_reach("miner-1", "miner-2")
+//!     }
+//!     fn apply(&self, state: &mut Cluster) { state.gossiped += 1; }
+//!     fn label(&self) -> String { "GOSSIP".to_string() }
+//!     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Cluster, Ctx>> {
+//!         Just(CommandWrapper::new(Gossip))
+//!     }
+//! }
+//!
+//! let ctx = Arc::new(Ctx::default());
+//! scenario![ctx, Gossip, Partition: 1, Gossip, Heal: 1];
+//!
+//! // Whatever ran, no link is left cut.
+//! assert_eq!(*ctx.cut.lock().unwrap(), 0);
+//! ```
+
+use crate::{Command, CommandError, CommandWrapper, State, TestContext};
+use proptest::prelude::Strategy;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Network faults currently in place.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Network {
+    partitions: BTreeSet<(String, String)>,
+    latencies: BTreeMap<String, Duration>,
+    conn_drops: BTreeMap<String, u8>,
+}
+
+/// Orders the ends of a link, so that `a`-`b` and `b`-`a` are the same.
+fn link(a: &str, b: &str) -> (String, String) {
+    if a <= b {
+        (a.to_string(), b.to_string())
+    } else {
+        (b.to_string(), a.to_string())
+    }
+}
+
+impl Network {
+    /// Returns true if the link between `a` and `b` is cut, in either
+    /// direction.
+    pub fn is_partitioned(&self, a: &str, b: &str) -> bool {
+        self.partitions.contains(&link(a, b))
+    }
+
+    /// Returns true if `a` and `b` can exchange messages: their link is
+    /// not cut and neither drops every connection.
+    pub fn can_reach(&self, a: &str, b: &str) -> bool {
+        !self.is_partitioned(a, b) && self.conn_drops(a) < 100 && self.conn_drops(b) < 100
+    }
+
+    /// Returns the latency added to the traffic of `endpoint`.
+    pub fn latency(&self, endpoint: &str) -> Duration {
+        self.latencies.get(endpoint).copied().unwrap_or_default()
+    }
+
+    /// Returns the percentage of the new connections to `endpoint` dropped.
+    pub fn conn_drops(&self, endpoint: &str) -> u8 {
+        self.conn_drops.get(endpoint).copied().unwrap_or_default()
+    }
+
+    /// Returns true if no fault is in place.
+    pub fn is_healthy(&self) -> bool {
+        self.partitions.is_empty() && self.latencies.is_empty() && self.conn_drops.is_empty()
+    }
+}
+
+/// State that tracks the faults injected into the [`Network`].
+pub trait NetState: State {
+    /// Returns the network faults in place.
+    fn network(&self) -> &Network;
+
+    /// Returns the network faults in place for modification.
+    fn network_mut(&mut self) -> &mut Network;
+}
+
+/// Context carrying out network faults between the endpoints of the
+/// system under test.
+///
+/// Only [`endpoints`](Self::endpoints) is required; each fault's method
+/// fails by default, so implement those of the commands the scenario uses.
+pub trait NetContext: TestContext {
+    /// Names of the endpoints faults can target.
+    fn endpoints(&self) -> Vec<String>;
+
+    /// Cuts the link between `a` and `b`, in both directions.
+    fn partition(&self, a: &str, b: &str) -> Result<(), CommandError> {
+        unsupported("partition", &format!("{}-{}", a, b))
+    }
+
+    /// Restores the link between `a` and `b`.
+    fn heal(&self, a: &str, b: &str) -> Result<(), CommandError> {
+        unsupported("heal", &format!("{}-{}", a, b))
+    }
+
+    /// Delays the traffic of `endpoint` by `latency`; zero removes the
+    /// delay.
+    fn set_latency(&self, endpoint: &str, latency: Duration) -> Result<(), CommandError> {
+        let _ = latency;
+        unsupported("set_latency", endpoint)
+    }
+
+    /// Drops `percent` of the new connections to `endpoint`; zero drops
+    /// none.
+    fn set_conn_drops(&self, endpoint: &str, percent: u8) -> Result<(), CommandError> {
+        let _ = percent;
+        unsupported("set_conn_drops", endpoint)
+    }
+
+    /// Largest latency a single [`Delay`] adds (default one second).
+    fn max_latency(&self) -> Duration {
+        Duration::from_secs(1)
+    }
+}
+
+fn unsupported(fault: &str, target: &str) -> Result<(), CommandError> {
+    Err(CommandError::new(format!(
+        "cannot {} {}: NetContext::{} is not implemented",
+        fault, target, fault
+    )))
+}
+
+/// Strategy picking one of the context's endpoints.
+///
+/// # Panics
+///
+/// Panics if the context has no endpoints.
+fn endpoint<C: NetContext>(ctx: &C) -> impl Strategy<Value = String> {
+    let endpoints = ctx.endpoints();
+    assert!(
+        !endpoints.is_empty(),
+        "NetContext::endpoints returned no endpoints"
+    );
+    crate::gen::pick(endpoints)
+}
+
+/// Strategy picking two distinct endpoints of the context.
+///
+/// # Panics
+///
+/// Panics if the context has fewer than two endpoints.
+fn endpoint_pair<C: NetContext>(ctx: &C) -> impl Strategy<Value = (String, String)> {
+    let endpoints = ctx.endpoints();
+    let n = endpoints.len();
+    assert!(
+        n >= 2,
+        "NetContext::endpoints returned fewer than two endpoints"
+    );
+    (0..n, 1..n)
+        .prop_map(move |(a, offset)| (endpoints[a].clone(), endpoints[(a + offset) % n].clone()))
+}
+
+/// Defines a command taking a link, applicable when `check` holds.
+macro_rules! link_command {
+    (
+        $(#[$doc:meta])*
+        $name:ident, $label:literal, $fault:ident,
+        check = |$network:ident, $a:ident, $b:ident| $check:expr,
+        apply = |$network_mut:ident, $link:ident| $apply:expr,
+        cleanup = $cleanup:expr $(,)?
+    ) => {
+        $(#[$doc])*
+        pub struct $name<C> {
+            ctx: Arc<C>,
+            /// One end of the link.
+            pub a: String,
+            /// The other end of the link.
+            pub b: String,
+        }
+
+        impl<C> $name<C> {
+            /// Creates the command targeting the link between `a` and `b`.
+            pub fn new(ctx: Arc<C>, a: impl Into<String>, b: impl Into<String>) -> Self {
+                Self {
+                    ctx,
+                    a: a.into(),
+                    b: b.into(),
+                }
+            }
+        }
+
+        impl<S, C> Command<S, C> for $name<C>
+        where
+            S: NetState,
+            C: NetContext + 'static,
+        {
+            fn check(&self, state: &S) -> bool {
+                let ($network, $a, $b) = (state.network(), self.a.as_str(), self.b.as_str());
+                $a != $b && $check
+            }
+
+            fn apply(&self, state: &mut S) {
+                if let Err(err) = self.try_apply(state) {
+                    panic!("{}: {}", Command::<S, C>::label(self), err);
+                }
+            }
+
+            fn try_apply(&self, state: &mut S) -> Result<(), CommandError> {
+                self.ctx.$fault(&self.a, &self.b)?;
+                let ($network_mut, $link) = (state.network_mut(), link(&self.a, &self.b));
+                $apply;
+                Ok(())
+            }
+
+            fn cleanup(&self, state: &mut S) {
+                let cleanup: fn(&C, &mut Network, &str, &str) = $cleanup;
+                cleanup(&self.ctx, state.network_mut(), &self.a, &self.b);
+            }
+
+            fn label(&self) -> String {
+                format!(concat!($label, "({}, {})"), self.a, self.b)
+            }
+
+            fn build(ctx: Arc<C>) -> impl Strategy<Value = CommandWrapper<S, C>> {
+                endpoint_pair(ctx.as_ref())
+                    .prop_map(move |(a, b)| CommandWrapper::new($name::new(ctx.clone(), a, b)))
+            }
+
+            fn parse(label: &str, ctx: Arc<C>) -> Option<CommandWrapper<S, C>> {
+                let inner = label
+                    .strip_prefix(concat!($label, "("))?
+                    .strip_suffix(')')?;
+                let (a, b) = inner.split_once(", ")?;
+                Some(CommandWrapper::new($name::new(ctx, a, b)))
+            }
+        }
+    };
+}
+
+link_command!(
+    /// Cuts the link between two endpoints.
+    Partition, "PARTITION", partition,
+    check = |network, a, b| !network.is_partitioned(a, b),
+    apply = |network, link| network.partitions.insert(link),
+    cleanup = |ctx, network, a, b| {
+        if network.partitions.remove(&link(a, b)) {
+            let _ = ctx.heal(a, b);
+        }
+    },
+);
+
+link_command!(
+    /// Restores a cut link.
+    Heal, "HEAL", heal,
+    check = |network, a, b| network.is_partitioned(a, b),
+    apply = |network, link| network.partitions.remove(&link),
+    cleanup = |_, _, _, _| {},
+);
+
+/// Delays the traffic of an endpoint.
+pub struct Delay<C> {
+    ctx: Arc<C>,
+    /// The endpoint whose traffic is delayed.
+    pub endpoint: String,
+    /// The latency added; zero removes it.
+    pub latency: Duration,
+}
+
+impl<C> Delay<C> {
+    /// Creates the command delaying the traffic of `endpoint` by `latency`.
+    pub fn new(ctx: Arc<C>, endpoint: impl Into<String>, latency: Duration) -> Self {
+        Self {
+            ctx,
+            endpoint: endpoint.into(),
+            latency,
+        }
+    }
+}
+
+impl<S, C> Command<S, C> for Delay<C>
+where
+    S: NetState,
+    C: NetContext + 'static,
+{
+    fn check(&self, state: &S) -> bool {
+        state.network().latency(&self.endpoint) != self.latency
+    }
+
+    fn apply(&self, state: &mut S) {
+        if let Err(err) = self.try_apply(state) {
+            panic!("{}: {}", Command::<S, C>::label(self), err);
+        }
+    }
+
+    fn try_apply(&self, state: &mut S) -> Result<(), CommandError> {
+        self.ctx.set_latency(&self.endpoint, self.latency)?;
+        let latencies = &mut state.network_mut().latencies;
+        if self.latency.is_zero() {
+            latencies.remove(&self.endpoint);
+        } else {
+            latencies.insert(self.endpoint.clone(), self.latency);
+        }
+        Ok(())
+    }
+
+    fn cleanup(&self, state: &mut S) {
+        if state
+            .network_mut()
+            .latencies
+            .remove(&self.endpoint)
+            .is_some()
+        {
+            let _ = self.ctx.set_latency(&self.endpoint, Duration::ZERO);
+        }
+    }
+
+    fn label(&self) -> String {
+        format!("DELAY({}, {}ms)", self.endpoint, self.latency.as_millis())
+    }
+
+    /// Delays 0ms up to the context's `max_latency`, shrinking toward no
+    /// delay.
+    fn build(ctx: Arc<C>) -> impl Strategy<Value = CommandWrapper<S, C>> {
+        let max = ctx.max_latency().as_millis() as u64;
+        (endpoint(ctx.as_ref()), 0..=max).prop_map(move |(endpoint, ms)| {
+            CommandWrapper::new(Delay::new(ctx.clone(), endpoint, Duration::from_millis(ms)))
+        })
+    }
+
+    fn parse(label: &str, ctx: Arc<C>) -> Option<CommandWrapper<S, C>> {
+        let inner = label.strip_prefix("DELAY(")?.strip_suffix("ms)")?;
+        let (endpoint, ms) = inner.rsplit_once(", ")?;
+        Some(CommandWrapper::new(Delay::new(
+            ctx,
+            endpoint,
+            Duration::from_millis(ms.parse().ok()?),
+        )))
+    }
+}
+
+/// Drops a percentage of the new connections to an endpoint.
+pub struct DropConnections<C> {
+    ctx: Arc<C>,
+    /// The endpoint whose connections are dropped.
+    pub endpoint: String,
+    /// The percentage of connections dropped, up to 100; zero drops none.
+    pub percent: u8,
+}
+
+impl<C> DropConnections<C> {
+    /// Creates the command dropping `percent` of the new connections to
+    /// `endpoint`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `percent` is over 100.
+    pub fn new(ctx: Arc<C>, endpoint: impl Into<String>, percent: u8) -> Self {
+        assert!(
+            percent <= 100,
+            "dropping {}% of connections is over 100%",
+            percent
+        );
+        Self {
+            ctx,
+            endpoint: endpoint.into(),
+            percent,
+        }
+    }
+}
+
+impl<S, C> Command<S, C> for DropConnections<C>
+where
+    S: NetState,
+    C: NetContext + 'static,
+{
+    fn check(&self, state: &S) -> bool {
+        state.network().conn_drops(&self.endpoint) != self.percent
+    }
+
+    fn apply(&self, state: &mut S) {
+        if let Err(err) = self.try_apply(state) {
+            panic!("{}: {}", Command::<S, C>::label(self), err);
+        }
+    }
+
+    fn try_apply(&self, state: &mut S) -> Result<(), CommandError> {
+        self.ctx.set_conn_drops(&self.endpoint, self.percent)?;
+        let conn_drops = &mut state.network_mut().conn_drops;
+        if self.percent == 0 {
+            conn_drops.remove(&self.endpoint);
+        } else {
+            conn_drops.insert(self.endpoint.clone(), self.percent);
+        }
+        Ok(())
+    }
+
+    fn cleanup(&self, state: &mut S) {
+        if state
+            .network_mut()
+            .conn_drops
+            .remove(&self.endpoint)
+            .is_some()
+        {
+            let _ = self.ctx.set_conn_drops(&self.endpoint, 0);
+        }
+    }
+
+    fn label(&self) -> String {
+        format!("DROP_CONN({}, {}%)", self.endpoint, self.percent)
+    }
+
+    /// Drops 0% up to 100% of the connections, shrinking toward none.
+    fn build(ctx: Arc<C>) -> impl Strategy<Value = CommandWrapper<S, C>> {
+        (endpoint(ctx.as_ref()), 0..=100u8).prop_map(move |(endpoint, percent)| {
+            CommandWrapper::new(DropConnections::new(ctx.clone(), endpoint, percent))
+        })
+    }
+
+    fn parse(label: &str, ctx: Arc<C>) -> Option<CommandWrapper<S, C>> {
+        let inner = label.strip_prefix("DROP_CONN(")?.strip_suffix("%)")?;
+        let (endpoint, percent) = inner.rsplit_once(", ")?;
+        let percent = percent.parse().ok().filter(|&p| p <= 100)?;
+        Some(CommandWrapper::new(DropConnections::new(
+            ctx, endpoint, percent,
+        )))
+    }
+}
+
+/// Faults a [`Proxy`] applies to the traffic it forwards.
+#[derive(Debug, Default)]
+struct Faults {
+    blocked: bool,
+    latency: Duration,
+    conn_drops: u8,
+    accepted: u64,
+}
+
+impl Faults {
+    /// Counts a new connection, returning true if it is to be dropped.
+    ///
+    /// Drops are spread evenly: out of any 100 connections in a row,
+    /// `conn_drops` are dropped.
+    fn drops_next(&mut self) -> bool {
+        let percent = u64::from(self.conn_drops);
+        let n = self.accepted;
+        self.accepted += 1;
+        (n + 1) * percent / 100 > n * percent / 100
+    }
+}
+
+/// An in-process TCP proxy in front of an endpoint, injecting faults into
+/// the connections it forwards.
+///
+/// Point the system under test at [`addr`](Self::addr) instead of the
+/// endpoint, and carry out the [`NetContext`] faults with the proxy:
+/// [`set_blocked`](Self::set_blocked) cuts every connection (a partition),
+/// [`set_latency`](Self::set_latency) delays each forwarded chunk, and
+/// [`set_conn_drops`](Self::set_conn_drops) resets that percentage of new
+/// connections.
+///
+/// The proxy stops when dropped.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::net::Proxy;
+/// use std::io::{Read, Write};
+/// use std::net::{TcpListener, TcpStream};
+///
+/// let upstream = TcpListener::bind("127.0.0.1:0").unwrap();
+/// let proxy = Proxy::start(upstream.local_addr().unwrap()).unwrap();
+///
+/// let mut client = TcpStream::connect(proxy.addr()).unwrap();
+/// client.write_all(b"ping").unwrap();
+/// let (mut server, _) = upstream.accept().unwrap();
+/// let mut buf = [0; 4];
+/// server.read_exact(&mut buf).unwrap();
+/// assert_eq!(&buf, b"ping");
+/// ```
+pub struct Proxy {
+    addr: SocketAddr,
+    faults: Arc<Mutex<Faults>>,
+    stopped: Arc<AtomicBool>,
+}
+
+/// How often the proxy's threads check for faults and for being stopped.
+const PROXY_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How long the proxy waits to connect a client to the upstream endpoint
+/// before closing the client.
+const PROXY_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+impl Proxy {
+    /// Starts a proxy forwarding connections to `upstream`, listening on a
+    /// free local port.
+    ///
+    /// # Errors
+    ///
+    /// Fails if no local port can be bound.
+    pub fn start(upstream: SocketAddr) -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", 0))?;
+        listener.set_nonblocking(true)?;
+        let proxy = Self {
+            addr: listener.local_addr()?,
+            faults: Arc::default(),
+            stopped: Arc::default(),
+        };
+        let (faults, stopped) = (proxy.faults.clone(), proxy.stopped.clone());
+        thread::spawn(move || accept(listener, upstream, faults, stopped));
+        Ok(proxy)
+    }
+
+    /// Returns the address the proxy listens on.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Cuts every connection and refuses new ones while `blocked`.
+    pub fn set_blocked(&self, blocked: bool) {
+        self.faults.lock().unwrap().blocked = blocked;
+    }
+
+    /// Delays each forwarded chunk by `latency`.
+    pub fn set_latency(&self, latency: Duration) {
+        self.faults.lock().unwrap().latency = latency;
+    }
+
+    /// Resets `percent` of the new connections.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `percent` is over 100.
+    pub fn set_conn_drops(&self, percent: u8) {
+        assert!(
+            percent <= 100,
+            "dropping {}% of connections is over 100%",
+            percent
+        );
+        self.faults.lock().unwrap().conn_drops = percent;
+    }
+}
+
+impl Debug for Proxy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("Proxy")
+            .field("addr", &self.addr)
+            .field("faults", &*self.faults.lock().unwrap())
+            .finish()
+    }
+}
+
+impl Drop for Proxy {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Accepts connections until stopped, forwarding each to `upstream` on its
+/// own threads, so that a slow upstream does not hold up the others.
+fn accept(
+    listener: TcpListener,
+    upstream: SocketAddr,
+    faults: Arc<Mutex<Faults>>,
+    stopped: Arc<AtomicBool>,
+) {
+    while !stopped.load(Ordering::SeqCst) {
+        let client = match listener.accept() {
+            Ok((client, _)) => client,
+            // Nothing to accept yet, or a persistent error such as running
+            // out of file descriptors: wait rather than spin.
+            Err(_) => {
+                thread::sleep(PROXY_POLL_INTERVAL);
+                continue;
+            }
+        };
+        let dropped = {
+            let mut faults = faults.lock().unwrap();
+            faults.blocked || faults.drops_next()
+        };
+        if dropped {
+            continue;
+        }
+        let (faults, stopped) = (faults.clone(), stopped.clone());
+        thread::spawn(move || forward(client, upstream, faults, stopped));
+    }
+}
+
+/// Connects `client` to `upstream` and copies both ways until either
+/// closes, the proxy blocks, or it stops.
+fn forward(
+    client: TcpStream,
+    upstream: SocketAddr,
+    faults: Arc<Mutex<Faults>>,
+    stopped: Arc<AtomicBool>,
+) {
+    let Ok(server) = TcpStream::connect_timeout(&upstream, PROXY_CONNECT_TIMEOUT) else {
+        return;
+    };
+    let streams = client
+        .set_nonblocking(false)
+        .and_then(|()| Ok((client.try_clone()?, server.try_clone()?)));
+    let Ok((client_reader, server_writer)) = streams else {
+        return;
+    };
+    let (upload_faults, upload_stopped) = (faults.clone(), stopped.clone());
+    thread::spawn(move || {
+        pump(
+            client_reader,
+            server_writer,
+            &upload_faults,
+            &upload_stopped,
+        )
+    });
+    pump(server, client, &faults, &stopped);
+}
+
+/// Copies `from` to `to` until either closes, the proxy blocks, or it
+/// stops.
+fn pump(mut from: TcpStream, mut to: TcpStream, faults: &Mutex<Faults>, stopped: &AtomicBool) {
+    let _ = from.set_read_timeout(Some(PROXY_POLL_INTERVAL));
+    let mut buf = [0; 8192];
+    loop {
+        if stopped.load(Ordering::SeqCst) || faults.lock().unwrap().blocked {
+            break;
+        }
+        let n = match from.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                continue
+            }
+            Err(_) => break,
+        };
+        let latency = faults.lock().unwrap().latency;
+        if !latency.is_zero() {
+            thread::sleep(latency);
+        }
+        if to.write_all(&buf[..n]).is_err() {
+            break;
+        }
+    }
+    let _ = from.shutdown(Shutdown::Both);
+    let _ = to.shutdown(Shutdown::Both);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execute_commands;
+    use std::sync::mpsc;
+    use std::time::Instant;
+
+    #[derive(Debug, Default)]
+    struct Cluster {
+        network: Network,
+    }
+
+    impl State for Cluster {}
+
+    impl NetState for Cluster {
+        fn network(&self) -> &Network {
+            &self.network
+        }
+
+        fn network_mut(&mut self) -> &mut Network {
+            &mut self.network
+        }
+    }
+
+    /// Carries out the faults on the proxy in front of `b`.
+    #[derive(Debug, Clone)]
+    struct Ctx {
+        proxy: Arc<Proxy>,
+    }
+
+    impl TestContext for Ctx {}
+
+    impl NetContext for Ctx {
+        fn endpoints(&self) -> Vec<String> {
+            vec!["a".to_string(), "b".to_string()]
+        }
+
+        fn partition(&self, _a: &str, _b: &str) -> Result<(), CommandError> {
+            self.proxy.set_blocked(true);
+            Ok(())
+        }
+
+        fn heal(&self, _a: &str, _b: &str) -> Result<(), CommandError> {
+            self.proxy.set_blocked(false);
+            Ok(())
+        }
+
+        fn set_latency(&self, _endpoint: &str, latency: Duration) -> Result<(), CommandError> {
+            self.proxy.set_latency(latency);
+            Ok(())
+        }
+    }
+
+    /// Starts an upstream endpoint echoing a byte on every connection,
+    /// announced on the returned channel once the byte was echoed.
+    fn echo() -> (SocketAddr, mpsc::Receiver<()>) {
+        let upstream = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = upstream.local_addr().unwrap();
+        let (echoed, wait) = mpsc::channel();
+        thread::spawn(move || {
+            for mut server in upstream.incoming().flatten() {
+                let mut buf = [0];
+                if server.read_exact(&mut buf).is_ok() && server.write_all(&buf).is_ok() {
+                    let _ = echoed.send(());
+                }
+            }
+        });
+        (addr, wait)
+    }
+
+    /// Sends a byte through the proxy, returning the round trip time.
+    fn round_trip(proxy: &Proxy, echoed: &mpsc::Receiver<()>) -> Duration {
+        let start = Instant::now();
+        let mut client = TcpStream::connect(proxy.addr()).unwrap();
+        client.write_all(b"x").unwrap();
+        let mut buf = [0];
+        client.read_exact(&mut buf).unwrap();
+        echoed.recv().unwrap();
+        start.elapsed()
+    }
+
+    #[test]
+    fn test_faults_are_carried_out_by_the_proxy_and_undone_by_cleanup() {
+        let (upstream, echoed) = echo();
+        let proxy = Arc::new(Proxy::start(upstream).unwrap());
+        let ctx = Arc::new(Ctx {
+            proxy: proxy.clone(),
+        });
+        let ms = Duration::from_millis;
+        let commands: Vec<CommandWrapper<Cluster, Ctx>> = vec![
+            CommandWrapper::new(Delay::new(ctx.clone(), "b", ms(50))),
+            CommandWrapper::new(Heal::new(ctx.clone(), "a", "b")),
+            CommandWrapper::new(Partition::new(ctx.clone(), "b", "a")),
+            CommandWrapper::new(DropConnections::new(ctx.clone(), "b", 10)),
+        ];
+        let mut cluster = Cluster::default();
+
+        let report = execute_commands(&commands, &mut cluster);
+
+        assert_eq!(report.executed().count(), 3);
+        assert_eq!(report.steps[2].label, "PARTITION(b, a)");
+        assert!(report
+            .failures()
+            .next()
+            .unwrap()
+            .1
+            .message()
+            .contains("cannot set_conn_drops b: NetContext::set_conn_drops is not implemented"));
+        assert!(cluster.network.is_healthy());
+        {
+            let faults = proxy.faults.lock().unwrap();
+            assert!(!faults.blocked);
+            assert_eq!(faults.latency, Duration::ZERO);
+        }
+        round_trip(&proxy, &echoed);
+
+        // A blocked proxy closes new connections without forwarding them.
+        proxy.set_blocked(true);
+        let mut client = TcpStream::connect(proxy.addr()).unwrap();
+        match client.read(&mut [0]) {
+            Ok(n) => assert_eq!(n, 0),
+            Err(err) => assert_eq!(err.kind(), ErrorKind::ConnectionReset),
+        }
+        proxy.set_blocked(false);
+        // Each way sleeps for the latency at least once.
+        proxy.set_latency(ms(50));
+        assert!(round_trip(&proxy, &echoed) >= ms(100));
+
+        type Drops = DropConnections<Ctx>;
+        let parsed = <Drops as Command<Cluster, Ctx>>::parse("DROP_CONN(b, 7%)", ctx.clone());
+        assert_eq!(parsed.unwrap().command.label(), "DROP_CONN(b, 7%)");
+        assert!(<Drops as Command<Cluster, Ctx>>::parse("DROP_CONN(b, 101%)", ctx).is_none());
+    }
+
+    #[test]
+    fn test_conn_drops_are_spread_evenly() {
+        let mut faults = Faults {
+            conn_drops: 25,
+            ..Faults::default()
+        };
+        let dropped: Vec<_> = (0..8).map(|_| faults.drops_next()).collect();
+        assert_eq!(
+            dropped,
+            [false, false, false, true, false, false, false, true]
+        );
+    }
+}
@@ -0,0 +1,281 @@
+//! Notifications for long campaigns.
+//!
+//! A [`Notifier`] registered with [`Scenario::notifier`](crate::Scenario::notifier)
+//! hears about the first failure of a run as soon as it is shrunk, and gets
+//! a summary when the run ends, so an overnight campaign can page the team
+//! instead of being discovered in the morning. [`Webhook`] posts both as
+//! JSON; setting `MADHOUSE_WEBHOOK=<url>` adds one to every scenario.
+
+use crate::report::json_string;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// The first distinct failure of a run, after shrinking.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FailureNotice {
+    /// Scenario name, usually the test's name.
+    pub scenario: String,
+    /// Seed the run was generated from.
+    pub seed: u64,
+    /// Index of the failing case.
+    pub case: u32,
+    /// Failure message.
+    pub message: String,
+    /// Labels of the shrunk failing sequence.
+    pub labels: Vec<String>,
+}
+
+impl FailureNotice {
+    /// Renders the notice as a JSON object.
+    ///
+    /// The `text` field holds a one-line human summary, which is what
+    /// Slack-compatible incoming webhooks display.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use madhouse::notify::FailureNotice;
+    ///
+    /// let notice = FailureNotice {
+    ///     scenario: "tests::counter".to_string(),
+    ///     seed: 42,
+    ///     case: 3,
+    ///     message: "value above max".to_string(),
+    ///     labels: vec!["INC(1)".to_string()],
+    /// };
+    /// assert_eq!(
+    ///     notice.to_json(),
+    ///     concat!(
+    ///         r#"{"event":"failure","text":"madhouse: tests::counter failed (case 3, seed 42): value above max","#,
+    ///         r#""scenario":"tests::counter","seed":42,"case":3,"message":"value above max","trace":["INC(1)"]}"#,
+    ///     )
+    /// );
+    /// ```
+    pub fn to_json(&self) -> String {
+        let text = format!(
+            "madhouse: {} failed (case {}, seed {}): {}",
+            self.scenario, self.case, self.seed, self.message
+        );
+        let trace: Vec<String> = self.labels.iter().map(|l| json_string(l)).collect();
+        format!(
+            r#"{{"event":"failure","text":{},"scenario":{},"seed":{},"case":{},"message":{},"trace":[{}]}}"#,
+            json_string(&text),
+            json_string(&self.scenario),
+            self.seed,
+            self.case,
+            json_string(&self.message),
+            trace.join(",")
+        )
+    }
+}
+
+/// How a run ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CampaignSummary {
+    /// Scenario name, usually the test's name.
+    pub scenario: String,
+    /// Seed the run was generated from.
+    pub seed: u64,
+    /// Cases executed.
+    pub cases: u32,
+    /// Distinct failures found.
+    pub failures: usize,
+    /// The failure summary the run panics with; empty if it passed.
+    pub summary: String,
+}
+
+impl CampaignSummary {
+    /// Renders the summary as a JSON object, with a human `text` field like
+    /// [`FailureNotice::to_json`].
+    pub fn to_json(&self) -> String {
+        let text = if self.failures == 0 {
+            format!(
+                "madhouse: {} passed {} cases (seed {})",
+                self.scenario, self.cases, self.seed
+            )
+        } else {
+            format!(
+                "madhouse: {} found {} distinct failures in {} cases (seed {})",
+                self.scenario, self.failures, self.cases, self.seed
+            )
+        };
+        format!(
+            r#"{{"event":"end","text":{},"scenario":{},"seed":{},"cases":{},"failures":{},"summary":{}}}"#,
+            json_string(&text),
+            json_string(&self.scenario),
+            self.seed,
+            self.cases,
+            self.failures,
+            json_string(&self.summary)
+        )
+    }
+}
+
+/// Receives campaign events.
+///
+/// Both methods have empty defaults. Notifiers run on the test thread and
+/// should report their own errors (e.g. on stderr) rather than panic.
+pub trait Notifier {
+    /// The first distinct failure of a run was found and shrunk.
+    fn on_first_failure(&self, notice: &FailureNotice) {
+        let _ = notice;
+    }
+
+    /// The run ended, passing or not.
+    fn on_campaign_end(&self, summary: &CampaignSummary) {
+        let _ = summary;
+    }
+}
+
+/// Posts events as JSON to a webhook URL.
+///
+/// Requests are sent with `curl`, which must be on the `PATH`; this keeps
+/// HTTPS support without a TLS dependency. The URL, which usually embeds a
+/// token, is handed to curl as a config file on its stdin rather than as
+/// an argument, where `ps` would show it, and is never printed. Failed
+/// requests are reported on stderr and never fail the run.
+///
+/// # Examples
+///
+/// ```no_run
+/// use madhouse::notify::Webhook;
+///
+/// let webhook = Webhook::new("https://hooks.slack.com/services/T000/B000/XXXX");
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+pub struct Webhook {
+    url: String,
+}
+
+impl std::fmt::Debug for Webhook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Webhook")
+            .field("url", &redact(&self.url))
+            .finish()
+    }
+}
+
+impl Webhook {
+    /// Creates a notifier posting to `url`.
+    pub fn new(url: impl Into<String>) -> Self {
+        Self { url: url.into() }
+    }
+
+    /// Returns the URL events are posted to.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Posts a JSON body, warning on stderr if it cannot be delivered.
+    fn post(&self, body: &str) {
+        if let Err(err) = self.try_post(body) {
+            eprintln!("madhouse: cannot notify {}: {}", redact(&self.url), err);
+        }
+    }
+
+    fn try_post(&self, body: &str) -> std::io::Result<()> {
+        let mut child = Command::new("curl")
+            .args(["--silent", "--show-error", "--fail", "--max-time", "10"])
+            .args(["--header", "Content-Type: application/json"])
+            .args(["--config", "-"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            if let Err(err) = stdin.write_all(curl_config(&self.url, body).as_bytes()) {
+                // Reap curl rather than leave a zombie behind.
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(err);
+            }
+        }
+        let status = child.wait()?;
+        if !status.success() {
+            return Err(std::io::Error::other(format!(
+                "curl exited with {}",
+                status
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Renders the curl config file posting `body` to `url`.
+fn curl_config(url: &str, body: &str) -> String {
+    let quote = |value: &str| {
+        let mut quoted = String::from("\"");
+        for c in value.chars() {
+            match c {
+                '\\' => quoted.push_str("\\\\"),
+                '"' => quoted.push_str("\\\""),
+                '\n' => quoted.push_str("\\n"),
+                '\r' => quoted.push_str("\\r"),
+                '\t' => quoted.push_str("\\t"),
+                c => quoted.push(c),
+            }
+        }
+        quoted.push('"');
+        quoted
+    };
+    format!("url = {}\ndata-binary = {}\n", quote(url), quote(body))
+}
+
+/// Returns the scheme and host of `url`, hiding its path and credentials.
+fn redact(url: &str) -> String {
+    let (scheme, rest) = url.split_once("://").unwrap_or(("", url));
+    let host = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = host.rsplit('@').next().unwrap_or_default();
+    match scheme {
+        "" => format!("{}/...", host),
+        scheme => format!("{}://{}/...", scheme, host),
+    }
+}
+
+impl Notifier for Webhook {
+    fn on_first_failure(&self, notice: &FailureNotice) {
+        self.post(&notice.to_json());
+    }
+
+    fn on_campaign_end(&self, summary: &CampaignSummary) {
+        self.post(&summary.to_json());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_curl_config_quotes_the_url_and_body() {
+        let url = "https://user:pw@hooks.slack.com/services/T0/B0/XXXX";
+        assert_eq!(
+            curl_config(url, r#"{"text":"a\"b"}"#),
+            concat!(
+                "url = \"https://user:pw@hooks.slack.com/services/T0/B0/XXXX\"\n",
+                r#"data-binary = "{\"text\":\"a\\\"b\"}""#,
+                "\n"
+            )
+        );
+        assert_eq!(redact(url), "https://hooks.slack.com/...");
+        assert!(!format!("{:?}", Webhook::new(url)).contains("XXXX"));
+    }
+
+    #[test]
+    fn test_summary_json() {
+        let summary = CampaignSummary {
+            scenario: "s".to_string(),
+            seed: 7,
+            cases: 100,
+            failures: 0,
+            summary: String::new(),
+        };
+
+        assert_eq!(
+            summary.to_json(),
+            concat!(
+                r#"{"event":"end","text":"madhouse: s passed 100 cases (seed 7)","#,
+                r#""scenario":"s","seed":7,"cases":100,"failures":0,"summary":""}"#,
+            )
+        );
+    }
+}
@@ -0,0 +1,258 @@
+//! Callbacks on the command lifecycle.
+//!
+//! An [`Observer`] is notified as [`execute_commands_with`] walks through a
+//! sequence, so progress can be streamed to custom logging or metrics
+//! instead of the printed summary.
+
+use crate::{run_commands, CommandWrapper, ExecutionReport, State, StepReport, TestContext};
+
+/// Receives command lifecycle events during execution.
+///
+/// Every method has an empty default, so implementors only override the
+/// events they care about. `()` is the observer that ignores everything.
+///
+/// For each selected command, `on_selected` is called first, then either
+/// `on_skipped` (precondition rejected it) or `on_before_apply` followed by
+/// `on_after_apply`. Heartbeats are not reported.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::observer::{execute_commands_with, Observer};
+/// use madhouse::{Command, CommandWrapper, State, StepReport, TestContext};
+/// use proptest::prelude::*;
+/// use std::sync::Arc;
+///
+/// #[derive(Debug, Default)]
+/// struct Counter { value: u64 }
+/// impl State for Counter {}
+///
+/// #[derive(Debug, Clone, Default)]
+/// struct Ctx {}
+/// impl TestContext for Ctx {}
+///
+/// struct Inc;
+/// impl Command<Counter, Ctx> for Inc {
+///     fn check(&self, _state: &Counter) -> bool { true }
+///     fn apply(&self, state: &mut Counter) { state.value += 1; }
+///     fn label(&self) -> String { "INC".to_string() }
+///     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Counter, Ctx>> {
+///         Just(CommandWrapper::new(Inc))
+///     }
+/// }
+///
+/// #[derive(Default)]
+/// struct Metrics { applied: Vec<String> }
+/// impl Observer<Counter, Ctx> for Metrics {
+///     fn on_after_apply(&mut self, step: &StepReport, _state: &Counter) {
+///         self.applied.push(format!("{} took {:?}", step.label, step.duration));
+///     }
+/// }
+///
+/// let commands = vec![CommandWrapper::new(Inc), CommandWrapper::new(Inc)];
+/// let mut state = Counter::default();
+/// let mut metrics = Metrics::default();
+/// execute_commands_with(&commands, &mut state, &mut metrics);
+/// assert_eq!(metrics.applied.len(), 2);
+/// ```
+pub trait Observer<S: State, C: TestContext> {
+    /// A command was selected, before its precondition is checked.
+    fn on_selected(&mut self, index: usize, command: &CommandWrapper<S, C>) {
+        let _ = (index, command);
+    }
+
+    /// A selected command was skipped because `check` returned false.
+    fn on_skipped(&mut self, step: &StepReport) {
+        let _ = step;
+    }
+
+    /// A command passed its precondition and is about to be applied.
+    fn on_before_apply(&mut self, index: usize, command: &CommandWrapper<S, C>, state: &S) {
+        let _ = (index, command, state);
+    }
+
+    /// A command was applied. `step` holds its outcome and duration.
+    fn on_after_apply(&mut self, step: &StepReport, state: &S) {
+        let _ = (step, state);
+    }
+}
+
+impl<S: State, C: TestContext> Observer<S, C> for () {}
+
+impl<S: State, C: TestContext, O: Observer<S, C> + ?Sized> Observer<S, C> for &mut O {
+    fn on_selected(&mut self, index: usize, command: &CommandWrapper<S, C>) {
+        (**self).on_selected(index, command);
+    }
+
+    fn on_skipped(&mut self, step: &StepReport) {
+        (**self).on_skipped(step);
+    }
+
+    fn on_before_apply(&mut self, index: usize, command: &CommandWrapper<S, C>, state: &S) {
+        (**self).on_before_apply(index, command, state);
+    }
+
+    fn on_after_apply(&mut self, step: &StepReport, state: &S) {
+        (**self).on_after_apply(step, state);
+    }
+}
+
+/// Observes with the inner observer, if any.
+impl<S: State, C: TestContext, O: Observer<S, C>> Observer<S, C> for Option<O> {
+    fn on_selected(&mut self, index: usize, command: &CommandWrapper<S, C>) {
+        if let Some(observer) = self {
+            observer.on_selected(index, command);
+        }
+    }
+
+    fn on_skipped(&mut self, step: &StepReport) {
+        if let Some(observer) = self {
+            observer.on_skipped(step);
+        }
+    }
+
+    fn on_before_apply(&mut self, index: usize, command: &CommandWrapper<S, C>, state: &S) {
+        if let Some(observer) = self {
+            observer.on_before_apply(index, command, state);
+        }
+    }
+
+    fn on_after_apply(&mut self, step: &StepReport, state: &S) {
+        if let Some(observer) = self {
+            observer.on_after_apply(step, state);
+        }
+    }
+}
+
+/// Observes with both observers, the first one first.
+impl<S: State, C: TestContext, A: Observer<S, C>, B: Observer<S, C>> Observer<S, C> for (A, B) {
+    fn on_selected(&mut self, index: usize, command: &CommandWrapper<S, C>) {
+        self.0.on_selected(index, command);
+        self.1.on_selected(index, command);
+    }
+
+    fn on_skipped(&mut self, step: &StepReport) {
+        self.0.on_skipped(step);
+        self.1.on_skipped(step);
+    }
+
+    fn on_before_apply(&mut self, index: usize, command: &CommandWrapper<S, C>, state: &S) {
+        self.0.on_before_apply(index, command, state);
+        self.1.on_before_apply(index, command, state);
+    }
+
+    fn on_after_apply(&mut self, step: &StepReport, state: &S) {
+        self.0.on_after_apply(step, state);
+        self.1.on_after_apply(step, state);
+    }
+}
+
+/// Executes a sequence of commands, notifying `observer` of every step.
+///
+/// Behaves like [`execute_commands`](crate::execute_commands), except that
+/// nothing is printed: the observer and the returned report carry all the
+/// information.
+///
+/// # Arguments
+/// * `commands` - Slice of commands to potentially execute.
+/// * `state` - Mutable state that commands will modify.
+/// * `observer` - Receives lifecycle events.
+pub fn execute_commands_with<S: State, C: TestContext>(
+    commands: &[CommandWrapper<S, C>],
+    state: &mut S,
+    observer: &mut impl Observer<S, C>,
+) -> ExecutionReport {
+    run_commands(commands, state, &[], observer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Command;
+    use proptest::prelude::{Just, Strategy};
+    use std::sync::Arc;
+
+    #[derive(Debug, Default)]
+    struct Gate {
+        open: bool,
+    }
+
+    impl State for Gate {}
+
+    #[derive(Debug, Clone, Default)]
+    struct Ctx {}
+
+    impl TestContext for Ctx {}
+
+    struct Open;
+
+    impl Command<Gate, Ctx> for Open {
+        fn check(&self, state: &Gate) -> bool {
+            !state.open
+        }
+
+        fn apply(&self, state: &mut Gate) {
+            state.open = true;
+        }
+
+        fn label(&self) -> String {
+            "OPEN".to_string()
+        }
+
+        fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Gate, Ctx>> {
+            Just(CommandWrapper::new(Open))
+        }
+    }
+
+    #[derive(Default)]
+    struct Log {
+        events: Vec<String>,
+    }
+
+    impl Observer<Gate, Ctx> for Log {
+        fn on_selected(&mut self, index: usize, command: &CommandWrapper<Gate, Ctx>) {
+            self.events
+                .push(format!("selected {} {}", index, command.command.label()));
+        }
+
+        fn on_skipped(&mut self, step: &StepReport) {
+            self.events.push(format!("skipped {}", step.index));
+        }
+
+        fn on_before_apply(
+            &mut self,
+            index: usize,
+            _command: &CommandWrapper<Gate, Ctx>,
+            state: &Gate,
+        ) {
+            self.events
+                .push(format!("before {} open={}", index, state.open));
+        }
+
+        fn on_after_apply(&mut self, step: &StepReport, state: &Gate) {
+            self.events
+                .push(format!("after {} open={}", step.index, state.open));
+        }
+    }
+
+    #[test]
+    fn test_observer_sees_lifecycle_in_order() {
+        let commands = vec![CommandWrapper::new(Open), CommandWrapper::new(Open)];
+        let mut state = Gate::default();
+        let mut log = Log::default();
+
+        let report = execute_commands_with(&commands, &mut state, &mut log);
+
+        assert_eq!(
+            log.events,
+            vec![
+                "selected 0 OPEN",
+                "before 0 open=false",
+                "after 0 open=true",
+                "selected 1 OPEN",
+                "skipped 1",
+            ]
+        );
+        assert_eq!(report.executed().count(), 1);
+    }
+}
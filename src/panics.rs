@@ -0,0 +1,293 @@
+//! Panic capture scoped to scenario execution.
+//!
+//! While a scenario executes a case, a panic hook records panics raised on
+//! the scenario's thread, together with the step that was executing,
+//! instead of letting the default hook write them to stderr, where they
+//! interleave with the output of other tests running in parallel. The
+//! scenario then reports the panic in its own output and failure message.
+//! Panics on other threads go to the previous hook, which is restored once
+//! no scenario is executing a case.
+//!
+//! Executors also catch a panic raised by a step, attach the labels of the
+//! commands executed before it, and raise it again, so the panic message
+//! carries its trace even outside a scenario (see
+//! [`Config::panic_trace`](crate::Config::panic_trace)).
+
+use std::any::Any;
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::cell::RefCell;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::panic::{self, AssertUnwindSafe, PanicHookInfo};
+use std::sync::{Arc, Mutex};
+
+type Hook = dyn Fn(&PanicHookInfo<'_>) + Sync + Send + 'static;
+
+/// A panic raised while a scenario was running.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedPanic {
+    /// Index and label of the command executing when the panic was raised,
+    /// if any (panics in report assertions have none).
+    pub step: Option<(usize, String)>,
+    /// Panic message.
+    pub message: String,
+    /// Source location, as `file:line:column`.
+    pub location: Option<String>,
+    /// Backtrace, if enabled with `RUST_BACKTRACE`.
+    pub backtrace: Option<String>,
+    /// Labels of the commands executed before the panicking step, oldest
+    /// first; empty if the panic was not raised by a step.
+    pub trace: Vec<String>,
+}
+
+impl Display for CapturedPanic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "panicked")?;
+        if let Some((index, label)) = &self.step {
+            write!(f, " in step {} ({})", index, label)?;
+        }
+        if let Some(location) = &self.location {
+            write!(f, " at {}", location)?;
+        }
+        write!(f, ": {}", self.message)?;
+        if !self.trace.is_empty() {
+            write!(f, "\nExecuted before it:")?;
+            for (i, label) in self.trace.iter().enumerate() {
+                write!(f, "\n{:02}. {}", i + 1, label)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct Capture {
+    step: Option<(usize, String)>,
+    panic: Option<CapturedPanic>,
+}
+
+thread_local! {
+    static CAPTURE: RefCell<Option<Capture>> = const { RefCell::new(None) };
+}
+
+/// Threads capturing, and the hook to restore when none is.
+static INSTALLED: Mutex<(usize, Option<Arc<Hook>>)> = Mutex::new((0, None));
+
+/// Runs `f`, capturing a panic it raises on this thread.
+///
+/// The first capture in the process installs the hook; the last one to end
+/// restores the previous hook. Nested captures on one thread share the
+/// outer capture.
+pub(crate) fn catch<T>(f: impl FnOnce() -> T) -> Result<T, Box<CapturedPanic>> {
+    let _scope = Scope::enter();
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(|payload| {
+        Box::new(take().unwrap_or_else(|| CapturedPanic {
+            step: None,
+            message: payload_message(payload.as_ref()),
+            location: None,
+            backtrace: None,
+            trace: Vec::new(),
+        }))
+    })
+}
+
+/// Keeps the hook installed and this thread capturing until dropped.
+struct Scope {
+    owns_capture: bool,
+}
+
+impl Scope {
+    fn enter() -> Self {
+        let mut installed = INSTALLED.lock().unwrap_or_else(|e| e.into_inner());
+        if installed.0 == 0 {
+            let previous: Arc<Hook> = Arc::from(panic::take_hook());
+            let fallback = Arc::clone(&previous);
+            panic::set_hook(Box::new(move |info| {
+                if !capture(info) {
+                    fallback(info);
+                }
+            }));
+            installed.1 = Some(previous);
+        }
+        installed.0 += 1;
+        drop(installed);
+
+        let owns_capture = CAPTURE.with(|c| {
+            let mut c = c.borrow_mut();
+            let owns = c.is_none();
+            if owns {
+                *c = Some(Capture::default());
+            }
+            owns
+        });
+        Self { owns_capture }
+    }
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        if self.owns_capture {
+            CAPTURE.with(|c| c.borrow_mut().take());
+        }
+        let mut installed = INSTALLED.lock().unwrap_or_else(|e| e.into_inner());
+        installed.0 -= 1;
+        if installed.0 == 0 {
+            if let Some(previous) = installed.1.take() {
+                let _ = panic::take_hook();
+                panic::set_hook(Box::new(move |info| previous(info)));
+            }
+        }
+    }
+}
+
+fn payload_message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<String>()
+        .cloned()
+        .or_else(|| payload.downcast_ref::<&str>().map(|s| s.to_string()))
+        .unwrap_or_else(|| "Box<dyn Any>".to_string())
+}
+
+/// Records a panic if the current thread is capturing.
+fn capture(info: &PanicHookInfo<'_>) -> bool {
+    CAPTURE.with(|c| {
+        let Ok(mut c) = c.try_borrow_mut() else {
+            return false;
+        };
+        let Some(capture) = c.as_mut() else {
+            return false;
+        };
+        let message = payload_message(info.payload());
+        let backtrace = Backtrace::capture();
+        capture.panic = Some(CapturedPanic {
+            step: capture.step.clone(),
+            message,
+            location: info.location().map(|l| l.to_string()),
+            backtrace: (backtrace.status() == BacktraceStatus::Captured)
+                .then(|| backtrace.to_string()),
+            trace: Vec::new(),
+        });
+        true
+    })
+}
+
+/// Marks the step now executing on this thread, or none.
+pub(crate) fn set_step(step: Option<(usize, &str)>) {
+    CAPTURE.with(|c| {
+        if let Some(capture) = c.borrow_mut().as_mut() {
+            capture.step = step.map(|(index, label)| (index, label.to_string()));
+        }
+    });
+}
+
+/// Returns the step marked on this thread, if it is capturing.
+pub(crate) fn step() -> Option<(usize, String)> {
+    CAPTURE.with(|c| c.borrow().as_ref().and_then(|capture| capture.step.clone()))
+}
+
+/// Raises again on this thread a panic captured on another one, e.g. a
+/// worker applying a step on its behalf.
+pub(crate) fn resume(panic: CapturedPanic) -> ! {
+    let capturing = CAPTURE.with(|c| match c.borrow_mut().as_mut() {
+        Some(capture) => {
+            capture.panic = Some(panic.clone());
+            true
+        }
+        None => false,
+    });
+    if capturing {
+        panic::resume_unwind(Box::new(panic.message));
+    }
+    panic!("{}", panic.message)
+}
+
+/// Raises again the panic `payload` of step `index` (`label`), with the
+/// labels of the commands executed before it.
+///
+/// A capturing thread recorded the panic already: the trace is attached to
+/// that record. Otherwise the panic is raised anew with the trace in its
+/// message, for the test harness to print.
+pub(crate) fn raise_with_trace(
+    payload: Box<dyn Any + Send>,
+    index: usize,
+    label: &str,
+    trace: Vec<String>,
+) -> ! {
+    let capturing = CAPTURE.with(|c| match c.borrow_mut().as_mut() {
+        Some(capture) => {
+            if let Some(panic) = &mut capture.panic {
+                panic.trace = trace.clone();
+            }
+            true
+        }
+        None => false,
+    });
+    if capturing {
+        panic::resume_unwind(payload);
+    }
+    let panic = CapturedPanic {
+        step: Some((index, label.to_string())),
+        message: payload_message(payload.as_ref()),
+        location: None,
+        backtrace: None,
+        trace,
+    };
+    panic!("{}", panic)
+}
+
+/// Runs `f`, keeping a panic captured before it over those `f` raises.
+pub(crate) fn preserving<T>(f: impl FnOnce() -> T) -> T {
+    let earlier = take();
+    let result = f();
+    if earlier.is_some() {
+        CAPTURE.with(|c| {
+            if let Some(capture) = c.borrow_mut().as_mut() {
+                capture.panic = earlier;
+            }
+        });
+    }
+    result
+}
+
+/// Calls `f` on every item even if some calls panic, in which case the
+/// first panic is raised afterwards, unless the thread is unwinding already.
+///
+/// Either way, a panic captured before keeps being the one reported.
+pub(crate) fn run_all<T>(items: impl IntoIterator<Item = T>, mut f: impl FnMut(T)) {
+    let unwinding = std::thread::panicking();
+    let mut failed = None;
+    for item in items {
+        let result = preserving(|| panic::catch_unwind(AssertUnwindSafe(|| f(item))));
+        if let Err(payload) = result {
+            failed.get_or_insert(payload);
+        }
+    }
+    if let Some(payload) = failed.filter(|_| !unwinding) {
+        panic::resume_unwind(payload);
+    }
+}
+
+/// Takes the last panic captured on this thread.
+fn take() -> Option<CapturedPanic> {
+    CAPTURE.with(|c| c.borrow_mut().as_mut().and_then(|c| c.panic.take()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_panics_are_captured_with_their_step() {
+        let captured = catch(|| {
+            set_step(Some((2, "RESET")));
+            panic!("counter already zero");
+        })
+        .unwrap_err();
+
+        assert_eq!(captured.step, Some((2, "RESET".to_string())));
+        assert!(captured
+            .to_string()
+            .starts_with("panicked in step 2 (RESET) at src/panics.rs:"));
+        assert!(captured.to_string().ends_with(": counter already zero"));
+        assert_eq!(catch(|| 7), Ok(7));
+    }
+}
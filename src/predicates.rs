@@ -0,0 +1,169 @@
+//! Predicates over the executed label sequence of a run.
+//!
+//! [`Scenario::assert_trace`](crate::Scenario::assert_trace) checks one
+//! after every case, failing the case (and shrinking it) if the labels of
+//! the commands that passed `check()` do not match. As an oracle, it states
+//! what a correct trace looks like; as a sanity check, it catches
+//! strategies and preconditions that never produce the interesting
+//! interleavings.
+//!
+//! Patterns name commands either by full label (`DEPOSIT(5)`) or by
+//! [`command_name`] (`DEPOSIT`), which matches the command whatever its
+//! parameters.
+
+use crate::report::command_name;
+use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
+use std::ops::Not;
+
+type Test = Box<dyn Fn(&[&str]) -> bool>;
+
+/// A named test over the executed labels of a run.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::predicates::{contains, contains_subsequence};
+///
+/// let trace = ["START_MINER", "DEPOSIT(5)", "SUBMIT_BLOCK_COMMIT", "SORTITION"];
+/// let mined = contains_subsequence(["START_MINER", "SUBMIT_BLOCK_COMMIT", "SORTITION"]);
+/// assert!(mined.matches(&trace));
+/// assert!(contains("DEPOSIT").matches(&trace));
+/// assert!(!contains("DEPOSIT(6)").matches(&trace));
+/// assert!(!contains_subsequence(["SORTITION", "START_MINER"]).matches(&trace));
+/// assert_eq!(
+///     (!mined).to_string(),
+///     "not contains subsequence [START_MINER, SUBMIT_BLOCK_COMMIT, SORTITION]"
+/// );
+/// ```
+pub struct TracePredicate {
+    description: String,
+    test: Test,
+}
+
+impl TracePredicate {
+    /// Creates a predicate from a description, shown when it fails, and a
+    /// test over the executed labels.
+    pub fn new(description: impl Into<String>, test: impl Fn(&[&str]) -> bool + 'static) -> Self {
+        Self {
+            description: description.into(),
+            test: Box::new(test),
+        }
+    }
+
+    /// Returns true if the executed labels satisfy the predicate.
+    pub fn matches(&self, labels: &[&str]) -> bool {
+        (self.test)(labels)
+    }
+
+    /// Returns the predicate holding when both this one and `other` do.
+    pub fn and(self, other: Self) -> Self {
+        let description = format!("{} and {}", self.description, other.description);
+        Self::new(description, move |labels| {
+            self.matches(labels) && other.matches(labels)
+        })
+    }
+}
+
+/// The predicate holding when this one does not.
+impl Not for TracePredicate {
+    type Output = Self;
+
+    fn not(self) -> Self {
+        let description = format!("not {}", self.description);
+        Self::new(description, move |labels| !self.matches(labels))
+    }
+}
+
+impl Display for TracePredicate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str(&self.description)
+    }
+}
+
+impl Debug for TracePredicate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_tuple("TracePredicate")
+            .field(&self.description)
+            .finish()
+    }
+}
+
+/// Returns true if `label` is `pattern`, or a command named `pattern`.
+fn label_matches(label: &str, pattern: &str) -> bool {
+    label == pattern || command_name(label) == pattern
+}
+
+/// Formats patterns as `[A, B, C]`.
+fn list(patterns: &[String]) -> String {
+    format!("[{}]", patterns.join(", "))
+}
+
+/// Collects patterns into owned strings.
+fn patterns<I: IntoIterator>(patterns: I) -> Vec<String>
+where
+    I::Item: Into<String>,
+{
+    patterns.into_iter().map(Into::into).collect()
+}
+
+/// Holds if a command matching `pattern` ran.
+pub fn contains(pattern: impl Into<String>) -> TracePredicate {
+    let pattern = pattern.into();
+    TracePredicate::new(format!("contains {}", pattern), move |labels| {
+        labels.iter().any(|label| label_matches(label, &pattern))
+    })
+}
+
+/// Holds if commands matching `patterns` ran in that order, possibly with
+/// other commands in between.
+pub fn contains_subsequence<I: IntoIterator>(patterns: I) -> TracePredicate
+where
+    I::Item: Into<String>,
+{
+    let patterns = self::patterns(patterns);
+    TracePredicate::new(
+        format!("contains subsequence {}", list(&patterns)),
+        move |labels| {
+            let mut labels = labels.iter();
+            patterns
+                .iter()
+                .all(|pattern| labels.any(|label| label_matches(label, pattern)))
+        },
+    )
+}
+
+/// Holds if commands matching `patterns` ran back to back, in that order.
+pub fn contains_run<I: IntoIterator>(patterns: I) -> TracePredicate
+where
+    I::Item: Into<String>,
+{
+    let patterns = self::patterns(patterns);
+    TracePredicate::new(format!("contains run {}", list(&patterns)), move |labels| {
+        patterns.is_empty()
+            || labels.windows(patterns.len()).any(|window| {
+                window
+                    .iter()
+                    .zip(&patterns)
+                    .all(|(label, pattern)| label_matches(label, pattern))
+            })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_runs_must_be_contiguous_and_and_combines_descriptions() {
+        let trace = ["A(1)", "B", "A(2)", "C"];
+        assert!(contains_run(["B", "A"]).matches(&trace));
+        assert!(!contains_run(["A", "C", "B"]).matches(&trace));
+        assert!(contains_run(Vec::<String>::new()).matches(&trace));
+        assert!(contains_subsequence(["A(1)", "A(2)"]).matches(&trace));
+        assert!(!contains_subsequence(["A(2)", "A(1)"]).matches(&trace));
+
+        let both = contains("C").and(!contains("D"));
+        assert!(both.matches(&trace));
+        assert_eq!(both.to_string(), "contains C and not contains D");
+    }
+}
@@ -0,0 +1,613 @@
+//! Commands spawning, signalling and awaiting external processes.
+//!
+//! Tests driving real binaries (a node, a miner, a signer) start and stop
+//! them as part of the workload. This module ships those commands ready to
+//! use. The programs are declared by a [`ProcessContext`], which names them
+//! and builds the [`std::process::Command`] starting each; the model keeps
+//! the running children in [`Processes`], through [`ProcessState`], so that
+//! `check` only spawns programs that are not running and only signals or
+//! awaits those that are.
+//!
+//! The stdout and stderr of every child are captured. When it exits, or is
+//! killed, its [`ProcessOutput`] is attached to the execution report, in
+//! [`ExecutionReport::processes`](crate::ExecutionReport::processes).
+//! Children still running when a case ends, whether it passed or failed,
+//! are killed by [`Spawn`]'s [`cleanup`](crate::Command::cleanup), and by
+//! [`Processes`] itself when dropped, so none outlive the case.
+//!
+//! Signals other than `KILL` are sent with the `kill` utility, so
+//! [`Signal`] needs a Unix system.
+//!
+//! # Examples
+//!
+//! ```
+//! use madhouse::process::{Await, ProcessContext, ProcessState, Processes, Spawn};
+//! use madhouse::{execute_commands, CommandWrapper, State, TestContext};
+//! use std::sync::Arc;
+//!
+//! #[derive(Debug, Default)]
+//! struct Node { processes: Processes }
+//! impl State for Node {}
+//! impl ProcessState for Node {
+//!     fn processes(&self) -> &Processes { &self.processes }
+//!     fn processes_mut(&mut self) -> &mut Processes { &mut self.processes }
+//! }
+//!
+//! #[derive(Debug, Clone, Default)]
+//! struct Ctx {}
+//! impl TestContext for Ctx {}
+//! impl ProcessContext for Ctx {
+//!     fn programs(&self) -> Vec<String> { vec!["version".into()] }
+//!     fn command(&self, _name: &str) -> std::process::Command {
+//!         let mut command = std::process::Command::new("echo");
+//!         command.arg("node 1.0");
+//!         command
+//!     }
+//! }
+//!
+//! let ctx = Arc::new(Ctx::default());
+//! let commands: Vec<CommandWrapper<Node, Ctx>> = vec![
+//!     CommandWrapper::new(Spawn::new(ctx.clone(), "version")),
+//!     CommandWrapper::new(Await::new(ctx.clone(), "version")),
+//! ];
+//! let report = execute_commands(&commands, &mut Node::default());
+//!
+//! assert!(report.is_ok());
+//! assert_eq!(report.processes[0].stdout, "node 1.0\n");
+//! assert_eq!(report.processes[0].to_string(), "version exited with status 0");
+//! ```
+
+use crate::{Command, CommandError, CommandWrapper, State, TestContext};
+use proptest::prelude::Strategy;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
+use std::io::Read;
+use std::marker::PhantomData;
+use std::process::{Child, Stdio};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How often [`Processes::wait`] polls a child for its exit.
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// How a child process ended, with everything it wrote.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessOutput {
+    /// Name of the program, as listed by [`ProcessContext::programs`].
+    pub name: String,
+    /// Exit code, or `None` if a signal terminated the process.
+    pub status: Option<i32>,
+    /// Whether the process was still running when the case ended, and was
+    /// killed.
+    pub killed: bool,
+    /// Everything written to stdout, lossily decoded as UTF-8.
+    pub stdout: String,
+    /// Everything written to stderr, lossily decoded as UTF-8.
+    pub stderr: String,
+}
+
+impl ProcessOutput {
+    /// Returns true if the process exited with status 0.
+    pub fn success(&self) -> bool {
+        self.status == Some(0)
+    }
+}
+
+impl Display for ProcessOutput {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self.status {
+            _ if self.killed => write!(f, "{} was killed at the end of the case", self.name),
+            Some(code) => write!(f, "{} exited with status {}", self.name, code),
+            None => write!(f, "{} was terminated by a signal", self.name),
+        }
+    }
+}
+
+/// A spawned child, with the threads draining its output.
+struct Running {
+    child: Child,
+    stdout: JoinHandle<Vec<u8>>,
+    stderr: JoinHandle<Vec<u8>>,
+}
+
+impl Running {
+    /// Reaps the exited child, recording and returning its output.
+    fn finish(self, name: String, status: Option<i32>, killed: bool) -> ProcessOutput {
+        let text = |handle: JoinHandle<Vec<u8>>| {
+            String::from_utf8_lossy(&handle.join().unwrap_or_default()).into_owned()
+        };
+        let output = ProcessOutput {
+            name,
+            status,
+            killed,
+            stdout: text(self.stdout),
+            stderr: text(self.stderr),
+        };
+        record(output.clone());
+        output
+    }
+}
+
+/// Reads `pipe` to its end on a new thread, so the child never blocks on a
+/// full pipe.
+fn drain(pipe: Option<impl Read + Send + 'static>) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut out = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut out);
+        }
+        out
+    })
+}
+
+/// Child processes running on behalf of the model, by name.
+///
+/// Dropping it kills the children still running.
+#[derive(Default)]
+pub struct Processes {
+    running: BTreeMap<String, Running>,
+}
+
+impl Processes {
+    /// Returns true if `name` was spawned and has not been awaited since.
+    pub fn is_running(&self, name: &str) -> bool {
+        self.running.contains_key(name)
+    }
+
+    /// Returns the process id of the running `name`.
+    pub fn pid(&self, name: &str) -> Option<u32> {
+        self.running.get(name).map(|running| running.child.id())
+    }
+
+    /// Spawns `command` as `name`, capturing its stdout and stderr.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `name` is already running or the process cannot start.
+    pub fn spawn(
+        &mut self,
+        name: impl Into<String>,
+        mut command: std::process::Command,
+    ) -> Result<(), CommandError> {
+        let name = name.into();
+        if self.is_running(&name) {
+            return Err(CommandError::new(format!("{} is already running", name)));
+        }
+        let mut child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|err| CommandError::new(format!("cannot spawn {}: {}", name, err)))?;
+        let running = Running {
+            stdout: drain(child.stdout.take()),
+            stderr: drain(child.stderr.take()),
+            child,
+        };
+        self.running.insert(name, running);
+        Ok(())
+    }
+
+    /// Sends `signal` (e.g. `TERM`, `STOP`, `CONT`) to the running `name`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `name` is not running or the signal cannot be sent.
+    pub fn signal(&mut self, name: &str, signal: &str) -> Result<(), CommandError> {
+        let running = self
+            .running
+            .get_mut(name)
+            .ok_or_else(|| CommandError::new(format!("{} is not running", name)))?;
+        if signal == "KILL" {
+            return running
+                .child
+                .kill()
+                .map_err(|err| CommandError::new(format!("cannot kill {}: {}", name, err)));
+        }
+        let status = std::process::Command::new("kill")
+            .args(["-s", signal, &running.child.id().to_string()])
+            .status()
+            .map_err(|err| CommandError::new(format!("cannot run kill: {}", err)))?;
+        if !status.success() {
+            return Err(CommandError::new(format!(
+                "cannot send {} to {}: kill exited with {}",
+                signal, name, status
+            )));
+        }
+        Ok(())
+    }
+
+    /// Waits up to `timeout` for the running `name` to exit, returning its
+    /// output.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `name` is not running, is still running after `timeout`, or
+    /// cannot be waited for; it is then killed.
+    pub fn wait(&mut self, name: &str, timeout: Duration) -> Result<ProcessOutput, CommandError> {
+        let (name, mut running) = self
+            .running
+            .remove_entry(name)
+            .ok_or_else(|| CommandError::new(format!("{} is not running", name)))?;
+        let deadline = Instant::now() + timeout;
+        loop {
+            match running.child.try_wait() {
+                Ok(Some(status)) => return Ok(running.finish(name, status.code(), false)),
+                Ok(None) if Instant::now() < deadline => thread::sleep(POLL_INTERVAL),
+                Ok(None) => {
+                    let _ = running.child.kill();
+                    let status = running.child.wait().ok().and_then(|s| s.code());
+                    running.finish(name.clone(), status, true);
+                    return Err(CommandError::new(format!(
+                        "{} still running after {:?}; killed",
+                        name, timeout
+                    )));
+                }
+                Err(err) => {
+                    let _ = running.child.kill();
+                    let status = running.child.wait().ok().and_then(|s| s.code());
+                    running.finish(name.clone(), status, true);
+                    return Err(CommandError::new(format!(
+                        "cannot wait for {}: {}; killed",
+                        name, err
+                    )));
+                }
+            }
+        }
+    }
+
+    /// Kills the running `name`, returning its output, or `None` if it is
+    /// not running.
+    pub fn kill(&mut self, name: &str) -> Option<ProcessOutput> {
+        let (name, mut running) = self.running.remove_entry(name)?;
+        let _ = running.child.kill();
+        let status = running.child.wait().ok().and_then(|s| s.code());
+        Some(running.finish(name, status, true))
+    }
+}
+
+impl Debug for Processes {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_map()
+            .entries(
+                self.running
+                    .iter()
+                    .map(|(name, running)| (name, running.child.id())),
+            )
+            .finish()
+    }
+}
+
+impl Drop for Processes {
+    fn drop(&mut self) {
+        let names: Vec<_> = self.running.keys().cloned().collect();
+        for name in names {
+            self.kill(&name);
+        }
+    }
+}
+
+/// State that tracks the [`Processes`] spawned by the model.
+pub trait ProcessState: State {
+    /// Returns the running processes.
+    fn processes(&self) -> &Processes;
+
+    /// Returns the running processes for modification.
+    fn processes_mut(&mut self) -> &mut Processes;
+}
+
+/// Context declaring the programs the commands manage.
+pub trait ProcessContext: TestContext {
+    /// Names of the programs that can be spawned.
+    fn programs(&self) -> Vec<String>;
+
+    /// Builds the command starting the program `name`.
+    fn command(&self, name: &str) -> std::process::Command;
+
+    /// Signals a [`Signal`] command picks from (default `TERM`).
+    fn signals(&self) -> Vec<String> {
+        vec!["TERM".to_string()]
+    }
+
+    /// How long an [`Await`] waits for a process to exit before killing it
+    /// (default ten seconds).
+    fn await_timeout(&self) -> Duration {
+        Duration::from_secs(10)
+    }
+
+    /// Checks how an awaited process ended, failing the [`Await`] if it
+    /// returns an error. Accepts any exit by default.
+    fn exited(&self, output: &ProcessOutput) -> Result<(), CommandError> {
+        let _ = output;
+        Ok(())
+    }
+}
+
+/// Strategy picking one of `names`.
+///
+/// # Panics
+///
+/// Panics if `names` is empty.
+fn pick(names: Vec<String>, what: &str) -> impl Strategy<Value = String> {
+    assert!(!names.is_empty(), "ProcessContext::{} returned none", what);
+    crate::gen::pick(names)
+}
+
+/// Defines a command taking a program name, applicable when `check` holds.
+macro_rules! program_command {
+    (
+        $(#[$doc:meta])*
+        $name:ident, $label:literal,
+        check = |$processes:ident, $program:ident| $check:expr,
+        apply = |$ctx:ident, $processes_mut:ident, $program_mut:ident| $apply:expr,
+        cleanup = |$processes_cleanup:ident, $program_cleanup:ident| $cleanup:expr $(,)?
+    ) => {
+        $(#[$doc])*
+        pub struct $name<C> {
+            ctx: Arc<C>,
+            /// The program the command manages.
+            pub name: String,
+        }
+
+        impl<C> $name<C> {
+            /// Creates the command managing the program `name`.
+            pub fn new(ctx: Arc<C>, name: impl Into<String>) -> Self {
+                Self {
+                    ctx,
+                    name: name.into(),
+                }
+            }
+        }
+
+        impl<S, C> Command<S, C> for $name<C>
+        where
+            S: ProcessState,
+            C: ProcessContext + 'static,
+        {
+            fn check(&self, state: &S) -> bool {
+                let ($processes, $program) = (state.processes(), self.name.as_str());
+                $check
+            }
+
+            fn apply(&self, state: &mut S) {
+                if let Err(err) = self.try_apply(state) {
+                    panic!("{}: {}", Command::<S, C>::label(self), err);
+                }
+            }
+
+            fn try_apply(&self, state: &mut S) -> Result<(), CommandError> {
+                let ($ctx, $processes_mut, $program_mut) =
+                    (self.ctx.as_ref(), state.processes_mut(), self.name.as_str());
+                $apply
+            }
+
+            fn cleanup(&self, state: &mut S) {
+                let ($processes_cleanup, $program_cleanup) =
+                    (state.processes_mut(), self.name.as_str());
+                $cleanup;
+            }
+
+            fn label(&self) -> String {
+                format!(concat!($label, "({})"), self.name)
+            }
+
+            fn build(ctx: Arc<C>) -> impl Strategy<Value = CommandWrapper<S, C>> {
+                pick(ctx.programs(), "programs")
+                    .prop_map(move |name| CommandWrapper::new($name::new(ctx.clone(), name)))
+            }
+
+            fn parse(label: &str, ctx: Arc<C>) -> Option<CommandWrapper<S, C>> {
+                let name = label
+                    .strip_prefix(concat!($label, "("))?
+                    .strip_suffix(')')?;
+                Some(CommandWrapper::new($name::new(ctx, name)))
+            }
+        }
+    };
+}
+
+program_command!(
+    /// Spawns a program that is not running.
+    Spawn, "SPAWN",
+    check = |processes, name| !processes.is_running(name),
+    apply = |ctx, processes, name| processes.spawn(name, ctx.command(name)),
+    cleanup = |processes, name| processes.kill(name),
+);
+
+program_command!(
+    /// Waits for a running program to exit, within the context's
+    /// [`await_timeout`](ProcessContext::await_timeout).
+    Await, "AWAIT",
+    check = |processes, name| processes.is_running(name),
+    apply = |ctx, processes, name| {
+        let output = processes.wait(name, ctx.await_timeout())?;
+        ctx.exited(&output)
+    },
+    cleanup = |_processes, _name| {},
+);
+
+/// Sends a signal to a running program.
+pub struct Signal<C> {
+    /// The program signalled.
+    pub name: String,
+    /// The signal sent, e.g. `TERM`.
+    pub signal: String,
+    _ctx: PhantomData<fn() -> C>,
+}
+
+impl<C> Signal<C> {
+    /// Creates the command sending `signal` to the program `name`.
+    pub fn new(name: impl Into<String>, signal: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            signal: signal.into(),
+            _ctx: PhantomData,
+        }
+    }
+}
+
+impl<S, C> Command<S, C> for Signal<C>
+where
+    S: ProcessState,
+    C: ProcessContext + 'static,
+{
+    fn check(&self, state: &S) -> bool {
+        state.processes().is_running(&self.name)
+    }
+
+    fn apply(&self, state: &mut S) {
+        if let Err(err) = self.try_apply(state) {
+            panic!("{}: {}", Command::<S, C>::label(self), err);
+        }
+    }
+
+    fn try_apply(&self, state: &mut S) -> Result<(), CommandError> {
+        state.processes_mut().signal(&self.name, &self.signal)
+    }
+
+    fn label(&self) -> String {
+        format!("SIGNAL({}, {})", self.name, self.signal)
+    }
+
+    fn build(ctx: Arc<C>) -> impl Strategy<Value = CommandWrapper<S, C>> {
+        let programs = pick(ctx.programs(), "programs");
+        let signals = pick(ctx.signals(), "signals");
+        (programs, signals)
+            .prop_map(move |(name, signal)| CommandWrapper::new(Signal::new(name, signal)))
+    }
+
+    fn parse(label: &str, _ctx: Arc<C>) -> Option<CommandWrapper<S, C>> {
+        let inner = label.strip_prefix("SIGNAL(")?.strip_suffix(')')?;
+        let (name, signal) = inner.rsplit_once(", ")?;
+        Some(CommandWrapper::new(Signal::new(name, signal)))
+    }
+}
+
+thread_local! {
+    static OUTPUTS: RefCell<Option<Vec<ProcessOutput>>> = const { RefCell::new(None) };
+}
+
+/// Attaches `output` to the report of the executing sequence, if any.
+fn record(output: ProcessOutput) {
+    OUTPUTS.with(|outputs| {
+        if let Some(outputs) = outputs.borrow_mut().as_mut() {
+            outputs.push(output);
+        }
+    });
+}
+
+/// Collects the process outputs of one execution until finished or
+/// dropped, then restores those of an enclosing execution.
+pub(crate) struct Scope {
+    outer: Option<Vec<ProcessOutput>>,
+}
+
+impl Scope {
+    pub(crate) fn enter() -> Self {
+        let outer = OUTPUTS.with(|outputs| outputs.borrow_mut().replace(Vec::new()));
+        Self { outer }
+    }
+
+    /// Ends the execution, returning the outputs collected, in the order
+    /// the processes ended.
+    pub(crate) fn finish(self) -> Vec<ProcessOutput> {
+        OUTPUTS.with(|outputs| {
+            outputs
+                .borrow_mut()
+                .as_mut()
+                .map(std::mem::take)
+                .unwrap_or_default()
+        })
+    }
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        let outer = self.outer.take();
+        OUTPUTS.with(|outputs| *outputs.borrow_mut() = outer);
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::execute_commands;
+
+    #[derive(Debug, Default)]
+    struct Node {
+        processes: Processes,
+    }
+
+    impl State for Node {}
+
+    impl ProcessState for Node {
+        fn processes(&self) -> &Processes {
+            &self.processes
+        }
+
+        fn processes_mut(&mut self) -> &mut Processes {
+            &mut self.processes
+        }
+    }
+
+    #[derive(Debug, Clone, Default)]
+    struct Ctx {}
+
+    impl TestContext for Ctx {}
+
+    impl ProcessContext for Ctx {
+        fn programs(&self) -> Vec<String> {
+            vec!["greeter".to_string(), "sleeper".to_string()]
+        }
+
+        fn command(&self, name: &str) -> std::process::Command {
+            let script = match name {
+                "greeter" => "echo hello; echo oops >&2",
+                _ => "exec sleep 30",
+            };
+            let mut command = std::process::Command::new("sh");
+            command.args(["-c", script]);
+            command
+        }
+    }
+
+    #[test]
+    fn test_outputs_are_reported_and_leftover_processes_killed() {
+        let ctx = Arc::new(Ctx::default());
+        let commands: Vec<CommandWrapper<Node, Ctx>> = vec![
+            CommandWrapper::new(Spawn::new(ctx.clone(), "greeter")),
+            CommandWrapper::new(Await::new(ctx.clone(), "greeter")),
+            CommandWrapper::new(Spawn::new(ctx.clone(), "sleeper")),
+            CommandWrapper::new(Spawn::new(ctx.clone(), "sleeper")),
+            CommandWrapper::new(Signal::new("sleeper", "TERM")),
+            CommandWrapper::new(Await::new(ctx.clone(), "sleeper")),
+            CommandWrapper::new(Spawn::new(ctx.clone(), "sleeper")),
+        ];
+        let mut node = Node::default();
+
+        let report = execute_commands(&commands, &mut node);
+
+        assert!(report.is_ok());
+        assert_eq!(report.executed().count(), 6);
+        assert!(!node.processes.is_running("sleeper"));
+        let outputs: Vec<_> = report.processes.iter().map(|o| o.to_string()).collect();
+        assert_eq!(
+            outputs,
+            [
+                "greeter exited with status 0",
+                "sleeper was terminated by a signal",
+                "sleeper was killed at the end of the case",
+            ]
+        );
+        let greeter = &report.processes[0];
+        assert_eq!(
+            (greeter.stdout.as_str(), greeter.stderr.as_str()),
+            ("hello\n", "oops\n")
+        );
+
+        let parsed = <Signal<Ctx> as Command<Node, Ctx>>::parse("SIGNAL(sleeper, STOP)", ctx);
+        assert_eq!(parsed.unwrap().command.label(), "SIGNAL(sleeper, STOP)");
+    }
+}
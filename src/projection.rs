@@ -0,0 +1,107 @@
+//! Named projections of the model state, for concise reports.
+//!
+//! Enabled with the `json` feature. A large state's `Debug` dump buries the
+//! few values that matter for a failure (the chain height, the number of
+//! miners). [`Projections`] registered with
+//! [`Scenario::projections`](crate::Scenario::projections) are evaluated on
+//! the state each case ends in, and the resulting JSON object is shown in
+//! the summary, the JSON report
+//! ([`ExecutionReport::projections`](crate::ExecutionReport::projections))
+//! and, in verbose mode, in place of the final state dump. They can also be
+//! evaluated at every [`checkpoint`](crate::checkpoint) with
+//! [`Checkpointed::project`](crate::checkpoint::Checkpointed::project).
+
+use serde_json::{Map, Value};
+use std::fmt::{self, Debug, Formatter};
+
+type View<S> = Box<dyn Fn(&S) -> Value>;
+
+/// Named views of a state, each rendering it as a JSON value.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::projection::Projections;
+/// use serde_json::json;
+///
+/// struct Chain { blocks: Vec<u64>, miners: Vec<String> }
+///
+/// let projections = Projections::new()
+///     .add("height", |chain: &Chain| json!(chain.blocks.len()))
+///     .add("miners", |chain: &Chain| json!(chain.miners.len()));
+///
+/// let chain = Chain { blocks: vec![1, 2, 3], miners: vec!["alice".to_string()] };
+/// assert_eq!(projections.evaluate(&chain), json!({"height": 3, "miners": 1}));
+/// ```
+pub struct Projections<S> {
+    views: Vec<(String, View<S>)>,
+}
+
+impl<S> Default for Projections<S> {
+    fn default() -> Self {
+        Self { views: Vec::new() }
+    }
+}
+
+impl<S> Debug for Projections<S> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.views.iter().map(|(name, _)| name))
+            .finish()
+    }
+}
+
+impl<S> Projections<S> {
+    /// Creates an empty set of projections.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a projection named `name`, replacing one of the same name.
+    pub fn add(mut self, name: impl Into<String>, view: impl Fn(&S) -> Value + 'static) -> Self {
+        let name = name.into();
+        self.views.retain(|(existing, _)| *existing != name);
+        self.views.push((name, Box::new(view)));
+        self
+    }
+
+    /// Appends the projections of `other`, which win on name clashes.
+    pub fn extend(&mut self, other: Projections<S>) {
+        for (name, view) in other.views {
+            self.views.retain(|(existing, _)| *existing != name);
+            self.views.push((name, view));
+        }
+    }
+
+    /// Returns true if no projection is registered.
+    pub fn is_empty(&self) -> bool {
+        self.views.is_empty()
+    }
+
+    /// Evaluates every projection on `state`, as an object keyed by name.
+    pub fn evaluate(&self, state: &S) -> Value {
+        let object: Map<String, Value> = self
+            .views
+            .iter()
+            .map(|(name, view)| (name.clone(), view(state)))
+            .collect();
+        Value::Object(object)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_later_projections_replace_earlier_ones_of_the_same_name() {
+        let mut projections = Projections::new()
+            .add("value", |n: &u64| json!(n))
+            .add("square", |n: &u64| json!(n * n));
+        projections.extend(Projections::new().add("value", |n: &u64| json!(n * 10)));
+
+        assert_eq!(projections.evaluate(&3), json!({"value": 30, "square": 9}));
+        assert_eq!(format!("{:?}", projections), r#"["square", "value"]"#);
+    }
+}
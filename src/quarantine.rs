@@ -0,0 +1,111 @@
+//! Quarantined traces and cases, skipped while a known bug awaits a fix.
+//!
+//! A quarantine file lists failures that should not block the rest of a
+//! campaign. Each line is either a trace fingerprint, as printed in failure
+//! summaries and used in regression file names, or a `seed` and case index.
+//! Lines starting with `#` are comments, so the file can say why each entry
+//! is there. See [`Config::quarantine`](crate::Config::quarantine).
+//!
+//! ```text
+//! # Counter overflow, fixed by #123.
+//! 5a0c3e0c1b8e7a51
+//! # Flaky leader election.
+//! seed 1234 7
+//! ```
+//!
+//! A case is skipped, without running, if its seed and index or the
+//! fingerprint of its generated trace is quarantined. A case whose shrunk
+//! trace is quarantined still runs but its failure is not reported. Either
+//! way the run prints how many cases were quarantined.
+
+use crate::regression::fingerprint;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Parsed quarantine file.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::quarantine::Quarantine;
+/// use madhouse::regression::fingerprint;
+///
+/// let labels = vec!["INC(3)".to_string(), "RESET".to_string()];
+/// let file = format!("# known bug\n{:016x}\nseed 42 7\n", fingerprint(&labels));
+/// let quarantine = Quarantine::parse(&file).unwrap();
+///
+/// assert!(quarantine.contains_trace(&labels));
+/// assert!(quarantine.contains_case(42, 7));
+/// assert!(!quarantine.contains_case(42, 8));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Quarantine {
+    traces: BTreeSet<u64>,
+    cases: BTreeSet<(u64, u32)>,
+}
+
+impl Quarantine {
+    /// Parses a quarantine file; `None` if a line is malformed.
+    pub fn parse(contents: &str) -> Option<Self> {
+        let mut quarantine = Self::default();
+        let lines = contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+        for line in lines {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words[..] {
+                [fingerprint] => {
+                    quarantine
+                        .traces
+                        .insert(u64::from_str_radix(fingerprint, 16).ok()?);
+                }
+                ["seed", seed, case] => {
+                    quarantine
+                        .cases
+                        .insert((seed.parse().ok()?, case.parse().ok()?));
+                }
+                _ => return None,
+            }
+        }
+        Some(quarantine)
+    }
+
+    /// Reads a quarantine file.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        Self::parse(&contents)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed quarantine file"))
+    }
+
+    /// Returns true if the trace with these labels is quarantined.
+    pub fn contains_trace(&self, labels: &[String]) -> bool {
+        self.traces.contains(&fingerprint(labels))
+    }
+
+    /// Returns true if case `case` of the run seeded with `seed` is
+    /// quarantined.
+    pub fn contains_case(&self, seed: u64, case: u32) -> bool {
+        self.cases.contains(&(seed, case))
+    }
+
+    /// Returns true if nothing is quarantined.
+    pub fn is_empty(&self) -> bool {
+        self.traces.is_empty() && self.cases.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rejects_malformed_lines() {
+        assert!(Quarantine::parse("# nothing yet\n\n").unwrap().is_empty());
+        assert!(Quarantine::parse("not-hex\n").is_none());
+        assert!(Quarantine::parse("seed 42\n").is_none());
+        assert!(Quarantine::parse("seed 42 x\n").is_none());
+    }
+}
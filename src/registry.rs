@@ -0,0 +1,220 @@
+//! Serde serialization of command sequences, with the `serde` feature.
+//!
+//! Commands are trait objects, so a sequence cannot be deserialized
+//! without knowing which command types it may hold. Like regression files,
+//! a serialized sequence stores each command as its
+//! [`label`](crate::Command::label); a [`CommandRegistry`] lists the
+//! commands that may appear and turns the labels back into commands with
+//! [`Command::parse`]. Any serde format works (JSON, RON, ...), and the
+//! files stay valid when strategies change.
+//!
+//! # Examples
+//!
+//! ```
+//! use madhouse::registry::CommandRegistry;
+//! use madhouse::{Command, CommandWrapper, State, TestContext};
+//! use proptest::prelude::*;
+//! use std::sync::Arc;
+//!
+//! #[derive(Debug, Default)]
+//! struct Counter { value: u64 }
+//! impl State for Counter {}
+//!
+//! #[derive(Debug, Clone, Default)]
+//! struct Ctx {}
+//! impl TestContext for Ctx {}
+//!
+//! struct Inc(u64);
+//! impl Command<Counter, Ctx> for Inc {
+//!     fn check(&self, _state: &Counter) -> bool { true }
+//!     fn apply(&self, state: &mut Counter) { state.value += self.0; }
+//!     fn label(&self) -> String { format!("INC({})", self.0) }
+//!     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Counter, Ctx>> {
+//!         (1..10u64).prop_map(|n| CommandWrapper::new(Inc(n)))
+//!     }
+//!     fn parse(label: &str, _ctx: Arc<Ctx>) -> Option<CommandWrapper<Counter, Ctx>> {
+//!         let n = label.strip_prefix("INC(")?.strip_suffix(')')?.parse().ok()?;
+//!         Some(CommandWrapper::new(Inc(n)))
+//!     }
+//! }
+//!
+//! struct Reset;
+//! impl Command<Counter, Ctx> for Reset {
+//!     fn check(&self, state: &Counter) -> bool { state.value > 0 }
+//!     fn apply(&self, state: &mut Counter) { state.value = 0; }
+//!     fn label(&self) -> String { "RESET".to_string() }
+//!     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Counter, Ctx>> {
+//!         Just(CommandWrapper::new(Reset))
+//!     }
+//! }
+//!
+//! let registry = CommandRegistry::new(Arc::new(Ctx::default()))
+//!     .command::<Inc>()
+//!     .fixed(CommandWrapper::new(Reset));
+//!
+//! let trace = vec![CommandWrapper::new(Inc(3)), CommandWrapper::new(Reset)];
+//! let json = serde_json::to_string(&trace).unwrap();
+//! assert_eq!(json, r#"["INC(3)","RESET"]"#);
+//!
+//! let loaded = registry.deserialize(&mut serde_json::Deserializer::from_str(&json)).unwrap();
+//! let labels: Vec<String> = loaded.iter().map(|c| c.command.label()).collect();
+//! assert_eq!(labels, ["INC(3)", "RESET"]);
+//! ```
+
+use crate::{Command, CommandWrapper, State, TestContext};
+pub use serde;
+use serde::de::{DeserializeSeed, Error as _};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::sync::Arc;
+
+/// Rebuilds a command from its label, if it recognizes it.
+type LabelParser<S, C> = Box<dyn Fn(&str, Arc<C>) -> Option<CommandWrapper<S, C>>>;
+
+/// A command serializes as its label.
+impl<S: State, C: TestContext> Serialize for CommandWrapper<S, C> {
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        serializer.serialize_str(&self.command.label())
+    }
+}
+
+/// The commands a serialized sequence may hold, and how to rebuild them
+/// from their labels.
+///
+/// The registry is also a [`DeserializeSeed`], for sequences nested in a
+/// larger document.
+pub struct CommandRegistry<S: State, C: TestContext> {
+    ctx: Arc<C>,
+    parsers: Vec<LabelParser<S, C>>,
+}
+
+impl<S: State + 'static, C: TestContext + 'static> CommandRegistry<S, C> {
+    /// Creates an empty registry for the given test context.
+    pub fn new(ctx: Arc<C>) -> Self {
+        Self {
+            ctx,
+            parsers: Vec::new(),
+        }
+    }
+
+    /// Returns the test context.
+    pub fn context(&self) -> Arc<C> {
+        Arc::clone(&self.ctx)
+    }
+
+    /// Registers a command type, rebuilt with [`Command::parse`].
+    pub fn command<Cmd: Command<S, C> + 'static>(self) -> Self {
+        self.parser(Cmd::parse)
+    }
+
+    /// Registers a command rebuilt from its exact label, without needing a
+    /// [`Command::parse`] implementation.
+    pub fn fixed(self, command: CommandWrapper<S, C>) -> Self {
+        let label = command.command.label();
+        self.parser(move |l, _| (l == label).then(|| command.clone()))
+    }
+
+    /// Registers a parser rebuilding commands from labels.
+    pub fn parser(
+        mut self,
+        parser: impl Fn(&str, Arc<C>) -> Option<CommandWrapper<S, C>> + 'static,
+    ) -> Self {
+        self.parsers.push(Box::new(parser));
+        self
+    }
+
+    /// Rebuilds the command with the given label, trying the parsers in
+    /// the order they were registered.
+    pub fn parse(&self, label: &str) -> Option<CommandWrapper<S, C>> {
+        self.parsers
+            .iter()
+            .find_map(|parse| parse(label, self.context()))
+    }
+
+    /// Deserializes a sequence of labels into commands.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the input is not a sequence of strings, or if no
+    /// registered command parses one of the labels.
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        &self,
+        deserializer: D,
+    ) -> Result<Vec<CommandWrapper<S, C>>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|label| {
+                self.parse(label)
+                    .ok_or_else(|| D::Error::custom(format!("no command parses label `{}`", label)))
+            })
+            .collect()
+    }
+}
+
+impl<'de, S: State + 'static, C: TestContext + 'static> DeserializeSeed<'de>
+    for &CommandRegistry<S, C>
+{
+    type Value = Vec<CommandWrapper<S, C>>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        CommandRegistry::deserialize(self, deserializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::strategy::{Just, Strategy};
+
+    #[derive(Debug, Default)]
+    struct Log(Vec<String>);
+    impl State for Log {}
+
+    #[derive(Debug, Clone, Default)]
+    struct Ctx;
+    impl TestContext for Ctx {}
+
+    struct Say(String);
+    impl Command<Log, Ctx> for Say {
+        fn check(&self, _state: &Log) -> bool {
+            true
+        }
+        fn apply(&self, state: &mut Log) {
+            state.0.push(self.0.clone());
+        }
+        fn label(&self) -> String {
+            format!("SAY({})", self.0)
+        }
+        fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Log, Ctx>> {
+            Just(CommandWrapper::new(Say("hi".to_string())))
+        }
+        fn parse(label: &str, _ctx: Arc<Ctx>) -> Option<CommandWrapper<Log, Ctx>> {
+            let text = label.strip_prefix("SAY(")?.strip_suffix(')')?;
+            Some(CommandWrapper::new(Say(text.to_string())))
+        }
+    }
+
+    #[test]
+    fn test_nested_sequences_round_trip_and_unknown_labels_fail() {
+        let registry = CommandRegistry::new(Arc::new(Ctx)).command::<Say>();
+        let trace = vec![
+            CommandWrapper::new(Say("a, \"b\"".to_string())),
+            CommandWrapper::new(Say("c".to_string())),
+        ];
+        let json = serde_json::json!({ "seed": 7, "commands": trace }).to_string();
+
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let loaded = registry.deserialize(&value["commands"]).unwrap();
+        let labels: Vec<String> = loaded.iter().map(|c| c.command.label()).collect();
+        assert_eq!(labels, ["SAY(a, \"b\")", "SAY(c)"]);
+
+        let error = DeserializeSeed::deserialize(
+            &registry,
+            &mut serde_json::Deserializer::from_str(r#"["SAY(a)", "SHOUT(b)"]"#),
+        )
+        .err()
+        .unwrap();
+        assert!(error
+            .to_string()
+            .contains("no command parses label `SHOUT(b)`"));
+    }
+}
@@ -0,0 +1,286 @@
+//! Failing command sequences persisted for replay.
+//!
+//! When a scenario fails, its shrunk sequence is written to a text file
+//! under `madhouse-regressions/`, one command label per line. Lines starting
+//! with `#` are comments. [`Scenario::replay_from_file`](crate::Scenario::replay_from_file)
+//! (or [`replay!`](crate::replay)) parses the labels back into commands via
+//! [`Command::parse`](crate::Command::parse) and re-runs them verbatim.
+//!
+//! Unlike proptest's regression files, which store seeds, these files stay
+//! valid when strategies change, and can be read and edited by hand. A
+//! `# env seed:` comment keeps the seed of the failing case's
+//! [`Env`](crate::Env), so commands drawing randomness draw the same values
+//! on replay (see [`env_seed`]).
+//! Failures recorded in proptest's files before they were persisted here
+//! can be migrated with [`load_proptest`] (see
+//! [`Scenario::import_proptest_regressions`](crate::Scenario::import_proptest_regressions)).
+//!
+//! ```text
+//! # madhouse regression: tests/counter.rs
+//! # Test failed: command 2 (RESET) failed: counter already zero.
+//! INC(3)
+//! RESET
+//! RESET
+//! ```
+
+use crate::gen::stable_hash;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Default directory regression files are written to.
+pub const DEFAULT_DIR: &str = "madhouse-regressions";
+
+/// Prefix of the message line recording the seed of the case's
+/// [`Env`](crate::Env), e.g. `env seed: 42`.
+pub const ENV_SEED: &str = "env seed: ";
+
+/// Returns the fingerprint of a trace: a stable hash of its labels.
+///
+/// Regression file names and [`quarantine`](crate::quarantine) files
+/// identify traces by it.
+pub fn fingerprint(labels: &[String]) -> u64 {
+    stable_hash(labels)
+}
+
+/// Returns the prefix of the files saved for a scenario: the stem of its
+/// source file, followed by its name, if any, in lowercase with runs of
+/// other characters than letters and digits replaced by `-`.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::regression::stem;
+///
+/// assert_eq!(stem(Some("tests/bank.rs"), None), "bank");
+/// assert_eq!(stem(Some("tests/bank.rs"), Some("Deposit flow")), "bank-deposit-flow");
+/// assert_eq!(stem(None, None), "scenario");
+/// ```
+pub fn stem(source_file: Option<&str>, scenario: Option<&str>) -> String {
+    let mut stem = source_file
+        .and_then(|file| Path::new(file).file_stem())
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("scenario")
+        .to_string();
+    if let Some(scenario) = scenario {
+        let slug = scenario
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .collect::<Vec<_>>()
+            .join("-")
+            .to_lowercase();
+        if !slug.is_empty() {
+            stem.push('-');
+            stem.push_str(&slug);
+        }
+    }
+    stem
+}
+
+/// Writes a failing sequence to `dir` and returns the file path.
+///
+/// The file name combines the [`stem`] of the scenario with the trace's
+/// [`fingerprint`], so the same failure is saved only once.
+///
+/// # Arguments
+/// * `dir` - Directory to write to, created if missing.
+/// * `source_file` - Source file of the failing scenario, if known.
+/// * `scenario` - Name of the failing scenario, if named.
+/// * `labels` - Command labels of the failing sequence, in order.
+/// * `message` - Failure message, stored as a comment.
+pub fn save(
+    dir: &Path,
+    source_file: Option<&str>,
+    scenario: Option<&str>,
+    labels: &[String],
+    message: &str,
+) -> io::Result<PathBuf> {
+    let stem = stem(source_file, scenario);
+    let path = dir.join(format!("{}-{:016x}.txt", stem, fingerprint(labels)));
+
+    let mut contents = format!(
+        "# madhouse regression: {}\n",
+        source_file.unwrap_or("unknown")
+    );
+    if let Some(scenario) = scenario {
+        contents.push_str(&format!("# scenario: {}\n", scenario));
+    }
+    for line in message.lines() {
+        contents.push_str(&format!("# {}\n", line));
+    }
+    for label in labels {
+        contents.push_str(label);
+        contents.push('\n');
+    }
+
+    fs::create_dir_all(dir)?;
+    fs::write(&path, contents)?;
+    Ok(path)
+}
+
+/// Reads the command labels stored in a regression file.
+///
+/// Blank lines and lines starting with `#` are ignored.
+pub fn load(path: impl AsRef<Path>) -> io::Result<Vec<String>> {
+    let contents = fs::read_to_string(path)?;
+    Ok(contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
+
+/// Reads the seed of the case's [`Env`](crate::Env) stored in a regression
+/// file as a `# env seed: N` comment, if any.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::regression::{env_seed, save};
+///
+/// let dir = std::env::temp_dir().join("madhouse-doc-env-seed");
+/// let labels = vec!["PAY(3)".to_string()];
+/// let path = save(&dir, None, None, &labels, "boom\nenv seed: 42").unwrap();
+/// assert_eq!(env_seed(&path).unwrap(), Some(42));
+/// ```
+pub fn env_seed(path: impl AsRef<Path>) -> io::Result<Option<u64>> {
+    let contents = fs::read_to_string(path)?;
+    Ok(contents.lines().find_map(|line| {
+        let comment = line.trim().strip_prefix('#')?.trim();
+        comment.strip_prefix(ENV_SEED)?.trim().parse().ok()
+    }))
+}
+
+/// Reads the failing sequences recorded in a proptest regression file.
+///
+/// Proptest stores each failure as a `cc <seed>` line whose comment shows
+/// the shrunk input, e.g. `cc 1f0c... # shrinks to commands = [INC(3),
+/// RESET]`; the labels are taken from the first list in that comment.
+/// Entries without one are ignored.
+pub fn load_proptest(path: impl AsRef<Path>) -> io::Result<Vec<Vec<String>>> {
+    Ok(parse_proptest(&fs::read_to_string(path)?))
+}
+
+/// Extracts the command labels of every entry in proptest regression file
+/// `contents`. See [`load_proptest`].
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::regression::parse_proptest;
+///
+/// let contents = "\
+/// # Seeds for failure cases proptest has generated in the past.
+/// cc 4b1e2f # shrinks to commands = [INC(3), TRANSFER(1, 2), RESET]
+/// cc 9a0d3c # shrinks to commands = []
+/// ";
+/// assert_eq!(
+///     parse_proptest(contents),
+///     vec![vec!["INC(3)", "TRANSFER(1, 2)", "RESET"]]
+/// );
+/// ```
+pub fn parse_proptest(contents: &str) -> Vec<Vec<String>> {
+    contents
+        .lines()
+        .filter(|line| line.trim_start().starts_with("cc "))
+        .filter_map(|line| line.split_once("# shrinks to "))
+        .filter_map(|(_, input)| first_list(input))
+        .filter(|labels| !labels.is_empty())
+        .collect()
+}
+
+/// Splits the first bracketed list of a `Debug` rendering at its top-level
+/// commas.
+fn first_list(input: &str) -> Option<Vec<String>> {
+    let start = input.find('[')?;
+    let mut labels = Vec::new();
+    let mut label = String::new();
+    let mut depth = 0;
+    let mut quoted = false;
+    let mut chars = input[start + 1..].chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if quoted => {
+                label.push(c);
+                label.extend(chars.next());
+                continue;
+            }
+            '"' => quoted = !quoted,
+            '(' | '[' | '{' if !quoted => depth += 1,
+            ']' if !quoted && depth == 0 => {
+                labels.extend(Some(label.trim().to_string()).filter(|l| !l.is_empty()));
+                return Some(labels);
+            }
+            ')' | ']' | '}' if !quoted => depth -= 1,
+            ',' if !quoted && depth == 0 => {
+                labels.push(label.trim().to_string());
+                label.clear();
+                continue;
+            }
+            _ => {}
+        }
+        label.push(c);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_and_load_round_trip() {
+        let dir = std::env::temp_dir().join(format!("madhouse-regression-{}", std::process::id()));
+        let labels = vec!["INC(3)".to_string(), "RESET".to_string()];
+
+        let path = save(
+            &dir,
+            Some("tests/counter.rs"),
+            None,
+            &labels,
+            "line one\nline two",
+        )
+        .unwrap();
+        let again = save(&dir, Some("tests/counter.rs"), None, &labels, "other").unwrap();
+        let named = save(
+            &dir,
+            Some("tests/counter.rs"),
+            Some("Reset twice"),
+            &labels,
+            "",
+        )
+        .unwrap();
+
+        assert_eq!(path, again);
+        assert!(path
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .starts_with("counter-"));
+        assert!(named
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .starts_with("counter-reset-twice-"));
+        assert_eq!(load(&path).unwrap(), labels);
+        assert_eq!(load(&named).unwrap(), labels);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_proptest_entries_keep_nested_commas_and_skip_unterminated_lists() {
+        let contents = concat!(
+            "cc 01 # shrinks to seed = 7, commands = [SAY(\"a, ]b\"), MAP({1: [2, 3]})]\n",
+            "cc 02 # shrinks to commands = [INC(3)\n",
+            "xx 03 # shrinks to commands = [INC(4)]\n",
+        );
+
+        assert_eq!(
+            parse_proptest(contents),
+            vec![vec![r#"SAY("a, ]b")"#, "MAP({1: [2, 3]})"]]
+        );
+    }
+}
@@ -0,0 +1,971 @@
+//! Execution reports returned by [`execute_commands`](crate::execute_commands).
+
+use crate::config::Format;
+use crate::diff::StateDiff;
+use crate::effects::Expectation;
+use crate::process::ProcessOutput;
+use crate::{CommandError, InvariantViolation, SkipReason};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Debug, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Returns the command name for a label: the label up to its first `(`.
+///
+/// For a label like `INCREMENT(3)` this is `INCREMENT`, so commands can be
+/// grouped regardless of their parameters.
+pub fn command_name(label: &str) -> &str {
+    label.split_once('(').map_or(label, |(name, _)| name)
+}
+
+/// Returns the default group of a command type: its module path.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::report::module_group;
+///
+/// assert_eq!(module_group("my_tests::ledger::Transfer"), "my_tests::ledger");
+/// assert_eq!(module_group("my_tests::Wrap<my_tests::ledger::Transfer>"), "my_tests");
+/// assert_eq!(module_group("Transfer"), "");
+/// ```
+pub fn module_group(type_name: &str) -> &str {
+    let path = type_name
+        .split_once('<')
+        .map_or(type_name, |(path, _)| path);
+    path.rsplit_once("::").map_or("", |(module, _)| module)
+}
+
+/// What happened to a single selected command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StepOutcome {
+    /// `check()` rejected the command, so it was never applied (see
+    /// [`Command::check_detailed`](crate::Command::check_detailed)).
+    Skipped(SkipReason),
+    /// The command was applied successfully.
+    Executed,
+    /// The command was applied but reported an error.
+    Failed(CommandError),
+}
+
+/// Record of a single selected command.
+#[derive(Debug, Clone)]
+pub struct StepReport {
+    /// Position of the command in the selected sequence (0-based).
+    pub index: usize,
+    /// Label of the command.
+    pub label: String,
+    /// Group of the command (see [`Command::group`](crate::Command::group)).
+    pub group: String,
+    /// Whether the command was skipped, executed, or failed.
+    pub outcome: StepOutcome,
+    /// Time spent applying the command (zero when skipped).
+    pub duration: Duration,
+    /// Time spent in [`Command::check`](crate::Command::check), which
+    /// runs whether or not the command is then applied.
+    pub check_duration: Duration,
+    /// Wall-clock time the step began, before `check`, for correlating it
+    /// with logs and metrics of the system under test (see [`rfc3339`]).
+    pub started: SystemTime,
+    /// Wall-clock time the step ended.
+    pub finished: SystemTime,
+}
+
+impl StepReport {
+    /// Returns true if the command was applied, successfully or not.
+    pub fn was_applied(&self) -> bool {
+        !matches!(self.outcome, StepOutcome::Skipped(_))
+    }
+
+    /// Returns the command name. See [`command_name`].
+    pub fn name(&self) -> &str {
+        command_name(&self.label)
+    }
+
+    /// Returns true if `key` is this step's full label or its name.
+    pub fn matches(&self, key: &str) -> bool {
+        self.label == key || self.name() == key
+    }
+
+    /// Returns the error reported by the command, if any.
+    pub fn error(&self) -> Option<&CommandError> {
+        match &self.outcome {
+            StepOutcome::Failed(err) => Some(err),
+            _ => None,
+        }
+    }
+
+    /// Returns why the command was skipped, if it was.
+    pub fn skip_reason(&self) -> Option<&SkipReason> {
+        match &self.outcome {
+            StepOutcome::Skipped(reason) => Some(reason),
+            _ => None,
+        }
+    }
+}
+
+/// Counts and durations of the steps of one command group or name.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GroupSummary {
+    /// Steps selected.
+    pub selected: usize,
+    /// Steps applied, including failures.
+    pub executed: usize,
+    /// Steps whose command reported an error.
+    pub failed: usize,
+    /// Total time spent applying the commands.
+    pub duration: Duration,
+    /// Total time spent checking the commands' preconditions.
+    pub check_duration: Duration,
+}
+
+/// The first [`State::invariants`](crate::State::invariants) violation
+/// observed during execution.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BrokenInvariant {
+    /// Index of the command (or of the command preceding the heartbeat)
+    /// after which the invariant was first violated.
+    pub index: usize,
+    /// Label of the command that broke the invariant.
+    pub label: String,
+    /// The violation returned by the state.
+    pub violation: InvariantViolation,
+}
+
+/// How the failing step changed the state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateChange {
+    /// Index of the command (or of the command preceding the heartbeat)
+    /// that failed.
+    pub index: usize,
+    /// Label of the command that failed.
+    pub label: String,
+    /// Diff between the state before and after the command.
+    pub diff: StateDiff,
+}
+
+/// A command applied without error that left the state unchanged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NoOp {
+    /// Index of the command.
+    pub index: usize,
+    /// Label of the command.
+    pub label: String,
+}
+
+/// Outcome of executing a sequence of commands.
+///
+/// Contains one [`StepReport`] per selected command, in selection order,
+/// plus one per injected [`Heartbeat`](crate::Heartbeat).
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::{Command, CommandError, CommandWrapper, State, TestContext, execute_commands};
+/// use proptest::prelude::*;
+/// use std::sync::Arc;
+///
+/// #[derive(Debug, Default)]
+/// struct Node { up: bool }
+/// impl State for Node {}
+///
+/// #[derive(Debug, Clone, Default)]
+/// struct Ctx {}
+/// impl TestContext for Ctx {}
+///
+/// struct Ping;
+/// impl Command<Node, Ctx> for Ping {
+///     fn check(&self, _state: &Node) -> bool { true }
+///     fn apply(&self, _state: &mut Node) {}
+///     fn try_apply(&self, state: &mut Node) -> Result<(), CommandError> {
+///         if state.up { Ok(()) } else { Err(CommandError::new("node is down")) }
+///     }
+///     fn label(&self) -> String { "PING".to_string() }
+///     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Node, Ctx>> {
+///         Just(CommandWrapper::new(Ping))
+///     }
+/// }
+///
+/// let mut state = Node::default();
+/// let report = execute_commands(&[CommandWrapper::new(Ping)], &mut state);
+///
+/// assert!(!report.is_ok());
+/// assert_eq!(report.executed().count(), 1);
+/// assert_eq!(report.failures().next().unwrap().1.message(), "node is down");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ExecutionReport {
+    /// Per-command records, in selection order.
+    pub steps: Vec<StepReport>,
+    /// Injected heartbeat records, in firing order. Each `index` is that of
+    /// the selected command the heartbeat fired after.
+    pub heartbeats: Vec<StepReport>,
+    /// The invariant violation that stopped execution, if any.
+    pub broken_invariant: Option<BrokenInvariant>,
+    /// Number of earliest steps dropped from `steps` to bound memory (see
+    /// [`Config::trace_limit`](crate::Config::trace_limit)).
+    pub dropped_steps: usize,
+    /// Number of earliest heartbeats dropped from `heartbeats`.
+    pub dropped_heartbeats: usize,
+    /// Whether execution stopped early on a
+    /// [`CancellationToken`](crate::cancel::CancellationToken); the steps
+    /// are then those run before the stop.
+    pub cancelled: bool,
+    /// How the state changed in the step that broke an invariant or,
+    /// failing that, in the first step that reported an error (see
+    /// [`Config::state_diff`](crate::Config::state_diff)).
+    pub state_change: Option<StateChange>,
+    /// Effects declared with [`effects::expect`](crate::effects::expect)
+    /// and never confirmed, in declaration order.
+    pub unconfirmed: Vec<Expectation>,
+    /// Commands whose `apply()` left the state's
+    /// [`fingerprint`](crate::State::fingerprint) unchanged, in execution
+    /// order, if detection is on (see
+    /// [`Config::no_ops`](crate::Config::no_ops)).
+    pub no_ops: Vec<NoOp>,
+    /// Labels recorded with [`stats::classify`](crate::stats::classify) and
+    /// [`stats::collect`](crate::stats::collect) during execution.
+    pub labels: BTreeSet<String>,
+    /// Output of the processes spawned with [`process`](crate::process)
+    /// that ended during execution, in the order they ended.
+    pub processes: Vec<ProcessOutput>,
+    /// [`Projections`](crate::projection::Projections) of the state
+    /// execution ended in, if a scenario registered any.
+    #[cfg(feature = "json")]
+    pub projections: Option<serde_json::Value>,
+}
+
+impl ExecutionReport {
+    /// Returns the steps whose command was applied (including failures).
+    pub fn executed(&self) -> impl Iterator<Item = &StepReport> {
+        self.steps.iter().filter(|step| step.was_applied())
+    }
+
+    /// Returns the steps whose command was rejected by `check()`.
+    pub fn skipped(&self) -> impl Iterator<Item = &StepReport> {
+        self.steps.iter().filter(|step| !step.was_applied())
+    }
+
+    /// Returns the steps and heartbeats whose command reported an error,
+    /// with the error.
+    pub fn failures(&self) -> impl Iterator<Item = (&StepReport, &CommandError)> {
+        self.steps
+            .iter()
+            .chain(&self.heartbeats)
+            .filter_map(|step| step.error().map(|err| (step, err)))
+    }
+
+    /// Counts applied steps whose label or name is `key`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use madhouse::{ExecutionReport, SkipReason, StepOutcome, StepReport};
+    /// use std::time::Duration;
+    ///
+    /// let step = |label: &str, outcome| StepReport {
+    ///     index: 0,
+    ///     label: label.to_string(),
+    ///     group: String::new(),
+    ///     outcome,
+    ///     duration: Duration::ZERO,
+    ///     check_duration: Duration::ZERO,
+    ///     started: std::time::UNIX_EPOCH,
+    ///     finished: std::time::UNIX_EPOCH,
+    /// };
+    /// let report = ExecutionReport {
+    ///     steps: vec![
+    ///         step("SUBMIT_BLOCK_COMMIT(1)", StepOutcome::Executed),
+    ///         step("SUBMIT_BLOCK_COMMIT(2)", StepOutcome::Executed),
+    ///         step("SORTITION", StepOutcome::Executed),
+    ///         step("SORTITION", StepOutcome::Skipped(SkipReason::default())),
+    ///     ],
+    ///     ..Default::default()
+    /// };
+    ///
+    /// assert_eq!(report.count("SUBMIT_BLOCK_COMMIT"), 2);
+    /// assert_eq!(report.count("SUBMIT_BLOCK_COMMIT(2)"), 1);
+    /// assert!(report.count("SORTITION") <= report.count("SUBMIT_BLOCK_COMMIT"));
+    /// assert_eq!(report.count_skipped("SORTITION"), 1);
+    /// ```
+    pub fn count(&self, key: &str) -> usize {
+        self.count_where(|step| step.was_applied() && step.matches(key))
+    }
+
+    /// Counts skipped steps whose label or name is `key`.
+    pub fn count_skipped(&self, key: &str) -> usize {
+        self.count_where(|step| !step.was_applied() && step.matches(key))
+    }
+
+    /// Counts steps satisfying an arbitrary predicate.
+    pub fn count_where(&self, predicate: impl Fn(&StepReport) -> bool) -> usize {
+        self.steps.iter().filter(|step| predicate(step)).count()
+    }
+
+    /// Aggregates the steps by command group, sorted by group.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use madhouse::{ExecutionReport, SkipReason, StepOutcome, StepReport};
+    /// use std::time::Duration;
+    ///
+    /// let step = |group: &str, millis| StepReport {
+    ///     index: 0,
+    ///     label: "CMD".to_string(),
+    ///     group: group.to_string(),
+    ///     outcome: StepOutcome::Executed,
+    ///     duration: Duration::from_millis(millis),
+    ///     check_duration: Duration::ZERO,
+    ///     started: std::time::UNIX_EPOCH,
+    ///     finished: std::time::UNIX_EPOCH,
+    /// };
+    /// let report = ExecutionReport {
+    ///     steps: vec![step("ledger", 2), step("network", 1), step("ledger", 3)],
+    ///     ..Default::default()
+    /// };
+    ///
+    /// let groups = report.groups();
+    /// assert_eq!(groups["ledger"].executed, 2);
+    /// assert_eq!(groups["ledger"].duration, Duration::from_millis(5));
+    /// assert_eq!(groups.keys().copied().collect::<Vec<_>>(), vec!["ledger", "network"]);
+    /// ```
+    pub fn groups(&self) -> BTreeMap<&str, GroupSummary> {
+        self.summarize_by(|step| &step.group)
+    }
+
+    /// Aggregates the steps by command name (see [`command_name`]), sorted
+    /// by name.
+    ///
+    /// Comparing `check_duration` with `duration` shows which commands
+    /// spend more time deciding whether to run than running, as happens
+    /// with preconditions that query the system under test.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use madhouse::{ExecutionReport, SkipReason, StepOutcome, StepReport};
+    /// use std::time::Duration;
+    ///
+    /// let step = |label: &str, outcome, check_millis| StepReport {
+    ///     index: 0,
+    ///     label: label.to_string(),
+    ///     group: String::new(),
+    ///     outcome,
+    ///     duration: Duration::ZERO,
+    ///     check_duration: Duration::from_millis(check_millis),
+    ///     started: std::time::UNIX_EPOCH,
+    ///     finished: std::time::UNIX_EPOCH,
+    /// };
+    /// let report = ExecutionReport {
+    ///     steps: vec![
+    ///         step("STACK_STX(1)", StepOutcome::Executed, 40),
+    ///         step("STACK_STX(2)", StepOutcome::Skipped(SkipReason::default()), 30),
+    ///         step("MINE", StepOutcome::Executed, 0),
+    ///     ],
+    ///     ..Default::default()
+    /// };
+    ///
+    /// let commands = report.commands();
+    /// assert_eq!(commands["STACK_STX"].selected, 2);
+    /// assert_eq!(commands["STACK_STX"].executed, 1);
+    /// assert_eq!(commands["STACK_STX"].check_duration, Duration::from_millis(70));
+    /// assert_eq!(report.check_duration(), Duration::from_millis(70));
+    /// ```
+    pub fn commands(&self) -> BTreeMap<&str, GroupSummary> {
+        self.summarize_by(StepReport::name)
+    }
+
+    /// Total time spent checking preconditions, skipped steps included.
+    pub fn check_duration(&self) -> Duration {
+        self.steps.iter().map(|step| step.check_duration).sum()
+    }
+
+    /// Total time spent applying commands.
+    pub fn apply_duration(&self) -> Duration {
+        self.steps.iter().map(|step| step.duration).sum()
+    }
+
+    /// Wall-clock time from the start of the first recorded step to the
+    /// end of the last one, heartbeats included; unlike
+    /// [`apply_duration`](Self::apply_duration) and
+    /// [`check_duration`](Self::check_duration), this covers invariant
+    /// checks and other overhead between steps.
+    pub fn duration(&self) -> Duration {
+        let steps = || self.steps.iter().chain(&self.heartbeats);
+        let (Some(started), Some(finished)) = (
+            steps().map(|step| step.started).min(),
+            steps().map(|step| step.finished).max(),
+        ) else {
+            return Duration::ZERO;
+        };
+        finished.duration_since(started).unwrap_or_default()
+    }
+
+    fn summarize_by<'a>(
+        &'a self,
+        key: impl Fn(&'a StepReport) -> &'a str,
+    ) -> BTreeMap<&'a str, GroupSummary> {
+        let mut summaries = BTreeMap::<&str, GroupSummary>::new();
+        for step in &self.steps {
+            let summary = summaries.entry(key(step)).or_default();
+            summary.selected += 1;
+            if step.was_applied() {
+                summary.executed += 1;
+            }
+            if step.error().is_some() {
+                summary.failed += 1;
+            }
+            summary.duration += step.duration;
+            summary.check_duration += step.check_duration;
+        }
+        summaries
+    }
+
+    /// Returns true if no applied command reported an error, no invariant
+    /// was violated and every expected effect was confirmed.
+    pub fn is_ok(&self) -> bool {
+        self.failure_message().is_none()
+    }
+
+    /// Describes the first problem in the report, if any: a broken
+    /// invariant, or else the first command error, or else the first
+    /// unconfirmed effect.
+    pub fn failure_message(&self) -> Option<String> {
+        if let Some(broken) = &self.broken_invariant {
+            return Some(format!(
+                "invariant violated after command {} ({}): {}",
+                broken.index + 1,
+                broken.label,
+                broken.violation
+            ));
+        }
+        if let Some((step, err)) = self.failures().next() {
+            return Some(format!(
+                "command {} ({}) failed: {}",
+                step.index + 1,
+                step.label,
+                err
+            ));
+        }
+        self.unconfirmed
+            .first()
+            .map(|expectation| format!("expected effect never confirmed: {}", expectation))
+    }
+
+    /// Renders the report as a single-line JSON document.
+    ///
+    /// Lists the selected and executed labels, every step with its outcome,
+    /// skip reason or error and apply and check durations in nanoseconds,
+    /// heartbeats, the number of steps and heartbeats dropped from the
+    /// report, whether it was cancelled, the broken invariant and the state
+    /// change of the failing step (or `null`), the no-op commands, the
+    /// unconfirmed effects, the projections of the final state if any (with the `json` feature) and,
+    /// if given, the final state's `Debug` output (or `null`).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use madhouse::{ExecutionReport, SkipReason, StepOutcome, StepReport};
+    /// use std::time::Duration;
+    ///
+    /// let report = ExecutionReport {
+    ///     steps: vec![StepReport {
+    ///         index: 0,
+    ///         label: "INC(1)".to_string(),
+    ///         group: String::new(),
+    ///         outcome: StepOutcome::Skipped(SkipReason::new("limit reached")),
+    ///         duration: Duration::ZERO,
+    ///         check_duration: Duration::ZERO,
+    ///         started: std::time::UNIX_EPOCH,
+    ///         finished: std::time::UNIX_EPOCH,
+    ///     }],
+    ///     ..Default::default()
+    /// };
+    ///
+    /// let json = report.to_json(Some(&42));
+    /// assert!(json.starts_with(r#"{"selected":["INC(1)"],"executed":[],"steps":["#));
+    /// assert!(json.contains(r#""outcome":"skipped","reason":"limit reached""#));
+    /// assert!(json.ends_with(r#""unconfirmed":[],"final_state":"42"}"#));
+    /// ```
+    pub fn to_json(&self, final_state: Option<&dyn Debug>) -> String {
+        let labels = |steps: &mut dyn Iterator<Item = &StepReport>| {
+            let quoted: Vec<String> = steps.map(|step| json_string(&step.label)).collect();
+            format!("[{}]", quoted.join(","))
+        };
+        let records = |steps: &[StepReport]| {
+            let records: Vec<String> = steps.iter().map(step_json).collect();
+            format!("[{}]", records.join(","))
+        };
+
+        let mut out = String::from("{");
+        let _ = write!(
+            out,
+            r#""selected":{},"executed":{},"steps":{},"heartbeats":{},"#,
+            labels(&mut self.steps.iter()),
+            labels(&mut self.executed()),
+            records(&self.steps),
+            records(&self.heartbeats)
+        );
+        let _ = write!(
+            out,
+            r#""dropped_steps":{},"dropped_heartbeats":{},"cancelled":{},"#,
+            self.dropped_steps, self.dropped_heartbeats, self.cancelled
+        );
+        match &self.broken_invariant {
+            Some(broken) => {
+                let _ = write!(
+                    out,
+                    r#""broken_invariant":{{"index":{},"label":{},"violation":{}}},"#,
+                    broken.index,
+                    json_string(&broken.label),
+                    json_string(broken.violation.message())
+                );
+            }
+            None => out.push_str(r#""broken_invariant":null,"#),
+        }
+        match &self.state_change {
+            Some(change) => {
+                let _ = write!(
+                    out,
+                    r#""state_change":{{"index":{},"label":{},"diff":{}}},"#,
+                    change.index,
+                    json_string(&change.label),
+                    json_string(&change.diff.to_string())
+                );
+            }
+            None => out.push_str(r#""state_change":null,"#),
+        }
+        let no_ops: Vec<String> = self
+            .no_ops
+            .iter()
+            .map(|no_op| {
+                format!(
+                    r#"{{"index":{},"label":{}}}"#,
+                    no_op.index,
+                    json_string(&no_op.label)
+                )
+            })
+            .collect();
+        let _ = write!(out, r#""no_ops":[{}],"#, no_ops.join(","));
+        let unconfirmed: Vec<String> = self
+            .unconfirmed
+            .iter()
+            .map(|expectation| {
+                format!(
+                    r#"{{"key":{},"index":{},"label":{}}}"#,
+                    json_string(&expectation.key),
+                    expectation.index,
+                    json_string(&expectation.label)
+                )
+            })
+            .collect();
+        let _ = write!(out, r#""unconfirmed":[{}],"#, unconfirmed.join(","));
+        #[cfg(feature = "json")]
+        if let Some(projections) = &self.projections {
+            let _ = write!(out, r#""projections":{},"#, projections);
+        }
+        match final_state {
+            Some(state) => {
+                let _ = write!(
+                    out,
+                    r#""final_state":{}"#,
+                    json_string(&format!("{:?}", state))
+                );
+            }
+            None => out.push_str(r#""final_state":null"#),
+        }
+        out.push('}');
+        out
+    }
+
+    /// Prints the report in the given format.
+    pub(crate) fn print(&self, format: Format, final_state: &dyn Debug) {
+        match format {
+            Format::Text => self.print_summary(),
+            Format::Json => println!("{}", self.to_json(Some(final_state))),
+        }
+    }
+
+    /// Prints the selected and executed commands to stdout.
+    pub(crate) fn print_summary(&self) {
+        // ANSI color codes.
+        let yellow = "\x1b[33m";
+        let reset = "\x1b[0m";
+
+        println!("Selected:");
+        if self.dropped_steps > 0 {
+            println!("... {} earlier steps dropped", self.dropped_steps);
+        }
+        for step in &self.steps {
+            println!("{:02}. {}{}{}", step.index + 1, yellow, step.label, reset);
+        }
+
+        println!("Executed:");
+        for (i, step) in self.executed().enumerate() {
+            print_applied(i, step);
+        }
+
+        if self.skipped().next().is_some() {
+            println!("Skipped:");
+            for step in self.skipped() {
+                if let Some(reason) = step.skip_reason() {
+                    println!("{:02}. {}: {}", step.index + 1, step.label, reason);
+                }
+            }
+        }
+
+        if !self.heartbeats.is_empty() {
+            println!("Heartbeats:");
+            for (i, step) in self
+                .heartbeats
+                .iter()
+                .filter(|s| s.was_applied())
+                .enumerate()
+            {
+                print_applied(i, step);
+            }
+        }
+
+        if self.cancelled {
+            println!(
+                "Cancelled after {} selected commands",
+                self.dropped_steps + self.steps.len()
+            );
+        }
+
+        if let Some(change) = &self.state_change {
+            println!(
+                "State change in step {} ({}):",
+                change.index + 1,
+                change.label
+            );
+            print!("{}", change.diff);
+        }
+
+        if !self.no_ops.is_empty() {
+            println!("Left the state unchanged:");
+            for no_op in &self.no_ops {
+                println!("{:02}. {}", no_op.index + 1, no_op.label);
+            }
+        }
+
+        if !self.unconfirmed.is_empty() {
+            println!("Unconfirmed effects:");
+            for expectation in &self.unconfirmed {
+                println!("- {}", expectation);
+            }
+        }
+
+        if !self.processes.is_empty() {
+            println!("Processes:");
+            for output in &self.processes {
+                println!("- {}", output);
+                for (stream, text) in [("stdout", &output.stdout), ("stderr", &output.stderr)] {
+                    for line in text.lines() {
+                        println!("  {}: {}", stream, line);
+                    }
+                }
+            }
+        }
+
+        #[cfg(feature = "json")]
+        if let Some(projections) = &self.projections {
+            println!("Projections: {}", projections);
+        }
+
+        let groups = self.groups();
+        if groups.len() > 1 {
+            println!("Groups:");
+            for (name, group) in groups {
+                println!(
+                    "{}: {} selected, {} executed, {} failed ({:.2?}, check {:.2?})",
+                    name,
+                    group.selected,
+                    group.executed,
+                    group.failed,
+                    group.duration,
+                    group.check_duration
+                );
+            }
+        }
+
+        if !self.steps.is_empty() {
+            println!(
+                "Time: {:.2?} total, {:.2?} in apply(), {:.2?} in check()",
+                self.duration(),
+                self.apply_duration(),
+                self.check_duration()
+            );
+            let slowest = self
+                .commands()
+                .into_iter()
+                .max_by_key(|(_, command)| command.check_duration);
+            if let Some((name, command)) =
+                slowest.filter(|(_, command)| command.check_duration > command.duration)
+            {
+                println!(
+                    "Slowest check(): {} ({:.2?} over {} selected, {:.2?} in apply())",
+                    name, command.check_duration, command.selected, command.duration
+                );
+            }
+        }
+    }
+}
+
+/// Renders one step as a JSON object.
+pub(crate) fn step_json(step: &StepReport) -> String {
+    let detail = match &step.outcome {
+        StepOutcome::Skipped(reason) => {
+            format!(
+                r#""outcome":"skipped","reason":{}"#,
+                json_string(reason.message())
+            )
+        }
+        StepOutcome::Executed => r#""outcome":"executed""#.to_string(),
+        StepOutcome::Failed(err) => {
+            format!(
+                r#""outcome":"failed","error":{}"#,
+                json_string(err.message())
+            )
+        }
+    };
+    format!(
+        r#"{{"index":{},"label":{},"group":{},{},"duration_ns":{},"check_ns":{},"started":"{}","finished":"{}"}}"#,
+        step.index,
+        json_string(&step.label),
+        json_string(&step.group),
+        detail,
+        step.duration.as_nanos(),
+        step.check_duration.as_nanos(),
+        rfc3339(step.started),
+        rfc3339(step.finished)
+    )
+}
+
+/// Formats a wall-clock time as an RFC 3339 UTC timestamp with
+/// microseconds.
+///
+/// Times before the Unix epoch are clamped to it.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::report::rfc3339;
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// let time = UNIX_EPOCH + Duration::from_micros(1_700_000_000_123_456);
+/// assert_eq!(rfc3339(time), "2023-11-14T22:13:20.123456Z");
+/// ```
+pub fn rfc3339(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, secs_of_day) = (secs / 86_400, secs % 86_400);
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm).
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        since_epoch.subsec_micros()
+    )
+}
+
+/// Quotes and escapes a string for JSON.
+pub(crate) fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Prints one applied step, green on success and red on failure.
+fn print_applied(i: usize, step: &StepReport) {
+    // ANSI color codes.
+    let green = "\x1b[32m";
+    let red = "\x1b[31m";
+    let reset = "\x1b[0m";
+
+    match step.error() {
+        None => println!(
+            "{:02}. {}{}{} ({:.2?})",
+            i + 1,
+            green,
+            step.label,
+            reset,
+            step.duration
+        ),
+        Some(err) => println!(
+            "{:02}. {}{}{} ({:.2?}) failed: {}",
+            i + 1,
+            red,
+            step.label,
+            reset,
+            step.duration,
+            err
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(index: usize, outcome: StepOutcome) -> StepReport {
+        StepReport {
+            index,
+            label: format!("CMD{}", index),
+            group: String::new(),
+            outcome,
+            duration: Duration::ZERO,
+            check_duration: Duration::ZERO,
+            started: std::time::UNIX_EPOCH,
+            finished: std::time::UNIX_EPOCH,
+        }
+    }
+
+    #[test]
+    fn test_report_partitions_steps() {
+        let report = ExecutionReport {
+            steps: vec![
+                step(0, StepOutcome::Executed),
+                step(1, StepOutcome::Skipped(SkipReason::default())),
+                step(2, StepOutcome::Failed(CommandError::new("boom"))),
+            ],
+            heartbeats: vec![step(3, StepOutcome::Failed(CommandError::new("late")))],
+            ..Default::default()
+        };
+
+        let executed: Vec<_> = report.executed().map(|s| s.index).collect();
+        let skipped: Vec<_> = report.skipped().map(|s| s.index).collect();
+        let failed: Vec<_> = report
+            .failures()
+            .map(|(s, e)| (s.index, e.message()))
+            .collect();
+
+        assert_eq!(executed, vec![0, 2]);
+        assert_eq!(skipped, vec![1]);
+        assert_eq!(failed, vec![(2, "boom"), (3, "late")]);
+        assert!(!report.is_ok());
+    }
+
+    #[test]
+    fn test_count_by_name_and_label() {
+        let mut report = ExecutionReport {
+            steps: vec![
+                step(0, StepOutcome::Executed),
+                step(1, StepOutcome::Skipped(SkipReason::default())),
+                step(2, StepOutcome::Failed(CommandError::new("boom"))),
+            ],
+            ..Default::default()
+        };
+        report.steps[0].label = "INC(1)".to_string();
+        report.steps[1].label = "INC(2)".to_string();
+        report.steps[2].label = "INC(3)".to_string();
+
+        assert_eq!(report.steps[0].name(), "INC");
+        assert_eq!(report.count("INC"), 2);
+        assert_eq!(report.count("INC(3)"), 1);
+        assert_eq!(report.count("IN"), 0);
+        assert_eq!(report.count_skipped("INC"), 1);
+        assert_eq!(report.count_where(|s| s.duration.is_zero()), 3);
+    }
+
+    #[test]
+    fn test_broken_invariant_takes_precedence() {
+        let report = ExecutionReport {
+            steps: vec![step(0, StepOutcome::Failed(CommandError::new("boom")))],
+            broken_invariant: Some(BrokenInvariant {
+                index: 0,
+                label: "CMD0".to_string(),
+                violation: InvariantViolation::new("negative balance"),
+            }),
+            ..Default::default()
+        };
+
+        assert!(!report.is_ok());
+        assert_eq!(
+            report.failure_message().unwrap(),
+            "invariant violated after command 1 (CMD0): negative balance"
+        );
+    }
+
+    #[test]
+    fn test_json_escapes_and_reports_outcomes() {
+        let mut report = ExecutionReport {
+            steps: vec![
+                step(0, StepOutcome::Executed),
+                step(1, StepOutcome::Failed(CommandError::new("said \"no\"\n"))),
+            ],
+            ..Default::default()
+        };
+        report.steps[0].duration = Duration::from_micros(3);
+        report.steps[1].check_duration = Duration::from_micros(2);
+        report.steps[1].finished = UNIX_EPOCH + Duration::from_micros(86_400_000_002);
+
+        assert_eq!(
+            report.to_json(None),
+            concat!(
+                r#"{"selected":["CMD0","CMD1"],"executed":["CMD0","CMD1"],"steps":["#,
+                r#"{"index":0,"label":"CMD0","group":"","outcome":"executed","duration_ns":3000,"check_ns":0,"#,
+                r#""started":"1970-01-01T00:00:00.000000Z","finished":"1970-01-01T00:00:00.000000Z"},"#,
+                r#"{"index":1,"label":"CMD1","group":"","outcome":"failed","error":"said \"no\"\n","duration_ns":0,"check_ns":2000,"#,
+                r#""started":"1970-01-01T00:00:00.000000Z","finished":"1970-01-02T00:00:00.000002Z"}"#,
+                r#"],"heartbeats":[],"dropped_steps":0,"dropped_heartbeats":0,"cancelled":false,"#,
+                r#""broken_invariant":null,"state_change":null,"no_ops":[],"unconfirmed":[],"#,
+                r#""final_state":null}"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_empty_report_is_ok() {
+        assert!(ExecutionReport::default().is_ok());
+    }
+
+    #[test]
+    fn test_duration_spans_steps_and_heartbeats() {
+        let at = |millis| UNIX_EPOCH + Duration::from_millis(millis);
+        let mut report = ExecutionReport {
+            steps: vec![
+                step(0, StepOutcome::Executed),
+                step(1, StepOutcome::Skipped(SkipReason::default())),
+            ],
+            heartbeats: vec![step(1, StepOutcome::Executed)],
+            ..Default::default()
+        };
+        report.steps[0].started = at(10);
+        report.steps[0].finished = at(12);
+        report.steps[1].started = at(15);
+        report.steps[1].finished = at(16);
+        report.heartbeats[0].started = at(16);
+        report.heartbeats[0].finished = at(25);
+
+        assert_eq!(report.duration(), Duration::from_millis(15));
+        assert_eq!(ExecutionReport::default().duration(), Duration::ZERO);
+    }
+}
@@ -0,0 +1,174 @@
+//! Estimated state-space coverage of a scenario run.
+//!
+//! Every applied command leads to a state, identified by its
+//! [`State::fingerprint`]. [`Saturation`] counts how many new fingerprints
+//! each window of applied commands discovers. While random testing is
+//! productive, windows keep finding new states; once they stop, further
+//! cases mostly revisit known states and the campaign can end (see
+//! [`Config::saturation_threshold`](crate::Config::saturation_threshold)).
+//! Scenarios only record it if asked to (see
+//! [`Config::saturation`](crate::Config::saturation)).
+
+use crate::observer::Observer;
+use crate::{State, StepReport, TestContext};
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+/// Applied commands per window.
+pub const WINDOW: usize = 1000;
+
+/// Windows shown by `Display`, the latest ones.
+const SHOWN_WINDOWS: usize = 20;
+
+/// Distinct state fingerprints reached, and how fast they were discovered.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::saturation::Saturation;
+///
+/// let mut saturation = Saturation::new(4);
+/// for fingerprint in [1, 2, 3, 1, 2, 4, 1, 2, 1, 2] {
+///     saturation.record(fingerprint);
+/// }
+///
+/// assert_eq!(saturation.distinct(), 4);
+/// assert_eq!(saturation.commands(), 10);
+/// // Two full windows: 3 new states, then 1.
+/// assert_eq!(saturation.windows(), &[3, 1]);
+/// assert!(saturation.is_saturated(1));
+/// assert!(!saturation.is_saturated(0));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Saturation {
+    window: usize,
+    seen: HashSet<u64>,
+    commands: usize,
+    windows: Vec<usize>,
+    current: usize,
+}
+
+impl Default for Saturation {
+    fn default() -> Self {
+        Self::new(WINDOW)
+    }
+}
+
+impl Saturation {
+    /// Creates an empty tracker counting new states per `window` commands.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `window` is 0.
+    pub fn new(window: usize) -> Self {
+        assert!(window > 0, "saturation window must be positive");
+        Self {
+            window,
+            seen: HashSet::new(),
+            commands: 0,
+            windows: Vec::new(),
+            current: 0,
+        }
+    }
+
+    /// Records the fingerprint of the state an applied command led to.
+    pub fn record(&mut self, fingerprint: u64) {
+        if self.seen.insert(fingerprint) {
+            self.current += 1;
+        }
+        self.commands += 1;
+        if self.commands.is_multiple_of(self.window) {
+            self.windows.push(self.current);
+            self.current = 0;
+        }
+    }
+
+    /// Returns the number of distinct states reached.
+    pub fn distinct(&self) -> usize {
+        self.seen.len()
+    }
+
+    /// Returns the number of applied commands recorded.
+    pub fn commands(&self) -> usize {
+        self.commands
+    }
+
+    /// Returns the number of new states found by each full window, in
+    /// order.
+    pub fn windows(&self) -> &[usize] {
+        &self.windows
+    }
+
+    /// Returns true if the latest full window found at most `threshold`
+    /// new states.
+    pub fn is_saturated(&self, threshold: usize) -> bool {
+        self.windows.last().is_some_and(|&new| new <= threshold)
+    }
+}
+
+impl<S: State, C: TestContext> Observer<S, C> for Saturation {
+    fn on_after_apply(&mut self, step: &StepReport, state: &S) {
+        let _ = step;
+        self.record(state.fingerprint());
+    }
+}
+
+impl Display for Saturation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        writeln!(
+            f,
+            "State-space saturation: {} distinct states over {} commands",
+            self.distinct(),
+            self.commands
+        )?;
+        if self.windows.is_empty() {
+            return Ok(());
+        }
+        let skipped = self.windows.len().saturating_sub(SHOWN_WINDOWS);
+        let shown: Vec<String> = self.windows[skipped..]
+            .iter()
+            .map(usize::to_string)
+            .collect();
+        writeln!(
+            f,
+            "  new states per {} commands: {}{}",
+            self.window,
+            if skipped > 0 { "..., " } else { "" },
+            shown.join(", ")
+        )?;
+        if self.is_saturated(0) {
+            writeln!(
+                f,
+                "  no new states in the last {} commands; more cases are unlikely to help",
+                self.window
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_shows_latest_windows_and_saturation() {
+        let mut saturation = Saturation::new(2);
+        for fingerprint in 0..(SHOWN_WINDOWS as u64 + 2) {
+            saturation.record(fingerprint);
+            saturation.record(fingerprint);
+        }
+        saturation.record(0);
+        saturation.record(1);
+
+        let rendered = saturation.to_string();
+        assert!(
+            rendered.starts_with("State-space saturation: 22 distinct states over 46 commands\n")
+        );
+        assert!(rendered.contains(&format!(
+            "new states per 2 commands: ..., {}0\n",
+            "1, ".repeat(SHOWN_WINDOWS - 1)
+        )));
+        assert!(rendered.ends_with("more cases are unlikely to help\n"));
+    }
+}
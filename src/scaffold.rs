@@ -0,0 +1,246 @@
+//! Command skeletons for `cargo madhouse new-command`.
+//!
+//! Large models maintained by many people drift into as many ways of laying
+//! out a command. [`CommandSkeleton`] renders the file every new command
+//! starts from: one struct, and a [`Command`](crate::Command) implementation
+//! wired to the model's state and context with `check`, `apply`, `label`
+//! and `build` stubs. [`register`] adds the new module to the `mod.rs` of
+//! the commands directory. The `cargo-madhouse` binary does both:
+//!
+//! ```text
+//! cargo install madhouse
+//! cargo madhouse new-command SubmitBlock --state crate::model::Chain \
+//!     --context crate::model::Ctx --dir tests/commands
+//! ```
+
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+/// Source of a new command file.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::scaffold::CommandSkeleton;
+///
+/// let skeleton = CommandSkeleton::new("SubmitBlock", "crate::model::Chain", "Ctx");
+///
+/// assert_eq!(skeleton.module(), "submit_block");
+/// let source = skeleton.to_string();
+/// assert!(source.contains("use crate::model::Chain;\nuse crate::Ctx;\n"));
+/// assert!(source.contains("impl Command<Chain, Ctx> for SubmitBlock {"));
+/// assert!(source.contains("\"SUBMIT_BLOCK\".to_string()"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandSkeleton {
+    name: String,
+    state: String,
+    context: String,
+}
+
+impl CommandSkeleton {
+    /// Creates the skeleton of command type `name` for the given state and
+    /// context types.
+    ///
+    /// Types are paths (`crate::model::Chain`); a bare name is taken from
+    /// the crate root.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` is not a Rust identifier.
+    pub fn new(
+        name: impl Into<String>,
+        state: impl Into<String>,
+        context: impl Into<String>,
+    ) -> Self {
+        let name = name.into();
+        assert!(
+            is_identifier(&name),
+            "command name must be a Rust identifier, got {:?}",
+            name
+        );
+        Self {
+            name,
+            state: state.into(),
+            context: context.into(),
+        }
+    }
+
+    /// Returns the module name of the command, in snake case.
+    pub fn module(&self) -> String {
+        words(&self.name).join("_")
+    }
+
+    /// Returns the label of the command, in screaming snake case.
+    pub fn label(&self) -> String {
+        words(&self.name).join("_").to_uppercase()
+    }
+}
+
+impl Display for CommandSkeleton {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let state = last_segment(&self.state);
+        let context = last_segment(&self.context);
+        let label = self.label();
+        writeln!(f, "//! The `{}` command.", label)?;
+        writeln!(f)?;
+        writeln!(f, "use {};", qualified(&self.state))?;
+        if context != state {
+            writeln!(f, "use {};", qualified(&self.context))?;
+        }
+        writeln!(f, "use madhouse::{{Command, CommandWrapper}};")?;
+        writeln!(f, "use proptest::prelude::{{Just, Strategy}};")?;
+        writeln!(f, "use std::sync::Arc;")?;
+        writeln!(f)?;
+        writeln!(f, "/// TODO: describe what `{}` does to the system.", label)?;
+        writeln!(f, "#[derive(Debug, Clone)]")?;
+        writeln!(f, "pub struct {};", self.name)?;
+        writeln!(f)?;
+        writeln!(
+            f,
+            "impl Command<{}, {}> for {} {{",
+            state, context, self.name
+        )?;
+        writeln!(f, "    fn check(&self, _state: &{}) -> bool {{", state)?;
+        writeln!(
+            f,
+            "        todo!(\"return whether {} can run in this state\")",
+            label
+        )?;
+        writeln!(f, "    }}")?;
+        writeln!(f)?;
+        writeln!(f, "    fn apply(&self, _state: &mut {}) {{", state)?;
+        writeln!(
+            f,
+            "        todo!(\"run {} against the system and update the model\")",
+            label
+        )?;
+        writeln!(f, "    }}")?;
+        writeln!(f)?;
+        writeln!(f, "    fn label(&self) -> String {{")?;
+        writeln!(f, "        \"{}\".to_string()", label)?;
+        writeln!(f, "    }}")?;
+        writeln!(f)?;
+        writeln!(
+            f,
+            "    fn build(_ctx: Arc<{}>) -> impl Strategy<Value = CommandWrapper<{}, {}>> {{",
+            context, state, context
+        )?;
+        writeln!(f, "        Just(CommandWrapper::new({}))", self.name)?;
+        writeln!(f, "    }}")?;
+        writeln!(f, "}}")
+    }
+}
+
+/// Adds `pub mod <module>;` to the source of a `mod.rs`, after its last
+/// module declaration, or at the end if it has none.
+///
+/// Returns `None` if the module is already declared.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::scaffold::register;
+///
+/// let source = "//! Commands.\n\npub mod mine;\npub mod stack;\n\nuse x;\n";
+/// assert_eq!(
+///     register(source, "submit_block").unwrap(),
+///     "//! Commands.\n\npub mod mine;\npub mod stack;\npub mod submit_block;\n\nuse x;\n"
+/// );
+/// assert_eq!(register("pub mod mine;\n", "mine"), None);
+/// ```
+pub fn register(source: &str, module: &str) -> Option<String> {
+    let declaration = format!("pub mod {};", module);
+    if source.lines().any(|line| declared(line) == Some(module)) {
+        return None;
+    }
+    let mut lines: Vec<&str> = source.lines().collect();
+    let at = lines
+        .iter()
+        .rposition(|line| declared(line).is_some())
+        .map_or(lines.len(), |i| i + 1);
+    lines.insert(at, &declaration);
+    Some(lines.join("\n") + "\n")
+}
+
+/// Returns the module declared by `line`, if any.
+fn declared(line: &str) -> Option<&str> {
+    let line = line.trim();
+    line.strip_prefix("pub ")
+        .unwrap_or(line)
+        .strip_prefix("mod ")
+        .map(|rest| rest.trim_end_matches(';').trim())
+}
+
+/// Splits a camel-case identifier into lowercase words.
+fn words(name: &str) -> Vec<String> {
+    let chars: Vec<char> = name.chars().collect();
+    let mut words: Vec<String> = Vec::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if c == '_' {
+            words.push(String::new());
+            continue;
+        }
+        // A new word starts at an uppercase letter after a lowercase letter
+        // or digit, or before a lowercase letter in an acronym (`HTTPServer`).
+        let boundary = c.is_uppercase()
+            && i > 0
+            && (chars[i - 1].is_lowercase()
+                || chars[i - 1].is_ascii_digit()
+                || (chars[i - 1].is_uppercase()
+                    && chars.get(i + 1).is_some_and(|n| n.is_lowercase())));
+        if boundary || words.is_empty() {
+            words.push(String::new());
+        }
+        words.last_mut().unwrap().extend(c.to_lowercase());
+    }
+    words.retain(|word| !word.is_empty());
+    words
+}
+
+/// Returns true if `name` can name a command type.
+pub fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+fn last_segment(path: &str) -> &str {
+    path.rsplit("::").next().unwrap_or(path)
+}
+
+fn qualified(path: &str) -> String {
+    if path.contains("::") {
+        path.to_string()
+    } else {
+        format!("crate::{}", path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names_follow_the_label_convention() {
+        let name = |name: &str| CommandSkeleton::new(name, "S", "C").label();
+
+        assert_eq!(name("Inc"), "INC");
+        assert_eq!(name("SubmitBlockCommit"), "SUBMIT_BLOCK_COMMIT");
+        assert_eq!(name("HTTPRequest"), "HTTP_REQUEST");
+        assert_eq!(name("Mine2Blocks"), "MINE2_BLOCKS");
+        assert_eq!(name("stack_stx"), "STACK_STX");
+        assert_eq!(
+            CommandSkeleton::new("HTTPRequest", "S", "C").module(),
+            "http_request"
+        );
+
+        let skeleton = CommandSkeleton::new("Tick", "crate::Clock", "crate::Clock").to_string();
+        assert_eq!(skeleton.matches("use crate::Clock;").count(), 1);
+        assert!(skeleton.contains(
+            "fn build(_ctx: Arc<Clock>) -> impl Strategy<Value = CommandWrapper<Clock, Clock>> {"
+        ));
+
+        assert_eq!(register("", "tick").unwrap(), "pub mod tick;\n");
+        assert_eq!(register("mod tick;\n", "tick"), None);
+    }
+}
@@ -0,0 +1,2084 @@
+//! Runtime behind the [`scenario!`](crate::scenario) macro.
+
+#[cfg(feature = "artifacts")]
+use crate::artifacts::{self, FailureArtifacts};
+use crate::cancel::CancellationToken;
+use crate::config::{Config, Format, Mode, Shard, Verbosity};
+use crate::constraints::Constraints;
+use crate::corpus::{self, Corpus, Mutation};
+use crate::coverage::{self, Coverage};
+use crate::diff::TraceDiff;
+use crate::differential::Lockstep;
+use crate::env;
+use crate::fixture;
+use crate::gen;
+use crate::golden::{GoldenTrace, Recorder};
+use crate::graph::StateGraph;
+use crate::guidance::{Feedback, Fingerprint, Guidance, StateAbstraction};
+use crate::html::{self, FailedCase, Histogram, ScenarioReport};
+use crate::junit::{JUnitCase, JUnitSuite};
+#[cfg(feature = "loom")]
+use crate::loom;
+use crate::markov::Transitions;
+use crate::mermaid::{SequenceDiagram, StateNotes};
+use crate::notify::{CampaignSummary, FailureNotice, Notifier, Webhook};
+use crate::observer::Observer;
+use crate::panics;
+use crate::predicates::TracePredicate;
+#[cfg(feature = "json")]
+use crate::projection::Projections;
+use crate::quarantine::Quarantine;
+use crate::regression;
+use crate::report::command_name;
+use crate::saturation::Saturation;
+use crate::sequence::{Enumeration, Sequence};
+use crate::shrink::Structural;
+use crate::stats::Statistics;
+use crate::summary::{self, Outcome};
+use crate::sut::{Comparison, Harness, SystemUnderTest};
+use crate::testgen::RegressionTest;
+use crate::timings;
+use crate::trace::Trace;
+use crate::{
+    run_commands, run_commands_traced, Command, CommandWrapper, ExecutionReport, Heartbeat, State,
+    StepReport, TestContext,
+};
+use proptest::strategy::{BoxedStrategy, Just, Strategy, Union, ValueTree};
+use proptest::test_runner::{
+    contextualize_config, Config as ProptestConfig, TestCaseError, TestError, TestRunner,
+};
+use std::cell::{Cell, RefCell};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Boxed strategy producing a single command.
+pub type CommandStrategy<S, C> = BoxedStrategy<CommandWrapper<S, C>>;
+
+type ReportAssertion = Box<dyn Fn(&ExecutionReport)>;
+
+type Setup = Box<dyn Fn()>;
+
+type Teardown<S> = Box<dyn Fn(&mut S)>;
+
+#[cfg(feature = "artifacts")]
+type FailureHook<C> = Box<dyn Fn(&C, &FailureArtifacts) -> std::io::Result<()>>;
+
+type BoxedNotifier = Box<dyn Notifier>;
+
+type BoxedHarness<S, C> = RefCell<Box<dyn Harness<S, C>>>;
+
+type LabelParser<S, C> = Box<dyn Fn(&str, Arc<C>) -> Option<CommandWrapper<S, C>>>;
+
+type SetEntry<S, C> = dyn Fn(Scenario<S, C>) -> Scenario<S, C>;
+
+/// A set of command strategies plus the options used to run them.
+///
+/// This is what [`scenario!`](crate::scenario) builds and runs. Options
+/// passed to the macro as `key = value` call the method of the same name.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::{Command, CommandWrapper, Scenario, State, TestContext};
+/// use proptest::prelude::*;
+/// use std::sync::Arc;
+///
+/// #[derive(Debug, Default)]
+/// struct Counter { value: u64 }
+/// impl State for Counter {}
+///
+/// #[derive(Debug, Clone, Default)]
+/// struct Ctx {}
+/// impl TestContext for Ctx {}
+///
+/// struct Inc;
+/// impl Command<Counter, Ctx> for Inc {
+///     fn check(&self, _state: &Counter) -> bool { true }
+///     fn apply(&self, state: &mut Counter) { state.value += 1; }
+///     fn label(&self) -> String { "INC".to_string() }
+///     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Counter, Ctx>> {
+///         Just(CommandWrapper::new(Inc))
+///     }
+/// }
+///
+/// let ctx = Arc::new(Ctx::default());
+/// Scenario::new(ctx.clone())
+///     .strategy(Inc::build(ctx.clone()))
+///     .strategy(Inc::build(ctx))
+///     .assert_report(|report| assert_eq!(report.count("INC"), 2))
+///     .run();
+/// ```
+pub struct Scenario<S: State, C: TestContext> {
+    ctx: Arc<C>,
+    strategies: Vec<(u32, CommandStrategy<S, C>)>,
+    /// Index of the first strategy of each declared phase, and its length
+    /// range if random.
+    phases: Vec<(usize, Option<Range<usize>>)>,
+    transitions: Transitions,
+    constraints: Constraints,
+    abstraction: Option<Box<dyn StateAbstraction<S>>>,
+    parsers: Vec<LabelParser<S, C>>,
+    assertions: Vec<ReportAssertion>,
+    setups: Vec<Setup>,
+    teardowns: Vec<Teardown<S>>,
+    heartbeats: Vec<Heartbeat<S, C>>,
+    sut: Option<BoxedHarness<S, C>>,
+    config: Config,
+    corpus: Vec<Vec<String>>,
+    corpus_probability: f64,
+    regressions: Option<PathBuf>,
+    notifiers: Vec<BoxedNotifier>,
+    #[cfg(feature = "artifacts")]
+    failure_hooks: Vec<FailureHook<C>>,
+    #[cfg(feature = "artifacts")]
+    artifacts_dir: PathBuf,
+    #[cfg(feature = "json")]
+    projections: Projections<S>,
+    #[cfg(feature = "loom")]
+    loom: bool,
+    source_file: Option<&'static str>,
+    name: Option<String>,
+    cancel: CancellationToken,
+}
+
+/// What the generated runs of a scenario explored; shrinking runs are not
+/// counted.
+#[derive(Default)]
+struct Exploration {
+    coverage: Coverage,
+    statistics: Statistics,
+    /// Recorded only if saturation is reported or bounded.
+    saturation: Option<Saturation>,
+    /// Recorded only if a graph file is configured.
+    graph: Option<StateGraph>,
+    /// Recorded only if an HTML report is configured.
+    timings: Option<Histogram>,
+    /// Rows of the latest case, recorded only if a timings CSV file is
+    /// configured.
+    timings_csv: Option<String>,
+    /// Index of the case being generated.
+    case: u32,
+    /// Persisted corpus, if a corpus directory is configured.
+    corpus: Option<Corpus>,
+    /// Fingerprints of the states the latest run reached, recorded only if
+    /// a corpus directory is configured.
+    states: Vec<u64>,
+    /// Scores of the commands, recorded only if generation is guided.
+    guidance: Option<Rc<RefCell<Guidance>>>,
+    /// Whether the latest run skipped a command.
+    skipped: bool,
+}
+
+impl<S: State, C: TestContext> Observer<S, C> for Exploration {
+    fn on_before_apply(&mut self, index: usize, command: &CommandWrapper<S, C>, state: &S) {
+        Observer::<S, C>::on_before_apply(&mut self.graph, index, command, state);
+    }
+
+    fn on_skipped(&mut self, _step: &StepReport) {
+        self.skipped = true;
+    }
+
+    fn on_after_apply(&mut self, step: &StepReport, state: &S) {
+        if self.saturation.is_some() || self.corpus.is_some() {
+            let fingerprint = state.fingerprint();
+            if let Some(saturation) = &mut self.saturation {
+                saturation.record(fingerprint);
+            }
+            if self.corpus.is_some() {
+                self.states.push(fingerprint);
+            }
+        }
+        Observer::<S, C>::on_after_apply(&mut self.graph, step, state);
+    }
+}
+
+/// A distinct failing case, after shrinking.
+struct Failure {
+    case: u32,
+    message: String,
+    labels: Vec<String>,
+}
+
+impl<S: State + Default + 'static, C: TestContext + 'static> Scenario<S, C> {
+    /// Creates an empty scenario for the given test context.
+    pub fn new(ctx: Arc<C>) -> Self {
+        Self {
+            ctx,
+            strategies: Vec::new(),
+            phases: Vec::new(),
+            transitions: Transitions::new(),
+            constraints: Constraints::new(),
+            abstraction: None,
+            parsers: Vec::new(),
+            assertions: Vec::new(),
+            setups: Vec::new(),
+            teardowns: Vec::new(),
+            heartbeats: Vec::new(),
+            sut: None,
+            config: Config::default(),
+            corpus: Vec::new(),
+            corpus_probability: 0.0,
+            regressions: Some(PathBuf::from(regression::DEFAULT_DIR)),
+            notifiers: Vec::new(),
+            #[cfg(feature = "artifacts")]
+            failure_hooks: Vec::new(),
+            #[cfg(feature = "artifacts")]
+            artifacts_dir: PathBuf::from(artifacts::DEFAULT_DIR),
+            #[cfg(feature = "json")]
+            projections: Projections::new(),
+            #[cfg(feature = "loom")]
+            loom: false,
+            source_file: None,
+            name: None,
+            cancel: CancellationToken::new(),
+        }
+    }
+
+    /// Returns the test context.
+    pub fn context(&self) -> Arc<C> {
+        Arc::clone(&self.ctx)
+    }
+
+    /// Adds the commands of type `T`, built from the scenario's context,
+    /// weighted by [`Command::weight`](crate::Command::weight) and replayed
+    /// with [`Command::parse`](crate::Command::parse).
+    ///
+    /// The builder equivalent of listing `T` in
+    /// [`scenario!`](crate::scenario), for scenarios assembled at runtime,
+    /// e.g. from a configuration file.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use madhouse::{Command, CommandWrapper, Scenario, State, TestContext};
+    /// use proptest::prelude::*;
+    /// use std::sync::Arc;
+    ///
+    /// #[derive(Debug, Default)]
+    /// struct Counter { value: u64 }
+    /// impl State for Counter {}
+    ///
+    /// #[derive(Debug, Clone, Default)]
+    /// struct Ctx {}
+    /// impl TestContext for Ctx {}
+    ///
+    /// struct Inc { amount: u64 }
+    /// impl Command<Counter, Ctx> for Inc {
+    ///     fn check(&self, _state: &Counter) -> bool { true }
+    ///     fn apply(&self, state: &mut Counter) { state.value += self.amount; }
+    ///     fn label(&self) -> String { format!("INC({})", self.amount) }
+    ///     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Counter, Ctx>> {
+    ///         (1..5u64).prop_map(|amount| CommandWrapper::new(Inc { amount }))
+    ///     }
+    /// }
+    ///
+    /// struct Reset;
+    /// impl Command<Counter, Ctx> for Reset {
+    ///     fn check(&self, state: &Counter) -> bool { state.value > 0 }
+    ///     fn apply(&self, state: &mut Counter) { state.value = 0; }
+    ///     fn label(&self) -> String { "RESET".to_string() }
+    ///     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Counter, Ctx>> {
+    ///         Just(CommandWrapper::new(Reset))
+    ///     }
+    /// }
+    ///
+    /// Scenario::new(Arc::new(Ctx::default()))
+    ///     .add::<Inc>()
+    ///     .add_instance(Reset)
+    ///     .cases(20)
+    ///     .assert_report(|report| assert_eq!(report.count("RESET"), 1))
+    ///     .run();
+    /// ```
+    pub fn add<T: Command<S, C> + 'static>(self) -> Self {
+        let weight = T::weight(&self.ctx);
+        self.add_weighted::<T>(weight)
+    }
+
+    /// Adds the commands of type `T` with the given weight (see
+    /// [`weighted`](Self::weighted)).
+    pub fn add_weighted<T: Command<S, C> + 'static>(self, weight: u32) -> Self {
+        let strategy = T::build(self.context());
+        self.weighted(weight, strategy).parser(T::parse)
+    }
+
+    /// Adds a command that is always generated as-is, like a parenthesized
+    /// command in [`scenario!`](crate::scenario), weighted by
+    /// [`Command::weight`](crate::Command::weight) (see
+    /// [`fixed`](Self::fixed)).
+    pub fn add_instance<T: Command<S, C> + 'static>(self, command: T) -> Self {
+        let weight = T::weight(&self.ctx);
+        self.fixed(weight, CommandWrapper::new(command))
+    }
+
+    /// Adds a command strategy with weight 1.
+    ///
+    /// In normal mode each strategy contributes one command per case, in the
+    /// order added. In random mode commands are drawn from all strategies.
+    pub fn strategy<T>(self, strategy: T) -> Self
+    where
+        T: Strategy<Value = CommandWrapper<S, C>> + 'static,
+    {
+        self.weighted(1, strategy)
+    }
+
+    /// Adds a command strategy with the given weight.
+    ///
+    /// In random mode a strategy with weight 5 is picked five times as often
+    /// as one with weight 1. Weights are ignored in normal mode.
+    pub fn weighted<T>(mut self, weight: u32, strategy: T) -> Self
+    where
+        T: Strategy<Value = CommandWrapper<S, C>> + 'static,
+    {
+        let strategy = Structural::new(strategy.boxed()).boxed();
+        self.strategies.push((weight, strategy));
+        self
+    }
+
+    /// Starts a phase in which each strategy added next contributes one
+    /// command, in order, whatever the mode.
+    ///
+    /// Sequences consist of the commands of the strategies added before any
+    /// phase, generated according to the mode, followed by those of each
+    /// phase in turn. [`scenario!`](crate::scenario) starts one with
+    /// `#[phase]` before a command.
+    pub fn ordered_phase(mut self) -> Self {
+        self.phases.push((self.strategies.len(), None));
+        self
+    }
+
+    /// Starts a phase of `len` commands drawn by weight from the strategies
+    /// added next, whatever the mode (see
+    /// [`ordered_phase`](Self::ordered_phase)).
+    /// [`scenario!`](crate::scenario) starts one with `#[phase(5..40)]`
+    /// before a command.
+    pub fn random_phase(mut self, len: Range<usize>) -> Self {
+        self.phases.push((self.strategies.len(), Some(len)));
+        self
+    }
+
+    /// Makes random mode pick each command depending on the previous one,
+    /// scaling weights by `transitions` (see [`markov`](crate::markov)).
+    pub fn transitions(mut self, transitions: Transitions) -> Self {
+        self.transitions = transitions;
+        self
+    }
+
+    /// Makes random mode favor the commands that led to new states in
+    /// earlier cases, telling states apart by `abstraction` (see
+    /// [`guidance`](crate::guidance)).
+    ///
+    /// `MADHOUSE_GUIDED=1` turns guidance on with
+    /// [`Fingerprint`](crate::guidance::Fingerprint) as the abstraction.
+    pub fn guided(mut self, abstraction: impl StateAbstraction<S> + 'static) -> Self {
+        self.abstraction = Some(Box::new(abstraction));
+        self.config.guided = true;
+        self
+    }
+
+    /// Makes generated sequences, and the sequences shrinking tries, follow
+    /// `constraints` (see [`constraints`](crate::constraints)).
+    pub fn constraints(mut self, constraints: Constraints) -> Self {
+        self.constraints = constraints;
+        self
+    }
+
+    /// Adds a command that is always generated as-is, with weight 1 (see
+    /// [`fixed`](Self::fixed)).
+    pub fn command(self, command: CommandWrapper<S, C>) -> Self {
+        self.fixed(1, command)
+    }
+
+    /// Adds the commands of a reusable set, built from this scenario's
+    /// context, as if listed here.
+    ///
+    /// [`scenario!`](crate::scenario) includes one with `include set`.
+    pub fn include(self, commands: &CommandSet<S, C>) -> Self {
+        commands
+            .entries
+            .iter()
+            .fold(self, |scenario, add| add(scenario))
+    }
+
+    /// Adds a command that is always generated as-is, with the given weight.
+    ///
+    /// The command also replays from its exact label, without needing a
+    /// [`Command::parse`](crate::Command::parse) implementation.
+    pub fn fixed(self, weight: u32, command: CommandWrapper<S, C>) -> Self {
+        let label = command.command.label();
+        let replayed = command.clone();
+        self.weighted(weight, Just(command))
+            .parser(move |l, _| (l == label).then(|| replayed.clone()))
+    }
+
+    /// Adds a parser used to rebuild commands from labels when replaying.
+    ///
+    /// [`scenario!`](crate::scenario) registers
+    /// [`Command::parse`](crate::Command::parse) for every command type.
+    pub fn parser(
+        mut self,
+        parser: impl Fn(&str, Arc<C>) -> Option<CommandWrapper<S, C>> + 'static,
+    ) -> Self {
+        self.parsers.push(Box::new(parser));
+        self
+    }
+
+    /// Adds an assertion evaluated against the report at the end of every
+    /// case. Assertions fail the case by panicking.
+    ///
+    /// Useful for properties over the executed trace as a whole, e.g. that
+    /// one command never ran more often than another, without keeping
+    /// mirror counters in the state.
+    pub fn assert_report(mut self, assertion: impl Fn(&ExecutionReport) + 'static) -> Self {
+        self.assertions.push(Box::new(assertion));
+        self
+    }
+
+    /// Adds a predicate the executed labels must satisfy at the end of
+    /// every case (see [`predicates`](crate::predicates)).
+    ///
+    /// A case whose trace does not match fails and is shrunk like any
+    /// other failure.
+    pub fn assert_trace(self, predicate: TracePredicate) -> Self {
+        self.assert_report(move |report| {
+            let labels: Vec<&str> = report.executed().map(|step| step.label.as_str()).collect();
+            assert!(
+                predicate.matches(&labels),
+                "trace does not satisfy `{}`; executed: {:?}",
+                predicate,
+                labels
+            );
+        })
+    }
+
+    /// Adds a hook run before every case, before its state is created.
+    ///
+    /// Use it to bring up what the commands talk to, rather than a first
+    /// command that random mode may shuffle to the middle of a sequence.
+    pub fn setup(mut self, setup: impl Fn() + 'static) -> Self {
+        self.setups.push(Box::new(setup));
+        self
+    }
+
+    /// Adds a hook run on the final state after every case, even if it
+    /// failed or panicked.
+    ///
+    /// Teardown hooks run in reverse order of registration, after the
+    /// commands' [`Command::cleanup`](crate::Command::cleanup).
+    pub fn teardown(mut self, teardown: impl Fn(&mut S) + 'static) -> Self {
+        self.teardowns.push(Box::new(teardown));
+        self
+    }
+
+    /// Adds a heartbeat command injected at a fixed interval in every case.
+    ///
+    /// Heartbeats are not part of the generated sequence, so they are never
+    /// shrunk away. See [`Heartbeat`].
+    pub fn heartbeat(mut self, heartbeat: Heartbeat<S, C>) -> Self {
+        self.heartbeats.push(heartbeat);
+        self
+    }
+
+    /// Applies every command the model applied to `sut` as well, comparing
+    /// the two after each one (see [`sut`](crate::sut)).
+    ///
+    /// The system is reset before every case, shrinking runs included.
+    pub fn system_under_test(mut self, sut: impl SystemUnderTest<S, C> + 'static) -> Self {
+        self.sut = Some(RefCell::new(Box::new(Comparison::new(sut))));
+        self
+    }
+
+    /// Applies every command the model applied to a reference model as
+    /// well, built by `reference`, failing the case at the first step after
+    /// which `eq` tells them apart (see [`differential`](crate::differential)).
+    ///
+    /// The reference is rebuilt before every case, shrinking runs included.
+    /// It takes the place of any [`system_under_test`](Self::system_under_test).
+    pub fn differential(
+        mut self,
+        reference: impl Fn() -> S + 'static,
+        eq: impl Fn(&S, &S) -> bool + 'static,
+    ) -> Self {
+        self.sut = Some(RefCell::new(Box::new(Lockstep::resetting(reference, eq))));
+        self
+    }
+
+    /// Replaces the whole run configuration.
+    ///
+    /// Environment variables still override it when the scenario runs; see
+    /// [`Config::with_env`].
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Sets the generation mode (default [`Mode::Normal`]).
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.config.mode = mode;
+        self
+    }
+
+    /// Sets the number of cases to run (default 1).
+    pub fn cases(mut self, cases: u32) -> Self {
+        self.config.cases = cases;
+        self
+    }
+
+    /// Keeps running cases until `budget` has passed, instead of a fixed
+    /// number of them.
+    ///
+    /// Useful when commands take a variable time: the run uses the CI time
+    /// it is given, whatever the speed of the system under test.
+    pub fn time_budget(mut self, budget: Duration) -> Self {
+        self.config.time_budget = Some(budget);
+        self
+    }
+
+    /// Sets the range of sequence lengths in random mode (default `1..16`).
+    pub fn seq_len(mut self, seq_len: Range<usize>) -> Self {
+        self.config.seq_len = seq_len;
+        self
+    }
+
+    /// Sets the shrink iterations per failing case (default 0).
+    pub fn max_shrink_iters(mut self, iters: u32) -> Self {
+        self.config.max_shrink_iters = iters;
+        self
+    }
+
+    /// Sets how many distinct failing cases to collect before stopping
+    /// (default 1). Zero means run every case regardless of failures.
+    ///
+    /// Failures are distinct when their shrunk command traces differ.
+    pub fn max_failures(mut self, max_failures: usize) -> Self {
+        self.config.max_failures = max_failures;
+        self
+    }
+
+    /// Sets the seed cases are generated from (random by default).
+    ///
+    /// The seed in use is printed at the start of every run and included in
+    /// failure messages, so a failing run, including a random-mode one, can
+    /// be reproduced exactly.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.config.seed = Some(seed);
+        self
+    }
+
+    /// Runs only the cases with the given indices (0-based).
+    ///
+    /// Every case is still generated from the seed, so case 17 is the same
+    /// sequence as in a full run, but only the listed ones are executed.
+    pub fn only_cases(mut self, cases: impl IntoIterator<Item = u32>) -> Self {
+        self.config.only_cases = Some(cases.into_iter().collect());
+        self
+    }
+
+    /// Runs only shard `index` (1-based) of `count` of the cases (see
+    /// [`Shard`]).
+    ///
+    /// # Panics
+    ///
+    /// Panics unless `1 <= index <= count`.
+    pub fn shard(mut self, index: u32, count: u32) -> Self {
+        self.config.shard = Some(Shard::new(index, count));
+        self
+    }
+
+    /// Sets how much the run prints (default [`Verbosity::Normal`]).
+    pub fn verbosity(mut self, verbosity: Verbosity) -> Self {
+        self.config.verbosity = verbosity;
+        self
+    }
+
+    /// Writes a JUnit XML report of every run into `dir` (see
+    /// [`junit`](crate::junit)).
+    pub fn junit_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.config.junit_dir = Some(dir.into());
+        self
+    }
+
+    /// Adds every run to an HTML report of the test binary in `dir` (see
+    /// [`html`](crate::html)).
+    pub fn html_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.config.html_dir = Some(dir.into());
+        self
+    }
+
+    /// Appends the duration of every command applied by a generated case
+    /// to a CSV file (see [`timings`](crate::timings)).
+    pub fn timings_csv(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.timings_csv = Some(path.into());
+        self
+    }
+
+    /// Appends the skip rate of every command `check()` rejected to a CSV
+    /// file at the end of the run (see [`Skips`](crate::coverage::Skips)).
+    pub fn skips_csv(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.skips_csv = Some(path.into());
+        self
+    }
+
+    /// Saves generated cases reaching new states or command sequences to
+    /// `dir`, and replays mutations of its entries in random modes (see
+    /// [`corpus`](crate::corpus)).
+    ///
+    /// Only traces whose labels all parse back into commands are saved.
+    pub fn persist_corpus(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.config.corpus_dir = Some(dir.into());
+        self
+    }
+
+    /// Fails the run if fewer than `ratio` of the selected commands pass
+    /// their preconditions, listing the most-skipped labels.
+    pub fn min_executed_ratio(mut self, ratio: f64) -> Self {
+        self.config.min_executed_ratio = Some(ratio);
+        self
+    }
+
+    /// Rejects and regenerates generated cases in which fewer than
+    /// `required` commands pass their preconditions. Such cases do not count
+    /// toward [`cases`](Self::cases); the run aborts if too many are rejected.
+    pub fn require_executed(mut self, required: usize) -> Self {
+        self.config.require_executed = required;
+        self
+    }
+
+    /// Skips or ignores the failures listed in a quarantine file (see
+    /// [`quarantine`](crate::quarantine)).
+    pub fn quarantine(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.quarantine = Some(path.into());
+        self
+    }
+
+    /// Mixes mutated recorded traces into generation.
+    ///
+    /// Each case replays one of `traces`, with a random [`Mutation`]
+    /// applied, with the given probability, and generates a fresh sequence
+    /// otherwise. Labels are turned back into commands like in
+    /// [`replay_from_file`](Self::replay_from_file); commands inserted by
+    /// mutations are drawn from the scenario's strategies.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `probability` is not between 0 and 1.
+    pub fn corpus(
+        mut self,
+        probability: f64,
+        traces: impl IntoIterator<Item = Vec<String>>,
+    ) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&probability),
+            "corpus probability must be between 0 and 1"
+        );
+        self.corpus_probability = probability;
+        self.corpus.extend(traces);
+        self
+    }
+
+    /// Like [`corpus`](Self::corpus), loading every trace file in `dir`
+    /// (e.g. `madhouse-regressions`), in file name order.
+    ///
+    /// A missing directory yields an empty corpus.
+    pub fn corpus_dir(self, probability: f64, dir: impl AsRef<Path>) -> Self {
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(dir.as_ref())
+            .map(|entries| entries.filter_map(|e| e.ok().map(|e| e.path())).collect())
+            .unwrap_or_default();
+        paths.sort();
+        let traces: Vec<Vec<String>> = paths
+            .iter()
+            .filter_map(|path| regression::load(path).ok())
+            .collect();
+        self.corpus(probability, traces)
+    }
+
+    /// Sets the directory failing sequences are saved to (default
+    /// `madhouse-regressions`).
+    pub fn regressions_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.regressions = Some(dir.into());
+        self
+    }
+
+    /// Enables or disables saving failing sequences (enabled by default).
+    pub fn persist_regressions(mut self, persist: bool) -> Self {
+        if !persist {
+            self.regressions = None;
+        } else if self.regressions.is_none() {
+            self.regressions = Some(PathBuf::from(regression::DEFAULT_DIR));
+        }
+        self
+    }
+
+    /// Adds a notifier told about the run's first failure and its end.
+    ///
+    /// A [`Webhook`] for `MADHOUSE_WEBHOOK`, if set, is added after the
+    /// registered notifiers.
+    pub fn notifier(mut self, notifier: impl Notifier + 'static) -> Self {
+        self.notifiers.push(Box::new(notifier));
+        self
+    }
+
+    /// Adds a hook run right after a case first fails, before shrinking,
+    /// to capture diagnostics from the system under test.
+    ///
+    /// The hook receives the test context and a [`FailureArtifacts`] whose
+    /// directory is unique to the failing trace. Hook errors are reported
+    /// on stderr and otherwise ignored.
+    #[cfg(feature = "artifacts")]
+    pub fn on_failure(
+        mut self,
+        hook: impl Fn(&C, &FailureArtifacts) -> std::io::Result<()> + 'static,
+    ) -> Self {
+        self.failure_hooks.push(Box::new(hook));
+        self
+    }
+
+    /// Sets the directory failure artifacts are written under (default
+    /// `madhouse-artifacts`).
+    #[cfg(feature = "artifacts")]
+    pub fn artifacts_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.artifacts_dir = dir.into();
+        self
+    }
+
+    /// Runs every case once per interleaving of the threads its commands
+    /// spawn, exploring them with loom (see [`loom`](crate::loom)).
+    ///
+    /// The state is created afresh for each interleaving; a failing one
+    /// fails the case, which is then shrunk, each shrinking run being
+    /// explored as well.
+    #[cfg(feature = "loom")]
+    pub fn loom(mut self, enabled: bool) -> Self {
+        self.loom = enabled;
+        self
+    }
+
+    /// Adds named projections of the state, evaluated on the state each
+    /// case ends in and reported instead of its full `Debug` output (see
+    /// [`projection`](crate::projection)).
+    #[cfg(feature = "json")]
+    pub fn projections(mut self, projections: Projections<S>) -> Self {
+        self.projections.extend(projections);
+        self
+    }
+
+    /// Stops the run gracefully once `token` is cancelled (see
+    /// [`cancel`](crate::cancel)).
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancel = token;
+        self
+    }
+
+    /// Returns a handle stopping the run gracefully once cancelled, from
+    /// any thread.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancel.clone()
+    }
+
+    /// Names the scenario.
+    ///
+    /// The name prefixes the run's output and failure message, titles its
+    /// reports and notifications, and is part of the file names of its
+    /// regressions and artifacts. Each named scenario prints its outcome to
+    /// stderr when its run ends, and is listed in the table
+    /// [`summary::print`](crate::summary::print) prints.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Sets the source file reported alongside failures.
+    #[doc(hidden)]
+    pub fn source_file(mut self, file: &'static str) -> Self {
+        self.source_file = Some(file);
+        self
+    }
+
+    /// Runs the scenario, panicking with the minimal failing input if any
+    /// case fails.
+    ///
+    /// Runs according to the scenario's [`Config`], overlaid with the
+    /// environment: by default 1 case with 0 shrink iterations, or as many
+    /// as fit in the [`time_budget`](Self::time_budget) if set. In random
+    /// mode (MADHOUSE=1), sequences of `seq_len` commands are drawn from all
+    /// strategies according to their weights; in swarm mode
+    /// (MADHOUSE=swarm), from a random subset of them, picked anew for
+    /// each case. In exhaustive mode
+    /// (MADHOUSE=exhaustive), every sequence of up to `max_len` commands
+    /// runs once, whatever the number of cases, shortest first and without
+    /// shrinking; phases and the corpus are ignored.
+    ///
+    /// When shrinking changed the failing trace, a side-by-side
+    /// [`TraceDiff`] of the original and shrunk traces is printed.
+    ///
+    /// By default the run stops at the first failing case. With
+    /// [`max_failures`](Self::max_failures) set
+    /// higher, later cases keep running until that many distinct failures
+    /// were found, and all of them are summarized.
+    ///
+    /// Each distinct failing sequence is saved to the
+    /// [`regressions_dir`](Self::regressions_dir) so it can be re-run with
+    /// [`replay_from_file`](Self::replay_from_file).
+    ///
+    /// Once the [`cancellation_token`](Self::cancellation_token) is
+    /// cancelled, the current case stops before its next command, skipping
+    /// report assertions, and no further case runs.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no strategy was added, or if a case fails.
+    pub fn run(mut self) {
+        assert!(
+            !self.strategies.is_empty(),
+            "scenario requires at least one command"
+        );
+
+        // Fixtures first used by this run are torn down when it returns.
+        let _fixtures = fixture::Scope::enter();
+        let config = self.config.clone().with_env();
+        let corpus = self.load_corpus(&config);
+        if let (Some(corpus), Mode::Random | Mode::Swarm) = (&corpus, config.mode) {
+            let traces: Vec<Vec<String>> = corpus
+                .traces()
+                .iter()
+                .filter(|labels| self.parses(labels))
+                .cloned()
+                .collect();
+            if !traces.is_empty() && self.corpus_probability == 0.0 {
+                self.corpus_probability = corpus::DEFAULT_PROBABILITY;
+            }
+            self.corpus.extend(traces);
+        }
+        Rc::new(self).run_cases(config, corpus);
+    }
+
+    /// Generates and runs the cases of [`run`](Self::run). The scenario is
+    /// shared, so that a case explored with loom can hold on to it.
+    fn run_cases(self: Rc<Self>, config: Config, corpus: Option<Corpus>) {
+        let verbose = config.verbosity >= Verbosity::Normal;
+        let seed = config.seed.unwrap_or_else(random_seed);
+        let runner_config = ProptestConfig {
+            cases: config.cases,
+            max_shrink_iters: config.max_shrink_iters,
+            // Failures are saved as replayable traces instead (see
+            // `persist`), not as proptest seeds next to the test.
+            failure_persistence: None,
+            ..contextualize_config(ProptestConfig::default())
+        };
+        let mut runner = TestRunner::new_with_rng(runner_config, gen::rng(seed));
+        let loaded = corpus.as_ref().map_or(0, |corpus| corpus.traces().len());
+
+        let guidance = (config.guided && config.mode == Mode::Random)
+            .then(|| Rc::new(RefCell::new(Guidance::new())));
+        let (len, banner) = match config.mode {
+            Mode::Random if guidance.is_some() => (
+                Some(config.seq_len.clone()),
+                "MADHOUSE mode, guided".to_string(),
+            ),
+            Mode::Random if !self.transitions.is_empty() => (
+                Some(config.seq_len.clone()),
+                "MADHOUSE mode, Markov chain".to_string(),
+            ),
+            Mode::Random => (Some(config.seq_len.clone()), "MADHOUSE mode".to_string()),
+            Mode::Swarm => (Some(config.seq_len.clone()), "swarm mode".to_string()),
+            Mode::Normal => (None, "deterministic mode".to_string()),
+            Mode::Exhaustive { max_len } => {
+                (None, format!("exhaustive mode, up to {} commands", max_len))
+            }
+            Mode::Mixed { prefix } => (
+                Some(config.seq_len.clone()),
+                format!("mixed mode, {} in order", prefix),
+            ),
+        };
+        let prefix = match config.mode {
+            Mode::Mixed { prefix } => prefix,
+            _ => 0,
+        };
+        let fresh = self
+            .sequence(len, prefix, config.mode == Mode::Swarm, guidance.as_ref())
+            .boxed();
+        let banner = match self.phases.len() {
+            0 => banner,
+            phases => format!("{}, {} phases", banner, phases),
+        };
+        let banner = match &self.name {
+            Some(name) => format!("{}: {}", name, banner),
+            None => banner,
+        };
+        let (strategy, banner) = if self.corpus.is_empty() || self.corpus_probability == 0.0 {
+            (fresh, banner)
+        } else {
+            let corpus = self.corpus_strategy();
+            let per_mille = (self.corpus_probability * 1000.0).round() as u32;
+            let strategy = if per_mille >= 1000 {
+                corpus
+            } else {
+                Union::new_weighted(vec![(per_mille.max(1), corpus), (1000 - per_mille, fresh)])
+                    .boxed()
+            };
+            (strategy, format!("{} with corpus", banner))
+        };
+        let strategy = if self.constraints.is_empty() {
+            strategy
+        } else {
+            let constraints = self.constraints.clone();
+            strategy
+                .prop_map(move |commands| {
+                    constraints.apply(commands, |command| {
+                        command_name(&command.command.label()).to_string()
+                    })
+                })
+                .boxed()
+        };
+
+        if verbose {
+            if let Some(name) = &self.name {
+                println!("Scenario: {}", name);
+            }
+            println!("Seed: {} (rerun with MADHOUSE_SEED={})", seed, seed);
+            if let Some(only) = &config.only_cases {
+                println!("Running only cases {:?} of {}", only, config.cases);
+            }
+            if let Some(shard) = config.shard {
+                println!("Running shard {} of the cases", shard);
+            }
+        }
+        if config.shard.is_some() && config.seed.is_none() {
+            eprintln!("madhouse: warning: sharded run without MADHOUSE_SEED; shards may overlap");
+        }
+
+        let quarantine = self.load_quarantine(&config);
+        let mut quarantined = 0;
+        let mut failures: Vec<Failure> = Vec::new();
+        let mut junit = Vec::new();
+        let mut rejected = 0;
+        let exploration = RefCell::new(Exploration {
+            graph: config.graph_file.as_ref().map(|_| StateGraph::default()),
+            saturation: (config.saturation || config.saturation_threshold.is_some())
+                .then(Saturation::default),
+            timings: config.html_dir.as_ref().map(|_| Histogram::default()),
+            timings_csv: config.timings_csv.as_ref().map(|_| String::new()),
+            corpus,
+            guidance,
+            ..Exploration::default()
+        });
+        let mut exhaustive = match config.mode {
+            Mode::Exhaustive { max_len } => Some(Enumeration::new(
+                self.strategies.iter().map(|(_, s)| s.clone()).collect(),
+                max_len,
+            )),
+            _ => None,
+        };
+        let run_started = Instant::now();
+        for case in 0u32.. {
+            if let Some(budget) = config.time_budget {
+                if run_started.elapsed() >= budget {
+                    if verbose {
+                        println!("\nTime budget of {:?} used after {} cases", budget, case);
+                    }
+                    break;
+                }
+            } else if exhaustive.is_none() && case >= config.cases + rejected {
+                break;
+            }
+            if self.cancel.is_cancelled() {
+                if verbose {
+                    println!("\nRun cancelled after {} cases", case);
+                }
+                break;
+            }
+            let tree = match &mut exhaustive {
+                Some(enumeration) => match enumeration.next(&mut runner) {
+                    Some(commands) => {
+                        commands.and_then(|commands| Just(commands).boxed().new_tree(&mut runner))
+                    }
+                    None => break,
+                },
+                None => strategy.new_tree(&mut runner),
+            }
+            .unwrap_or_else(|reason| panic!("failed to generate case: {}", reason));
+            // Enumerated sequences the constraints would change are pruned.
+            if exhaustive.is_some() && !self.constraints.is_empty() {
+                let commands = tree.current();
+                let constrained = self.constraints.apply(commands.clone(), |command| {
+                    command_name(&command.command.label()).to_string()
+                });
+                if labels(&constrained) != labels(&commands) {
+                    continue;
+                }
+            }
+            if config
+                .only_cases
+                .as_ref()
+                .is_some_and(|only| !only.contains(&case))
+            {
+                continue;
+            }
+            if config.shard.is_some_and(|shard| !shard.contains(case)) {
+                if let Some(enumeration) = &mut exhaustive {
+                    enumeration.extend_last();
+                }
+                continue;
+            }
+            if quarantine.contains_case(seed, case)
+                || quarantine.contains_trace(&labels(&tree.current()))
+            {
+                quarantined += 1;
+                continue;
+            }
+
+            // Labels of the first failing run of this case, before shrinking.
+            let original = RefCell::new(None);
+            // Diagram of the latest failing run, the shrunk one in the end.
+            let diagram = RefCell::new(None);
+            // Only the generated run counts toward exploration, not shrinking.
+            let first = Cell::new(true);
+            let started = Instant::now();
+            exploration.borrow_mut().skipped = false;
+            exploration.borrow_mut().case = case;
+            let env_seed = gen::stable_hash(&(seed, case));
+            let result = env::with_case_seed(env_seed, || {
+                runner.run_one(tree, |commands| {
+                    if verbose {
+                        println!("\n=== New Test Run ({}) ===\n", banner);
+                    }
+                    let exploration = first.replace(false).then_some(&exploration);
+                    self.run_recorded(&commands, &original, &diagram, case, &config, exploration)
+                })
+            });
+            let rows = exploration
+                .borrow_mut()
+                .timings_csv
+                .as_mut()
+                .map(std::mem::take);
+            if let (Some(path), Some(rows)) = (&config.timings_csv, rows) {
+                if let Err(err) = timings::append(path, timings::HEADER, &rows) {
+                    eprintln!(
+                        "madhouse: cannot write timings to {}: {}",
+                        path.display(),
+                        err
+                    );
+                }
+            }
+
+            let mut junit_case = JUnitCase {
+                name: format!("case {}", case),
+                time: started.elapsed(),
+                failure: None,
+            };
+            let (message, minimal) = match result {
+                Ok(accepted) => {
+                    if accepted {
+                        junit.push(junit_case);
+                    } else {
+                        rejected += 1;
+                        if verbose {
+                            println!("\nCase rejected: too few commands passed check()");
+                        }
+                    }
+                    if let Some(enumeration) = &mut exhaustive {
+                        if config.shard.is_some() || !exploration.borrow().skipped {
+                            enumeration.extend_last();
+                        }
+                    }
+                    if let Some(threshold) = config.saturation_threshold {
+                        let exploration = exploration.borrow();
+                        let saturation = exploration.saturation.as_ref();
+                        if saturation.is_some_and(|s| s.is_saturated(threshold)) {
+                            if verbose {
+                                println!(
+                                    "\nState space saturated after {} cases, stopping early",
+                                    case + 1
+                                );
+                            }
+                            break;
+                        }
+                    }
+                    continue;
+                }
+                Err(TestError::Abort(reason)) => panic!("Test aborted: {}", reason),
+                Err(TestError::Fail(reason, minimal)) => (reason.to_string(), minimal),
+            };
+
+            let labels = labels(&minimal);
+            if quarantine.contains_trace(&labels) {
+                quarantined += 1;
+                continue;
+            }
+            if let Some(original) = original.take() {
+                let diff = TraceDiff::new(&original, &labels);
+                if verbose && !diff.is_identical() {
+                    println!("\nShrinking (original vs shrunk):\n{}", diff);
+                }
+            }
+            if let Some(diagram) = diagram.take().filter(|_| verbose) {
+                if config.format == Format::Text {
+                    println!("\nSequence diagram:\n{}", diagram.to_markdown());
+                }
+            }
+            if config.regression_test && verbose && config.format == Format::Text {
+                let name = format!(
+                    "{} {:016x}",
+                    regression::stem(self.source_file, self.name.as_deref()),
+                    regression::fingerprint(&labels)
+                );
+                let test = RegressionTest::new(&name, &minimal, &self.ctx).describe(&message);
+                println!("\nRegression test:\n{}", test);
+            }
+
+            junit_case.failure = Some(format!(
+                "Test failed: {}.\nminimal failing input: {:#?}",
+                message, labels
+            ));
+            junit.push(junit_case);
+
+            if failures.iter().all(|f| f.labels != labels) {
+                self.persist(&labels, &message, env_seed, verbose);
+                if failures.is_empty() {
+                    let notice = FailureNotice {
+                        scenario: self.title(),
+                        seed,
+                        case,
+                        message: message.clone(),
+                        labels: labels.clone(),
+                    };
+                    self.notify(&config, |n| n.on_first_failure(&notice));
+                }
+                failures.push(Failure {
+                    case,
+                    message,
+                    labels,
+                });
+            }
+            if config.max_failures > 0 && failures.len() >= config.max_failures {
+                break;
+            }
+        }
+
+        let Exploration {
+            coverage,
+            statistics,
+            saturation,
+            graph,
+            timings,
+            corpus,
+            ..
+        } = exploration.into_inner();
+        let starved = config
+            .min_executed_ratio
+            .and_then(|min| Self::starvation(&coverage, min));
+        if verbose && config.format == Format::Text && !coverage.is_empty() {
+            println!("\n{}", coverage);
+            if let Some(saturation) = &saturation {
+                println!("{}", saturation);
+            }
+            if !coverage.skips().is_empty() {
+                println!("\n{}", coverage.skips());
+            }
+        }
+        if let Some(path) = &config.skips_csv {
+            let mut rows = String::new();
+            coverage.skips().rows(&mut rows, &self.title());
+            if let Err(err) = timings::append(path, coverage::SKIPS_HEADER, &rows) {
+                eprintln!(
+                    "madhouse: cannot write skip rates to {}: {}",
+                    path.display(),
+                    err
+                );
+            }
+        }
+        if verbose && config.format == Format::Text && !statistics.is_empty() {
+            println!("\n{}", statistics);
+        }
+        if let Some(corpus) = corpus.filter(|_| verbose) {
+            let saved = corpus.traces().len() - loaded;
+            if saved > 0 {
+                println!(
+                    "Saved {} new trace(s) to corpus {} ({} in total)",
+                    saved,
+                    corpus.dir().display(),
+                    corpus.traces().len()
+                );
+            }
+        }
+        if let (Some(path), Some(graph)) = (&config.graph_file, graph) {
+            match graph.write(path) {
+                Ok(()) if verbose => println!("Wrote state graph to {}", path.display()),
+                Ok(()) => {}
+                Err(err) => eprintln!(
+                    "madhouse: cannot write state graph to {}: {}",
+                    path.display(),
+                    err
+                ),
+            }
+        }
+        if let Some(path) = config.quarantine.as_ref().filter(|_| quarantined > 0) {
+            eprintln!(
+                "madhouse: warning: {} quarantined case(s) skipped or ignored ({})",
+                quarantined,
+                path.display()
+            );
+        }
+
+        let summary = CampaignSummary {
+            scenario: self.title(),
+            seed,
+            cases: junit.len() as u32,
+            failures: failures.len(),
+            summary: if failures.is_empty() {
+                starved.clone().unwrap_or_default()
+            } else {
+                self.summarize(seed, &failures)
+            },
+        };
+        self.notify(&config, |n| n.on_campaign_end(&summary));
+        if let Some(name) = &self.name {
+            let outcome = Outcome {
+                name: name.clone(),
+                cases: summary.cases,
+                failures: summary.failures + usize::from(starved.is_some()),
+            };
+            summary::record(outcome, verbose);
+        }
+        if let Some(dir) = &config.html_dir {
+            let report = ScenarioReport {
+                name: self.title(),
+                seed,
+                cases: junit.len() as u32,
+                duration: run_started.elapsed(),
+                failures: failures
+                    .iter()
+                    .map(|failure| FailedCase {
+                        case: failure.case,
+                        message: failure.message.clone(),
+                        labels: failure.labels.clone(),
+                    })
+                    .collect(),
+                coverage,
+                timings: timings.unwrap_or_default(),
+            };
+            match html::record(dir, report) {
+                Ok(path) if verbose => println!("Wrote HTML report to {}", path.display()),
+                Ok(_) => {}
+                Err(err) => eprintln!(
+                    "madhouse: cannot write HTML report to {}: {}",
+                    dir.display(),
+                    err
+                ),
+            }
+        }
+        if let Some(dir) = &config.junit_dir {
+            self.write_junit(dir, junit, verbose);
+        }
+        if !failures.is_empty() || starved.is_some() {
+            panic!("{}", summary.summary);
+        }
+    }
+
+    /// Describes why the run fails the `min_executed_ratio` threshold, if
+    /// too few selected commands passed their preconditions.
+    fn starvation(coverage: &Coverage, min: f64) -> Option<String> {
+        let ratio = coverage.executed_ratio()?;
+        if ratio >= min {
+            return None;
+        }
+        let mut message = format!(
+            "Only {:.1}% of selected commands passed check() (minimum {:.1}%). Most skipped:",
+            ratio * 100.0,
+            min * 100.0
+        );
+        for (name, skipped) in coverage.most_skipped().into_iter().take(5) {
+            let selected = coverage.get(name).map_or(skipped, |c| c.selected);
+            message.push_str(&format!("\n  {}: {} of {}", name, skipped, selected));
+        }
+        Some(message)
+    }
+
+    /// Re-runs a sequence saved by a failing run, verbatim.
+    ///
+    /// Labels are turned back into commands by the registered parsers, in
+    /// the order they were added; the first to recognize a label wins. The
+    /// sequence then runs once, with the scenario's heartbeats and report
+    /// assertions, in any mode.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the file cannot be read, if no parser recognizes a label,
+    /// or if the sequence fails again.
+    pub fn replay_from_file(self, path: impl AsRef<Path>) {
+        let path = path.as_ref();
+        let labels = regression::load(path)
+            .unwrap_or_else(|err| panic!("cannot read {}: {}", path.display(), err));
+
+        let env_seed = regression::env_seed(path)
+            .unwrap_or_else(|err| panic!("cannot read {}: {}", path.display(), err));
+        let commands = self.parse_trace(&labels, &path.display().to_string());
+
+        let config = self.config.clone().with_env();
+        if config.verbosity >= Verbosity::Normal {
+            println!("\n=== Replay ({}) ===\n", path.display());
+        }
+        let outcome = env::with_case_seed(env_seed.unwrap_or_default(), || {
+            panics::catch(|| self.run_case(&commands, &config, None, None))
+        })
+        .unwrap_or_else(|panic| Err(TestCaseError::fail(panic.to_string())));
+        if let Err(reason @ TestCaseError::Fail(_)) = outcome {
+            panic!(
+                "Replay of {} failed: {}.\nfailing input: {:#?}",
+                path.display(),
+                reason,
+                labels
+            );
+        }
+    }
+
+    /// Migrates the failures recorded in a proptest regression file into
+    /// madhouse regression files, returning the paths written.
+    ///
+    /// Each entry's labels (see [`regression::load_proptest`]) must be
+    /// recognized by the registered parsers, as in
+    /// [`replay_from_file`](Self::replay_from_file); entries with an
+    /// unknown label are reported on stderr and skipped. Files are written
+    /// to the [`regressions_dir`](Self::regressions_dir), or the default
+    /// one if saving is disabled. Nothing is executed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the file cannot be read, or a regression file cannot be
+    /// written.
+    pub fn import_proptest_regressions(self, path: impl AsRef<Path>) -> Vec<PathBuf> {
+        let path = path.as_ref();
+        let entries = regression::load_proptest(path)
+            .unwrap_or_else(|err| panic!("cannot read {}: {}", path.display(), err));
+        let dir = self
+            .regressions
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(regression::DEFAULT_DIR));
+        let message = format!("imported from {}", path.display());
+
+        let mut imported = Vec::new();
+        for labels in &entries {
+            if let Some(label) = labels.iter().find(|label| {
+                !self
+                    .parsers
+                    .iter()
+                    .any(|parse| parse(label, self.context()).is_some())
+            }) {
+                eprintln!(
+                    "madhouse: skipping proptest regression from {}: no command parses label `{}`",
+                    path.display(),
+                    label
+                );
+                continue;
+            }
+            let saved = regression::save(
+                &dir,
+                self.source_file,
+                self.name.as_deref(),
+                labels,
+                &message,
+            )
+            .unwrap_or_else(|err| panic!("cannot write to {}: {}", dir.display(), err));
+            imported.push(saved);
+        }
+        if self.config.clone().with_env().verbosity >= Verbosity::Normal {
+            println!(
+                "Imported {} of {} proptest regressions from {} into {}",
+                imported.len(),
+                entries.len(),
+                path.display(),
+                dir.display()
+            );
+        }
+        imported
+    }
+
+    /// Checks a sequence against a golden trace, failing on the first step
+    /// whose outcome or state digest differs from the recorded one.
+    ///
+    /// Labels are parsed like in [`replay_from_file`](Self::replay_from_file).
+    /// If the file lists bare labels, or `MADHOUSE_BLESS=1` is set, the run
+    /// records the trace into the file instead. Command errors recorded in
+    /// the trace are expected, not failures; report assertions still run.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the file cannot be read or written, if no parser recognizes
+    /// a label, or on the first divergence.
+    pub fn conform_to_file(self, path: impl AsRef<Path>) {
+        let path = path.as_ref();
+        let golden = GoldenTrace::load(path)
+            .unwrap_or_else(|err| panic!("cannot read {}: {}", path.display(), err));
+        let commands = self.parse_trace(&golden.labels, &path.display().to_string());
+
+        let config = self.config.clone().with_env();
+        if config.verbosity >= Verbosity::Normal {
+            println!("\n=== Conformance ({}) ===\n", path.display());
+        }
+        let mut recorder = Recorder::default();
+        let report = self.with_case(|state| {
+            let report = run_commands(&commands, state, &self.heartbeats, &mut recorder);
+            if config.verbosity >= Verbosity::Normal {
+                report.print(config.format, state);
+            }
+            report
+        });
+
+        if config.bless || !golden.is_recorded() {
+            GoldenTrace::save(path, &recorder.steps)
+                .unwrap_or_else(|err| panic!("cannot write {}: {}", path.display(), err));
+            if config.verbosity >= Verbosity::Normal {
+                println!("Recorded golden trace to {}", path.display());
+            }
+        } else if let Some(divergence) = golden.first_divergence(&recorder.steps) {
+            panic!(
+                "{} in {} (rerun with MADHOUSE_BLESS=1 if the change is intended)",
+                divergence,
+                path.display()
+            );
+        }
+
+        for assertion in &self.assertions {
+            assertion(&report);
+        }
+    }
+
+    /// Strategy for whole sequences: the commands of the strategies added
+    /// before any phase, `len` of them if random (from a random subset of
+    /// the strategies if `swarm`) or one per strategy if `None`, followed
+    /// by those of each phase. The first `prefix` strategies added before
+    /// any phase contribute one command each, in order, ahead of the rest.
+    fn sequence(
+        &self,
+        len: Option<Range<usize>>,
+        prefix: usize,
+        swarm: bool,
+        guidance: Option<&Rc<RefCell<Guidance>>>,
+    ) -> Sequence<CommandWrapper<S, C>> {
+        let first_phase = self
+            .phases
+            .first()
+            .map_or(self.strategies.len(), |(start, _)| *start);
+        let mut bounds = match prefix {
+            0 => vec![(0, len)],
+            prefix => vec![(0, None), (prefix.min(first_phase), len)],
+        };
+        bounds.extend(self.phases.iter().cloned());
+        let ends = bounds
+            .iter()
+            .skip(1)
+            .map(|(start, _)| *start)
+            .chain([self.strategies.len()]);
+        let mut phases: Vec<_> = bounds
+            .iter()
+            .zip(ends)
+            .enumerate()
+            .filter(|(_, ((start, _), end))| start < end)
+            .map(|(i, ((start, len), end))| {
+                (&self.strategies[*start..end], len.clone(), swarm && i == 0)
+            })
+            .collect();
+        if phases.len() == 1 && self.phases.is_empty() {
+            let (strategies, len, swarm) = phases.remove(0);
+            let min_len = match &len {
+                Some(len) => len.start.min(1),
+                None => strategies.len().min(1),
+            };
+            return self.phase(strategies, len, swarm, guidance, min_len);
+        }
+        let phases = phases
+            .into_iter()
+            .map(|(strategies, len, swarm)| self.phase(strategies, len, swarm, guidance, 0))
+            .collect();
+        Sequence::phased(phases, 1)
+    }
+
+    /// Strategy for one phase: `len` commands drawn by weight, from a
+    /// random subset of the strategies if `swarm`, scaled by `guidance` if
+    /// any, or one per strategy in order if `None`.
+    fn phase(
+        &self,
+        strategies: &[(u32, CommandStrategy<S, C>)],
+        len: Option<Range<usize>>,
+        swarm: bool,
+        guidance: Option<&Rc<RefCell<Guidance>>>,
+        min_len: usize,
+    ) -> Sequence<CommandWrapper<S, C>> {
+        match len {
+            None => Sequence::fixed(strategies.iter().map(|(_, s)| s.clone()).collect(), min_len),
+            Some(len) if swarm => Sequence::swarm(strategies.to_vec(), len, min_len),
+            Some(len) if guidance.is_some() => Sequence::guided(
+                strategies.to_vec(),
+                self.transitions.clone(),
+                Rc::clone(guidance.unwrap()),
+                |command: &CommandWrapper<S, C>| command_name(&command.command.label()).to_string(),
+                len,
+                min_len,
+            ),
+            Some(len) if !self.transitions.is_empty() => Sequence::markov(
+                strategies.to_vec(),
+                self.transitions.clone(),
+                |command: &CommandWrapper<S, C>| command_name(&command.command.label()).to_string(),
+                len,
+                min_len,
+            ),
+            Some(len) => Sequence::random(
+                Union::new_weighted(strategies.to_vec()).boxed(),
+                len,
+                min_len,
+            ),
+        }
+    }
+
+    /// Returns true if every label parses back into a command.
+    fn parses(&self, labels: &[String]) -> bool {
+        labels.iter().all(|label| {
+            self.parsers
+                .iter()
+                .any(|parse| parse(label, self.context()).is_some())
+        })
+    }
+
+    /// Turns labels back into commands with the registered parsers.
+    fn parse_trace(&self, labels: &[String], source: &str) -> Vec<CommandWrapper<S, C>> {
+        labels
+            .iter()
+            .map(|label| {
+                self.parsers
+                    .iter()
+                    .find_map(|parse| parse(label, self.context()))
+                    .unwrap_or_else(|| {
+                        panic!(
+                            "no command parses label `{}` from {}; implement Command::parse",
+                            label, source
+                        )
+                    })
+            })
+            .collect()
+    }
+
+    /// Strategy replaying a corpus trace with one mutation applied.
+    fn corpus_strategy(&self) -> BoxedStrategy<Vec<CommandWrapper<S, C>>> {
+        let traces: Vec<Vec<CommandWrapper<S, C>>> = self
+            .corpus
+            .iter()
+            .map(|labels| self.parse_trace(labels, "the corpus"))
+            .collect();
+        let fresh = Union::new_weighted(self.strategies.clone());
+        (
+            gen::pick((0..traces.len()).collect::<Vec<_>>()),
+            gen::pick(Mutation::ALL),
+            0..1024u64,
+            fresh,
+        )
+            .prop_map(move |(trace, mutation, pos, fresh)| {
+                mutation.apply(&traces[trace], pos as usize, fresh)
+            })
+            .boxed()
+    }
+
+    /// Saves a failing sequence with the seed of its case's [`Env`](crate::Env),
+    /// warning instead of failing on I/O errors.
+    fn persist(&self, labels: &[String], message: &str, env_seed: u64, verbose: bool) {
+        let Some(dir) = &self.regressions else {
+            return;
+        };
+        let message = format!("{}\n{}{}", message, regression::ENV_SEED, env_seed);
+        match regression::save(
+            dir,
+            self.source_file,
+            self.name.as_deref(),
+            labels,
+            &message,
+        ) {
+            Ok(path) if verbose => println!("Saved failing sequence to {}", path.display()),
+            Ok(_) => {}
+            Err(err) => eprintln!(
+                "madhouse: cannot save failing sequence to {}: {}",
+                dir.display(),
+                err
+            ),
+        }
+    }
+
+    /// Returns the scenario's name: the one it was given, or else the
+    /// running test's (its thread name under `cargo test`), falling back to
+    /// the source file.
+    fn title(&self) -> String {
+        if let Some(name) = &self.name {
+            return name.clone();
+        }
+        std::thread::current()
+            .name()
+            .filter(|name| *name != "main")
+            .map(str::to_string)
+            .or_else(|| {
+                self.source_file.map(|file| {
+                    Path::new(file)
+                        .file_stem()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .into_owned()
+                })
+            })
+            .unwrap_or_else(|| "madhouse".to_string())
+    }
+
+    /// Sends an event to every notifier, including the configured webhook.
+    fn notify(&self, config: &Config, event: impl Fn(&dyn Notifier)) {
+        for notifier in &self.notifiers {
+            event(notifier.as_ref());
+        }
+        if let Some(url) = &config.webhook {
+            event(&Webhook::new(url.clone()));
+        }
+    }
+
+    /// Writes the JUnit report of a run, warning instead of failing on I/O
+    /// errors.
+    fn write_junit(&self, dir: &Path, cases: Vec<JUnitCase>, verbose: bool) {
+        let suite = JUnitSuite {
+            name: self.title(),
+            cases,
+        };
+        match suite.write(dir) {
+            Ok(path) if verbose => println!("Wrote JUnit report to {}", path.display()),
+            Ok(_) => {}
+            Err(err) => eprintln!(
+                "madhouse: cannot write JUnit report to {}: {}",
+                dir.display(),
+                err
+            ),
+        }
+    }
+
+    /// Formats the distinct failures found during a run.
+    fn summarize(&self, seed: u64, failures: &[Failure]) -> String {
+        let mut out = String::new();
+        if let Some(name) = &self.name {
+            out.push_str(&format!("Scenario {}: ", name));
+        }
+        if failures.len() > 1 {
+            out.push_str(&format!("{} distinct failing cases", failures.len()));
+            if let Some(file) = self.source_file {
+                out.push_str(&format!(" in {}", file));
+            }
+            out.push_str(":\n");
+        }
+        for failure in failures {
+            if failures.len() > 1 {
+                out.push_str(&format!("\n--- case {} ---\n", failure.case));
+            }
+            out.push_str(&format!(
+                "Test failed: {}.\nminimal failing input: {:#?}\ntrace fingerprint: {:016x}\n",
+                failure.message,
+                failure.labels,
+                regression::fingerprint(&failure.labels)
+            ));
+        }
+        let cases: Vec<String> = failures.iter().map(|f| f.case.to_string()).collect();
+        out.push_str(&format!(
+            "seed: {} (rerun with MADHOUSE_SEED={} MADHOUSE_ONLY_CASES={})\n",
+            seed,
+            seed,
+            cases.join(",")
+        ));
+        out
+    }
+
+    /// Opens the configured corpus directory, if any; one that cannot be
+    /// opened is reported on stderr and ignored.
+    fn load_corpus(&self, config: &Config) -> Option<Corpus> {
+        let dir = config.corpus_dir.as_ref()?;
+        Corpus::open(dir)
+            .map_err(|err| eprintln!("madhouse: ignoring corpus {}: {}", dir.display(), err))
+            .ok()
+    }
+
+    /// Reads the configured quarantine file; a missing or malformed one is
+    /// reported on stderr and treated as empty.
+    fn load_quarantine(&self, config: &Config) -> Quarantine {
+        let Some(path) = &config.quarantine else {
+            return Quarantine::default();
+        };
+        Quarantine::load(path).unwrap_or_else(|err| {
+            eprintln!("madhouse: ignoring quarantine {}: {}", path.display(), err);
+            Quarantine::default()
+        })
+    }
+
+    /// Runs a case, remembering its labels if it is the first to fail.
+    fn run_recorded(
+        self: &Rc<Self>,
+        commands: &[CommandWrapper<S, C>],
+        original: &RefCell<Option<Vec<String>>>,
+        diagram: &RefCell<Option<SequenceDiagram>>,
+        case: u32,
+        config: &Config,
+        exploration: Option<&RefCell<Exploration>>,
+    ) -> Result<(), TestCaseError> {
+        let outcome =
+            panics::catch(|| self.run_interleavings(commands, config, exploration, diagram));
+        if !matches!(outcome, Ok(Ok(()))) && original.borrow().is_none() {
+            let labels: Vec<String> = commands.iter().map(|c| c.command.label()).collect();
+            #[cfg(feature = "artifacts")]
+            self.capture_artifacts(case, &labels, &outcome);
+            #[cfg(not(feature = "artifacts"))]
+            let _ = case;
+            *original.borrow_mut() = Some(labels);
+        }
+        match outcome {
+            Ok(result) => result,
+            Err(panic) => {
+                if config.verbosity >= Verbosity::Normal {
+                    println!("\n{}", panic);
+                    if let Some(backtrace) = &panic.backtrace {
+                        println!("{}", backtrace);
+                    }
+                }
+                Err(TestCaseError::fail(panic.to_string()))
+            }
+        }
+    }
+
+    /// Runs a case, under every interleaving loom explores if enabled.
+    ///
+    /// Only the first interleaving counts toward exploration.
+    fn run_interleavings(
+        self: &Rc<Self>,
+        commands: &[CommandWrapper<S, C>],
+        config: &Config,
+        exploration: Option<&RefCell<Exploration>>,
+        diagram: &RefCell<Option<SequenceDiagram>>,
+    ) -> Result<(), TestCaseError> {
+        #[cfg(feature = "loom")]
+        if self.loom {
+            // The model owns what it runs: the exploration and diagram are
+            // moved in for the case, and back out once it was explored.
+            let scenario = Rc::clone(self);
+            let (commands, config) = (commands.to_vec(), config.clone());
+            let explored_first = exploration.map(|e| Rc::new(RefCell::new(e.take())));
+            let first = Cell::new(explored_first.clone());
+            let shared_diagram = Rc::new(RefCell::new(diagram.take()));
+            let case_diagram = Rc::clone(&shared_diagram);
+            let failure = Rc::new(RefCell::new(None));
+            let case_failure = Rc::clone(&failure);
+            let explored = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                loom::explore(move || {
+                    let exploration = first.take();
+                    let result = scenario.run_case(
+                        &commands,
+                        &config,
+                        exploration.as_deref(),
+                        Some(&case_diagram),
+                    );
+                    if let Err(err) = result {
+                        *case_failure.borrow_mut() = Some(err);
+                        // Stops exploring, without reporting a panic.
+                        std::panic::resume_unwind(Box::new(()));
+                    }
+                })
+            }));
+            if let (Some(exploration), Some(explored)) = (exploration, explored_first) {
+                exploration.replace(explored.take());
+            }
+            diagram.replace(shared_diagram.take());
+            return match (failure.take(), explored) {
+                (Some(err), _) => Err(err),
+                (None, Ok(())) => Ok(()),
+                (None, Err(payload)) => std::panic::resume_unwind(payload),
+            };
+        }
+        self.run_case(commands, config, exploration, Some(diagram))
+    }
+
+    /// Runs the failure hooks for a case's first failing run.
+    #[cfg(feature = "artifacts")]
+    fn capture_artifacts(
+        &self,
+        case: u32,
+        labels: &[String],
+        outcome: &Result<Result<(), TestCaseError>, Box<panics::CapturedPanic>>,
+    ) {
+        if self.failure_hooks.is_empty() {
+            return;
+        }
+        let message = match outcome {
+            Ok(Err(reason)) => reason.to_string(),
+            Ok(Ok(())) => return,
+            Err(panic) => panic.message.clone(),
+        };
+        let stem = regression::stem(self.source_file, self.name.as_deref());
+        let failure = FailureArtifacts {
+            dir: self
+                .artifacts_dir
+                .join(format!("{}-{:016x}", stem, gen::stable_hash(labels))),
+            case,
+            message,
+            labels: labels.to_vec(),
+        };
+        for hook in &self.failure_hooks {
+            if let Err(err) = hook(&self.ctx, &failure) {
+                eprintln!(
+                    "madhouse: failure hook could not save artifacts to {}: {}",
+                    failure.dir.display(),
+                    err
+                );
+            }
+        }
+    }
+
+    /// Runs a case, storing its sequence diagram in `diagram` if it fails
+    /// and [`Config::mermaid`] is set.
+    fn run_case(
+        &self,
+        commands: &[CommandWrapper<S, C>],
+        config: &Config,
+        exploration: Option<&RefCell<Exploration>>,
+        diagram: Option<&RefCell<Option<SequenceDiagram>>>,
+    ) -> Result<(), TestCaseError> {
+        self.with_case(|state| {
+            let mut exploration = exploration.map(RefCell::borrow_mut);
+            let mut notes = (config.mermaid && diagram.is_some()).then(|| self.state_notes());
+            let mut sut = self.sut.as_ref().map(RefCell::borrow_mut);
+            if let Some(sut) = &mut sut {
+                sut.reset();
+            }
+            let guidance = exploration.as_ref().and_then(|e| e.guidance.clone());
+            let abstraction = self.abstraction.as_deref().unwrap_or(&Fingerprint);
+            let feedback = guidance.as_deref().map(|g| Feedback::new(abstraction, g));
+            #[allow(unused_mut)]
+            let mut report = run_commands_traced(
+                commands,
+                state,
+                &self.heartbeats,
+                &mut (
+                    (exploration.as_deref_mut(), feedback),
+                    (&mut notes, sut.as_deref_mut().map(Box::as_mut)),
+                ),
+                &mut Trace::new(config).cancellable(self.cancel.clone()),
+                None,
+            );
+            if let Some(exploration) = &mut exploration {
+                let labels: Vec<String> = commands.iter().map(|c| c.command.label()).collect();
+                exploration
+                    .coverage
+                    .record(labels.iter().map(String::as_str), &report);
+                exploration.statistics.record(&report);
+                if let Some(timings) = &mut exploration.timings {
+                    for step in report.executed() {
+                        timings.record(step.duration);
+                    }
+                }
+                let case = exploration.case;
+                if let Some(csv) = &mut exploration.timings_csv {
+                    timings::rows(csv, &self.title(), case, &report);
+                }
+                let states = std::mem::take(&mut exploration.states);
+                if let Some(corpus) = exploration.corpus.as_mut().filter(|corpus| {
+                    report.failure_message().is_none()
+                        && corpus.is_interesting(&labels, &states)
+                        && self.parses(&labels)
+                }) {
+                    if let Err(err) = corpus.add(&labels, &states) {
+                        eprintln!(
+                            "madhouse: cannot save trace to corpus {}: {}",
+                            corpus.dir().display(),
+                            err
+                        );
+                    }
+                }
+            }
+            #[cfg(feature = "json")]
+            if !self.projections.is_empty() {
+                report.projections = Some(self.projections.evaluate(state));
+            }
+            if config.verbosity >= Verbosity::Normal {
+                report.print(config.format, state);
+            }
+            if config.verbosity >= Verbosity::Verbose
+                && config.format == Format::Text
+                && !self.projects()
+            {
+                println!("Final state: {:#?}", state);
+            }
+
+            if let Some(message) = report.failure_message() {
+                if let (Some(diagram), Some(notes)) = (diagram, notes) {
+                    let mut drawn = SequenceDiagram::new(&report);
+                    notes.annotate(&mut drawn);
+                    *diagram.borrow_mut() = Some(drawn);
+                }
+                return Err(TestCaseError::fail(message));
+            }
+
+            let executed = report.executed().count();
+            if executed < config.require_executed && !report.cancelled {
+                return Err(TestCaseError::reject(format!(
+                    "only {} of {} required commands passed check()",
+                    executed, config.require_executed
+                )));
+            }
+
+            // Assertions over the whole trace do not hold for a partial one.
+            if !report.cancelled {
+                for assertion in &self.assertions {
+                    assertion(&report);
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Returns the observer noting the state after each command for
+    /// sequence diagrams: the projections if any, else the `Debug` output.
+    fn state_notes(&self) -> StateNotes<'_, S> {
+        #[cfg(feature = "json")]
+        if !self.projections.is_empty() {
+            return StateNotes::new(|state| self.projections.evaluate(state).to_string());
+        }
+        StateNotes::new(|state| format!("{:?}", state))
+    }
+
+    /// Returns true if projections replace the final state dump.
+    fn projects(&self) -> bool {
+        #[cfg(feature = "json")]
+        return !self.projections.is_empty();
+        #[cfg(not(feature = "json"))]
+        false
+    }
+
+    /// Runs the setup hooks, then `case` on a fresh state, then the
+    /// teardown hooks, even if `case` panicked.
+    fn with_case<T>(&self, case: impl FnOnce(&mut S) -> T) -> T {
+        for setup in &self.setups {
+            setup();
+        }
+        let mut state = S::default();
+        let guard = TeardownGuard {
+            state: &mut state,
+            teardowns: &self.teardowns,
+        };
+        case(guard.state)
+    }
+}
+
+/// A reusable list of commands, included in scenarios as a whole.
+///
+/// Built by [`commands!`](crate::commands) from the same entries as
+/// [`scenario!`](crate::scenario) takes, and included with `include set`
+/// (see [`Scenario::include`]). The set holds no context: each scenario
+/// including it builds the commands from its own, so shared bootstrap
+/// commands are written once.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::{commands, scenario, Command, CommandWrapper, State, TestContext};
+/// use proptest::prelude::*;
+/// use std::sync::Arc;
+///
+/// #[derive(Debug, Default)]
+/// struct Node { started: bool, blocks: u64 }
+/// impl State for Node {}
+///
+/// #[derive(Debug, Clone, Default)]
+/// struct Ctx {}
+/// impl TestContext for Ctx {}
+///
+/// struct Start;
+/// impl Command<Node, Ctx> for Start {
+///     fn check(&self, state: &Node) -> bool { !state.started }
+///     fn apply(&self, state: &mut Node) { state.started = true; }
+///     fn label(&self) -> String { "START".to_string() }
+///     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Node, Ctx>> {
+///         Just(CommandWrapper::new(Start))
+///     }
+/// }
+///
+/// struct Mine;
+/// impl Command<Node, Ctx> for Mine {
+///     fn check(&self, state: &Node) -> bool { state.started }
+///     fn apply(&self, state: &mut Node) { state.blocks += 1; }
+///     fn label(&self) -> String { "MINE".to_string() }
+///     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Node, Ctx>> {
+///         Just(CommandWrapper::new(Mine))
+///     }
+/// }
+///
+/// let bootstrap = commands![(Start), Mine];
+///
+/// let ctx = Arc::new(Ctx::default());
+/// scenario![
+///     assert_report = |report| assert_eq!(report.count("MINE"), 3),
+///     ctx,
+///     include bootstrap,
+///     Mine,
+///     Mine
+/// ];
+/// ```
+pub struct CommandSet<S: State, C: TestContext> {
+    entries: Vec<Rc<SetEntry<S, C>>>,
+}
+
+impl<S: State, C: TestContext> CommandSet<S, C> {
+    /// Creates an empty set.
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Adds an entry, applied by `add` to each scenario including the set.
+    pub fn then(mut self, add: impl Fn(Scenario<S, C>) -> Scenario<S, C> + 'static) -> Self {
+        self.entries.push(Rc::new(add));
+        self
+    }
+
+    /// Adds the entries of another set.
+    pub fn include(mut self, other: &CommandSet<S, C>) -> Self {
+        self.entries.extend(other.entries.iter().cloned());
+        self
+    }
+}
+
+impl<S: State, C: TestContext> Default for CommandSet<S, C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: State, C: TestContext> Clone for CommandSet<S, C> {
+    fn clone(&self) -> Self {
+        Self {
+            entries: self.entries.clone(),
+        }
+    }
+}
+
+/// Runs the teardown hooks, in reverse order, when dropped.
+struct TeardownGuard<'a, S> {
+    state: &'a mut S,
+    teardowns: &'a [Teardown<S>],
+}
+
+impl<S> Drop for TeardownGuard<'_, S> {
+    fn drop(&mut self) {
+        panics::run_all(self.teardowns.iter().rev(), |teardown| teardown(self.state));
+    }
+}
+
+/// Draws a fresh seed from the OS-randomized std hasher keys.
+fn random_seed() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+/// Returns the labels of a command sequence.
+fn labels<S: State, C: TestContext>(commands: &[CommandWrapper<S, C>]) -> Vec<String> {
+    commands.iter().map(|c| c.command.label()).collect()
+}
@@ -0,0 +1,260 @@
+//! Scheduling policies for interleaving per-actor command streams.
+//!
+//! There is no parallel runner: a scenario applies its commands one at a
+//! time, on one thread. A model of a concurrent system still often has
+//! several actors (signers, miners, clients) each issuing its own stream of
+//! commands, and the order in which those streams are interleaved decides
+//! which concurrency bugs a run can reach. A [`Scheduler`] picks the actor
+//! whose next command comes next; [`interleave`] merges per-actor sequences
+//! into the single sequence a scenario executes, keeping each actor's own
+//! order. As only one command is ever in flight, no scheduler needs to
+//! keep conflicting commands (two node restarts, say) from overlapping.
+//!
+//! Three policies are provided:
+//!
+//! - [`RandomInterleaving`] picks uniformly among the actors with commands
+//!   left.
+//! - [`RoundRobin`] cycles through the actors, for fair, predictable
+//!   schedules.
+//! - [`Pct`] implements probabilistic concurrency testing (Burckhardt et
+//!   al., ASPLOS 2010): actors get random priorities, the highest runs, and
+//!   `depth - 1` random steps lower the running actor's priority. A bug that
+//!   needs `depth` ordering constraints among `n` actors and `k` steps is
+//!   found with probability at least `1 / (n * k^(depth - 1))` per run.
+
+use proptest::prelude::RngCore;
+use std::collections::VecDeque;
+
+/// Picks the actor that runs the next step.
+///
+/// Actors are numbered from 0. A scheduler is [`reset`](Self::reset) before
+/// each interleaving, then asked for one actor per step.
+pub trait Scheduler {
+    /// Prepares a new interleaving of `actors` actors over `steps` steps.
+    fn reset(&mut self, actors: usize, steps: usize, rng: &mut dyn RngCore) {
+        let _ = (actors, steps, rng);
+    }
+
+    /// Returns the actor to run next, one of `runnable` (non-empty, in
+    /// ascending order).
+    fn next(&mut self, runnable: &[usize], rng: &mut dyn RngCore) -> usize;
+}
+
+impl<S: Scheduler + ?Sized> Scheduler for &mut S {
+    fn reset(&mut self, actors: usize, steps: usize, rng: &mut dyn RngCore) {
+        (**self).reset(actors, steps, rng)
+    }
+
+    fn next(&mut self, runnable: &[usize], rng: &mut dyn RngCore) -> usize {
+        (**self).next(runnable, rng)
+    }
+}
+
+impl<S: Scheduler + ?Sized> Scheduler for Box<S> {
+    fn reset(&mut self, actors: usize, steps: usize, rng: &mut dyn RngCore) {
+        (**self).reset(actors, steps, rng)
+    }
+
+    fn next(&mut self, runnable: &[usize], rng: &mut dyn RngCore) -> usize {
+        (**self).next(runnable, rng)
+    }
+}
+
+/// Picks uniformly among the runnable actors.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RandomInterleaving;
+
+impl Scheduler for RandomInterleaving {
+    fn next(&mut self, runnable: &[usize], rng: &mut dyn RngCore) -> usize {
+        runnable[below(rng, runnable.len())]
+    }
+}
+
+/// Runs the actors in turn, skipping those with nothing left to run.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RoundRobin {
+    last: Option<usize>,
+}
+
+impl Scheduler for RoundRobin {
+    fn reset(&mut self, _actors: usize, _steps: usize, _rng: &mut dyn RngCore) {
+        self.last = None;
+    }
+
+    fn next(&mut self, runnable: &[usize], _rng: &mut dyn RngCore) -> usize {
+        let actor = match self.last {
+            Some(last) => runnable
+                .iter()
+                .copied()
+                .find(|&actor| actor > last)
+                .unwrap_or(runnable[0]),
+            None => runnable[0],
+        };
+        self.last = Some(actor);
+        actor
+    }
+}
+
+/// Probabilistic concurrency testing with a given bug depth.
+#[derive(Debug, Clone, Default)]
+pub struct Pct {
+    depth: usize,
+    priorities: Vec<usize>,
+    /// Steps lowering the running actor's priority, in ascending order.
+    change_points: Vec<usize>,
+    step: usize,
+}
+
+impl Pct {
+    /// Creates a scheduler targeting bugs of `depth` ordering constraints.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `depth` is 0.
+    pub fn new(depth: usize) -> Self {
+        assert!(depth > 0, "PCT depth must be at least 1");
+        Self {
+            depth,
+            ..Self::default()
+        }
+    }
+}
+
+impl Scheduler for Pct {
+    fn reset(&mut self, actors: usize, steps: usize, rng: &mut dyn RngCore) {
+        // Initial priorities are depth..depth + actors, shuffled; lowered
+        // ones are below depth, so they never tie with initial ones.
+        self.priorities = (self.depth..self.depth + actors).collect();
+        for i in (1..actors).rev() {
+            let j = below(rng, i + 1);
+            self.priorities.swap(i, j);
+        }
+        let mut candidates: Vec<usize> = (1..=steps).collect();
+        self.change_points.clear();
+        for _ in 1..self.depth.min(steps + 1) {
+            let i = below(rng, candidates.len());
+            self.change_points.push(candidates.swap_remove(i));
+        }
+        self.change_points.sort_unstable();
+        self.step = 0;
+    }
+
+    fn next(&mut self, runnable: &[usize], _rng: &mut dyn RngCore) -> usize {
+        self.step += 1;
+        let highest = |priorities: &[usize]| {
+            runnable
+                .iter()
+                .copied()
+                .max_by_key(|&actor| priorities.get(actor).copied().unwrap_or(0))
+                .expect("runnable actors")
+        };
+        let actor = highest(&self.priorities);
+        if let Some(i) = self.change_points.iter().position(|&s| s == self.step) {
+            if let Some(priority) = self.priorities.get_mut(actor) {
+                *priority = self.depth - 1 - i;
+            }
+            return highest(&self.priorities);
+        }
+        actor
+    }
+}
+
+/// Merges per-actor sequences into one, in the order `scheduler` picks.
+///
+/// Each actor's items keep their relative order.
+///
+/// # Panics
+///
+/// Panics if the scheduler picks an actor that is not runnable.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::gen::rng;
+/// use madhouse::schedule::{interleave, RoundRobin};
+///
+/// let merged = interleave(
+///     &mut RoundRobin::default(),
+///     vec![vec!["a1", "a2", "a3"], vec!["b1"]],
+///     &mut rng(7),
+/// );
+/// assert_eq!(merged, vec!["a1", "b1", "a2", "a3"]);
+/// ```
+pub fn interleave<T>(
+    mut scheduler: impl Scheduler,
+    actors: Vec<Vec<T>>,
+    rng: &mut dyn RngCore,
+) -> Vec<T> {
+    let steps = actors.iter().map(Vec::len).sum();
+    let mut queues: Vec<VecDeque<T>> = actors.into_iter().map(VecDeque::from).collect();
+    scheduler.reset(queues.len(), steps, rng);
+    let mut merged = Vec::with_capacity(steps);
+    loop {
+        let runnable: Vec<usize> = (0..queues.len())
+            .filter(|&actor| !queues[actor].is_empty())
+            .collect();
+        if runnable.is_empty() {
+            return merged;
+        }
+        let actor = scheduler.next(&runnable, rng);
+        assert!(
+            runnable.contains(&actor),
+            "scheduler picked actor {}, which is not runnable",
+            actor
+        );
+        merged.extend(queues[actor].pop_front());
+    }
+}
+
+/// Returns a number in `0..n`, for a non-zero `n`.
+fn below(rng: &mut dyn RngCore, n: usize) -> usize {
+    (rng.next_u64() % n as u64) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gen::rng;
+
+    fn actors() -> Vec<Vec<(usize, usize)>> {
+        (0..3)
+            .map(|actor| (0..4).map(|i| (actor, i)).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_policies_keep_each_actors_order() {
+        let mut random = rng(1);
+        let policies: Vec<Box<dyn Scheduler>> = vec![
+            Box::new(RandomInterleaving),
+            Box::new(RoundRobin::default()),
+            Box::new(Pct::new(3)),
+        ];
+        for policy in policies {
+            let merged = interleave(policy, actors(), &mut random);
+            assert_eq!(merged.len(), 12);
+            for actor in 0..3 {
+                let own: Vec<usize> = merged
+                    .iter()
+                    .filter(|(a, _)| *a == actor)
+                    .map(|(_, i)| *i)
+                    .collect();
+                assert_eq!(own, vec![0, 1, 2, 3]);
+            }
+        }
+
+        // Depth 1 has no change points: the highest-priority actor runs to
+        // completion before the next one starts.
+        let merged = interleave(Pct::new(1), actors(), &mut random);
+        for chunk in merged.chunks(4) {
+            assert!(chunk.iter().all(|(actor, _)| *actor == chunk[0].0));
+        }
+
+        // Depth 2 preempts one actor at a random step.
+        let preempted = (0..50).any(|seed| {
+            let merged = interleave(Pct::new(2), actors(), &mut rng(seed));
+            merged.windows(2).filter(|w| w[0].0 != w[1].0).count() > 2
+        });
+        assert!(preempted);
+    }
+}
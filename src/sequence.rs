@@ -0,0 +1,700 @@
+//! Command sequences that shrink by dropping commands first.
+//!
+//! [`Sequence`] generates a `Vec` of commands like
+//! [`proptest::collection::vec`], but shrinks it the way a failing trace is
+//! best debugged: it first removes whole commands, in chunks halving down to
+//! single commands and repeated until nothing more can go, and only then
+//! shrinks the parameters of the commands that remain. When parameter
+//! shrinking made progress, removal is tried again. The result is a short
+//! trace rather than a long one with tiny values.
+//!
+//! Commands of random sequences are drawn independently, or, with
+//! [`Sequence::markov`], depending on the previous command (see
+//! [`markov`](crate::markov)), or, with [`Sequence::guided`], favoring the
+//! commands that found new states (see [`guidance`](crate::guidance)), or,
+//! with [`Sequence::swarm`], from a random subset of the strategies.
+
+use crate::guidance::Guidance;
+use crate::markov::{self, Transitions};
+use proptest::prelude::RngCore;
+use proptest::strategy::{BoxedStrategy, NewTree, Strategy, ValueTree};
+use proptest::test_runner::{Reason, TestRunner};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::ops::Range;
+use std::rc::Rc;
+
+/// Strategy for a sequence of values that shrinks by removal first.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::sequence::Sequence;
+/// use proptest::strategy::{Strategy, ValueTree};
+/// use proptest::test_runner::TestRunner;
+///
+/// let sequence = Sequence::random((0..100u32).boxed(), 5..10, 1);
+/// let mut runner = TestRunner::deterministic();
+/// let mut tree = sequence.new_tree(&mut runner).unwrap();
+/// assert!((5..10).contains(&tree.current().len()));
+///
+/// // The first simplification drops half of the sequence.
+/// let len = tree.current().len();
+/// assert!(tree.simplify());
+/// assert_eq!(tree.current().len(), len - len / 2);
+/// ```
+pub struct Sequence<T> {
+    shape: Shape<T>,
+    min_len: usize,
+}
+
+enum Shape<T> {
+    /// One value per strategy, in order.
+    Fixed(Vec<BoxedStrategy<T>>),
+    /// A random number of values from one strategy.
+    Random(BoxedStrategy<T>, Range<usize>),
+    /// A random number of values from weighted strategies, picked
+    /// depending on the name of the previous value, and on how often each
+    /// name found new states if guided.
+    Markov {
+        elements: Vec<(u32, BoxedStrategy<T>)>,
+        len: Range<usize>,
+        transitions: Transitions,
+        guidance: Option<Rc<RefCell<Guidance>>>,
+        name: Box<dyn Fn(&T) -> String>,
+    },
+    /// A random number of values from a random subset of weighted
+    /// strategies.
+    Swarm(Vec<(u32, BoxedStrategy<T>)>, Range<usize>),
+    /// The values of each sequence, one after the other.
+    Phased(Vec<Sequence<T>>),
+}
+
+type Element<T> = Box<dyn ValueTree<Value = T>>;
+
+impl<T: Debug> Sequence<T> {
+    /// Generates one value per strategy, in order.
+    ///
+    /// Shrinking may drop values down to `min_len`.
+    pub fn fixed(strategies: Vec<BoxedStrategy<T>>, min_len: usize) -> Self {
+        Self {
+            shape: Shape::Fixed(strategies),
+            min_len,
+        }
+    }
+
+    /// Generates a sequence with a length drawn from `len`, each value from
+    /// `element`.
+    ///
+    /// Shrinking may drop values down to `min_len`.
+    pub fn random(element: BoxedStrategy<T>, len: Range<usize>, min_len: usize) -> Self {
+        Self {
+            shape: Shape::Random(element, len),
+            min_len,
+        }
+    }
+
+    /// Generates a sequence with a length drawn from `len`, following a
+    /// Markov chain over value names.
+    ///
+    /// Each position draws one candidate from every strategy, then picks
+    /// among them by weight, scaled by the [`Transitions`] factor from the
+    /// previous value's `name` to the candidate's. Shrinking may drop values
+    /// down to `min_len`, regardless of the transitions.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use madhouse::markov::Transitions;
+    /// use madhouse::sequence::Sequence;
+    /// use proptest::strategy::{Just, Strategy, ValueTree};
+    /// use proptest::test_runner::TestRunner;
+    ///
+    /// let sequence = Sequence::markov(
+    ///     vec![(1, Just("COMMIT").boxed()), (1, Just("MINE").boxed())],
+    ///     Transitions::new().after("COMMIT", "COMMIT", 0.0),
+    ///     |name: &&str| name.to_string(),
+    ///     20..21,
+    ///     1,
+    /// );
+    /// let mut runner = TestRunner::deterministic();
+    /// let names = sequence.new_tree(&mut runner).unwrap().current();
+    /// assert!(names.windows(2).all(|pair| pair != ["COMMIT", "COMMIT"]));
+    /// ```
+    pub fn markov(
+        elements: Vec<(u32, BoxedStrategy<T>)>,
+        transitions: Transitions,
+        name: impl Fn(&T) -> String + 'static,
+        len: Range<usize>,
+        min_len: usize,
+    ) -> Self {
+        Self {
+            shape: Shape::Markov {
+                elements,
+                len,
+                transitions,
+                guidance: None,
+                name: Box::new(name),
+            },
+            min_len,
+        }
+    }
+
+    /// Like [`markov`](Self::markov), also scaling the weight of each
+    /// candidate by the [`Guidance::factor`] of its name.
+    ///
+    /// `guidance` is read whenever a sequence is generated, so the scores
+    /// recorded while earlier sequences executed steer the next ones.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use madhouse::guidance::Guidance;
+    /// use madhouse::markov::Transitions;
+    /// use madhouse::sequence::Sequence;
+    /// use proptest::strategy::{Just, Strategy, ValueTree};
+    /// use proptest::test_runner::TestRunner;
+    /// use std::cell::RefCell;
+    /// use std::rc::Rc;
+    ///
+    /// let guidance = Rc::new(RefCell::new(Guidance::new()));
+    /// for state in 0..50 {
+    ///     guidance.borrow_mut().record("DEPOSIT", state);
+    ///     guidance.borrow_mut().record("BALANCE", 0);
+    /// }
+    /// let sequence = Sequence::guided(
+    ///     vec![(1, Just("DEPOSIT").boxed()), (1, Just("BALANCE").boxed())],
+    ///     Transitions::new(),
+    ///     guidance,
+    ///     |name: &&str| name.to_string(),
+    ///     100..101,
+    ///     1,
+    /// );
+    /// let mut runner = TestRunner::deterministic();
+    /// let names = sequence.new_tree(&mut runner).unwrap().current();
+    /// let deposits = names.iter().filter(|name| **name == "DEPOSIT").count();
+    /// assert!(deposits > 70);
+    /// ```
+    pub fn guided(
+        elements: Vec<(u32, BoxedStrategy<T>)>,
+        transitions: Transitions,
+        guidance: Rc<RefCell<Guidance>>,
+        name: impl Fn(&T) -> String + 'static,
+        len: Range<usize>,
+        min_len: usize,
+    ) -> Self {
+        Self {
+            shape: Shape::Markov {
+                elements,
+                len,
+                transitions,
+                guidance: Some(guidance),
+                name: Box::new(name),
+            },
+            min_len,
+        }
+    }
+
+    /// Generates a sequence with a length drawn from `len`, from a subset of
+    /// `elements` picked anew for each sequence (swarm testing).
+    ///
+    /// Each strategy is left out with probability one half, keeping at
+    /// least one; the values are then drawn by weight from the others. A
+    /// sequence never mixing in some commands reaches states that uniform
+    /// mixing rarely does, like a queue that only grows. Shrinking may drop
+    /// values down to `min_len`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use madhouse::sequence::Sequence;
+    /// use proptest::strategy::{Just, Strategy, ValueTree};
+    /// use proptest::test_runner::TestRunner;
+    ///
+    /// let commands = ["PUSH", "POP", "PEEK"];
+    /// let sequence = Sequence::swarm(
+    ///     commands.iter().map(|c| (1, Just(*c).boxed())).collect(),
+    ///     30..31,
+    ///     1,
+    /// );
+    /// let mut runner = TestRunner::deterministic();
+    /// let mut partial = 0;
+    /// for _ in 0..20 {
+    ///     let values = sequence.new_tree(&mut runner).unwrap().current();
+    ///     assert!(!values.is_empty());
+    ///     partial += usize::from(commands.iter().any(|c| !values.contains(c)));
+    /// }
+    /// assert!(partial > 0);
+    /// ```
+    pub fn swarm(
+        elements: Vec<(u32, BoxedStrategy<T>)>,
+        len: Range<usize>,
+        min_len: usize,
+    ) -> Self {
+        Self {
+            shape: Shape::Swarm(elements, len),
+            min_len,
+        }
+    }
+
+    /// Generates the values of every sequence in `phases`, in order.
+    ///
+    /// Each phase keeps its own shape and length range, so e.g. a fixed
+    /// bootstrap can precede a random middle and a fixed tail. Shrinking
+    /// treats the result as one sequence and may drop values of any phase
+    /// down to `min_len`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use madhouse::sequence::Sequence;
+    /// use proptest::strategy::{Just, Strategy, ValueTree};
+    /// use proptest::test_runner::TestRunner;
+    ///
+    /// let sequence = Sequence::phased(
+    ///     vec![
+    ///         Sequence::fixed(vec![Just(0).boxed(), Just(1).boxed()], 0),
+    ///         Sequence::random((10..20u32).boxed(), 3..6, 0),
+    ///     ],
+    ///     1,
+    /// );
+    /// let mut runner = TestRunner::deterministic();
+    /// let values = sequence.new_tree(&mut runner).unwrap().current();
+    /// assert_eq!(values[..2], [0, 1]);
+    /// assert!((5..8).contains(&values.len()));
+    /// ```
+    pub fn phased(phases: Vec<Sequence<T>>, min_len: usize) -> Self {
+        Self {
+            shape: Shape::Phased(phases),
+            min_len,
+        }
+    }
+}
+
+impl<T> Debug for Sequence<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match &self.shape {
+            Shape::Fixed(strategies) => write!(f, "Sequence::fixed({})", strategies.len()),
+            Shape::Random(_, len) => write!(f, "Sequence::random({:?})", len),
+            Shape::Markov {
+                elements,
+                len,
+                guidance,
+                ..
+            } => {
+                let kind = if guidance.is_some() {
+                    "guided"
+                } else {
+                    "markov"
+                };
+                write!(f, "Sequence::{}({}, {:?})", kind, elements.len(), len)
+            }
+            Shape::Swarm(elements, len) => {
+                write!(f, "Sequence::swarm({}, {:?})", elements.len(), len)
+            }
+            Shape::Phased(phases) => f.debug_tuple("Sequence::phased").field(phases).finish(),
+        }
+    }
+}
+
+impl<T: Debug> Strategy for Sequence<T> {
+    type Tree = SequenceTree<T>;
+    type Value = Vec<T>;
+
+    fn new_tree(&self, runner: &mut TestRunner) -> NewTree<Self> {
+        Ok(SequenceTree::new(self.elements(runner)?, self.min_len))
+    }
+}
+
+impl<T: Debug> Sequence<T> {
+    fn elements(&self, runner: &mut TestRunner) -> Result<Vec<Element<T>>, Reason> {
+        Ok(match &self.shape {
+            Shape::Fixed(strategies) => strategies
+                .iter()
+                .map(|s| s.new_tree(runner))
+                .collect::<Result<Vec<_>, _>>()?,
+            Shape::Random(element, len) => {
+                let len = len.new_tree(runner)?.current();
+                (0..len)
+                    .map(|_| element.new_tree(runner))
+                    .collect::<Result<Vec<_>, _>>()?
+            }
+            Shape::Markov {
+                elements,
+                len,
+                transitions,
+                guidance,
+                name,
+            } => {
+                let len = len.new_tree(runner)?.current();
+                let mut chain = Vec::with_capacity(len);
+                let mut previous: Option<String> = None;
+                for _ in 0..len {
+                    let mut candidates = elements
+                        .iter()
+                        .map(|(_, s)| s.new_tree(runner))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let names: Vec<String> =
+                        candidates.iter().map(|c| name(&c.current())).collect();
+                    let weights: Vec<f64> = match guidance {
+                        Some(guidance) => {
+                            let guidance = guidance.borrow();
+                            elements
+                                .iter()
+                                .zip(&names)
+                                .map(|((w, _), name)| f64::from(*w) * guidance.factor(name))
+                                .collect()
+                        }
+                        None => elements.iter().map(|(w, _)| f64::from(*w)).collect(),
+                    };
+                    let scaled: Vec<f64> = match &previous {
+                        Some(previous) => weights
+                            .iter()
+                            .zip(&names)
+                            .map(|(w, next)| w * transitions.factor(previous, next))
+                            .collect(),
+                        None => weights.clone(),
+                    };
+                    let picked = markov::pick(&scaled, runner.rng())
+                        .or_else(|| markov::pick(&weights, runner.rng()))
+                        .ok_or("all strategies have weight 0")?;
+                    previous = Some(names[picked].clone());
+                    chain.push(candidates.swap_remove(picked));
+                }
+                chain
+            }
+            Shape::Swarm(elements, len) => {
+                let candidates: Vec<_> = elements.iter().filter(|(w, _)| *w > 0).collect();
+                if candidates.is_empty() {
+                    return Err("all strategies have weight 0".into());
+                }
+                let mut enabled: Vec<_> = candidates
+                    .iter()
+                    .copied()
+                    .filter(|_| runner.rng().next_u32() & 1 == 1)
+                    .collect();
+                if enabled.is_empty() {
+                    let kept = runner.rng().next_u64() % candidates.len() as u64;
+                    enabled.push(candidates[kept as usize]);
+                }
+                let weights: Vec<f64> = enabled.iter().map(|(w, _)| f64::from(*w)).collect();
+                let len = len.new_tree(runner)?.current();
+                (0..len)
+                    .map(|_| {
+                        let picked = markov::pick(&weights, runner.rng()).unwrap_or(0);
+                        enabled[picked].1.new_tree(runner)
+                    })
+                    .collect::<Result<Vec<_>, _>>()?
+            }
+            Shape::Phased(phases) => {
+                let mut elements = Vec::new();
+                for phase in phases {
+                    elements.extend(phase.elements(runner)?);
+                }
+                elements
+            }
+        })
+    }
+}
+
+/// What the tree tries next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    /// Remove `chunk` included elements starting at the `start`-th one.
+    Remove { chunk: usize, start: usize },
+    /// Shrink the element at this index.
+    Shrink(usize),
+    /// Nothing left to try.
+    Done,
+}
+
+/// The last change, for [`ValueTree::complicate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Change {
+    Removed(Vec<usize>),
+    Shrunk(usize),
+}
+
+/// Value tree of a [`Sequence`].
+pub struct SequenceTree<T> {
+    elements: Vec<Box<dyn ValueTree<Value = T>>>,
+    included: Vec<bool>,
+    min_len: usize,
+    phase: Phase,
+    last: Option<Change>,
+    /// Whether an element shrink stuck since the last removal pass.
+    shrunk: bool,
+}
+
+impl<T: Debug> SequenceTree<T> {
+    fn new(elements: Vec<Box<dyn ValueTree<Value = T>>>, min_len: usize) -> Self {
+        let included = vec![true; elements.len()];
+        let mut tree = Self {
+            elements,
+            included,
+            min_len,
+            phase: Phase::Done,
+            last: None,
+            shrunk: false,
+        };
+        tree.phase = tree.first_removal();
+        tree
+    }
+
+    fn len(&self) -> usize {
+        self.included.iter().filter(|&&i| i).count()
+    }
+
+    /// Starts a removal pass with the largest chunk: half the sequence.
+    fn first_removal(&self) -> Phase {
+        let removable = self.len().saturating_sub(self.min_len);
+        if removable == 0 {
+            Phase::Shrink(0)
+        } else {
+            Phase::Remove {
+                chunk: (self.len() / 2).clamp(1, removable),
+                start: 0,
+            }
+        }
+    }
+
+    fn try_remove(&mut self, chunk: usize, start: usize) -> bool {
+        let removed: Vec<usize> = (0..self.elements.len())
+            .filter(|&i| self.included[i])
+            .skip(start)
+            .take(chunk)
+            .collect();
+        if removed.is_empty() {
+            // Past the end: halve the chunk, or move on to parameters.
+            self.phase = match chunk / 2 {
+                0 => Phase::Shrink(0),
+                half => Phase::Remove {
+                    chunk: half,
+                    start: 0,
+                },
+            };
+            return false;
+        }
+        for &i in &removed {
+            self.included[i] = false;
+        }
+        // Retry the same position: it now holds the next chunk.
+        self.last = Some(Change::Removed(removed));
+        true
+    }
+
+    fn try_shrink(&mut self, ix: usize) -> bool {
+        if ix >= self.elements.len() {
+            self.phase = if self.shrunk {
+                self.shrunk = false;
+                self.first_removal()
+            } else {
+                Phase::Done
+            };
+            return false;
+        }
+        if self.included[ix] && self.elements[ix].simplify() {
+            self.last = Some(Change::Shrunk(ix));
+            self.shrunk = true;
+            return true;
+        }
+        self.phase = Phase::Shrink(ix + 1);
+        false
+    }
+}
+
+impl<T: Debug> ValueTree for SequenceTree<T> {
+    type Value = Vec<T>;
+
+    fn current(&self) -> Vec<T> {
+        self.elements
+            .iter()
+            .zip(&self.included)
+            .filter(|(_, &included)| included)
+            .map(|(element, _)| element.current())
+            .collect()
+    }
+
+    fn simplify(&mut self) -> bool {
+        loop {
+            let changed = match self.phase {
+                Phase::Remove { chunk, start } => {
+                    if self.len() - chunk.min(self.len()) < self.min_len {
+                        // Too few elements left for this chunk size.
+                        self.phase = match chunk / 2 {
+                            0 => Phase::Shrink(0),
+                            half => Phase::Remove {
+                                chunk: half,
+                                start: 0,
+                            },
+                        };
+                        false
+                    } else {
+                        self.try_remove(chunk, start)
+                    }
+                }
+                Phase::Shrink(ix) => self.try_shrink(ix),
+                Phase::Done => return false,
+            };
+            if changed {
+                return true;
+            }
+        }
+    }
+
+    fn complicate(&mut self) -> bool {
+        match self.last.take() {
+            None => false,
+            Some(Change::Removed(removed)) => {
+                for &i in &removed {
+                    self.included[i] = true;
+                }
+                // Keep the chunk, skip past it.
+                if let Phase::Remove { chunk, start } = self.phase {
+                    self.phase = Phase::Remove {
+                        chunk,
+                        start: start + chunk,
+                    };
+                }
+                true
+            }
+            Some(Change::Shrunk(ix)) if self.elements[ix].complicate() => {
+                // The element may be able to complicate again.
+                self.last = Some(Change::Shrunk(ix));
+                true
+            }
+            Some(Change::Shrunk(_)) => false,
+        }
+    }
+}
+
+/// Every sequence of up to `max_len` values, one strategy per position,
+/// shortest first (see [`Mode::Exhaustive`](crate::config::Mode::Exhaustive)).
+///
+/// Only sequences passed to [`extend_last`](Self::extend_last) get longer
+/// ones built on them, so the caller prunes the tree.
+pub(crate) struct Enumeration<T> {
+    strategies: Vec<BoxedStrategy<T>>,
+    max_len: usize,
+    /// Sequences to extend, shortest first.
+    prefixes: VecDeque<Vec<T>>,
+    /// Sequence being extended, and the strategy appended next.
+    prefix: Option<(Vec<T>, usize)>,
+    last: Vec<T>,
+}
+
+impl<T: Clone + Debug> Enumeration<T> {
+    pub(crate) fn new(strategies: Vec<BoxedStrategy<T>>, max_len: usize) -> Self {
+        let prefix = (max_len > 0 && !strategies.is_empty()).then(|| (Vec::new(), 0));
+        Self {
+            strategies,
+            max_len,
+            prefixes: VecDeque::new(),
+            prefix,
+            last: Vec::new(),
+        }
+    }
+
+    /// Returns the next sequence, or `None` once all were returned.
+    pub(crate) fn next(&mut self, runner: &mut TestRunner) -> Option<Result<Vec<T>, Reason>> {
+        loop {
+            match &mut self.prefix {
+                Some((prefix, next)) if *next < self.strategies.len() => {
+                    let value = match self.strategies[*next].new_tree(runner) {
+                        Ok(tree) => tree.current(),
+                        Err(reason) => return Some(Err(reason)),
+                    };
+                    *next += 1;
+                    self.last = prefix.clone();
+                    self.last.push(value);
+                    return Some(Ok(self.last.clone()));
+                }
+                _ => self.prefix = Some((self.prefixes.pop_front()?, 0)),
+            }
+        }
+    }
+
+    /// Queues the sequence last returned for extension, unless it is
+    /// `max_len` long.
+    pub(crate) fn extend_last(&mut self) {
+        if self.last.len() < self.max_len {
+            self.prefixes.push_back(std::mem::take(&mut self.last));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Shrinks like the test runner does and returns the last failing
+    /// value.
+    fn minimize<T: Debug>(tree: &mut SequenceTree<T>, fails: impl Fn(&[T]) -> bool) -> Vec<T> {
+        let mut minimal = tree.current();
+        if !tree.simplify() {
+            return minimal;
+        }
+        loop {
+            let current = tree.current();
+            let more = if fails(&current) {
+                minimal = current;
+                tree.simplify()
+            } else {
+                tree.complicate()
+            };
+            if !more {
+                return minimal;
+            }
+        }
+    }
+
+    #[test]
+    fn test_removes_commands_before_shrinking_values() {
+        let sequence = Sequence::random((0..1000u32).boxed(), 30..31, 1);
+        let mut runner = TestRunner::deterministic();
+        let mut tree = sequence.new_tree(&mut runner).unwrap();
+        // Fails when the values sum above 100: one large value suffices.
+        let fails = |values: &[u32]| values.iter().sum::<u32>() > 100;
+        assert!(fails(&tree.current()));
+
+        assert_eq!(minimize(&mut tree, fails), vec![101]);
+    }
+
+    #[test]
+    fn test_enumeration_extends_only_kept_sequences() {
+        let strategies = (0..3u32)
+            .map(|i| proptest::strategy::Just(i).boxed())
+            .collect();
+        let mut enumeration = Enumeration::new(strategies, 3);
+        let mut runner = TestRunner::deterministic();
+        let mut sequences = Vec::new();
+        while let Some(sequence) = enumeration.next(&mut runner) {
+            let sequence = sequence.unwrap();
+            // Prune every sequence ending in 2.
+            if sequence.last() != Some(&2) {
+                enumeration.extend_last();
+            }
+            sequences.push(sequence);
+        }
+
+        assert_eq!(sequences.len(), 3 + 2 * 3 + 4 * 3);
+        assert_eq!(sequences[..4], [vec![0], vec![1], vec![2], vec![0, 0]]);
+        assert_eq!(sequences.last(), Some(&vec![1, 1, 2]));
+    }
+
+    #[test]
+    fn test_fixed_sequences_keep_order_and_min_len() {
+        let strategies = (0..6u32)
+            .map(|i| proptest::strategy::Just(i).boxed())
+            .collect();
+        let sequence = Sequence::fixed(strategies, 2);
+        let mut runner = TestRunner::deterministic();
+        let mut tree = sequence.new_tree(&mut runner).unwrap();
+
+        assert_eq!(
+            minimize(&mut tree, |values| values.contains(&4)),
+            vec![4, 5]
+        );
+    }
+}
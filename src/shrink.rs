@@ -0,0 +1,138 @@
+//! Structural shrinking of single commands.
+//!
+//! [`Structural`] wraps a command strategy so that, once the strategy's own
+//! value tree cannot simplify a command any further, the command's
+//! [`Command::shrink`](crate::Command::shrink) variants are tried too.
+//! Scenarios wrap every strategy they are given.
+
+use crate::scenario::CommandStrategy;
+use crate::{CommandWrapper, State, TestContext};
+use proptest::strategy::{NewTree, Strategy, ValueTree};
+use proptest::test_runner::TestRunner;
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+
+/// Strategy shrinking commands with their custom shrinkers after the
+/// inner strategy's value tree.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::shrink::Structural;
+/// use madhouse::{Command, CommandWrapper, State, TestContext};
+/// use proptest::prelude::*;
+/// use proptest::strategy::ValueTree;
+/// use proptest::test_runner::TestRunner;
+/// use std::sync::Arc;
+///
+/// #[derive(Debug, Default)]
+/// struct Counter { value: u64 }
+/// impl State for Counter {}
+///
+/// #[derive(Debug, Clone, Default)]
+/// struct Ctx {}
+/// impl TestContext for Ctx {}
+///
+/// struct Add { amount: u64 }
+/// impl Command<Counter, Ctx> for Add {
+///     fn check(&self, _state: &Counter) -> bool { true }
+///     fn apply(&self, state: &mut Counter) { state.value += self.amount; }
+///     fn label(&self) -> String { format!("ADD({})", self.amount) }
+///     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Counter, Ctx>> {
+///         Just(CommandWrapper::new(Add { amount: 8 }))
+///     }
+///     fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+///         Box::new((self.amount > 0).then(|| Add { amount: self.amount / 2 }).into_iter())
+///     }
+/// }
+///
+/// let strategy = Structural::new(Add::build(Arc::new(Ctx::default())).boxed());
+/// let mut tree = strategy.new_tree(&mut TestRunner::deterministic()).unwrap();
+/// assert!(tree.simplify());
+/// assert_eq!(tree.current().command.label(), "ADD(4)");
+/// ```
+pub struct Structural<S: State, C: TestContext> {
+    inner: CommandStrategy<S, C>,
+}
+
+impl<S: State, C: TestContext> Structural<S, C> {
+    /// Wraps a command strategy.
+    pub fn new(inner: CommandStrategy<S, C>) -> Self {
+        Self { inner }
+    }
+}
+
+impl<S: State, C: TestContext> Debug for Structural<S, C> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "Structural({:?})", self.inner)
+    }
+}
+
+impl<S: State, C: TestContext> Strategy for Structural<S, C> {
+    type Tree = StructuralTree<S, C>;
+    type Value = CommandWrapper<S, C>;
+
+    fn new_tree(&self, runner: &mut TestRunner) -> NewTree<Self> {
+        Ok(StructuralTree {
+            inner: self.inner.new_tree(runner)?,
+            custom: None,
+        })
+    }
+}
+
+/// Custom shrinking in progress.
+struct Custom<S: State, C: TestContext> {
+    /// Simplest variant known to still fail.
+    accepted: CommandWrapper<S, C>,
+    /// Remaining variants of `accepted`.
+    candidates: Box<dyn Iterator<Item = CommandWrapper<S, C>>>,
+    /// Variant being tried, if any.
+    trying: Option<CommandWrapper<S, C>>,
+}
+
+/// Value tree of a [`Structural`] strategy.
+pub struct StructuralTree<S: State, C: TestContext> {
+    inner: Box<dyn ValueTree<Value = CommandWrapper<S, C>>>,
+    custom: Option<Custom<S, C>>,
+}
+
+impl<S: State, C: TestContext> ValueTree for StructuralTree<S, C> {
+    type Value = CommandWrapper<S, C>;
+
+    fn current(&self) -> CommandWrapper<S, C> {
+        match &self.custom {
+            Some(custom) => custom.trying.as_ref().unwrap_or(&custom.accepted).clone(),
+            None => self.inner.current(),
+        }
+    }
+
+    fn simplify(&mut self) -> bool {
+        let custom = match &mut self.custom {
+            Some(custom) => custom,
+            None => {
+                if self.inner.simplify() {
+                    return true;
+                }
+                let accepted = self.inner.current();
+                self.custom.insert(Custom {
+                    candidates: accepted.shrink(),
+                    accepted,
+                    trying: None,
+                })
+            }
+        };
+        // A variant still being tried was not complicated: it still fails.
+        if let Some(trying) = custom.trying.take() {
+            custom.candidates = trying.shrink();
+            custom.accepted = trying;
+        }
+        custom.trying = custom.candidates.next();
+        custom.trying.is_some()
+    }
+
+    fn complicate(&mut self) -> bool {
+        match &mut self.custom {
+            Some(custom) => custom.trying.take().is_some(),
+            None => self.inner.complicate(),
+        }
+    }
+}
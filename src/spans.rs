@@ -0,0 +1,200 @@
+//! `tracing` spans for runs and commands, with the `tracing` feature.
+//!
+//! Every execution of a sequence is wrapped in a `madhouse.run` span, and
+//! every selected command in a `madhouse.command` span entered around its
+//! `check` and `apply`. Events the system under test emits while a command
+//! runs are therefore recorded inside that command's span, next to its
+//! label, index and group. Once the step ends, its span records whether the
+//! command was skipped, how long `apply` took, and the error of a failed
+//! step; the run span records how many commands were executed, skipped and
+//! failed. Spans are at the `INFO` level and cost nothing without a
+//! subscriber.
+
+use crate::{ExecutionReport, StepOutcome, StepReport};
+use tracing::field::{debug, display, Empty};
+use tracing::span::EnteredSpan;
+
+/// Enters the span of a run of `commands` selected commands.
+pub(crate) fn run(commands: usize) -> EnteredSpan {
+    tracing::info_span!(
+        "madhouse.run",
+        commands,
+        executed = Empty,
+        skipped = Empty,
+        failed = Empty,
+    )
+    .entered()
+}
+
+/// Records the outcome of the run on its span.
+pub(crate) fn finish_run(span: &EnteredSpan, report: &ExecutionReport) {
+    span.record("executed", report.executed().count());
+    span.record("skipped", report.skipped().count());
+    span.record("failed", report.failures().count());
+}
+
+/// Enters the span of step `index`, the command `label` of `group`.
+pub(crate) fn step(index: usize, label: &str, group: &str) -> EnteredSpan {
+    tracing::info_span!(
+        "madhouse.command",
+        index,
+        label,
+        group,
+        skipped = Empty,
+        duration = Empty,
+        error = Empty,
+    )
+    .entered()
+}
+
+/// Records the outcome of `step` on its span.
+pub(crate) fn finish_step(span: &EnteredSpan, step: &StepReport) {
+    span.record("skipped", !step.was_applied());
+    span.record("duration", debug(step.duration));
+    if let StepOutcome::Failed(err) = &step.outcome {
+        span.record("error", display(err));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{execute_commands_silent, Command, CommandWrapper, State, TestContext};
+    use proptest::prelude::{Just, Strategy};
+    use std::fmt::Debug;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    /// Fields of each span, in creation order, and the span of each event.
+    #[derive(Default)]
+    struct Recorded {
+        spans: Vec<(&'static str, Vec<(String, String)>)>,
+        current: Vec<u64>,
+        events: Vec<Option<u64>>,
+    }
+
+    struct Fields<'a>(&'a mut Vec<(String, String)>);
+
+    impl Visit for Fields<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+            self.0.retain(|(name, _)| name != field.name());
+            self.0
+                .push((field.name().to_string(), format!("{:?}", value)));
+        }
+    }
+
+    #[derive(Default)]
+    struct Recorder {
+        next: AtomicU64,
+        recorded: Arc<Mutex<Recorded>>,
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let mut fields = Vec::new();
+            span.record(&mut Fields(&mut fields));
+            let mut recorded = self.recorded.lock().unwrap();
+            recorded.spans.push((span.metadata().name(), fields));
+            Id::from_u64(self.next.fetch_add(1, Ordering::Relaxed) + 1)
+        }
+
+        fn record(&self, span: &Id, values: &Record<'_>) {
+            let mut recorded = self.recorded.lock().unwrap();
+            let fields = &mut recorded.spans[span.into_u64() as usize - 1].1;
+            values.record(&mut Fields(fields));
+        }
+
+        fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+        fn event(&self, _event: &Event<'_>) {
+            let mut recorded = self.recorded.lock().unwrap();
+            let current = recorded.current.last().copied();
+            recorded.events.push(current);
+        }
+
+        fn enter(&self, span: &Id) {
+            self.recorded.lock().unwrap().current.push(span.into_u64());
+        }
+
+        fn exit(&self, _span: &Id) {
+            self.recorded.lock().unwrap().current.pop();
+        }
+    }
+
+    #[derive(Debug, Default)]
+    struct Log {
+        lines: usize,
+    }
+
+    impl State for Log {}
+
+    #[derive(Debug, Clone, Default)]
+    struct Ctx {}
+
+    impl TestContext for Ctx {}
+
+    struct Write(usize);
+
+    impl Command<Log, Ctx> for Write {
+        fn check(&self, state: &Log) -> bool {
+            state.lines < self.0
+        }
+
+        fn apply(&self, state: &mut Log) {
+            tracing::info!("writing");
+            state.lines += 1;
+        }
+
+        fn label(&self) -> String {
+            format!("WRITE({})", self.0)
+        }
+
+        fn group(&self) -> String {
+            "log".to_string()
+        }
+
+        fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Log, Ctx>> {
+            Just(CommandWrapper::new(Write(1)))
+        }
+    }
+
+    #[test]
+    fn test_runs_and_commands_are_traced_as_spans() {
+        let recorder = Recorder::default();
+        let recorded = recorder.recorded.clone();
+        let commands = [Write(1), Write(1)].map(CommandWrapper::new);
+
+        tracing::subscriber::with_default(recorder, || {
+            execute_commands_silent(&commands, &mut Log::default());
+        });
+
+        let recorded = recorded.lock().unwrap();
+        let field = |span: usize, name: &str| {
+            let (_, fields) = &recorded.spans[span];
+            let (_, value) = fields.iter().find(|(field, _)| field == name).unwrap();
+            value.clone()
+        };
+        let names: Vec<_> = recorded.spans.iter().map(|(name, _)| *name).collect();
+        assert_eq!(
+            names,
+            ["madhouse.run", "madhouse.command", "madhouse.command"]
+        );
+        assert_eq!(field(0, "commands"), "2");
+        assert_eq!(field(0, "executed"), "1");
+        assert_eq!(field(0, "skipped"), "1");
+        assert_eq!(field(1, "label"), "\"WRITE(1)\"");
+        assert_eq!(field(1, "group"), "\"log\"");
+        assert_eq!(field(1, "skipped"), "false");
+        assert_eq!(field(2, "skipped"), "true");
+        assert_eq!(field(2, "duration"), "0ns");
+
+        // The command's own event is recorded inside its span.
+        assert_eq!(recorded.events, [Some(2)]);
+    }
+}
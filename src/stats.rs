@@ -0,0 +1,201 @@
+//! Labels classifying the generated cases, like QuickCheck's `label`.
+//!
+//! Whether random generation reaches the interesting situations (a
+//! sortition with two competing commits, a withdrawal of the full balance)
+//! is not visible from command coverage alone. Commands record what they
+//! observe with [`classify`] or [`collect`] while a sequence executes; the
+//! labels end up in [`ExecutionReport::labels`](crate::ExecutionReport::labels)
+//! and a scenario prints, at the end of a run, the share of cases in which
+//! each label was recorded.
+//!
+//! Labels are recorded per execution, on the executing thread; outside an
+//! execution, they are ignored.
+//!
+//! # Examples
+//!
+//! ```
+//! use madhouse::stats::{self, Statistics};
+//! use madhouse::{execute_commands, Command, CommandWrapper, State, TestContext};
+//! use proptest::prelude::*;
+//! use std::sync::Arc;
+//!
+//! #[derive(Debug, Default)]
+//! struct Chain { commits: usize }
+//! impl State for Chain {}
+//!
+//! #[derive(Debug, Clone, Default)]
+//! struct Ctx {}
+//! impl TestContext for Ctx {}
+//!
+//! struct Commit;
+//! impl Command<Chain, Ctx> for Commit {
+//!     fn check(&self, _state: &Chain) -> bool { true }
+//!     fn apply(&self, state: &mut Chain) { state.commits += 1; }
+//!     fn label(&self) -> String { "COMMIT".to_string() }
+//!     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Chain, Ctx>> {
+//!         Just(CommandWrapper::new(Commit))
+//!     }
+//! }
+//!
+//! struct Sortition;
+//! impl Command<Chain, Ctx> for Sortition {
+//!     fn check(&self, _state: &Chain) -> bool { true }
+//!     fn apply(&self, state: &mut Chain) {
+//!         stats::classify(state.commits == 0, "empty sortition");
+//!         stats::collect(format!("sortition with {} commits", state.commits));
+//!         state.commits = 0;
+//!     }
+//!     fn label(&self) -> String { "SORTITION".to_string() }
+//!     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Chain, Ctx>> {
+//!         Just(CommandWrapper::new(Sortition))
+//!     }
+//! }
+//!
+//! let mut statistics = Statistics::default();
+//! for commits in [0, 2, 2, 1] {
+//!     let mut commands: Vec<_> = (0..commits).map(|_| CommandWrapper::new(Commit)).collect();
+//!     commands.push(CommandWrapper::new(Sortition));
+//!     let report = execute_commands(&commands, &mut Chain::default());
+//!     statistics.record(&report);
+//! }
+//!
+//! assert_eq!(statistics.cases, 4);
+//! assert_eq!(statistics.get("sortition with 2 commits"), 2);
+//! assert_eq!(
+//!     statistics.to_string(),
+//!     "Labels (4 cases):\n\
+//!      50.00% sortition with 2 commits\n\
+//!      25.00% empty sortition\n\
+//!      25.00% sortition with 0 commits\n\
+//!      25.00% sortition with 1 commits"
+//! );
+//! ```
+
+use crate::ExecutionReport;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+thread_local! {
+    static LABELS: RefCell<Option<BTreeSet<String>>> = const { RefCell::new(None) };
+}
+
+/// Records `label` for the executing sequence if `condition` holds.
+pub fn classify(condition: bool, label: impl Into<String>) {
+    if condition {
+        record(label.into());
+    }
+}
+
+/// Records `value`, rendered with `Display`, as a label of the executing
+/// sequence.
+pub fn collect(value: impl Display) {
+    record(value.to_string());
+}
+
+fn record(label: String) {
+    LABELS.with(|labels| {
+        if let Some(labels) = labels.borrow_mut().as_mut() {
+            labels.insert(label);
+        }
+    });
+}
+
+/// Collects the labels of one execution until finished or dropped, then
+/// restores those of an enclosing execution.
+pub(crate) struct Scope {
+    outer: Option<BTreeSet<String>>,
+}
+
+impl Scope {
+    pub(crate) fn enter() -> Self {
+        let outer = LABELS.with(|labels| labels.borrow_mut().replace(BTreeSet::new()));
+        Self { outer }
+    }
+
+    /// Ends the execution, returning its labels.
+    pub(crate) fn finish(self) -> BTreeSet<String> {
+        LABELS.with(|labels| {
+            labels
+                .borrow_mut()
+                .as_mut()
+                .map(std::mem::take)
+                .unwrap_or_default()
+        })
+    }
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        let outer = self.outer.take();
+        LABELS.with(|labels| *labels.borrow_mut() = outer);
+    }
+}
+
+/// Number of cases each label was recorded in.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Statistics {
+    /// Cases recorded.
+    pub cases: usize,
+    labels: BTreeMap<String, usize>,
+}
+
+impl Statistics {
+    /// Records the labels of one case.
+    pub fn record(&mut self, report: &ExecutionReport) {
+        self.cases += 1;
+        for label in &report.labels {
+            *self.labels.entry(label.clone()).or_default() += 1;
+        }
+    }
+
+    /// Returns the number of cases `label` was recorded in.
+    pub fn get(&self, label: &str) -> usize {
+        self.labels.get(label).copied().unwrap_or_default()
+    }
+
+    /// Returns true if no label was recorded.
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+}
+
+/// Prints the share of cases of each label, most frequent first.
+impl Display for Statistics {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "Labels ({} cases):", self.cases)?;
+        let mut labels: Vec<_> = self.labels.iter().collect();
+        labels.sort_by(|a, b| b.1.cmp(a.1));
+        for (label, count) in labels {
+            let share = 100.0 * *count as f64 / self.cases.max(1) as f64;
+            write!(f, "\n{:.2}% {}", share, label)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_labels_are_recorded_once_per_scope() {
+        collect("outside a scope");
+
+        let outer = Scope::enter();
+        classify(true, "outer");
+        classify(false, "never");
+        collect(3);
+        collect(3);
+        {
+            let inner = Scope::enter();
+            collect("inner");
+            assert_eq!(inner.finish(), BTreeSet::from(["inner".to_string()]));
+        }
+        assert_eq!(
+            outer.finish(),
+            BTreeSet::from(["3".to_string(), "outer".to_string()])
+        );
+        assert!(LABELS.with(|labels| labels.borrow().is_none()));
+    }
+}
@@ -0,0 +1,150 @@
+//! Summary of named scenarios.
+//!
+//! With dozens of scenarios in one test binary, their output interleaves
+//! and is hard to attribute. Every scenario given a name (see
+//! [`Scenario::name`](crate::Scenario::name)) records its outcome here when
+//! its run ends, and prints it as one line to stderr unless it ran quiet.
+//! [`print`] prints a table of all of them, e.g. from a test running last
+//! or a custom test harness:
+//!
+//! ```text
+//! madhouse: 3 named scenarios, 1 failed
+//!   PASS  deposit flow (100 cases)
+//!   FAIL  overdraft (12 cases, 1 distinct failure)
+//!   PASS  transfers (100 cases)
+//! ```
+
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::sync::{Mutex, PoisonError};
+
+/// Outcomes recorded so far, in the order the runs ended.
+static OUTCOMES: Mutex<Vec<Outcome>> = Mutex::new(Vec::new());
+
+/// How the run of a named scenario ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outcome {
+    /// Name of the scenario.
+    pub name: String,
+    /// Number of cases executed.
+    pub cases: u32,
+    /// Number of distinct failing cases.
+    pub failures: usize,
+}
+
+impl Outcome {
+    /// Returns true if no case failed.
+    pub fn passed(&self) -> bool {
+        self.failures == 0
+    }
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "{}  {} ({} case{}",
+            if self.passed() { "PASS" } else { "FAIL" },
+            self.name,
+            self.cases,
+            if self.cases == 1 { "" } else { "s" }
+        )?;
+        match self.failures {
+            0 => write!(f, ")"),
+            1 => write!(f, ", 1 distinct failure)"),
+            n => write!(f, ", {} distinct failures)", n),
+        }
+    }
+}
+
+/// A table of outcomes, one line per scenario.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::summary::{Outcome, Summary};
+///
+/// let outcomes = [
+///     Outcome { name: "deposit flow".to_string(), cases: 100, failures: 0 },
+///     Outcome { name: "overdraft".to_string(), cases: 12, failures: 1 },
+/// ];
+/// assert_eq!(
+///     Summary(&outcomes).to_string(),
+///     "madhouse: 2 named scenarios, 1 failed\n\
+///      \x20 PASS  deposit flow (100 cases)\n\
+///      \x20 FAIL  overdraft (12 cases, 1 distinct failure)\n"
+/// );
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Summary<'a>(pub &'a [Outcome]);
+
+impl Display for Summary<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let failed = self.0.iter().filter(|outcome| !outcome.passed()).count();
+        writeln!(
+            f,
+            "madhouse: {} named scenario{}, {} failed",
+            self.0.len(),
+            if self.0.len() == 1 { "" } else { "s" },
+            failed
+        )?;
+        for outcome in self.0 {
+            writeln!(f, "  {}", outcome)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the outcomes of the named scenarios whose runs ended so far in
+/// this process, in the order they ended.
+pub fn outcomes() -> Vec<Outcome> {
+    OUTCOMES
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+}
+
+/// Prints the table of the named scenarios whose runs ended so far to
+/// stderr.
+pub fn print() {
+    eprint!("\n{}", Summary(&outcomes()));
+}
+
+/// Records the outcome of a named scenario, printing it to stderr if
+/// `print` is set.
+pub(crate) fn record(outcome: Outcome, print: bool) {
+    if print {
+        eprintln!("madhouse: {}", outcome);
+    }
+    OUTCOMES
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .push(outcome);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_pluralizes_and_counts_failures() {
+        let outcomes = [
+            Outcome {
+                name: "one".to_string(),
+                cases: 1,
+                failures: 0,
+            },
+            Outcome {
+                name: "two".to_string(),
+                cases: 5,
+                failures: 2,
+            },
+        ];
+        assert_eq!(
+            Summary(&outcomes[..1]).to_string(),
+            "madhouse: 1 named scenario, 0 failed\n  PASS  one (1 case)\n"
+        );
+        assert!(Summary(&outcomes)
+            .to_string()
+            .ends_with("  FAIL  two (5 cases, 2 distinct failures)\n"));
+    }
+}
@@ -0,0 +1,313 @@
+//! Comparing the model with the real system, step by step.
+//!
+//! A model-based test applies every command twice: to the in-memory model
+//! [`State`] and to the implementation. Rather than doing both inside
+//! `apply` and comparing by hand, implement [`SystemUnderTest`]: the
+//! executor applies each command the model applied to the system too,
+//! observes it, and compares the observation with the model. The first
+//! mismatch fails the step, with a diff of what the model predicted and
+//! what the system showed. Run with [`execute_against`], or attach to a
+//! scenario with [`Scenario::system_under_test`](crate::Scenario::system_under_test).
+//!
+//! # Examples
+//!
+//! ```
+//! use madhouse::sut::{execute_against, Mismatch, SystemUnderTest};
+//! use madhouse::{Command, CommandError, CommandWrapper, State, TestContext};
+//! use proptest::prelude::*;
+//! use std::panic::AssertUnwindSafe;
+//! use std::sync::Arc;
+//!
+//! #[derive(Debug, Default)]
+//! struct Model { items: Vec<u32> }
+//! impl State for Model {}
+//!
+//! #[derive(Debug, Clone, Default)]
+//! struct Ctx {}
+//! impl TestContext for Ctx {}
+//!
+//! struct Push(u32);
+//! impl Command<Model, Ctx> for Push {
+//!     fn check(&self, _state: &Model) -> bool { true }
+//!     fn apply(&self, state: &mut Model) { state.items.push(self.0); }
+//!     fn label(&self) -> String { format!("PUSH({})", self.0) }
+//!     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Model, Ctx>> {
+//!         any::<u32>().prop_map(|n| CommandWrapper::new(Push(n)))
+//!     }
+//! }
+//!
+//! /// A buggy stack, keeping at most two items.
+//! #[derive(Default)]
+//! struct Bounded { items: Vec<u32> }
+//! impl SystemUnderTest<Model, Ctx> for Bounded {
+//!     type Observation = Vec<u32>;
+//!     fn apply(&mut self, command: &CommandWrapper<Model, Ctx>) -> Result<(), CommandError> {
+//!         if let Some(Push(n)) = command.downcast_ref::<Push>() {
+//!             self.items.push(*n);
+//!             self.items.truncate(2);
+//!         }
+//!         Ok(())
+//!     }
+//!     fn observe(&mut self) -> Vec<u32> { self.items.clone() }
+//!     fn compare(&self, model: &Model, observed: &Vec<u32>) -> Result<(), Mismatch> {
+//!         Mismatch::check(&model.items, observed)
+//!     }
+//! }
+//!
+//! let commands: Vec<_> = (1..=3).map(|n| CommandWrapper::new(Push(n))).collect();
+//! let panic = std::panic::catch_unwind(AssertUnwindSafe(|| {
+//!     execute_against(&commands, &mut Model::default(), &mut Bounded::default())
+//! }))
+//! .unwrap_err();
+//! let message = panic.downcast_ref::<String>().unwrap();
+//! assert!(message.starts_with("panicked in step 2 (PUSH(3)): after PUSH(3): system under test"));
+//! assert!(message.contains("-     3,\n"));
+//! ```
+
+use crate::diff::StateDiff;
+use crate::observer::Observer;
+use crate::{run_commands, CommandError, CommandWrapper, ExecutionReport, State, StepReport};
+use crate::{StepOutcome, TestContext};
+use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
+
+/// The real system a model is compared with.
+pub trait SystemUnderTest<S: State, C: TestContext> {
+    /// What can be observed of the system, for comparing with the model.
+    type Observation: Debug;
+
+    /// Applies `command` to the system, after the model applied it.
+    ///
+    /// Commands are told apart with
+    /// [`CommandWrapper::downcast_ref`] or by their label.
+    fn apply(&mut self, command: &CommandWrapper<S, C>) -> Result<(), CommandError>;
+
+    /// Observes the system, after each applied command.
+    fn observe(&mut self) -> Self::Observation;
+
+    /// Compares the model with an observation of the system.
+    fn compare(&self, model: &S, observation: &Self::Observation) -> Result<(), Mismatch>;
+
+    /// Brings the system back to its initial state, before each scenario
+    /// case. The default does nothing.
+    fn reset(&mut self) {}
+}
+
+/// A difference between the model and the system.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    /// What differs.
+    pub message: String,
+    /// Diff of the model's prediction (`-`) against the observation (`+`),
+    /// if rendered.
+    pub diff: Option<StateDiff>,
+}
+
+impl Mismatch {
+    /// Creates a mismatch described by `message`.
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            diff: None,
+        }
+    }
+
+    /// Returns a mismatch, with the diff of their `Debug` output, if the
+    /// value the model `expected` differs from the `observed` one.
+    pub fn check<T: Debug + PartialEq + ?Sized>(expected: &T, observed: &T) -> Result<(), Self> {
+        if expected == observed {
+            return Ok(());
+        }
+        Err(Self {
+            message: "system under test differs from the model".to_string(),
+            diff: Some(StateDiff::between(expected, observed)),
+        })
+    }
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}", self.message)?;
+        if let Some(diff) = &self.diff {
+            write!(f, " (- model, + system):\n{}", diff)?;
+        }
+        Ok(())
+    }
+}
+
+/// Observer applying every command the model applied to a system, and
+/// panicking on the first mismatch.
+pub(crate) struct Comparison<S: State, C: TestContext, T> {
+    sut: T,
+    command: Option<CommandWrapper<S, C>>,
+}
+
+impl<S: State, C: TestContext, T: SystemUnderTest<S, C>> Comparison<S, C, T> {
+    pub(crate) fn new(sut: T) -> Self {
+        Self { sut, command: None }
+    }
+}
+
+impl<S: State, C: TestContext, T: SystemUnderTest<S, C>> Observer<S, C> for Comparison<S, C, T> {
+    fn on_before_apply(&mut self, _index: usize, command: &CommandWrapper<S, C>, _state: &S) {
+        self.command = Some(command.clone());
+    }
+
+    fn on_after_apply(&mut self, step: &StepReport, state: &S) {
+        let Some(command) = self.command.take() else {
+            return;
+        };
+        // The step already failed on the model.
+        if !matches!(step.outcome, StepOutcome::Executed) {
+            return;
+        }
+        if let Err(err) = self.sut.apply(&command) {
+            panic!("system under test failed to apply {}: {}", step.label, err);
+        }
+        let observation = self.sut.observe();
+        if let Err(mismatch) = self.sut.compare(state, &observation) {
+            panic!("after {}: {}", step.label, mismatch);
+        }
+    }
+}
+
+/// A [`Comparison`] whose system can be reset, with its type erased.
+pub(crate) trait Harness<S: State, C: TestContext>: Observer<S, C> {
+    fn reset(&mut self);
+}
+
+impl<S: State, C: TestContext, T: SystemUnderTest<S, C>> Harness<S, C> for Comparison<S, C, T> {
+    fn reset(&mut self) {
+        self.command = None;
+        self.sut.reset();
+    }
+}
+
+/// Executes a sequence of commands on the model and on `sut`, comparing
+/// them after every applied command.
+///
+/// Behaves like [`execute_commands_with`](crate::observer::execute_commands_with)
+/// otherwise.
+///
+/// # Panics
+///
+/// Panics if the system fails to apply a command the model applied, or
+/// does not match the model afterwards.
+pub fn execute_against<S: State, C: TestContext>(
+    commands: &[CommandWrapper<S, C>],
+    state: &mut S,
+    sut: &mut impl SystemUnderTest<S, C>,
+) -> ExecutionReport {
+    run_commands(commands, state, &[], &mut Comparison::new(sut))
+}
+
+impl<S, C, T> SystemUnderTest<S, C> for &mut T
+where
+    S: State,
+    C: TestContext,
+    T: SystemUnderTest<S, C> + ?Sized,
+{
+    type Observation = T::Observation;
+
+    fn apply(&mut self, command: &CommandWrapper<S, C>) -> Result<(), CommandError> {
+        (**self).apply(command)
+    }
+
+    fn observe(&mut self) -> Self::Observation {
+        (**self).observe()
+    }
+
+    fn compare(&self, model: &S, observation: &Self::Observation) -> Result<(), Mismatch> {
+        (**self).compare(model, observation)
+    }
+
+    fn reset(&mut self) {
+        (**self).reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{panics, Command};
+    use proptest::prelude::{Just, Strategy};
+    use std::sync::Arc;
+
+    #[derive(Debug, Default)]
+    struct Counter {
+        value: u64,
+    }
+
+    impl State for Counter {}
+
+    #[derive(Debug, Clone, Default)]
+    struct Ctx {}
+
+    impl TestContext for Ctx {}
+
+    struct Add(u64);
+
+    impl Command<Counter, Ctx> for Add {
+        fn check(&self, _state: &Counter) -> bool {
+            self.0 > 0
+        }
+
+        fn apply(&self, state: &mut Counter) {
+            state.value += self.0;
+        }
+
+        fn label(&self) -> String {
+            format!("ADD({})", self.0)
+        }
+
+        fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Counter, Ctx>> {
+            Just(CommandWrapper::new(Add(1)))
+        }
+    }
+
+    /// Saturates at 5.
+    #[derive(Default)]
+    struct Saturating {
+        value: u64,
+        applied: usize,
+    }
+
+    impl SystemUnderTest<Counter, Ctx> for Saturating {
+        type Observation = u64;
+
+        fn apply(&mut self, command: &CommandWrapper<Counter, Ctx>) -> Result<(), CommandError> {
+            let Add(n) = command.downcast_ref::<Add>().unwrap();
+            self.value = (self.value + n).min(5);
+            self.applied += 1;
+            Ok(())
+        }
+
+        fn observe(&mut self) -> u64 {
+            self.value
+        }
+
+        fn compare(&self, model: &Counter, observed: &u64) -> Result<(), Mismatch> {
+            Mismatch::check(&model.value, observed)
+        }
+    }
+
+    #[test]
+    fn test_the_first_mismatching_step_fails() {
+        let commands: Vec<_> = [2, 0, 3, 4]
+            .map(|n| CommandWrapper::new(Add(n)))
+            .into_iter()
+            .collect();
+        let mut sut = Saturating::default();
+
+        let panic = panics::catch(|| {
+            execute_against(&commands, &mut Counter::default(), &mut sut);
+        })
+        .unwrap_err();
+
+        // Skipped commands are not applied to the system.
+        assert_eq!(sut.applied, 3);
+        assert_eq!(
+            panic.message,
+            "after ADD(4): system under test differs from the model (- model, + system):\n- 9\n+ 5\n"
+        );
+    }
+}
@@ -0,0 +1,282 @@
+//! Standalone regression tests generated from failing traces.
+//!
+//! A failing sequence saved under `madhouse-regressions/` replays inside
+//! its scenario (see [`replay!`](crate::replay)). A [`RegressionTest`]
+//! renders it instead as a `#[test]` function that builds exactly those
+//! commands and runs them with [`execute_commands`](crate::execute_commands),
+//! ready to paste into the test suite so the bug stays covered by a
+//! deterministic test. With
+//! [`Config::regression_test`](crate::Config::regression_test) set, a
+//! scenario prints one for every failing case after shrinking.
+//!
+//! Commands are rebuilt from their labels with
+//! [`Command::parse`](crate::Command::parse); a command whose type does not
+//! parse its own label back is left as a `todo!()` naming it. Scenario
+//! hooks (setup, teardown, report assertions) are not part of the test.
+
+use crate::{CommandWrapper, State, TestContext};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::sync::Arc;
+
+/// A failing command sequence rendered as Rust test code.
+///
+/// Type paths are taken from [`std::any::type_name`], with the crate of the
+/// state type written as `crate`; the context is built with `Default`.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::testgen::RegressionTest;
+/// use madhouse::{Command, CommandWrapper, State, TestContext};
+/// use proptest::prelude::*;
+/// use std::sync::Arc;
+///
+/// #[derive(Debug, Default)]
+/// struct Counter { value: u64 }
+/// impl State for Counter {}
+///
+/// #[derive(Debug, Clone, Default)]
+/// struct Ctx {}
+/// impl TestContext for Ctx {}
+///
+/// struct Inc { amount: u64 }
+/// impl Command<Counter, Ctx> for Inc {
+///     fn check(&self, _state: &Counter) -> bool { true }
+///     fn apply(&self, state: &mut Counter) { state.value += self.amount; }
+///     fn label(&self) -> String { format!("INC({})", self.amount) }
+///     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Counter, Ctx>> {
+///         (1..10u64).prop_map(|amount| CommandWrapper::new(Inc { amount }))
+///     }
+///     fn parse(label: &str, _ctx: Arc<Ctx>) -> Option<CommandWrapper<Counter, Ctx>> {
+///         let amount = label.strip_prefix("INC(")?.strip_suffix(')')?.parse().ok()?;
+///         Some(CommandWrapper::new(Inc { amount }))
+///     }
+/// }
+///
+/// let ctx = Arc::new(Ctx::default());
+/// let commands = vec![CommandWrapper::new(Inc { amount: 3 })];
+/// let test = RegressionTest::new("counter overflow", &commands, &ctx)
+///     .describe("command 1 (INC(3)) failed: overflow");
+///
+/// let code = test.to_string();
+/// assert!(code.contains("fn regression_counter_overflow() {"));
+/// assert!(code.contains(r#"::parse("INC(3)", ctx.clone()).unwrap(),"#));
+/// assert!(code.contains("madhouse::execute_commands(&commands, &mut state);"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegressionTest {
+    name: String,
+    description: Option<String>,
+    state: String,
+    context: String,
+    steps: Vec<Step>,
+}
+
+/// One command of the test.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Step {
+    label: String,
+    /// Path of the command type, or `None` if it cannot parse the label.
+    parser: Option<String>,
+}
+
+impl RegressionTest {
+    /// Renders `commands` as a test function named after `name`, checking
+    /// that they run without failures. Each command type is asked to parse
+    /// its label back with `ctx`.
+    pub fn new<S: State, C: TestContext>(
+        name: &str,
+        commands: &[CommandWrapper<S, C>],
+        ctx: &Arc<C>,
+    ) -> Self {
+        let state = std::any::type_name::<S>();
+        let local = state.split("::").next().unwrap_or_default();
+        let steps = commands
+            .iter()
+            .map(|command| {
+                let label = command.command.label();
+                let parsed = (command.parse)(&label, Arc::clone(ctx))
+                    .is_some_and(|parsed| parsed.command.label() == label);
+                Step {
+                    parser: parsed.then(|| localize(command.type_name, local)),
+                    label,
+                }
+            })
+            .collect();
+        Self {
+            name: identifier(name),
+            description: None,
+            state: localize(state, local),
+            context: localize(std::any::type_name::<C>(), local),
+            steps,
+        }
+    }
+
+    /// Adds a comment above the test, e.g. the failure it reproduces.
+    pub fn describe(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Returns true if every command is rebuilt from its label, so the test
+    /// compiles and runs without edits.
+    pub fn is_complete(&self) -> bool {
+        self.steps.iter().all(|step| step.parser.is_some())
+    }
+}
+
+impl Display for RegressionTest {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let (state, context) = (&self.state, &self.context);
+        if let Some(description) = &self.description {
+            for line in description.lines() {
+                writeln!(f, "// {}", line)?;
+            }
+        }
+        writeln!(f, "#[test]")?;
+        writeln!(f, "fn regression_{}() {{", self.name)?;
+        writeln!(
+            f,
+            "    let ctx = std::sync::Arc::new({}::default());",
+            context
+        )?;
+        writeln!(
+            f,
+            "    let commands: Vec<madhouse::CommandWrapper<{}, {}>> = vec![",
+            state, context
+        )?;
+        for step in &self.steps {
+            match &step.parser {
+                Some(command) => writeln!(
+                    f,
+                    "        <{} as madhouse::Command<{}, {}>>::parse({:?}, ctx.clone()).unwrap(),",
+                    command, state, context, step.label
+                )?,
+                None => writeln!(
+                    f,
+                    "        todo!({:?}),",
+                    format!("build {}: its type does not parse the label", step.label)
+                )?,
+            }
+        }
+        writeln!(f, "    ];")?;
+        writeln!(f, "    let mut state = {}::default();", state)?;
+        writeln!(
+            f,
+            "    let report = madhouse::execute_commands(&commands, &mut state);"
+        )?;
+        writeln!(f, "    assert_eq!(report.failure_message(), None);")?;
+        writeln!(f, "}}")
+    }
+}
+
+/// Turns `name` into a snake case identifier.
+fn identifier(name: &str) -> String {
+    let words: Vec<String> = name
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    words.join("_")
+}
+
+/// Writes the paths of crate `local` in `type_name` as `crate::` paths.
+fn localize(type_name: &str, local: &str) -> String {
+    let prefix = format!("{}::", local);
+    let mut out = String::new();
+    let mut rest = type_name;
+    while let Some(at) = rest.find(&prefix) {
+        let before = rest[..at].chars().next_back().or(out.chars().next_back());
+        out.push_str(&rest[..at]);
+        match before {
+            Some(c) if c.is_alphanumeric() || c == '_' || c == ':' => out.push_str(&prefix),
+            _ => out.push_str("crate::"),
+        }
+        rest = &rest[at + prefix.len()..];
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Command;
+    use proptest::prelude::{Just, Strategy};
+
+    #[derive(Debug, Default)]
+    struct Counter {}
+    impl State for Counter {}
+
+    #[derive(Debug, Clone, Default)]
+    struct Ctx {}
+    impl TestContext for Ctx {}
+
+    struct Reset;
+    impl Command<Counter, Ctx> for Reset {
+        fn check(&self, _state: &Counter) -> bool {
+            true
+        }
+        fn apply(&self, _state: &mut Counter) {}
+        fn label(&self) -> String {
+            "RESET".to_string()
+        }
+        fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Counter, Ctx>> {
+            Just(CommandWrapper::new(Reset))
+        }
+        fn parse(label: &str, _ctx: Arc<Ctx>) -> Option<CommandWrapper<Counter, Ctx>> {
+            (label == "RESET").then(|| CommandWrapper::new(Reset))
+        }
+    }
+
+    struct Opaque;
+    impl Command<Counter, Ctx> for Opaque {
+        fn check(&self, _state: &Counter) -> bool {
+            true
+        }
+        fn apply(&self, _state: &mut Counter) {}
+        fn label(&self) -> String {
+            "OPAQUE".to_string()
+        }
+        fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Counter, Ctx>> {
+            Just(CommandWrapper::new(Opaque))
+        }
+    }
+
+    #[test]
+    fn test_renders_parseable_commands_and_todos() {
+        let commands = vec![CommandWrapper::new(Reset), CommandWrapper::new(Opaque)];
+        let test = RegressionTest::new("bank-Deposit flow_01", &commands, &Arc::new(Ctx {}))
+            .describe("invariant violated\nafter RESET");
+        assert!(!test.is_complete());
+
+        let (state, ctx) = (
+            "crate::testgen::tests::Counter",
+            "crate::testgen::tests::Ctx",
+        );
+        let expected = format!(
+            r#"// invariant violated
+// after RESET
+#[test]
+fn regression_bank_deposit_flow_01() {{
+    let ctx = std::sync::Arc::new({ctx}::default());
+    let commands: Vec<madhouse::CommandWrapper<{state}, {ctx}>> = vec![
+        <crate::testgen::tests::Reset as madhouse::Command<{state}, {ctx}>>::parse("RESET", ctx.clone()).unwrap(),
+        todo!("build OPAQUE: its type does not parse the label"),
+    ];
+    let mut state = {state}::default();
+    let report = madhouse::execute_commands(&commands, &mut state);
+    assert_eq!(report.failure_message(), None);
+}}
+"#
+        );
+        assert_eq!(test.to_string(), expected);
+        assert_eq!(
+            localize(
+                "madhouse::nemesis::Kill<madhouse::X, mymadhouse::Y>",
+                "madhouse"
+            ),
+            "crate::nemesis::Kill<crate::X, mymadhouse::Y>"
+        );
+    }
+}
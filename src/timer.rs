@@ -0,0 +1,388 @@
+//! Virtual-time timers in the model.
+//!
+//! Commands register timers on the model's [`TimerModel`] (e.g. "expire the
+//! lease 30s from now"). Time only moves when [`AdvanceClock`] runs: it
+//! advances the virtual clock and fires every timer that became due, in
+//! deadline order, by calling the callback given at registration. This makes
+//! timeout-driven behavior deterministic and shrinkable, unlike sleeping on
+//! the wall clock.
+//!
+//! # Examples
+//!
+//! ```
+//! use madhouse::timer::{AdvanceClock, ClockContext, TimerModel, TimerState};
+//! use madhouse::{scenario, Command, CommandWrapper, State, TestContext};
+//! use proptest::prelude::*;
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//!
+//! #[derive(Debug, Default)]
+//! struct Lease {
+//!     timers: TimerModel<Lease>,
+//!     held: bool,
+//! }
+//! impl State for Lease {}
+//! impl TimerState for Lease {
+//!     fn timers(&self) -> &TimerModel<Self> { &self.timers }
+//!     fn timers_mut(&mut self) -> &mut TimerModel<Self> { &mut self.timers }
+//! }
+//!
+//! #[derive(Debug, Clone, Default)]
+//! struct Ctx {}
+//! impl TestContext for Ctx {}
+//! impl ClockContext for Ctx {
+//!     fn max_advance(&self) -> Duration { Duration::from_secs(60) }
+//! }
+//!
+//! struct Acquire;
+//! impl Command<Lease, Ctx> for Acquire {
+//!     fn check(&self, state: &Lease) -> bool { !state.held }
+//!     fn apply(&self, state: &mut Lease) {
+//!         state.held = true;
+//!         state
+//!             .timers
+//!             .schedule_in(Duration::from_secs(30), "LEASE_EXPIRY", |s: &mut Lease| {
+//!                 s.held = false;
+//!             });
+//!     }
+//!     fn label(&self) -> String { "ACQUIRE".to_string() }
+//!     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Lease, Ctx>> {
+//!         Just(CommandWrapper::new(Acquire))
+//!     }
+//! }
+//!
+//! let ctx = Arc::new(Ctx::default());
+//! scenario![ctx, Acquire, AdvanceClock: 1];
+//! ```
+
+use crate::{Command, CommandError, CommandWrapper, State, TestContext};
+use proptest::prelude::Strategy;
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Identifier of a scheduled timer, unique within one [`TimerModel`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TimerId(u64);
+
+type TimerCallback<S> = Arc<dyn Fn(&mut S)>;
+
+/// A pending timer.
+struct Timer<S> {
+    name: String,
+    callback: TimerCallback<S>,
+}
+
+impl<S> Clone for Timer<S> {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name.clone(),
+            callback: Arc::clone(&self.callback),
+        }
+    }
+}
+
+/// Virtual clock plus the timers pending on it.
+///
+/// Timers with the same deadline fire in the order they were scheduled.
+pub struct TimerModel<S> {
+    now: Duration,
+    next_id: u64,
+    pending: BTreeMap<(Duration, TimerId), Timer<S>>,
+}
+
+impl<S> TimerModel<S> {
+    /// Creates a model at virtual time zero with no timers.
+    pub fn new() -> Self {
+        Self {
+            now: Duration::ZERO,
+            next_id: 0,
+            pending: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the current virtual time.
+    pub fn now(&self) -> Duration {
+        self.now
+    }
+
+    /// Schedules `callback` to run once the clock reaches `deadline`.
+    ///
+    /// A deadline in the past fires on the next [`advance`](Self::advance).
+    ///
+    /// # Arguments
+    /// * `deadline` - Virtual time the timer fires at.
+    /// * `name` - Name shown in `Debug` output and [`pending`](Self::pending).
+    /// * `callback` - Called with the model state when the timer fires.
+    pub fn schedule_at(
+        &mut self,
+        deadline: Duration,
+        name: impl Into<String>,
+        callback: impl Fn(&mut S) + 'static,
+    ) -> TimerId {
+        let id = TimerId(self.next_id);
+        self.next_id += 1;
+        self.pending.insert(
+            (deadline, id),
+            Timer {
+                name: name.into(),
+                callback: Arc::new(callback),
+            },
+        );
+        id
+    }
+
+    /// Schedules `callback` to run `delay` after the current virtual time.
+    pub fn schedule_in(
+        &mut self,
+        delay: Duration,
+        name: impl Into<String>,
+        callback: impl Fn(&mut S) + 'static,
+    ) -> TimerId {
+        self.schedule_at(self.now + delay, name, callback)
+    }
+
+    /// Cancels a pending timer. Returns false if it already fired or was
+    /// cancelled.
+    pub fn cancel(&mut self, id: TimerId) -> bool {
+        let key = self.pending.keys().find(|(_, k)| *k == id).copied();
+        key.and_then(|key| self.pending.remove(&key)).is_some()
+    }
+
+    /// Returns the deadline of the next timer to fire, if any.
+    pub fn next_deadline(&self) -> Option<Duration> {
+        self.pending.keys().next().map(|(deadline, _)| *deadline)
+    }
+
+    /// Iterates over pending timers as `(deadline, name)`, in firing order.
+    pub fn pending(&self) -> impl Iterator<Item = (Duration, &str)> {
+        self.pending
+            .iter()
+            .map(|((deadline, _), timer)| (*deadline, timer.name.as_str()))
+    }
+
+    /// Removes the next timer due at or before `until`, moving the clock to
+    /// its deadline.
+    fn pop_due(&mut self, until: Duration) -> Option<TimerCallback<S>> {
+        let entry = self.pending.first_entry()?;
+        let (deadline, _) = *entry.key();
+        if deadline > until {
+            return None;
+        }
+        let timer = entry.remove();
+        self.now = self.now.max(deadline);
+        Some(timer.callback)
+    }
+}
+
+impl<S: TimerState> TimerModel<S> {
+    /// Advances the clock of `state` by `by`, firing due timers in order.
+    ///
+    /// Callbacks see the clock at their own deadline, and timers they
+    /// schedule within the window fire during the same advance. Returns
+    /// the number of timers fired.
+    pub fn advance(state: &mut S, by: Duration) -> usize {
+        let until = state.timers().now + by;
+        let mut fired = 0;
+        while let Some(callback) = state.timers_mut().pop_due(until) {
+            callback(state);
+            fired += 1;
+        }
+        state.timers_mut().now = until;
+        fired
+    }
+}
+
+impl<S> Default for TimerModel<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Clone for TimerModel<S> {
+    fn clone(&self) -> Self {
+        Self {
+            now: self.now,
+            next_id: self.next_id,
+            pending: self.pending.clone(),
+        }
+    }
+}
+
+impl<S> Debug for TimerModel<S> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("TimerModel")
+            .field("now", &self.now)
+            .field("pending", &self.pending().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// State that owns a [`TimerModel`].
+pub trait TimerState: State + Sized {
+    /// Returns the timer model.
+    fn timers(&self) -> &TimerModel<Self>;
+
+    /// Returns the timer model for modification.
+    fn timers_mut(&mut self) -> &mut TimerModel<Self>;
+}
+
+/// Context describing how far [`AdvanceClock`] may move time, and how to
+/// move the SUT's clock along with the model's.
+pub trait ClockContext: TestContext {
+    /// Largest step a single [`AdvanceClock`] may take.
+    fn max_advance(&self) -> Duration;
+
+    /// Advances the system under test's (mock) clock. The default does
+    /// nothing, for SUTs whose timeouts are modeled only.
+    fn advance_sut(&self, by: Duration) -> Result<(), CommandError> {
+        let _ = by;
+        Ok(())
+    }
+}
+
+/// Command that advances virtual time and fires due timers.
+pub struct AdvanceClock<C> {
+    ctx: Arc<C>,
+    /// How far to advance.
+    pub by: Duration,
+}
+
+impl<C> AdvanceClock<C> {
+    /// Creates a command advancing the clock by `by`.
+    pub fn new(ctx: Arc<C>, by: Duration) -> Self {
+        Self { ctx, by }
+    }
+}
+
+impl<S, C> Command<S, C> for AdvanceClock<C>
+where
+    S: TimerState,
+    C: ClockContext + 'static,
+{
+    fn check(&self, _state: &S) -> bool {
+        true
+    }
+
+    fn apply(&self, state: &mut S) {
+        if let Err(err) = self.try_apply(state) {
+            panic!("{}: {}", Command::<S, C>::label(self), err);
+        }
+    }
+
+    fn try_apply(&self, state: &mut S) -> Result<(), CommandError> {
+        self.ctx.advance_sut(self.by)?;
+        TimerModel::advance(state, self.by);
+        Ok(())
+    }
+
+    fn label(&self) -> String {
+        format!("ADVANCE_CLOCK({}ms)", self.by.as_millis())
+    }
+
+    /// Advances by 1ms up to the context's `max_advance`, shrinking toward
+    /// short steps.
+    fn build(ctx: Arc<C>) -> impl Strategy<Value = CommandWrapper<S, C>> {
+        let max = ctx.max_advance().as_millis().max(1) as u64;
+        (1..=max).prop_map(move |ms| {
+            CommandWrapper::new(AdvanceClock::new(ctx.clone(), Duration::from_millis(ms)))
+        })
+    }
+
+    fn parse(label: &str, ctx: Arc<C>) -> Option<CommandWrapper<S, C>> {
+        let inner = label.strip_prefix("ADVANCE_CLOCK(")?.strip_suffix("ms)")?;
+        let ms = inner.parse().ok()?;
+        Some(CommandWrapper::new(AdvanceClock::new(
+            ctx,
+            Duration::from_millis(ms),
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execute_commands;
+
+    #[derive(Debug, Default)]
+    struct Node {
+        timers: TimerModel<Node>,
+        fired: Vec<(String, Duration)>,
+    }
+
+    impl State for Node {}
+
+    impl TimerState for Node {
+        fn timers(&self) -> &TimerModel<Self> {
+            &self.timers
+        }
+
+        fn timers_mut(&mut self) -> &mut TimerModel<Self> {
+            &mut self.timers
+        }
+    }
+
+    fn record(name: &'static str) -> impl Fn(&mut Node) {
+        move |node: &mut Node| {
+            let now = node.timers.now();
+            node.fired.push((name.to_string(), now));
+        }
+    }
+
+    #[derive(Debug, Clone, Default)]
+    struct Ctx {}
+
+    impl TestContext for Ctx {}
+
+    impl ClockContext for Ctx {
+        fn max_advance(&self) -> Duration {
+            Duration::from_secs(1)
+        }
+    }
+
+    #[test]
+    fn test_timers_fire_in_deadline_order() {
+        let mut node = Node::default();
+        let ms = Duration::from_millis;
+        node.timers.schedule_at(ms(30), "late", record("late"));
+        node.timers.schedule_at(ms(10), "early", record("early"));
+        node.timers.schedule_at(ms(10), "tie", record("tie"));
+        let cancelled = node.timers.schedule_at(ms(5), "never", record("never"));
+        assert!(node.timers.cancel(cancelled));
+        assert!(!node.timers.cancel(cancelled));
+
+        assert_eq!(TimerModel::advance(&mut node, ms(20)), 2);
+        assert_eq!(
+            node.fired,
+            vec![("early".to_string(), ms(10)), ("tie".to_string(), ms(10))]
+        );
+        assert_eq!(node.timers.now(), ms(20));
+        assert_eq!(node.timers.next_deadline(), Some(ms(30)));
+    }
+
+    #[test]
+    fn test_callbacks_can_reschedule_within_window() {
+        let mut node = Node::default();
+        let ms = Duration::from_millis;
+        node.timers.schedule_at(ms(10), "first", |node: &mut Node| {
+            node.fired.push(("first".to_string(), node.timers.now()));
+            node.timers
+                .schedule_in(Duration::from_millis(5), "second", record("second"));
+        });
+
+        let commands: Vec<CommandWrapper<Node, Ctx>> = vec![CommandWrapper::new(
+            AdvanceClock::new(Arc::new(Ctx::default()), ms(15)),
+        )];
+        let report = execute_commands(&commands, &mut node);
+
+        assert_eq!(report.steps[0].label, "ADVANCE_CLOCK(15ms)");
+        assert_eq!(
+            node.fired,
+            vec![
+                ("first".to_string(), ms(10)),
+                ("second".to_string(), ms(15))
+            ]
+        );
+    }
+}
@@ -0,0 +1,127 @@
+//! CSV export of command timings, for tracking the system's performance
+//! across releases.
+//!
+//! With [`Config::timings_csv`](crate::Config::timings_csv) set (or
+//! `MADHOUSE_TIMINGS_CSV`), scenarios append one row per command applied by
+//! a generated case (shrinking runs are left out) to that file:
+//!
+//! ```text
+//! scenario,case,index,label,duration_ns
+//! tests::counter,0,0,INC(3),1250
+//! ```
+//!
+//! The first scenario of a test binary to write the file truncates it;
+//! the others, running in parallel or not, append to it.
+
+use crate::ExecutionReport;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Header row of the file.
+pub const HEADER: &str = "scenario,case,index,label,duration_ns";
+
+/// Files written by this process.
+static FILES: Mutex<BTreeMap<PathBuf, File>> = Mutex::new(BTreeMap::new());
+
+/// Appends to `out` one row per command `report` applied, in case `case`
+/// of `scenario`.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::report::{ExecutionReport, StepOutcome, StepReport};
+/// use madhouse::{timings, SkipReason};
+/// use std::time::Duration;
+///
+/// let step = |label: &str, outcome, micros| StepReport {
+///     index: 0,
+///     label: label.to_string(),
+///     group: String::new(),
+///     outcome,
+///     duration: Duration::from_micros(micros),
+///     check_duration: Duration::ZERO,
+///     started: std::time::UNIX_EPOCH,
+///     finished: std::time::UNIX_EPOCH,
+/// };
+/// let mut report = ExecutionReport {
+///     steps: vec![
+///         step("TRANSFER(1, 2)", StepOutcome::Executed, 3),
+///         step("RESET", StepOutcome::Skipped(SkipReason::default()), 0),
+///     ],
+///     ..Default::default()
+/// };
+/// report.steps[1].index = 1;
+///
+/// let mut csv = String::new();
+/// timings::rows(&mut csv, "tests::bank", 7, &report);
+/// assert_eq!(csv, "tests::bank,7,0,\"TRANSFER(1, 2)\",3000\n");
+/// ```
+pub fn rows(out: &mut String, scenario: &str, case: u32, report: &ExecutionReport) {
+    for step in report.executed() {
+        let _ = writeln!(
+            out,
+            "{},{},{},{},{}",
+            field(scenario),
+            case,
+            step.index,
+            field(&step.label),
+            step.duration.as_nanos()
+        );
+    }
+}
+
+/// Appends `rows` to the file at `path`, truncating it and writing
+/// `header` first if this process did not write it yet.
+pub(crate) fn append(path: &Path, header: &str, rows: &str) -> io::Result<()> {
+    let mut files = FILES.lock().unwrap_or_else(|e| e.into_inner());
+    let file = match files.get_mut(path) {
+        Some(file) => file,
+        None => {
+            if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                std::fs::create_dir_all(dir)?;
+            }
+            let mut file = OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(path)?;
+            writeln!(file, "{}", header)?;
+            files.entry(path.to_path_buf()).or_insert(file)
+        }
+    };
+    file.write_all(rows.as_bytes())
+}
+
+/// Quotes a CSV field containing a separator, a quote or a line break.
+pub(crate) fn field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_append_writes_the_header_once() {
+        let path = std::env::temp_dir()
+            .join(format!("madhouse-timings-{}", std::process::id()))
+            .join("timings.csv");
+        append(&path, HEADER, "a,0,0,X,1\n").unwrap();
+        append(&path, HEADER, "b,0,0,\"Y \"\"1\"\"\",2\n").unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            format!("{}\na,0,0,X,1\nb,0,0,\"Y \"\"1\"\"\",2\n", HEADER)
+        );
+        assert_eq!(field("Y \"1\""), "\"Y \"\"1\"\"\"");
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
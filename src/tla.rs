@@ -0,0 +1,450 @@
+//! Export of executed traces for TLA+ tools.
+//!
+//! A protocol often has a TLA+ specification next to its implementation.
+//! [`TraceExporter`] records the state before the first command and after
+//! every applied one as TLA+ variables, so a trace found by madhouse can be
+//! checked against the spec: either as a TLA+ module of `State0`,
+//! `State1`, ... operators (the shape of Apalache counterexamples), or in
+//! the Informal Trace Format (ITF) JSON that Apalache and Quint read.
+//! Variables are declared with [`TraceExporter::var`], each mapping the
+//! model state to a [`TlaValue`].
+
+use crate::observer::Observer;
+use crate::report::{json_string, rfc3339};
+use crate::{CommandWrapper, State, StepReport, TestContext};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt::{Display, Formatter, Result as FmtResult, Write as _};
+use std::fs;
+use std::hash::Hash;
+use std::io;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// A TLA+ value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TlaValue {
+    /// `TRUE` or `FALSE`.
+    Bool(bool),
+    /// An integer.
+    Int(i128),
+    /// A string.
+    Str(String),
+    /// A sequence, `<<a, b>>`.
+    Seq(Vec<TlaValue>),
+    /// A set, `{a, b}`.
+    Set(Vec<TlaValue>),
+    /// A tuple, `<<a, b>>`; a distinct type in ITF.
+    Tuple(Vec<TlaValue>),
+    /// A record, `[field |-> value]`.
+    Record(Vec<(String, TlaValue)>),
+    /// A function, `(key :> value @@ ...)`.
+    Map(Vec<(TlaValue, TlaValue)>),
+}
+
+impl TlaValue {
+    /// Renders the value as a JSON value of the Informal Trace Format.
+    pub fn to_itf(&self) -> String {
+        let list = |values: &[TlaValue]| {
+            let items: Vec<String> = values.iter().map(TlaValue::to_itf).collect();
+            format!("[{}]", items.join(","))
+        };
+        match self {
+            Self::Bool(value) => value.to_string(),
+            Self::Int(value) => format!(r##"{{"#bigint":"{}"}}"##, value),
+            Self::Str(value) => json_string(value),
+            Self::Seq(values) => list(values),
+            Self::Set(values) => format!(r##"{{"#set":{}}}"##, list(values)),
+            Self::Tuple(values) => format!(r##"{{"#tup":{}}}"##, list(values)),
+            Self::Record(fields) => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(name, value)| format!("{}:{}", json_string(name), value.to_itf()))
+                    .collect();
+                format!("{{{}}}", fields.join(","))
+            }
+            Self::Map(pairs) => {
+                let pairs: Vec<String> = pairs
+                    .iter()
+                    .map(|(key, value)| format!("[{},{}]", key.to_itf(), value.to_itf()))
+                    .collect();
+                format!(r##"{{"#map":[{}]}}"##, pairs.join(","))
+            }
+        }
+    }
+}
+
+/// Renders the value as a TLA+ expression.
+impl Display for TlaValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let join = |values: &[TlaValue]| {
+            let items: Vec<String> = values.iter().map(ToString::to_string).collect();
+            items.join(", ")
+        };
+        match self {
+            Self::Bool(true) => write!(f, "TRUE"),
+            Self::Bool(false) => write!(f, "FALSE"),
+            Self::Int(value) => write!(f, "{}", value),
+            Self::Str(value) => write!(f, "{}", tla_string(value)),
+            Self::Seq(values) | Self::Tuple(values) => write!(f, "<<{}>>", join(values)),
+            Self::Set(values) => write!(f, "{{{}}}", join(values)),
+            Self::Record(fields) => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(name, value)| format!("{} |-> {}", name, value))
+                    .collect();
+                write!(f, "[{}]", fields.join(", "))
+            }
+            Self::Map(pairs) if pairs.is_empty() => write!(f, "[x \\in {{}} |-> x]"),
+            Self::Map(pairs) => {
+                let pairs: Vec<String> = pairs
+                    .iter()
+                    .map(|(key, value)| format!("{} :> {}", key, value))
+                    .collect();
+                write!(f, "({})", pairs.join(" @@ "))
+            }
+        }
+    }
+}
+
+impl From<bool> for TlaValue {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+macro_rules! tla_int {
+    ($($int:ty),*) => {
+        $(impl From<$int> for TlaValue {
+            fn from(value: $int) -> Self {
+                Self::Int(value as i128)
+            }
+        })*
+    };
+}
+
+tla_int!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, usize);
+
+impl From<&str> for TlaValue {
+    fn from(value: &str) -> Self {
+        Self::Str(value.to_string())
+    }
+}
+
+impl From<String> for TlaValue {
+    fn from(value: String) -> Self {
+        Self::Str(value)
+    }
+}
+
+impl<T: Clone + Into<TlaValue>> From<&[T]> for TlaValue {
+    fn from(values: &[T]) -> Self {
+        Self::Seq(values.iter().cloned().map(Into::into).collect())
+    }
+}
+
+impl<T: Into<TlaValue>> From<Vec<T>> for TlaValue {
+    fn from(values: Vec<T>) -> Self {
+        Self::Seq(values.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<T: Into<TlaValue>> From<BTreeSet<T>> for TlaValue {
+    fn from(values: BTreeSet<T>) -> Self {
+        Self::Set(values.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<T: Into<TlaValue> + Ord + Hash> From<HashSet<T>> for TlaValue {
+    /// Elements are sorted, so the rendering is stable.
+    fn from(values: HashSet<T>) -> Self {
+        let sorted: BTreeSet<T> = values.into_iter().collect();
+        sorted.into()
+    }
+}
+
+impl<K: Into<TlaValue>, V: Into<TlaValue>> From<BTreeMap<K, V>> for TlaValue {
+    fn from(map: BTreeMap<K, V>) -> Self {
+        Self::Map(
+            map.into_iter()
+                .map(|(key, value)| (key.into(), value.into()))
+                .collect(),
+        )
+    }
+}
+
+/// Quotes and escapes a string for TLA+.
+fn tla_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+type Var<S> = Box<dyn Fn(&S) -> TlaValue>;
+
+/// One recorded state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Snapshot {
+    /// Label of the command that led to it; `None` for the initial state.
+    action: Option<String>,
+    /// Wall-clock start and end of that command, if observed.
+    times: Option<(SystemTime, SystemTime)>,
+    values: Vec<TlaValue>,
+}
+
+/// Observer recording the states of a trace as TLA+ variables.
+///
+/// The state before the first applied command is the initial state; each
+/// applied command, failed or not, adds the state after it. Heartbeats are
+/// not observed, so their effect shows up in the next command's state.
+///
+/// # Examples
+///
+/// ```
+/// use madhouse::observer::execute_commands_with;
+/// use madhouse::tla::TraceExporter;
+/// use madhouse::{Command, CommandWrapper, State, TestContext};
+/// use proptest::prelude::*;
+/// use std::sync::Arc;
+///
+/// #[derive(Debug, Default)]
+/// struct Chain { height: u64, miners: Vec<String> }
+/// impl State for Chain {}
+///
+/// #[derive(Debug, Clone, Default)]
+/// struct Ctx {}
+/// impl TestContext for Ctx {}
+///
+/// struct Mine(&'static str);
+/// impl Command<Chain, Ctx> for Mine {
+///     fn check(&self, _state: &Chain) -> bool { true }
+///     fn apply(&self, state: &mut Chain) {
+///         state.height += 1;
+///         state.miners.push(self.0.to_string());
+///     }
+///     fn label(&self) -> String { format!("MINE({})", self.0) }
+///     fn build(_ctx: Arc<Ctx>) -> impl Strategy<Value = CommandWrapper<Chain, Ctx>> {
+///         Just(CommandWrapper::new(Mine("alice")))
+///     }
+/// }
+///
+/// let mut exporter = TraceExporter::new()
+///     .var("height", |chain: &Chain| chain.height.into())
+///     .var("miners", |chain: &Chain| chain.miners.clone().into());
+/// let commands = vec![CommandWrapper::new(Mine("alice"))];
+/// execute_commands_with(&commands, &mut Chain::default(), &mut exporter);
+///
+/// assert_eq!(exporter.len(), 2);
+/// assert!(exporter.to_tla("Trace").contains(concat!(
+///     "\\* MINE(alice)\n",
+///     "State1 ==\n",
+///     "    /\\ height = 1\n",
+///     "    /\\ miners = <<\"alice\">>\n",
+/// )));
+/// let itf = exporter.to_itf();
+/// assert!(itf.contains(r##"{"#meta":{"index":1,"action":"MINE(alice)","started":""##));
+/// assert!(itf.contains(r##"Z"},"height":{"#bigint":"1"},"miners":["alice"]}"##));
+/// ```
+pub struct TraceExporter<S> {
+    vars: Vec<(String, Var<S>)>,
+    states: Vec<Snapshot>,
+}
+
+impl<S> Default for TraceExporter<S> {
+    fn default() -> Self {
+        Self {
+            vars: Vec::new(),
+            states: Vec::new(),
+        }
+    }
+}
+
+impl<S> TraceExporter<S> {
+    /// Creates an exporter without variables.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Declares the TLA+ variable `name`, computed from the state by
+    /// `value`.
+    pub fn var(
+        mut self,
+        name: impl Into<String>,
+        value: impl Fn(&S) -> TlaValue + 'static,
+    ) -> Self {
+        self.vars.push((name.into(), Box::new(value)));
+        self
+    }
+
+    /// Records `state`, reached by the command labeled `action`, or the
+    /// initial state if `None`.
+    pub fn record(&mut self, action: Option<&str>, state: &S) {
+        self.push(action, None, state);
+    }
+
+    fn push(&mut self, action: Option<&str>, times: Option<(SystemTime, SystemTime)>, state: &S) {
+        self.states.push(Snapshot {
+            action: action.map(str::to_string),
+            times,
+            values: self.vars.iter().map(|(_, value)| value(state)).collect(),
+        });
+    }
+
+    /// Returns the number of recorded states.
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    /// Returns true if no state was recorded.
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+
+    /// Forgets the recorded states, to export another trace.
+    pub fn clear(&mut self) {
+        self.states.clear();
+    }
+
+    /// Renders the trace as TLA+ module `module`, with one `StateN`
+    /// operator per state.
+    pub fn to_tla(&self, module: &str) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "---- MODULE {} ----", module);
+        let _ = writeln!(out, "EXTENDS Integers, Sequences, TLC");
+        for (i, snapshot) in self.states.iter().enumerate() {
+            out.push('\n');
+            match &snapshot.action {
+                Some(action) => {
+                    let _ = writeln!(out, "\\* {}", action.replace(['\n', '\r'], " "));
+                }
+                None => out.push_str("\\* Initial state\n"),
+            }
+            let _ = writeln!(out, "State{} ==", i);
+            for ((name, _), value) in self.vars.iter().zip(&snapshot.values) {
+                let _ = writeln!(out, "    /\\ {} = {}", name, value);
+            }
+            if self.vars.is_empty() {
+                out.push_str("    TRUE\n");
+            }
+        }
+        out.push_str("\n====\n");
+        out
+    }
+
+    /// Renders the trace in the Informal Trace Format, as a JSON document.
+    ///
+    /// Each state's `#meta` holds its index and, past the initial state,
+    /// the label of the command that led to it and, for states recorded as
+    /// an observer, the RFC 3339 times that command started and finished
+    /// (see [`rfc3339`]).
+    pub fn to_itf(&self) -> String {
+        let vars: Vec<String> = self
+            .vars
+            .iter()
+            .map(|(name, _)| json_string(name))
+            .collect();
+        let states: Vec<String> = self
+            .states
+            .iter()
+            .enumerate()
+            .map(|(i, snapshot)| {
+                let mut state = format!(r##"{{"#meta":{{"index":{}"##, i);
+                if let Some(action) = &snapshot.action {
+                    let _ = write!(state, r#","action":{}"#, json_string(action));
+                }
+                if let Some((started, finished)) = snapshot.times {
+                    let _ = write!(
+                        state,
+                        r#","started":"{}","finished":"{}""#,
+                        rfc3339(started),
+                        rfc3339(finished)
+                    );
+                }
+                state.push('}');
+                for ((name, _), value) in self.vars.iter().zip(&snapshot.values) {
+                    let _ = write!(state, ",{}:{}", json_string(name), value.to_itf());
+                }
+                state.push('}');
+                state
+            })
+            .collect();
+        format!(
+            r##"{{"#meta":{{"format":"ITF","source":"madhouse"}},"vars":[{}],"states":[{}]}}"##,
+            vars.join(","),
+            states.join(",")
+        )
+    }
+
+    /// Writes the trace as TLA+ module `module` to `path`.
+    pub fn write_tla(&self, path: impl AsRef<Path>, module: &str) -> io::Result<()> {
+        fs::write(path, self.to_tla(module))
+    }
+
+    /// Writes the trace in the Informal Trace Format to `path`.
+    pub fn write_itf(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_itf())
+    }
+}
+
+impl<S: State, C: TestContext> Observer<S, C> for TraceExporter<S> {
+    fn on_before_apply(&mut self, index: usize, command: &CommandWrapper<S, C>, state: &S) {
+        let _ = (index, command);
+        if self.states.is_empty() {
+            self.record(None, state);
+        }
+    }
+
+    fn on_after_apply(&mut self, step: &StepReport, state: &S) {
+        self.push(
+            Some(&step.label),
+            Some((step.started, step.finished)),
+            state,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_values_render_in_tla_and_itf() {
+        let value = TlaValue::Record(vec![
+            ("ok".to_string(), true.into()),
+            ("name".to_string(), "a\"b".into()),
+            ("peers".to_string(), BTreeSet::from([2u8, 1]).into()),
+            ("votes".to_string(), BTreeMap::from([("x", -1i64)]).into()),
+            (
+                "last".to_string(),
+                TlaValue::Tuple(vec![1u8.into(), "y".into()]),
+            ),
+            ("none".to_string(), BTreeMap::<u8, u8>::new().into()),
+        ]);
+
+        assert_eq!(
+            value.to_string(),
+            concat!(
+                r#"[ok |-> TRUE, name |-> "a\"b", peers |-> {1, 2}, "#,
+                r#"votes |-> ("x" :> -1), last |-> <<1, "y">>, none |-> [x \in {} |-> x]]"#
+            )
+        );
+        assert_eq!(
+            value.to_itf(),
+            concat!(
+                r##"{"ok":true,"name":"a\"b","peers":{"#set":[{"#bigint":"1"},{"#bigint":"2"}]},"##,
+                r##""votes":{"#map":[["x",{"#bigint":"-1"}]]},"##,
+                r##""last":{"#tup":[{"#bigint":"1"},"y"]},"none":{"#map":[]}}"##
+            )
+        );
+    }
+}
@@ -0,0 +1,298 @@
+//! Bounded step traces for long runs.
+//!
+//! An [`ExecutionReport`] keeps a record of every step, which for
+//! million-step endurance runs costs more memory than the run itself. With
+//! [`Config::trace_limit`](crate::Config::trace_limit) set, only the last
+//! steps are kept (memory stays flat at no more than twice the limit) and
+//! the report counts the dropped ones, so failure reports still show the
+//! recent history. [`Config::trace_file`](crate::Config::trace_file)
+//! additionally streams every step, as one JSON object per line, to a file.
+//!
+//! The trace also renders the state before each step, while no failure has
+//! been recorded yet, so the report can show how the failing step changed
+//! it (see [`Config::state_diff`](crate::Config::state_diff)), attaches
+//! the commands executed so far to a panicking step (see
+//! [`Config::panic_trace`](crate::Config::panic_trace)), fingerprints the
+//! state around each command to spot those that leave it unchanged (see
+//! [`Config::no_ops`](crate::Config::no_ops)), and tells the runner when a [`CancellationToken`] asks it to stop.
+
+use crate::cancel::CancellationToken;
+use crate::config::{self, Config};
+use crate::diff::StateDiff;
+use crate::panics;
+use crate::report::{step_json, NoOp, StateChange};
+use crate::{ExecutionReport, State, StepReport};
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+
+/// Where the steps of a run are recorded.
+pub(crate) struct Trace {
+    /// Steps (and heartbeats) to keep in the report; all if `None`.
+    keep: Option<usize>,
+    /// File streaming every step, and its path for error messages.
+    stream: Option<(PathBuf, BufWriter<File>)>,
+    /// Whether to diff the state around the failing step.
+    state_diff: bool,
+    /// Whether to attach the executed commands to a panicking step.
+    panic_trace: bool,
+    /// Whether to record the commands that leave the state unchanged.
+    no_ops: bool,
+    /// Token stopping the run before the next command once cancelled.
+    cancel: Option<CancellationToken>,
+}
+
+impl Default for Trace {
+    fn default() -> Self {
+        Self {
+            keep: None,
+            stream: None,
+            state_diff: true,
+            panic_trace: config::panic_trace_from_env(),
+            no_ops: config::no_ops_from_env(),
+            cancel: None,
+        }
+    }
+}
+
+impl Trace {
+    /// Creates the trace configured by `trace_limit` and `trace_file`.
+    ///
+    /// The file is truncated; if it cannot be created, the error goes to
+    /// stderr and nothing is streamed.
+    pub(crate) fn new(config: &Config) -> Self {
+        let stream = config
+            .trace_file
+            .as_ref()
+            .and_then(|path| match File::create(path) {
+                Ok(file) => Some((path.clone(), BufWriter::new(file))),
+                Err(err) => {
+                    eprintln!("madhouse: cannot write trace {}: {}", path.display(), err);
+                    None
+                }
+            });
+        Self {
+            keep: config.trace_limit,
+            stream,
+            state_diff: config.state_diff,
+            panic_trace: config.panic_trace,
+            no_ops: config.no_ops,
+            cancel: None,
+        }
+    }
+
+    /// Stops the run before the next command once `cancel` is cancelled.
+    pub(crate) fn cancellable(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    /// Returns true if the run must stop before the next command.
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.cancel
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+    }
+
+    /// Runs step `index`, the command `label`. If it panics, the panic is
+    /// raised again with the labels of the commands executed before it.
+    pub(crate) fn guard<T>(
+        &self,
+        report: &ExecutionReport,
+        index: usize,
+        label: impl FnOnce() -> String,
+        f: impl FnOnce() -> T,
+    ) -> T {
+        if !self.panic_trace {
+            return f();
+        }
+        panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+            let executed = report.executed().map(|step| step.label.clone()).collect();
+            panics::raise_with_trace(payload, index, &label(), executed)
+        })
+    }
+
+    /// Renders the state before a step, if a failure in that step would be
+    /// the first one whose state change is recorded.
+    pub(crate) fn before_step(
+        &self,
+        report: &ExecutionReport,
+        state: &impl Debug,
+    ) -> Option<String> {
+        (self.state_diff && report.state_change.is_none()).then(|| format!("{:#?}", state))
+    }
+
+    /// Records how a failing step changed the state rendered by
+    /// [`before_step`](Self::before_step).
+    pub(crate) fn after_step(
+        &self,
+        report: &mut ExecutionReport,
+        step: &StepReport,
+        failed: bool,
+        before: Option<String>,
+        state: &impl Debug,
+    ) {
+        if let Some(before) = before.filter(|_| failed) {
+            report.state_change = Some(StateChange {
+                index: step.index,
+                label: step.label.clone(),
+                diff: StateDiff::new(&before, &format!("{:#?}", state)),
+            });
+        }
+    }
+
+    /// Fingerprints the state before a command, if no-ops are recorded.
+    pub(crate) fn fingerprint(&self, state: &impl State) -> Option<u64> {
+        self.no_ops.then(|| state.fingerprint())
+    }
+
+    /// Records `step` as a no-op if it was applied without error and the
+    /// state still has the fingerprint taken before it.
+    pub(crate) fn after_apply(
+        &self,
+        report: &mut ExecutionReport,
+        step: &StepReport,
+        before: Option<u64>,
+        state: &impl State,
+    ) {
+        let unchanged = before.is_some_and(|before| before == state.fingerprint());
+        if unchanged && step.was_applied() && step.error().is_none() {
+            report.no_ops.push(NoOp {
+                index: step.index,
+                label: step.label.clone(),
+            });
+            // Bounded like the steps, without counting the dropped ones.
+            if let Some(keep) = self
+                .keep
+                .filter(|&keep| report.no_ops.len() >= 2 * keep.max(1))
+            {
+                report.no_ops.drain(..report.no_ops.len() - keep);
+            }
+        }
+    }
+
+    /// Records a step in the report.
+    pub(crate) fn step(&mut self, report: &mut ExecutionReport, step: StepReport) {
+        self.write(&step, false);
+        Self::push(
+            self.keep,
+            &mut report.steps,
+            &mut report.dropped_steps,
+            step,
+        );
+    }
+
+    /// Records a heartbeat in the report.
+    pub(crate) fn heartbeat(&mut self, report: &mut ExecutionReport, step: StepReport) {
+        self.write(&step, true);
+        Self::push(
+            self.keep,
+            &mut report.heartbeats,
+            &mut report.dropped_heartbeats,
+            step,
+        );
+    }
+
+    /// Drops the records beyond the limit and flushes the file.
+    pub(crate) fn finish(&mut self, report: &mut ExecutionReport) {
+        if let Some(keep) = self.keep {
+            Self::trim(keep, &mut report.steps, &mut report.dropped_steps);
+            Self::trim(keep, &mut report.heartbeats, &mut report.dropped_heartbeats);
+        }
+        if let Some((path, file)) = &mut self.stream {
+            if let Err(err) = file.flush() {
+                eprintln!("madhouse: cannot write trace {}: {}", path.display(), err);
+                self.stream = None;
+            }
+        }
+    }
+
+    fn push(
+        keep: Option<usize>,
+        records: &mut Vec<StepReport>,
+        dropped: &mut usize,
+        step: StepReport,
+    ) {
+        records.push(step);
+        // Trimming only once twice the limit is reached keeps pushes O(1)
+        // amortized.
+        if let Some(keep) = keep.filter(|&keep| records.len() >= 2 * keep.max(1)) {
+            Self::trim(keep, records, dropped);
+        }
+    }
+
+    fn trim(keep: usize, records: &mut Vec<StepReport>, dropped: &mut usize) {
+        let excess = records.len().saturating_sub(keep);
+        records.drain(..excess);
+        *dropped += excess;
+    }
+
+    fn write(&mut self, step: &StepReport, heartbeat: bool) {
+        let Some((path, file)) = &mut self.stream else {
+            return;
+        };
+        let mut line = step_json(step);
+        if heartbeat {
+            line.insert_str(1, r#""heartbeat":true,"#);
+        }
+        if let Err(err) = writeln!(file, "{}", line) {
+            eprintln!("madhouse: cannot write trace {}: {}", path.display(), err);
+            self.stream = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StepOutcome;
+    use std::time::Duration;
+
+    fn step(index: usize) -> StepReport {
+        StepReport {
+            index,
+            label: format!("STEP({})", index),
+            group: String::new(),
+            outcome: StepOutcome::Executed,
+            duration: Duration::ZERO,
+            check_duration: Duration::ZERO,
+            started: std::time::UNIX_EPOCH,
+            finished: std::time::UNIX_EPOCH,
+        }
+    }
+
+    #[test]
+    fn test_keeps_the_last_steps_and_streams_all_of_them() {
+        let path =
+            std::env::temp_dir().join(format!("madhouse-trace-{}.jsonl", std::process::id()));
+        let config = Config {
+            trace_limit: Some(3),
+            trace_file: Some(path.clone()),
+            ..Config::default()
+        };
+        let mut trace = Trace::new(&config);
+        let mut report = ExecutionReport::default();
+
+        for index in 0..10 {
+            trace.step(&mut report, step(index));
+            assert!(report.steps.len() < 6);
+        }
+        trace.heartbeat(&mut report, step(9));
+        trace.finish(&mut report);
+
+        let kept: Vec<usize> = report.steps.iter().map(|s| s.index).collect();
+        assert_eq!(kept, vec![7, 8, 9]);
+        assert_eq!(report.dropped_steps, 7);
+        assert_eq!(report.heartbeats.len(), 1);
+        let streamed = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(streamed.lines().count(), 11);
+        assert!(streamed
+            .lines()
+            .last()
+            .unwrap()
+            .starts_with(r#"{"heartbeat":true,"index":9,"#));
+    }
+}
@@ -0,0 +1,133 @@
+//! Timeouts for commands that drive external systems.
+//!
+//! A command declaring a [`Command::timeout`](crate::Command::timeout)
+//! fails its step if applying it takes longer than its limit. A thread
+//! cannot be stopped from the outside, so a command wrapped with
+//! [`CommandWrapper::new`](crate::CommandWrapper::new) is only caught once
+//! it returns. One wrapped with
+//! [`CommandWrapper::detachable`](crate::CommandWrapper::detachable) is
+//! applied on a worker thread instead, handed the state: if it is still
+//! running at its limit (e.g. on an external process that never answers),
+//! its step fails right away with its label and limit, and the case ends
+//! there, like one breaking an invariant, and shrinks like any other
+//! failing one.
+//!
+//! The worker keeps the state it was handed, so no later command,
+//! invariant or cleanup runs on the case's state; the executor's caller
+//! gets `S::default()` back in its place. The worker can be neither
+//! stopped nor joined: it runs until its command returns, or the process
+//! exits. Every timed-out step leaves one such thread behind, including
+//! each rerun of it while shrinking, so bound shrinking (e.g. with
+//! `PROPTEST_MAX_SHRINK_ITERS`) if a command may hang for good.
+
+use crate::env;
+use crate::panics;
+use crate::CommandError;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+/// How a command applied on a worker thread ended.
+#[derive(Debug)]
+pub(crate) enum Outcome {
+    /// The command returned within its limit; the state is back in place.
+    Returned(Result<(), CommandError>),
+    /// The command is still running after its limit, with the state.
+    Abandoned(CommandError),
+}
+
+/// Runs `apply` on `state` on a worker thread, giving up on it if it is
+/// still running after `limit`.
+///
+/// The worker is marked as executing the step this thread is, so it hands
+/// the command the same [`Env`](crate::Env), and a panic in `apply` is
+/// raised again on this thread.
+pub(crate) fn detach<S: Send + Default + 'static>(
+    limit: Duration,
+    state: &mut S,
+    apply: impl FnOnce(&mut S) -> Result<(), CommandError> + Send + 'static,
+) -> Outcome {
+    let (done, wait) = mpsc::channel();
+    let mut owned = std::mem::take(state);
+    let step = panics::step();
+    let seed = env::step_seed();
+    thread::spawn(move || {
+        env::adopt(seed);
+        let result = panics::catch(|| {
+            panics::set_step(step.as_ref().map(|(index, label)| (*index, label.as_str())));
+            apply(&mut owned)
+        });
+        // The receiver is gone if the command timed out.
+        let _ = done.send((owned, result));
+    });
+    match wait.recv_timeout(limit) {
+        Ok((owned, result)) => {
+            *state = owned;
+            Outcome::Returned(result.unwrap_or_else(|panic| panics::resume(*panic)))
+        }
+        Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => {
+            Outcome::Abandoned(CommandError::new(format!(
+                "timed out: still running after its limit of {:?}, left running detached",
+                limit
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Env;
+
+    #[test]
+    fn test_detach_gives_up_on_a_hung_command() {
+        let limit = Duration::from_millis(20);
+        let mut state = vec![1];
+        let outcome = detach(limit, &mut state, |state| {
+            state.push(2);
+            Ok(())
+        });
+        assert!(matches!(outcome, Outcome::Returned(Ok(()))));
+        assert_eq!(state, [1, 2]);
+
+        let outcome = detach(limit, &mut state, |_| {
+            thread::sleep(Duration::from_secs(60));
+            Ok(())
+        });
+        let Outcome::Abandoned(err) = outcome else {
+            panic!("expected the command to be abandoned");
+        };
+        assert_eq!(
+            err.message(),
+            "timed out: still running after its limit of 20ms, left running detached"
+        );
+        assert!(state.is_empty());
+    }
+
+    #[test]
+    fn test_detach_raises_panics_with_the_step_executing() {
+        let captured = panics::catch(|| {
+            panics::set_step(Some((1, "BOOM")));
+            detach(Duration::from_secs(10), &mut 0, |_| panic!("boom"))
+        })
+        .unwrap_err();
+        assert_eq!(captured.step, Some((1, "BOOM".to_string())));
+        assert_eq!(captured.message, "boom");
+    }
+
+    #[test]
+    fn test_detach_hands_the_worker_the_step_seed() {
+        env::with_case_seed(7, || {
+            let _scope = env::Scope::enter();
+            env::set_step(0, "DRAW");
+            let mut seed = 0;
+            let outcome = detach(Duration::from_secs(10), &mut seed, |seed| {
+                *seed = Env::current().seed();
+                Ok(())
+            });
+            assert!(matches!(outcome, Outcome::Returned(Ok(()))));
+            assert_eq!(seed, Env::current().seed());
+            assert_ne!(seed, 0);
+        });
+    }
+}